@@ -0,0 +1,47 @@
+//! Times the lexer against the one it replaced, which is kept in `baseline.rs`.
+
+mod baseline;
+
+use std::time::{Duration, Instant};
+
+use rstex::lexer::Lexer;
+
+const PARAGRAPH: &str = r#"
+Once upon a time, in a distant
+  galaxy called \"O\"o\c c,
+there lived a computer
+named R.~J.~Drofnats.
+
+Mr.~Drofnats---or \lq\lq R.~J.,\rq\rq as he preferred to be called---
+was happiest when he was at work % typesetting
+typesetting beautiful documents. \TeX ^^`^^` $E = mc^2$ \centerline{\bf A SHORT STORY}
+"#;
+
+/// The average time `lex` takes over `runs` runs, and the number of tokens it returns
+fn time(runs: u32, mut lex: impl FnMut() -> usize) -> (Duration, usize) {
+    let mut tokens = 0;
+    let start = Instant::now();
+    for _ in 0..runs {
+        tokens = lex();
+    }
+    (start.elapsed() / runs, tokens)
+}
+
+fn main() {
+    let source = PARAGRAPH.repeat(20_000);
+    let runs = 10;
+
+    let (before, _) = time(runs, || baseline::Lexer::new(source.lines()).count());
+    let (elapsed, tokens) = time(runs, || Lexer::new(source.lines()).count());
+
+    println!(
+        "lexed {} bytes into {tokens} tokens in {elapsed:?} ({:.1} MB/s, {:.1} Mtokens/s)",
+        source.len(),
+        source.len() as f64 / elapsed.as_secs_f64() / 1e6,
+        tokens as f64 / elapsed.as_secs_f64() / 1e6,
+    );
+    println!(
+        "the baseline lexer took {before:?}, {:.2}x as long",
+        before.as_secs_f64() / elapsed.as_secs_f64(),
+    );
+}
@@ -1,11 +1,39 @@
-fn main() {
-    eprintln!("{}", rstex::render_as_html(r"
-\def\mat#1{\begin{bmatrix}#1\end{bmatrix}}
-\mat{1 & 2 \\ 3 & 4}"));
-    eprintln!("{}", rstex::render_as_html(r"$E   =   mc^2$"));
+//! Typesets a displayed matrix, a formula and some text with the built-in LaTeX kernel and
+//! prints the pages as HTML.
+
+use rstex::engine::Engine;
+
+const DISPLAY: &str = r#"
+\documentclass{article}
+\usepackage{amsmath}
+\newcommand\mat[1]{\begin{bmatrix}#1\end{bmatrix}}
+\begin{document}
+The rotation by a quarter turn is
+\[ \mat{0 & -1 \\ 1 & 0}, \]
+and in general
+\[ \mat{1 & 2 \\ 3 & 4} \mat{x \\ y} = \mat{x + 2y \\ 3x + 4y}. \]
+\end{document}
+"#;
 
-    eprintln!("{}", rstex::render_as_html(r"\TeX  is
+const FORMULA: &str = r"$E   =   mc^2$";
+
+/// Spaces are skipped after control words and collapsed, even when made with `^^`
+const TEXT: &str = r"\TeX  is
   cool  \TeX ^^`^^` abc  ^^`^^` ab
 
-a"));
+a";
+
+fn main() {
+    for tex in [DISPLAY, FORMULA, TEXT] {
+        match Engine::builder().latex().render(tex) {
+            Ok(doc) => {
+                eprint!("{}", doc.report());
+                println!("{}", doc.to_html());
+            },
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            },
+        }
+    }
 }
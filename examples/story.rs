@@ -1,5 +1,10 @@
-fn main() {
-    eprintln!("{}", rstex::render_as_html(r#"
+//! Typesets the story from chapter 6 of The TeXbook with the macros of plain TeX, given the path
+//! to `plain.tex`, and prints the page as HTML. The last line reports an error, since `^^61` is
+//! the letter `a` and makes `\b^^61ch` the undefined `\bach`.
+
+use std::path::Path;
+
+const STORY: &str = r#"
 \hrule
 \vskip 1in
 \centerline{\bf A SHORT STORY}
@@ -18,5 +23,22 @@ typesetting beautiful documents.
 \hrule
 \vfill\eject
 \b^^61ch
-"#));
+"#;
+
+fn main() {
+    let Some(format) = std::env::args().nth(1) else {
+        eprintln!("usage: story <path to plain.tex>");
+        std::process::exit(2);
+    };
+
+    match rstex::render_with_format(STORY, Path::new(&format)) {
+        Ok(doc) => {
+            eprint!("{}", doc.report());
+            println!("{}", doc.to_html());
+        },
+        Err(e) => {
+            eprintln!("{format}: {e}");
+            std::process::exit(1);
+        },
+    }
 }
@@ -0,0 +1,113 @@
+//! The little-endian encoding of compiled hyphenation patterns and format files
+
+/// Appends values to a buffer
+pub(crate) trait Put {
+    fn put_u8(&mut self, n: u8);
+    fn put_u32(&mut self, n: u32);
+
+    /// A length or index
+    fn put_int(&mut self, n: usize) {
+        self.put_u32(n as u32);
+    }
+
+    fn put_i32(&mut self, n: i32) {
+        self.put_u32(n as u32);
+    }
+
+    fn put_f32(&mut self, x: f32) {
+        self.put_u32(x.to_bits());
+    }
+
+    fn put_char(&mut self, c: char) {
+        self.put_u32(c as u32);
+    }
+
+    /// A length followed by the bytes
+    fn put_bytes(&mut self, bytes: &[u8]);
+
+    fn put_str(&mut self, s: &str) {
+        self.put_bytes(s.as_bytes());
+    }
+}
+
+impl Put for Vec<u8> {
+    fn put_u8(&mut self, n: u8) {
+        self.push(n);
+    }
+
+    fn put_u32(&mut self, n: u32) {
+        self.extend(n.to_le_bytes());
+    }
+
+    fn put_bytes(&mut self, bytes: &[u8]) {
+        self.put_int(bytes.len());
+        self.extend(bytes);
+    }
+}
+
+/// Reads values written with [`Put`], failing with `err` if the data runs out or is invalid
+pub(crate) struct Reader<'b, E> {
+    bytes: &'b [u8],
+    err: E,
+}
+
+impl<'b, E: Copy> Reader<'b, E> {
+    pub fn new(bytes: &'b [u8], err: E) -> Self {
+        Self { bytes, err }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn u8(&mut self) -> Result<u8, E> {
+        let (n, rest) = self.bytes.split_first().ok_or(self.err)?;
+        self.bytes = rest;
+        Ok(*n)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, E> {
+        let (n, rest) = self.bytes.split_first_chunk::<4>().ok_or(self.err)?;
+        self.bytes = rest;
+        Ok(u32::from_le_bytes(*n))
+    }
+
+    pub fn int(&mut self) -> Result<usize, E> {
+        self.u32().map(|n| n as usize)
+    }
+
+    pub fn i32(&mut self) -> Result<i32, E> {
+        self.u32().map(|n| n as i32)
+    }
+
+    pub fn f32(&mut self) -> Result<f32, E> {
+        self.u32().map(f32::from_bits)
+    }
+
+    pub fn char(&mut self) -> Result<char, E> {
+        let n = self.u32()?;
+        char::from_u32(n).ok_or(self.err)
+    }
+
+    /// `n` bytes, without a length before them
+    pub fn raw(&mut self, n: usize) -> Result<&'b [u8], E> {
+        let (bytes, rest) = self.bytes.split_at_checked(n).ok_or(self.err)?;
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    pub fn bytes(&mut self) -> Result<&'b [u8], E> {
+        let n = self.int()?;
+        self.raw(n)
+    }
+
+    pub fn str(&mut self) -> Result<&'b str, E> {
+        let bytes = self.bytes()?;
+        core::str::from_utf8(bytes).map_err(|_| self.err)
+    }
+
+    /// The error to fail with
+    pub fn err(&self) -> E {
+        self.err
+    }
+}
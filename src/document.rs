@@ -0,0 +1,113 @@
+use crate::engine::SourceFile;
+use crate::error::{Diagnostic, ErrorKind};
+use crate::font::{Font, FontId};
+use crate::layout::Node;
+use crate::{dvi, html, pdf, svg};
+
+/// A typeset document, with everything needed to write it out
+#[derive(Debug, Clone)]
+pub struct Document {
+    /// The pages, as vertical boxes
+    pub pages: Vec<Node>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// The files read, which the spans of the diagnostics and of the nodes refer to
+    pub files: Vec<SourceFile>,
+    /// The fonts loaded, indexed by [`FontId`]
+    pub fonts: Vec<Font>,
+    pub metadata: Metadata,
+}
+
+/// What is known about how a document was typeset
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    /// The name of the format loaded, like `plain`
+    pub format: Option<String>,
+    /// The name of the main file
+    pub job_name: String,
+    /// Everything printed, as TeX writes it to its log file
+    pub log: String,
+    pub math: MathRenderer,
+}
+
+/// How math is written in HTML output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MathRenderer {
+    /// Positioned glyphs and rules, like the rest of the page
+    #[default]
+    Html,
+    /// MathML, which browsers lay out themselves
+    MathMl,
+}
+
+/// The file formats a document can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Absolutely positioned HTML, with the source of every glyph
+    #[default]
+    Html,
+    Svg,
+    /// PDF in the standard fonts
+    Pdf,
+    /// DVI as TeX writes it, referring to the fonts by name
+    Dvi,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "html" => Self::Html,
+            "svg" => Self::Svg,
+            "pdf" => Self::Pdf,
+            "dvi" => Self::Dvi,
+            _ => return None,
+        })
+    }
+
+    /// The extension of files in this format, which is also its name
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Svg => "svg",
+            Self::Pdf => "pdf",
+            Self::Dvi => "dvi",
+        }
+    }
+}
+
+impl Document {
+    pub fn font(&self, id: FontId) -> &Font {
+        &self.fonts[id.0 as usize]
+    }
+
+    /// Writes every page in `format`
+    pub fn write(&self, format: OutputFormat) -> Vec<u8> {
+        match format {
+            OutputFormat::Html => html::render(self).into_bytes(),
+            OutputFormat::Svg => svg::render(self).into_bytes(),
+            OutputFormat::Pdf => pdf::render(self),
+            OutputFormat::Dvi => dvi::render(self, " rstex output"),
+        }
+    }
+
+    pub fn to_html(&self) -> String {
+        html::render(self)
+    }
+
+    /// Formats every diagnostic with its source snippet, as TeX would show them in its log
+    pub fn report(&self) -> String {
+        self.diagnostics.iter().map(|d| d.render(&self.files) + "\n").collect()
+    }
+
+    /// The limit that stopped processing, with its value, if one was reached
+    pub fn limit_exceeded(&self) -> Option<(&'static str, usize)> {
+        self.diagnostics.iter().find_map(|d| match d.kind {
+            ErrorKind::CapacityExceeded(resource, limit) => Some((resource, limit)),
+            _ => None,
+        })
+    }
+
+    /// Whether there were errors, as opposed to only warnings
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity() == crate::error::Severity::Error)
+    }
+}
@@ -0,0 +1,188 @@
+use crate::document::Document;
+use crate::engine::to_scaled;
+use crate::font::FontId;
+use crate::layout::{self, BoxContent, Node, Whatsit};
+
+const PUT1: u8 = 133;
+const PUT_RULE: u8 = 137;
+const BOP: u8 = 139;
+const EOP: u8 = 140;
+const RIGHT4: u8 = 146;
+const DOWN4: u8 = 160;
+const FNT4: u8 = 238;
+const XXX1: u8 = 239;
+const XXX4: u8 = 242;
+const FNT_DEF4: u8 = 246;
+const PRE: u8 = 247;
+const POST: u8 = 248;
+const POST_POST: u8 = 249;
+const ID: u8 = 2;
+
+/// Units of scaled points, as TeX uses, and no magnification
+const NUM: u32 = 25_400_000;
+const DEN: u32 = 473_628_672;
+const MAG: u32 = 1000;
+
+/// Renders a document as a DVI file, as TeX writes them, referring to the fonts by name.
+/// `comment` is the preamble comment, which TeX fills with the date.
+pub fn render(doc: &Document, comment: &str) -> Vec<u8> {
+    let mut dvi = Dvi::default();
+
+    dvi.out.push(PRE);
+    dvi.out.push(ID);
+    dvi.u32(NUM);
+    dvi.u32(DEN);
+    dvi.u32(MAG);
+    let comment = &comment.as_bytes()[..comment.len().min(255)];
+    dvi.out.push(comment.len() as u8);
+    dvi.out.extend(comment);
+
+    let mut bop = -1;
+    let (mut max_height, mut max_width) = (0.0, 0.0);
+    for (n, page) in doc.pages.iter().enumerate() {
+        let [width, height, depth] = page.size();
+        max_height = f32::max(max_height, height + depth);
+        max_width = f32::max(max_width, width);
+
+        let prev = bop;
+        bop = dvi.out.len() as i32;
+        dvi.out.push(BOP);
+        // `\count0` is the page number
+        dvi.i32(n as i32 + 1);
+        for _ in 1..10 {
+            dvi.i32(0);
+        }
+        dvi.i32(prev);
+        // every page starts at the top left corner with no font selected
+        (dvi.h, dvi.v, dvi.font) = (0, 0, None);
+
+        layout::walk(page, 0.0, height, &mut |n, x, y| {
+            if let Node::Whatsit(Whatsit::Special(text)) = n {
+                dvi.move_to(to_scaled(x), to_scaled(y));
+                dvi.special(text.as_bytes());
+                return;
+            }
+            let Node::Box { content, size: [w, h, d], .. } = n else { return };
+            match content {
+                BoxContent::Character(c, f) => {
+                    dvi.move_to(to_scaled(x), to_scaled(y));
+                    dvi.select(*f, doc);
+                    dvi.put(*c as u32);
+                },
+                _ => {
+                    // a rule's reference point is at its bottom left corner in DVI
+                    dvi.move_to(to_scaled(x), to_scaled(y + d));
+                    dvi.out.push(PUT_RULE);
+                    dvi.i32(to_scaled(h + d));
+                    dvi.i32(to_scaled(*w));
+                },
+            }
+        });
+        dvi.out.push(EOP);
+    }
+
+    let post = dvi.out.len();
+    dvi.out.push(POST);
+    dvi.i32(bop);
+    dvi.u32(NUM);
+    dvi.u32(DEN);
+    dvi.u32(MAG);
+    dvi.i32(to_scaled(max_height));
+    dvi.i32(to_scaled(max_width));
+    // the stack is never used
+    dvi.out.extend([0, 0]);
+    dvi.out.extend((doc.pages.len() as u16).to_be_bytes());
+    for f in dvi.fonts.clone() {
+        dvi.define(f, doc);
+    }
+
+    dvi.out.push(POST_POST);
+    dvi.i32(post as i32);
+    dvi.out.push(ID);
+    dvi.out.extend([223; 4]);
+    while dvi.out.len() % 4 != 0 {
+        dvi.out.push(223);
+    }
+    dvi.out
+}
+
+#[derive(Default)]
+struct Dvi {
+    out: Vec<u8>,
+    /// The current position, in scaled points
+    h: i32,
+    v: i32,
+    font: Option<FontId>,
+    /// The fonts defined so far
+    fonts: Vec<FontId>,
+}
+
+impl Dvi {
+    fn u32(&mut self, n: u32) {
+        self.out.extend(n.to_be_bytes());
+    }
+
+    fn i32(&mut self, n: i32) {
+        self.out.extend(n.to_be_bytes());
+    }
+
+    fn move_to(&mut self, h: i32, v: i32) {
+        if h != self.h {
+            self.out.push(RIGHT4);
+            self.i32(h - self.h);
+            self.h = h;
+        }
+        if v != self.v {
+            self.out.push(DOWN4);
+            self.i32(v - self.v);
+            self.v = v;
+        }
+    }
+
+    fn select(&mut self, f: FontId, doc: &Document) {
+        if self.font == Some(f) {
+            return;
+        }
+        if !self.fonts.contains(&f) {
+            self.fonts.push(f);
+            self.define(f, doc);
+        }
+        self.out.push(FNT4);
+        self.u32(f.0);
+        self.font = Some(f);
+    }
+
+    fn define(&mut self, f: FontId, doc: &Document) {
+        let font = doc.font(f);
+        self.out.push(FNT_DEF4);
+        self.u32(f.0);
+        // there are no font files to take a checksum from
+        self.u32(0);
+        self.i32(to_scaled(font.size));
+        self.i32(to_scaled(font.design_size));
+        let name = &font.name.as_bytes()[..font.name.len().min(255)];
+        self.out.push(0);
+        self.out.push(name.len() as u8);
+        self.out.extend(name);
+    }
+
+    /// Typesets character `c` without moving, as the positions are all absolute
+    fn put(&mut self, c: u32) {
+        let bytes = c.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count().min(3);
+        self.out.push(PUT1 + (3 - skip) as u8);
+        self.out.extend(&bytes[skip..]);
+    }
+
+    /// Passes the text of a `\special` on to the program reading the file
+    fn special(&mut self, text: &[u8]) {
+        match u8::try_from(text.len()) {
+            Ok(len) => self.out.extend([XXX1, len]),
+            Err(_) => {
+                self.out.push(XXX4);
+                self.u32(text.len() as u32);
+            },
+        }
+        self.out.extend(text);
+    }
+}
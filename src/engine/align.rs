@@ -0,0 +1,491 @@
+use crate::layout::{self, BoxContent, Node, RUNNING};
+use crate::lexer::{Span, Token, TokenType};
+use super::build::{glue_node, Group, List, Mode};
+use super::scan::to_pt;
+use super::*;
+
+/// A column of an alignment, with the template its entries are put in
+#[derive(Debug, Clone)]
+struct Column {
+    /// The template before the entry
+    u: Vec<Token>,
+    /// The template after the entry
+    v: Vec<Token>,
+    /// The `\tabskip` glue after the column
+    tabskip: Glue,
+}
+
+/// What an alignment entry ended with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryEnd {
+    Tab,
+    Span,
+    Cr,
+}
+
+/// An entry of a row, packed at its natural width, spanning columns `first` to `last`
+#[derive(Debug)]
+struct Entry {
+    first: usize,
+    last: usize,
+    b: Node,
+}
+
+#[derive(Debug)]
+struct Row {
+    entries: Vec<Entry>,
+    /// Where the row is in the list of the alignment, which holds an empty box of its height and
+    /// depth until the widths of the columns are known
+    index: usize,
+}
+
+/// An alignment being built by `\halign`
+#[derive(Debug)]
+pub(super) struct Align {
+    columns: Vec<Column>,
+    /// The `\tabskip` glue before the first column
+    tabskip: Glue,
+    /// The column to copy next when an entry is past the last column, for a preamble whose
+    /// periodic part starts with `&`
+    repeat: Option<usize>,
+    /// The width given with `to`
+    to: Option<f32>,
+    /// The amount given with `spread`
+    spread: f32,
+    rows: Vec<Row>,
+    /// The column of the current entry, and the column it started in if it spans several
+    column: usize,
+    span_start: usize,
+    /// Set if the template of the current entry is left out, by `\omit`
+    omit: bool,
+    /// Set while an entry is being read, before the `v` part of its template is inserted
+    in_entry: bool,
+    end: EntryEnd,
+    /// `align_state` outside the alignment
+    align_state: i32,
+    /// Set for an alignment between `$$` and `$$`
+    display: bool,
+}
+
+impl Engine {
+    /// Starts an alignment after `\halign`, reading its preamble, in a display if `display` is
+    /// set
+    pub(super) fn init_align(&mut self, display: bool) {
+        let align_state = self.align_state;
+        let (to, spread) = if self.scan_keyword("to") {
+            (Some(to_pt(self.scan_dimen())), 0.0)
+        } else if self.scan_keyword("spread") {
+            (None, to_pt(self.scan_dimen()))
+        } else {
+            (None, 0.0)
+        };
+        self.begin_group();
+        self.groups.push(Group::Align);
+        self.scan_left_brace();
+
+        // the rows go in a list of their own, which is added to the enclosing one at the end
+        let enclosing = self.nest.len() - if display { 2 } else { 1 };
+        let mut list = List::new(Mode::InternalVertical);
+        list.prev_depth = self.nest[enclosing].prev_depth;
+        self.push_nest(list);
+
+        self.align_state = -1_000_000;
+        let tabskip = self.eqtb.glue(&Key::GlueParam(GlueParam::TabSkip));
+        let (columns, repeat) = self.scan_preamble();
+        self.aligns.push(Align {
+            columns,
+            tabskip,
+            repeat,
+            to,
+            spread,
+            rows: Vec::new(),
+            column: 0,
+            span_start: 0,
+            omit: false,
+            in_entry: false,
+            end: EntryEnd::Cr,
+            align_state,
+            display,
+        });
+
+        let toks = self.eqtb.toks(&Key::ToksParam(ToksParam::EveryCr));
+        self.back_list(toks.iter().cloned());
+        self.align_peek();
+    }
+
+    /// Reads a preamble up to its `\cr`, splitting each template at its `#`. A preamble that
+    /// has `&` right after an `&` or at its start repeats from there for as many columns as the
+    /// rows need.
+    fn scan_preamble(&mut self) -> (Vec<Column>, Option<usize>) {
+        let mut columns = Vec::new();
+        let mut repeat = None;
+
+        loop {
+            let mut u = Vec::new();
+            loop {
+                let Some((t, m)) = self.get_preamble_token() else {
+                    // the input ended, but every alignment has a column to put its entries in
+                    self.error(ErrorKind::MissingSharp);
+                    let tabskip = self.eqtb.glue(&Key::GlueParam(GlueParam::TabSkip));
+                    columns.push(Column { u, v: Vec::new(), tabskip });
+                    return (columns, repeat);
+                };
+                if matches!(m, Some(Meaning::Char(TokenType::Parameter, _))) {
+                    break;
+                }
+                if self.align_state == -1_000_000 && entry_end(&m).is_some() {
+                    if u.is_empty() && repeat.is_none() && entry_end(&m) == Some(EntryEnd::Tab) {
+                        repeat = Some(columns.len());
+                        continue;
+                    }
+                    self.error(ErrorKind::MissingSharp);
+                    self.back_input(t);
+                    break;
+                }
+                // spaces at the start of a template are ignored
+                if t.typ != TokenType::Space || !u.is_empty() {
+                    u.push(t);
+                }
+            }
+
+            let mut v = Vec::new();
+            let last = loop {
+                let Some((t, m)) = self.get_preamble_token() else { break true };
+                if matches!(m, Some(Meaning::Char(TokenType::Parameter, _))) {
+                    self.error(ErrorKind::OnlyOneSharp);
+                    continue;
+                }
+                if self.align_state == -1_000_000 {
+                    match entry_end(&m) {
+                        Some(EntryEnd::Cr) => break true,
+                        Some(_) => break false,
+                        None => {},
+                    }
+                }
+                v.push(t);
+            };
+
+            let tabskip = self.eqtb.glue(&Key::GlueParam(GlueParam::TabSkip));
+            columns.push(Column { u, v, tabskip });
+            if last {
+                return (columns, repeat);
+            }
+        }
+    }
+
+    /// Gets a token of a preamble, expanding the one after `\span` and performing `\tabskip`
+    /// assignments
+    fn get_preamble_token(&mut self) -> Option<Command> {
+        loop {
+            let (t, noexpand) = self.get_next()?;
+            let m = if noexpand { Some(Meaning::Primitive(Primitive::Relax)) } else { self.meaning(&t) };
+            match m {
+                Some(Meaning::Primitive(Primitive::Span)) => {
+                    let (t, noexpand) = self.get_next()?;
+                    let m = self.meaning(&t);
+                    if !noexpand && m.as_ref().is_none_or(Meaning::is_expandable) {
+                        self.expand(t, m);
+                    } else {
+                        self.reread(t, noexpand);
+                    }
+                },
+                Some(Meaning::GlueParam(GlueParam::TabSkip)) => {
+                    self.scan_optional_equals();
+                    let g = self.scan_glue();
+                    let global = self.int_param(IntParam::GlobalDefs) > 0;
+                    self.assign(Key::GlueParam(GlueParam::TabSkip), Some(Value::Glue(g)), global);
+                },
+                m => return Some((t, m)),
+            }
+        }
+    }
+
+    /// Inserts the `v` part of the template when an `&`, `\span` or `\cr` ends an alignment
+    /// entry, returning `false` if `t` does not end one
+    pub(super) fn insert_v_template(&mut self, t: &Token) -> bool {
+        let Some(end) = entry_end(&self.meaning(t)) else { return false };
+        let Some(align) = self.aligns.last_mut().filter(|a| a.in_entry) else { return false };
+        align.in_entry = false;
+        align.end = end;
+
+        let mut toks = if align.omit { Vec::new() } else { align.columns[align.column].v.clone() };
+        toks.push(Token::new_cs(self.cs.intern("endtemplate"), 0..0).at(&t.span()));
+        self.back_list(toks.into_iter());
+        self.align_state = 1_000_000;
+        true
+    }
+
+    /// Starts the next row, or the material of `\noalign`, or ends the alignment at its `}`
+    pub(super) fn align_peek(&mut self) {
+        loop {
+            self.align_state = 1_000_000;
+            let Some((t, m)) = self.get_x_nonblank() else { return };
+            match m {
+                Some(Meaning::Primitive(Primitive::NoAlign)) => {
+                    self.scan_left_brace();
+                    self.begin_group();
+                    self.groups.push(Group::NoAlign);
+                },
+                Some(Meaning::Char(TokenType::EndGroup, _)) => {
+                    self.groups.pop();
+                    self.fin_align();
+                },
+                Some(Meaning::Primitive(Primitive::CrCr)) => continue,
+                _ => {
+                    if let Some(align) = self.aligns.last_mut() {
+                        align.rows.push(Row { entries: Vec::new(), index: 0 });
+                        align.column = 0;
+                    }
+                    self.init_span(0);
+                    self.init_col(t, m);
+                },
+            }
+            return;
+        }
+    }
+
+    /// Ends the material of `\noalign` at its `}`
+    pub(super) fn fin_no_align(&mut self) {
+        if self.mode() == Mode::Horizontal {
+            self.end_paragraph();
+        }
+        self.end_group();
+        self.align_peek();
+    }
+
+    /// Starts an entry in `column`, which may go on into the columns after it
+    fn init_span(&mut self, column: usize) {
+        if let Some(align) = self.aligns.last_mut() {
+            align.span_start = column;
+        }
+        self.begin_group();
+        self.groups.push(Group::Cell);
+        self.push_nest(List::new(Mode::RestrictedHorizontal));
+    }
+
+    /// Starts reading the entry of the current column, which begins with `t`, inserting the `u`
+    /// part of its template unless `t` is `\omit`
+    fn init_col(&mut self, t: Token, m: Option<Meaning>) {
+        let Some(align) = self.aligns.last_mut() else { return };
+        align.in_entry = true;
+        align.omit = matches!(m, Some(Meaning::Primitive(Primitive::Omit)));
+        if align.omit {
+            self.align_state = 0;
+            return;
+        }
+
+        // the braces of the template do not count, so the entry ends at an `&` outside of the
+        // braces of the entry itself
+        let u = align.columns[align.column].u.clone();
+        self.back_input(t);
+        self.align_state = -u.iter().map(|t| match t.typ {
+            TokenType::BeginGroup => 1,
+            TokenType::EndGroup => -1,
+            _ => 0,
+        }).sum::<i32>();
+        self.back_list(u.into_iter());
+    }
+
+    /// Ends the current entry when its `\endtemplate` is read, going on to the next column or
+    /// ending the row
+    pub(super) fn fin_col(&mut self, tok: Token) {
+        if !matches!(self.groups.last(), Some(Group::Cell)) {
+            self.off_save();
+            self.back_input(tok);
+            return;
+        }
+
+        let Some(align) = self.aligns.last_mut() else { return };
+        let mut end = align.end;
+        if end != EntryEnd::Cr && align.column + 1 == align.columns.len() {
+            match align.repeat {
+                Some(r) => {
+                    let column = align.columns[r].clone();
+                    align.columns.push(column);
+                    align.repeat = Some(r + 1);
+                },
+                None => {
+                    align.end = EntryEnd::Cr;
+                    end = EntryEnd::Cr;
+                    self.error(ErrorKind::ExtraAlignmentTab);
+                },
+            }
+        }
+
+        if end != EntryEnd::Span {
+            self.groups.pop();
+            self.end_group();
+            let nodes = self.nest.pop().unwrap().nodes;
+            let span = layout::span_of(&nodes);
+            let b = layout::hpack(nodes, None, span);
+            if let Some(align) = self.aligns.last_mut() {
+                let entry = Entry { first: align.span_start, last: align.column, b };
+                if let Some(row) = align.rows.last_mut() {
+                    row.entries.push(entry);
+                }
+            }
+        }
+        if end == EntryEnd::Cr {
+            self.fin_row();
+            return;
+        }
+
+        let Some(align) = self.aligns.last_mut() else { return };
+        align.column += 1;
+        let column = align.column;
+        if end != EntryEnd::Span {
+            self.init_span(column);
+        }
+        self.align_state = 1_000_000;
+        if let Some((t, m)) = self.get_x_nonblank() {
+            self.init_col(t, m);
+        }
+    }
+
+    /// Ends the current entry as if by `\cr`, when the input ends or a group is closed inside it
+    pub(super) fn end_row_early(&mut self) {
+        if let Some(align) = self.aligns.last_mut() {
+            align.end = EntryEnd::Cr;
+            align.in_entry = false;
+        }
+        let tok = Token::new_cs(self.cs.intern("endtemplate"), 0..0);
+        self.fin_col(tok);
+    }
+
+    /// Appends a row to the alignment, as an empty box of its height and depth for now
+    fn fin_row(&mut self) {
+        let Some(row) = self.aligns.last().and_then(|a| a.rows.last()) else { return };
+        let (height, depth) = row.entries.iter().fold((0.0f32, 0.0f32), |(h, d), e| {
+            let [_, eh, ed] = e.b.size();
+            (h.max(eh), d.max(ed))
+        });
+        let b = Node::Box { content: BoxContent::HBox(Vec::new()), size: [0.0, height, depth], shift: 0.0, nesting: 1, span: Span::default() };
+        self.append_box(b);
+
+        let index = self.list().nodes.len() - 1;
+        if let Some(row) = self.aligns.last_mut().and_then(|a| a.rows.last_mut()) {
+            row.index = index;
+        }
+        let toks = self.eqtb.toks(&Key::ToksParam(ToksParam::EveryCr));
+        self.back_list(toks.iter().cloned());
+        self.align_peek();
+    }
+
+    /// Ends an alignment after its `}`, setting every row to the widths of the columns and
+    /// adding the rows to the enclosing list
+    pub(super) fn fin_align(&mut self) {
+        let Some(mut align) = self.aligns.pop() else { return };
+        self.end_group();
+        self.align_state = align.align_state;
+
+        // columns that no entry ends in take no space
+        let n = align.columns.len();
+        let mut used = vec![false; n];
+        for e in align.rows.iter().flat_map(|r| r.entries.iter()) {
+            used[e.last] = true;
+        }
+        for (c, used) in align.columns.iter_mut().zip(used) {
+            if !used {
+                c.tabskip = Glue::default();
+            }
+        }
+
+        // a column is as wide as its widest entry, and an entry spanning several columns widens
+        // the last of them if it needs to
+        let tabskip = |c: usize| to_pt(align.columns[c].tabskip.width);
+        let mut widths = vec![0.0f32; n];
+        for k in 0..n {
+            for e in align.rows.iter().flat_map(|r| r.entries.iter()).filter(|e| e.last == k) {
+                let before: f32 = (e.first..k).map(|c| widths[c] + tabskip(c)).sum();
+                widths[k] = widths[k].max(e.b.size()[0] - before);
+            }
+        }
+        let natural = to_pt(align.tabskip.width) + (0..n).map(|c| widths[c] + tabskip(c)).sum::<f32>();
+        let width = align.to.unwrap_or(natural + align.spread);
+        let shift = match align.display {
+            true => to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::DisplayIndent))),
+            false => 0.0,
+        };
+
+        let mut list = self.nest.pop().unwrap();
+        for row in core::mem::take(&mut align.rows) {
+            let Some(node @ Node::Box { .. }) = list.nodes.get_mut(row.index) else { continue };
+            let [_, height, depth] = node.size();
+            let mut items = vec![glue_node(align.tabskip)];
+            let mut next = 0;
+            for e in row.entries {
+                let w = widths[e.first..=e.last].iter().sum::<f32>() + (e.first..e.last).map(tabskip).sum::<f32>();
+                items.push(set_entry(e.b, w, height, depth));
+                items.push(glue_node(align.columns[e.last].tabskip));
+                next = e.last + 1;
+            }
+            for (w, column) in widths.iter().zip(&align.columns).take(n).skip(next) {
+                let empty = Node::Box { content: BoxContent::HBox(Vec::new()), size: [*w, 0.0, 0.0], shift: 0.0, nesting: 1, span: Span::default() };
+                items.extend([empty, glue_node(column.tabskip)]);
+            }
+
+            let span = layout::span_of(&items);
+            *node = layout::hpack(items, Some(width), span);
+            if let Node::Box { shift: s, .. } = node {
+                *s = shift;
+            }
+        }
+
+        if !align.display {
+            let outer = self.list();
+            outer.nodes.extend(list.nodes);
+            outer.prev_depth = list.prev_depth;
+            return;
+        }
+
+        match self.get_x_nonblank_nonrelax() {
+            Some((_, Some(Meaning::Char(TokenType::MathShift, _)))) => self.expect_dollar(),
+            t => {
+                self.error(ErrorKind::DisplayMathShouldEnd);
+                if let Some((t, _)) = t {
+                    self.back_input(t);
+                }
+            },
+        }
+        self.nest.pop();
+        self.tail_append(Node::Penalty(self.int_param(IntParam::PreDisplayPenalty)));
+        self.tail_append(glue_node(self.eqtb.glue(&Key::GlueParam(GlueParam::AboveDisplaySkip))));
+        self.list().nodes.extend(list.nodes);
+        self.tail_append(Node::Penalty(self.int_param(IntParam::PostDisplayPenalty)));
+        self.tail_append(glue_node(self.eqtb.glue(&Key::GlueParam(GlueParam::BelowDisplaySkip))));
+        self.list().prev_depth = list.prev_depth;
+        self.resume_after_display();
+    }
+}
+
+/// How an `&`, `\span` or `\cr` ends an entry, `None` for other commands
+fn entry_end(m: &Option<Meaning>) -> Option<EntryEnd> {
+    match m {
+        Some(Meaning::Char(TokenType::AlignTab, _)) => Some(EntryEnd::Tab),
+        Some(Meaning::Primitive(Primitive::Span)) => Some(EntryEnd::Span),
+        Some(Meaning::Primitive(Primitive::Cr | Primitive::CrCr)) => Some(EntryEnd::Cr),
+        _ => None,
+    }
+}
+
+/// Sets an entry to width `w` and the height and depth of its row, which rules of running
+/// height in it extend to
+fn set_entry(b: Node, w: f32, height: f32, depth: f32) -> Node {
+    let Node::Box { content: BoxContent::HBox(mut list), span, .. } = b else { return b };
+    for n in list.iter_mut() {
+        if let Node::Box { content: BoxContent::VRule, size, .. } = n {
+            if size[1] == RUNNING {
+                size[1] = height;
+            }
+            if size[2] == RUNNING {
+                size[2] = depth;
+            }
+        }
+    }
+
+    let mut b = layout::hpack(list, Some(w), span);
+    if let Node::Box { size, .. } = &mut b {
+        *size = [w, height, depth];
+    }
+    b
+}
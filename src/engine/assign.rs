@@ -0,0 +1,607 @@
+use std::rc::Rc;
+
+use crate::font::FontId;
+use crate::layout::Node;
+use crate::lexer::{Token, TokenType};
+use super::build::BoxContext;
+use super::scan::{math_font_key, to_pt, Internal};
+use super::*;
+
+/// The kind of value held by a register or parameter that `\advance` can change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Int,
+    Dimen,
+    Glue,
+    MuGlue,
+}
+
+impl Engine {
+    /// Performs an assignment, along with its `\global`, `\long` and `\outer` prefixes
+    pub(super) fn prefixed_command(&mut self, mut meaning: Meaning) {
+        let mut global = false;
+
+        loop {
+            match meaning {
+                Meaning::Primitive(Primitive::Global) => global = true,
+                Meaning::Primitive(Primitive::Long | Primitive::Outer) => {},
+                _ => break,
+            }
+
+            let Some((t, m)) = self.get_x_nonblank_nonrelax() else { return };
+            match m {
+                Some(m) if m.is_assignment() => meaning = m,
+                _ => {
+                    self.error(ErrorKind::CantUsePrefix(self.print_cs(&t)));
+                    self.back_input(t);
+                    return;
+                },
+            }
+        }
+
+        match meaning {
+            Meaning::Primitive(p @ (Primitive::Def | Primitive::Gdef | Primitive::Edef | Primitive::Xdef)) => {
+                let global = global || matches!(p, Primitive::Gdef | Primitive::Xdef);
+                let Some(key) = self.get_r_token() else { return };
+                let m = self.scan_macro(matches!(p, Primitive::Edef | Primitive::Xdef));
+                self.assign(key, Some(Value::Meaning(Meaning::Macro(Rc::new(m)))), global);
+            },
+            Meaning::Primitive(Primitive::Let) => {
+                let Some(key) = self.get_r_token() else { return };
+
+                let mut next = self.get_next();
+                while next.as_ref().is_some_and(|t| t.0.typ == TokenType::Space) {
+                    next = self.get_next();
+                }
+                if next.as_ref().is_some_and(|t| t.0.is_other('=')) {
+                    next = self.get_next();
+                    if next.as_ref().is_some_and(|t| t.0.typ == TokenType::Space) {
+                        next = self.get_next();
+                    }
+                }
+
+                let Some((t, noexpand)) = next else { return };
+                let m = if noexpand { Some(Meaning::Primitive(Primitive::Relax)) } else { self.meaning(&t) };
+                self.assign(key, m.map(Value::Meaning), global);
+            },
+            Meaning::Primitive(Primitive::FutureLet) => {
+                let Some(key) = self.get_r_token() else { return };
+                let Some(first) = self.get_next() else { return };
+                let Some((t, noexpand)) = self.get_next() else {
+                    self.reread(first.0, first.1);
+                    return;
+                };
+
+                let m = if noexpand { Some(Meaning::Primitive(Primitive::Relax)) } else { self.meaning(&t) };
+                self.reread(t, noexpand);
+                self.reread(first.0, first.1);
+                self.assign(key, m.map(Value::Meaning), global);
+            },
+            Meaning::Primitive(p @ (Primitive::CountDef | Primitive::DimenDef | Primitive::SkipDef | Primitive::MuSkipDef | Primitive::ToksDef)) => {
+                let Some(key) = self.get_r_token() else { return };
+                self.assign(key.clone(), Some(Value::Meaning(Meaning::Primitive(Primitive::Relax))), global);
+                self.scan_optional_equals();
+                let n = self.scan_register();
+                let m = match p {
+                    Primitive::CountDef => Meaning::CountDef(n),
+                    Primitive::DimenDef => Meaning::DimenDef(n),
+                    Primitive::SkipDef => Meaning::SkipDef(n),
+                    Primitive::MuSkipDef => Meaning::MuSkipDef(n),
+                    _ => Meaning::ToksDef(n),
+                };
+                self.assign(key, Some(Value::Meaning(m)), global);
+            },
+            Meaning::Primitive(p @ (Primitive::CharDef | Primitive::MathCharDef)) => {
+                let Some(key) = self.get_r_token() else { return };
+                self.assign(key.clone(), Some(Value::Meaning(Meaning::Primitive(Primitive::Relax))), global);
+                self.scan_optional_equals();
+                let m = match p {
+                    Primitive::CharDef => Meaning::CharDef(self.scan_char_code()),
+                    _ => {
+                        let n = self.scan_int();
+                        let max = match self.eqtb.encoding {
+                            Encoding::Classic => 0x7FFF,
+                            Encoding::Unicode => i32::MAX,
+                        };
+                        if !(0..=max).contains(&n) {
+                            self.error(ErrorKind::BadMathChar(n));
+                            return;
+                        }
+                        Meaning::MathCharDef(n)
+                    },
+                };
+                self.assign(key, Some(Value::Meaning(m)), global);
+            },
+            Meaning::Primitive(Primitive::Count) => {
+                let n = self.scan_register();
+                self.assign_int(Key::Count(n), global);
+            },
+            Meaning::CountDef(n) => self.assign_int(Key::Count(n), global),
+            Meaning::IntParam(p) => self.assign_int(Key::Int(p), global),
+            Meaning::Primitive(Primitive::Dimen) => {
+                let n = self.scan_register();
+                self.assign_dimen(Key::Dimen(n), global);
+            },
+            Meaning::DimenDef(n) => self.assign_dimen(Key::Dimen(n), global),
+            Meaning::DimenParam(p) => self.assign_dimen(Key::DimenParam(p), global),
+            Meaning::Primitive(Primitive::Skip) => {
+                let n = self.scan_register();
+                self.assign_glue(Key::Skip(n), global);
+            },
+            Meaning::SkipDef(n) => self.assign_glue(Key::Skip(n), global),
+            Meaning::GlueParam(p) => self.assign_glue(Key::GlueParam(p), global),
+            Meaning::Primitive(Primitive::MuSkip) => {
+                let n = self.scan_register();
+                self.assign_mu_glue(Key::MuSkip(n), global);
+            },
+            Meaning::MuSkipDef(n) => self.assign_mu_glue(Key::MuSkip(n), global),
+            Meaning::MuGlueParam(p) => self.assign_mu_glue(Key::MuGlueParam(p), global),
+            Meaning::Primitive(Primitive::Toks) => {
+                let n = self.scan_register();
+                self.assign_toks(Key::Toks(n), global);
+            },
+            Meaning::ToksDef(n) => self.assign_toks(Key::Toks(n), global),
+            Meaning::ToksParam(p) => self.assign_toks(Key::ToksParam(p), global),
+            Meaning::Primitive(p @ (Primitive::Advance | Primitive::Multiply | Primitive::Divide)) => self.do_register_command(p, global),
+            Meaning::Primitive(Primitive::Font) => self.new_font(global),
+            Meaning::Font(f) => self.assign(Key::CurFont, Some(Value::Font(f)), global),
+            Meaning::Primitive(Primitive::FontDimen) => {
+                // font parameters are always global
+                let (n, f) = self.scan_font_dimen();
+                self.scan_optional_equals();
+                let d = to_pt(self.scan_dimen());
+                if let Some(p) = Rc::make_mut(&mut self.fonts[f.0 as usize].0).params.get_mut(n) {
+                    *p = d;
+                }
+            },
+            Meaning::Primitive(p @ (Primitive::HyphenChar | Primitive::SkewChar)) => {
+                let f = self.scan_font_ident();
+                self.scan_optional_equals();
+                let n = self.scan_int();
+                let font = Rc::make_mut(&mut self.fonts[f.0 as usize].0);
+                match p {
+                    Primitive::HyphenChar => font.hyphen_char = n,
+                    _ => font.skew_char = n,
+                }
+            },
+            Meaning::Primitive(p @ (Primitive::TextFont | Primitive::ScriptFont | Primitive::ScriptScriptFont)) => {
+                let fam = self.scan_four_bit_int();
+                self.scan_optional_equals();
+                let f = self.scan_font_ident();
+                self.assign(math_font_key(p, fam), Some(Value::Font(f)), global);
+            },
+            Meaning::Primitive(Primitive::SetBox) => {
+                let n = self.scan_register();
+                self.scan_optional_equals();
+                self.scan_box(BoxContext::SetBox(n, global));
+            },
+            Meaning::Primitive(p @ (Primitive::Wd | Primitive::Ht | Primitive::Dp)) => {
+                // box dimensions are changed in place, without regard to grouping
+                let n = self.scan_register();
+                self.scan_optional_equals();
+                let d = to_pt(self.scan_dimen());
+                let i = match p {
+                    Primitive::Wd => 0,
+                    Primitive::Ht => 1,
+                    _ => 2,
+                };
+                if let Some(Node::Box { size, .. }) = self.eqtb.box_reg_mut(n) {
+                    size[i] = d;
+                }
+            },
+            Meaning::Primitive(Primitive::Catcode) => {
+                let c = self.scan_char_code();
+                self.scan_optional_equals();
+                let n = self.scan_int();
+                if !(0..=15).contains(&n) {
+                    self.error(ErrorKind::InvalidCode(n, 15));
+                    return;
+                }
+                self.assign(Key::Catcode(c), Some(Value::Int(n)), global);
+            },
+            Meaning::Primitive(p @ (Primitive::LcCode | Primitive::UcCode | Primitive::SfCode | Primitive::MathCode)) => {
+                let c = self.scan_char_code();
+                self.scan_optional_equals();
+                let n = self.scan_int();
+                let max = match (p, self.eqtb.encoding) {
+                    (Primitive::LcCode | Primitive::UcCode, e) => e.max_char() as i32,
+                    (Primitive::SfCode, _) => 0x7FFF,
+                    (_, Encoding::Classic) => 0x8000,
+                    (_, Encoding::Unicode) => i32::MAX,
+                };
+                if !(0..=max).contains(&n) {
+                    self.error(ErrorKind::InvalidCode(n, max));
+                    return;
+                }
+                self.assign(code_key(p, c), Some(Value::Int(n)), global);
+            },
+            Meaning::Primitive(Primitive::DelCode) => {
+                let c = self.scan_char_code();
+                self.scan_optional_equals();
+                let n = self.scan_int();
+                let max = match self.eqtb.encoding {
+                    Encoding::Classic => 0xFFFFFF,
+                    Encoding::Unicode => i32::MAX,
+                };
+                // negative codes mark characters that are not delimiters
+                if n > max {
+                    self.error(ErrorKind::InvalidCode(n, max));
+                    return;
+                }
+                self.assign(Key::DelCode(c), Some(Value::Int(n)), global);
+            },
+            Meaning::Primitive(p @ (Primitive::BatchMode | Primitive::NonStopMode | Primitive::ScrollMode | Primitive::ErrorStopMode)) => {
+                // the interaction mode is always global
+                self.interaction = match p {
+                    Primitive::BatchMode => InteractionMode::Batch,
+                    Primitive::NonStopMode => InteractionMode::NonStop,
+                    Primitive::ScrollMode => InteractionMode::Scroll,
+                    _ => InteractionMode::ErrorStop,
+                };
+            },
+            Meaning::Primitive(Primitive::Patterns) => self.new_patterns(),
+            Meaning::Primitive(Primitive::Hyphenation) => self.new_hyph_exceptions(),
+            Meaning::Primitive(Primitive::Read) => {
+                let n = self.scan_int();
+                if !self.scan_keyword("to") {
+                    self.error(ErrorKind::MissingTo);
+                }
+                let Some(key) = self.get_r_token() else { return };
+                let prompt = match &key {
+                    Key::Cs(cs) => self.cs_string(&self.cs.name(*cs)) + "=",
+                    Key::Active(c) => format!("{c}="),
+                    _ => String::new(),
+                };
+                let body: Vec<_> = self.read_toks(n, &prompt).into_iter().map(MacroToken::Token).collect();
+                self.use_tokens(body.len());
+                let m = Macro { params: Vec::new(), body };
+                self.assign(key, Some(Value::Meaning(Meaning::Macro(Rc::new(m)))), global);
+            },
+            m => unreachable!("{m:?} is not an assignment"),
+        }
+    }
+
+    fn assign_int(&mut self, key: Key, global: bool) {
+        self.scan_optional_equals();
+        let n = self.scan_int();
+        self.assign(key, Some(Value::Int(n)), global);
+    }
+
+    fn assign_dimen(&mut self, key: Key, global: bool) {
+        self.scan_optional_equals();
+        let d = self.scan_dimen();
+        self.assign(key, Some(Value::Int(d)), global);
+    }
+
+    fn assign_glue(&mut self, key: Key, global: bool) {
+        self.scan_optional_equals();
+        let g = self.scan_glue();
+        self.assign(key, Some(Value::Glue(g)), global);
+    }
+
+    fn assign_mu_glue(&mut self, key: Key, global: bool) {
+        self.scan_optional_equals();
+        let g = self.scan_mu_glue();
+        self.assign(key, Some(Value::Glue(g)), global);
+    }
+
+    fn assign_toks(&mut self, key: Key, global: bool) {
+        self.scan_optional_equals();
+        let Some((t, m)) = self.get_x_nonblank_nonrelax() else { return };
+
+        let toks = match m {
+            Some(m @ (Meaning::Primitive(Primitive::Toks) | Meaning::ToksDef(_) | Meaning::ToksParam(_))) => match self.scan_internal(&m) {
+                Some(Internal::Toks(t)) => t,
+                _ => return,
+            },
+            m => {
+                if !matches!(m, Some(Meaning::Char(TokenType::BeginGroup, _))) {
+                    self.error(ErrorKind::MissingLeftBrace);
+                    self.back_input(t);
+                }
+
+                self.scan_body(None, false).into_iter().filter_map(|t| match t {
+                    MacroToken::Token(t) => Some(t),
+                    MacroToken::Param(_) => None,
+                }).collect()
+            },
+        };
+
+        self.assign(key, Some(Value::Toks(toks)), global);
+    }
+
+    /// Performs `\advance`, `\multiply` or `\divide`, with an optional `by`
+    fn do_register_command(&mut self, p: Primitive, global: bool) {
+        let Some((t, m)) = self.get_x_nonblank_nonrelax() else { return };
+        let (key, level) = match m {
+            Some(Meaning::Primitive(Primitive::Count)) => (Key::Count(self.scan_register()), Level::Int),
+            Some(Meaning::Primitive(Primitive::Dimen)) => (Key::Dimen(self.scan_register()), Level::Dimen),
+            Some(Meaning::Primitive(Primitive::Skip)) => (Key::Skip(self.scan_register()), Level::Glue),
+            Some(Meaning::Primitive(Primitive::MuSkip)) => (Key::MuSkip(self.scan_register()), Level::MuGlue),
+            Some(Meaning::CountDef(n)) => (Key::Count(n), Level::Int),
+            Some(Meaning::DimenDef(n)) => (Key::Dimen(n), Level::Dimen),
+            Some(Meaning::SkipDef(n)) => (Key::Skip(n), Level::Glue),
+            Some(Meaning::MuSkipDef(n)) => (Key::MuSkip(n), Level::MuGlue),
+            Some(Meaning::IntParam(p)) => (Key::Int(p), Level::Int),
+            Some(Meaning::DimenParam(p)) => (Key::DimenParam(p), Level::Dimen),
+            Some(Meaning::GlueParam(p)) => (Key::GlueParam(p), Level::Glue),
+            Some(Meaning::MuGlueParam(p)) => (Key::MuGlueParam(p), Level::MuGlue),
+            _ => {
+                self.error(ErrorKind::CantUseAfter(self.print_cs(&t), self.cs_string(p.name())));
+                self.back_input(t);
+                return;
+            },
+        };
+        self.scan_keyword("by");
+
+        const MAX_DIMEN: i64 = 0x3FFFFFFF;
+        let value = match (p, level) {
+            (Primitive::Advance, Level::Int) => Some(Value::Int(self.eqtb.int(&key).wrapping_add(self.scan_int()))),
+            (Primitive::Advance, Level::Dimen) => {
+                let d = self.eqtb.int(&key) as i64 + self.scan_dimen() as i64;
+                (d.abs() <= MAX_DIMEN).then_some(Value::Int(d as i32))
+            },
+            (Primitive::Advance, _) => {
+                let g = match level {
+                    Level::Glue => self.scan_glue(),
+                    _ => self.scan_mu_glue(),
+                };
+                Some(Value::Glue(add_glue(self.eqtb.glue(&key), g)))
+            },
+            (Primitive::Multiply, _) => {
+                let n = self.scan_int() as i64;
+                let mul = |x: i32| {
+                    let r = x as i64 * n;
+                    let max = if level == Level::Int { i32::MAX as i64 } else { MAX_DIMEN };
+                    (r.abs() <= max).then_some(r as i32)
+                };
+                match level {
+                    Level::Int | Level::Dimen => mul(self.eqtb.int(&key)).map(Value::Int),
+                    _ => {
+                        let g = self.eqtb.glue(&key);
+                        match (mul(g.width), mul(g.stretch), mul(g.shrink)) {
+                            (Some(width), Some(stretch), Some(shrink)) => Some(Value::Glue(Glue { width, stretch, shrink, ..g })),
+                            _ => None,
+                        }
+                    },
+                }
+            },
+            _ => match self.scan_int() {
+                0 => None,
+                n => match level {
+                    Level::Int | Level::Dimen => Some(Value::Int(self.eqtb.int(&key) / n)),
+                    _ => {
+                        let g = self.eqtb.glue(&key);
+                        Some(Value::Glue(Glue { width: g.width / n, stretch: g.stretch / n, shrink: g.shrink / n, ..g }))
+                    },
+                },
+            },
+        };
+
+        match value {
+            Some(v) => self.assign(key, Some(v), global),
+            None => self.error(ErrorKind::ArithmeticOverflow),
+        }
+    }
+
+    /// Performs `\font\x=name`, loading the font at its design size or the size given with `at`
+    /// or `scaled`. A font already loaded at the same size is used again.
+    fn new_font(&mut self, global: bool) {
+        let Some(key) = self.get_r_token() else { return };
+        self.assign(key.clone(), Some(Value::Meaning(Meaning::Font(FontId::NULL))), global);
+        self.scan_optional_equals();
+        let name = self.scan_file_name();
+
+        let design = self.load_font(&name, None).map(|f| f.design_size);
+        let size = if self.scan_keyword("at") {
+            Some(to_pt(self.scan_dimen()))
+        } else if self.scan_keyword("scaled") {
+            let n = self.scan_int();
+            design.map(|d| to_pt((to_scaled(d) as i64 * n as i64 / 1000) as i32))
+        } else {
+            design
+        };
+
+        let cs = match &key {
+            Key::Cs(cs) => *cs,
+            _ => self.cs.intern(&format!("FONT{name}")),
+        };
+        let loaded = self.fonts.iter().position(|(f, _)| f.name == name && Some(f.size) == size);
+        let id = match (loaded, size.and_then(|s| self.load_font(&name, Some(s)))) {
+            (Some(i), _) => {
+                self.fonts[i].1 = cs;
+                FontId(i as u32)
+            },
+            (None, Some(mut font)) => {
+                font.hyphen_char = self.int_param(IntParam::DefaultHyphenChar);
+                font.skew_char = self.int_param(IntParam::DefaultSkewChar);
+                self.fonts.push((Rc::new(font), cs));
+                FontId(self.fonts.len() as u32 - 1)
+            },
+            (None, None) => {
+                let cs_name = self.cs_string(&self.cs.name(cs));
+                self.error(ErrorKind::FontNotLoadable(cs_name, name));
+                FontId::NULL
+            },
+        };
+        self.assign(key, Some(Value::Meaning(Meaning::Font(id))), global);
+    }
+
+    /// Loads font `name` at `size` points or its design size, from `name.tfm` in the font
+    /// directories, or built in if it is one of Computer Modern
+    pub(super) fn load_font(&self, name: &str, size: Option<f32>) -> Option<Font> {
+        let tfm = self.font_dirs.iter().find_map(|dir| std::fs::read(dir.join(format!("{name}.tfm"))).ok());
+        match tfm {
+            Some(bytes) => Font::from_tfm(&bytes, name, size),
+            None => Font::computer_modern(name, size),
+        }
+    }
+
+    /// Performs `\lowercase` or `\uppercase`, changing the characters of the braced text with
+    /// their `\lccode` or `\uccode` and reading the result again
+    pub(super) fn case_shift(&mut self, lower: bool) {
+        let toks: Vec<Token> = self.scan_toks(false).into_iter().map(|t| {
+            // control sequences and active characters are left alone
+            if Self::key_of(&t).is_some() {
+                return t;
+            }
+
+            let key = match lower {
+                true => Key::LcCode(t.char()),
+                false => Key::UcCode(t.char()),
+            };
+            match char::from_u32(self.eqtb.int(&key) as u32) {
+                Some(c) if c != '\0' => Token::new_char(t.typ, c, 0..0).at(&t.span()),
+                _ => t,
+            }
+        }).collect();
+        self.back_list(toks.into_iter());
+    }
+
+    /// Scans a `{`, reporting it missing if it is not there
+    pub(super) fn scan_left_brace(&mut self) {
+        match self.get_x_nonblank_nonrelax() {
+            Some((_, Some(Meaning::Char(TokenType::BeginGroup, _)))) => {},
+            t => {
+                self.error(ErrorKind::MissingLeftBrace);
+                if let Some((t, _)) = t {
+                    self.back_input(t);
+                }
+            },
+        }
+    }
+
+    /// Scans a braced text, expanding it if `xpand` is set
+    pub(super) fn scan_toks(&mut self, xpand: bool) -> Vec<Token> {
+        self.scan_left_brace();
+        self.scan_body(None, xpand).into_iter().filter_map(|t| match t {
+            MacroToken::Token(t) => Some(t),
+            MacroToken::Param(_) => None,
+        }).collect()
+    }
+
+    /// Gets the control sequence to be defined
+    fn get_r_token(&mut self) -> Option<Key> {
+        loop {
+            let (t, _) = self.get_next()?;
+            if t.typ == TokenType::Space {
+                continue;
+            }
+
+            if let Some(key) = Self::key_of(&t) {
+                return Some(key);
+            }
+
+            // define a control sequence that can not be written instead
+            self.error(ErrorKind::MissingControlSequence);
+            self.back_input(t);
+            return Some(Key::Cs(self.cs.intern("inaccessible ")));
+        }
+    }
+
+    /// Scans the parameter text and body of a macro definition. A parameter text ending in `#{`
+    /// is delimited by the brace, which is put back at the end of the body as TeX does.
+    fn scan_macro(&mut self, xpand: bool) -> Macro {
+        let mut params = Vec::new();
+        let mut n = 0;
+        let mut brace = None;
+
+        while let Some((t, _)) = self.get_next() {
+            match t.typ {
+                TokenType::BeginGroup => break,
+                TokenType::Parameter => {
+                    let Some((next, _)) = self.get_next() else { break };
+                    if next.typ == TokenType::BeginGroup {
+                        params.push(MacroToken::Token(next.clone()));
+                        brace = Some(next);
+                        break;
+                    } else if next.typ == TokenType::Other && next.char().to_digit(10) == Some(n as u32 + 1) {
+                        n += 1;
+                        params.push(MacroToken::Param(n));
+                    } else {
+                        self.error(ErrorKind::NonConsecutiveParameters);
+                        self.back_input(next);
+                    }
+                },
+                _ => params.push(MacroToken::Token(t)),
+            }
+        }
+
+        let mut body = self.scan_body(Some(n), xpand);
+        if let Some(brace) = brace {
+            self.use_tokens(1);
+            body.push(MacroToken::Token(brace));
+        }
+        Macro { params, body }
+    }
+
+    /// Scans a balanced text after its opening brace, expanding it if `xpand` is set. If `params`
+    /// is given, parameter characters are treated as macro parameters.
+    fn scan_body(&mut self, params: Option<u8>, xpand: bool) -> Vec<MacroToken> {
+        let mut body = Vec::new();
+        let mut depth = 0;
+
+        while let Some(t) = self.get_body_token(xpand, &mut body) {
+            match t.typ {
+                TokenType::BeginGroup => depth += 1,
+                TokenType::EndGroup if depth == 0 => break,
+                TokenType::EndGroup => depth -= 1,
+                TokenType::Parameter if params.is_some() => {
+                    let Some(next) = self.get_body_token(xpand, &mut body) else { break };
+                    match next.char().to_digit(10) {
+                        _ if next.typ == TokenType::Parameter => body.push(MacroToken::Token(next)),
+                        Some(d) if next.typ == TokenType::Other && (1..=params.unwrap() as u32).contains(&d) => {
+                            body.push(MacroToken::Param(d as u8));
+                        },
+                        _ => {
+                            self.error(ErrorKind::IllegalParameterNumber);
+                            self.back_input(next);
+                        },
+                    }
+                    continue;
+                },
+                _ => {},
+            }
+
+            body.push(MacroToken::Token(t));
+        }
+
+        self.use_tokens(body.len());
+        body
+    }
+
+    /// Gets the next token of a body. When expanding, the result of `\the` is appended to `body`
+    /// directly so that it does not get expanded any further.
+    fn get_body_token(&mut self, xpand: bool, body: &mut Vec<MacroToken>) -> Option<Token> {
+        loop {
+            let (t, noexpand) = self.get_next()?;
+            if !xpand || noexpand {
+                return Some(t);
+            }
+
+            match self.meaning(&t) {
+                Some(Meaning::Primitive(Primitive::The)) => {
+                    let toks = self.the_toks(&t.span());
+                    body.extend(toks.into_iter().map(MacroToken::Token));
+                },
+                m if m.as_ref().is_none_or(Meaning::is_expandable) => self.expand(t, m),
+                _ => return Some(t),
+            }
+        }
+    }
+}
+
+/// Adds glue `b` to `a` as `\advance` does, keeping the stretch and shrink of the highest order
+fn add_glue(a: Glue, b: Glue) -> Glue {
+    let add = |x: i32, x_order: u8, y: i32, y_order: u8| {
+        let x_order = if x == 0 { 0 } else { x_order };
+        match x_order.cmp(&y_order) {
+            core::cmp::Ordering::Equal => (x.wrapping_add(y), x_order),
+            core::cmp::Ordering::Less if y != 0 => (y, y_order),
+            _ => (x, x_order),
+        }
+    };
+    let (stretch, stretch_order) = add(a.stretch, a.stretch_order, b.stretch, b.stretch_order);
+    let (shrink, shrink_order) = add(a.shrink, a.shrink_order, b.shrink, b.shrink_order);
+    Glue { width: a.width.wrapping_add(b.width), stretch, stretch_order, shrink, shrink_order }
+}
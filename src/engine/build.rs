@@ -0,0 +1,1114 @@
+use crate::font::FontId;
+use crate::layout::{self, BoxContent, Node, RUNNING};
+use crate::lexer::{Span, Token, TokenType};
+use super::math::{Atom, AtomKind, Delimiter, Field, Fraction, MathGroup, Noad};
+use super::scan::{to_pt, to_scaled};
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Mode {
+    Vertical,
+    /// Inside a `\vbox`
+    InternalVertical,
+    /// Building a paragraph
+    Horizontal,
+    /// Inside an `\hbox`
+    RestrictedHorizontal,
+    /// Between `$` and `$`
+    Math,
+    /// Between `$$` and `$$`
+    DisplayMath,
+}
+
+impl Mode {
+    pub(super) fn is_vertical(self) -> bool {
+        matches!(self, Self::Vertical | Self::InternalVertical)
+    }
+
+    pub(super) fn is_math(self) -> bool {
+        matches!(self, Self::Math | Self::DisplayMath)
+    }
+
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            Self::Vertical => "vertical mode",
+            Self::InternalVertical => "internal vertical mode",
+            Self::Horizontal => "horizontal mode",
+            Self::RestrictedHorizontal => "restricted horizontal mode",
+            Self::Math => "math mode",
+            Self::DisplayMath => "display math mode",
+        }
+    }
+}
+
+/// A list being built, one for each level of the semantic nest
+#[derive(Debug)]
+pub(super) struct List {
+    mode: Mode,
+    pub(super) nodes: Vec<Node>,
+    /// The depth of the last box on a vertical list, `None` if no interline glue is to be added
+    pub(super) prev_depth: Option<f32>,
+    /// The space factor of a horizontal list, which scales the spaces after characters by their
+    /// `\sfcode`
+    pub(super) space_factor: i32,
+    /// The formula of a math list. Nodes appended in math mode, like glue and boxes, are moved
+    /// here as they are made.
+    pub(super) math: Vec<Noad>,
+    /// A fraction whose numerator is all that came before `\over` or the like, waiting for the
+    /// rest of the list as its denominator
+    pub(super) incompleat: Option<Fraction>,
+}
+
+impl List {
+    pub(super) fn new(mode: Mode) -> Self {
+        Self { mode, nodes: Vec::new(), prev_depth: None, space_factor: 1000, math: Vec::new(), incompleat: None }
+    }
+}
+
+/// What a group was started by
+#[derive(Debug, Clone)]
+pub(super) enum Group {
+    /// `{`
+    Simple,
+    /// `\begingroup`
+    SemiSimple,
+    /// `\hbox`, `\vbox`, `\vtop` or `\vcenter`
+    Box(BoxGroup),
+    /// A formula or a part of one
+    Math(MathGroup),
+    /// `\halign`, while its rows are read
+    Align,
+    /// An entry of an alignment
+    Cell,
+    /// `\noalign`
+    NoAlign,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct BoxGroup {
+    /// `\hbox`, `\vbox`, `\vtop` or `\vcenter`
+    kind: Primitive,
+    /// The size given with `to`
+    size: Option<f32>,
+    /// Where the box came from
+    span: Span,
+    context: BoxContext,
+}
+
+/// What to do with a box once it is made
+#[derive(Debug, Clone, Copy)]
+pub(super) enum BoxContext {
+    /// Append it to the current list
+    Append,
+    /// Append it moved down or right by this much, as by `\lower` and `\moveright`
+    Shift(f32),
+    /// Put it in a box register, globally if the flag is set, as by `\setbox`
+    SetBox(u8, bool),
+}
+
+impl Engine {
+    /// Typesets the input until `\end` or the end of the main file, returning everything built as
+    /// one page. The whatsits on it, like `\write`, act once the whole input has been read.
+    pub fn run(&mut self) -> Node {
+        let list = self.run_list();
+        let span = layout::span_of(&list);
+        let page = layout::vpack(list, None, span);
+        self.ship_out(&page);
+        self.close_files();
+        page
+    }
+
+    /// Typesets the input like [`Self::run`], breaking the result into pages of height `\vsize`.
+    /// Everything is on one page if `\vsize` is not positive.
+    pub fn run_pages(&mut self) -> Vec<Node> {
+        let list = self.run_list();
+        let vsize = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::VSize)));
+        let mut pages = if vsize <= 0.0 {
+            let span = layout::span_of(&list);
+            vec![layout::vpack(list, None, span)]
+        } else {
+            break_pages(list, vsize)
+        };
+        if pages.len() > self.limits.max_pages {
+            self.overflow("pages", self.limits.max_pages);
+            pages.truncate(self.limits.max_pages);
+        }
+
+        for page in pages.iter() {
+            self.ship_out(page);
+        }
+        self.close_files();
+        pages
+    }
+
+    /// Typesets the input, returning the main vertical list
+    fn run_list(&mut self) -> Vec<Node> {
+        while let Some((tok, meaning)) = self.get_x_token() {
+            if !self.main_control(tok, meaning) {
+                break;
+            }
+        }
+
+        while !self.groups.is_empty() {
+            self.off_save();
+        }
+
+        if self.mode() == Mode::Horizontal {
+            self.end_paragraph();
+        }
+
+        core::mem::replace(&mut self.nest[0], List::new(Mode::Vertical)).nodes
+    }
+
+    /// Runs `format`, like `plain.tex`, to set up the macros, fonts, codes and parameters the
+    /// main file is typeset with. The main file is read with the catcodes the format leaves.
+    pub fn load_format(&mut self, format: SourceFile) {
+        let name = std::path::Path::new(&format.name).file_stem().map(|s| s.to_string_lossy().into_owned());
+        self.format_name = name;
+        let main = core::mem::take(&mut self.inputs);
+        // the format is trusted, and what it uses does not count against the document
+        let limits = core::mem::replace(&mut self.limits, Limits::trusted());
+        self.push_input(format);
+        while let Some((tok, meaning)) = self.get_x_token() {
+            if !self.main_control(tok, meaning) {
+                break;
+            }
+        }
+        self.limits = limits;
+        self.usage = limits::Usage::default();
+
+        let catcodes = self.inputs.first().map(|l| l.catcodes.clone()).unwrap_or_default();
+        let end_line_char = self.end_line_char();
+        self.inputs = main;
+        for lexer in self.inputs.iter_mut() {
+            lexer.catcodes = catcodes.clone();
+            lexer.end_line_char = end_line_char;
+        }
+
+        // anything the format typeset is thrown away
+        self.nest = vec![List::new(Mode::Vertical)];
+        self.error_count = 0;
+    }
+
+    pub(super) fn mode(&self) -> Mode {
+        self.nest.last().unwrap().mode
+    }
+
+    pub(super) fn list(&mut self) -> &mut List {
+        self.nest.last_mut().unwrap()
+    }
+
+    /// Starts building `list` inside the current one, stopping if that nests the lists deeper
+    /// than the limits allow
+    pub(super) fn push_nest(&mut self, list: List) {
+        if self.nest.len() >= self.limits.max_nest {
+            self.overflow("semantic nest size", self.limits.max_nest);
+        }
+        self.nest.push(list);
+    }
+
+    /// Acts on a command, returning `false` when `\end` is reached
+    pub(super) fn main_control(&mut self, tok: Token, meaning: Option<Meaning>) -> bool {
+        if self.usage.steps >= self.limits.max_steps {
+            self.overflow("steps", self.limits.max_steps);
+            return false;
+        }
+        self.usage.steps += 1;
+
+        let mode = self.mode();
+        let Some(meaning) = meaning else { return true };
+        if mode.is_math() && self.math_control(&tok, &meaning) {
+            self.sweep_math();
+            return true;
+        }
+
+        match meaning {
+            Meaning::Char(TokenType::BeginGroup, _) => {
+                self.begin_group();
+                self.groups.push(Group::Simple);
+            },
+            Meaning::Char(TokenType::EndGroup, _) => self.handle_right_brace(),
+            Meaning::Primitive(Primitive::BeginGroup) => {
+                self.begin_group();
+                self.groups.push(Group::SemiSimple);
+            },
+            Meaning::Primitive(Primitive::EndGroup) => match self.groups.last() {
+                Some(Group::SemiSimple) => {
+                    self.groups.pop();
+                    self.end_group();
+                },
+                _ => self.error(ErrorKind::ExtraEndGroup),
+            },
+            Meaning::Primitive(Primitive::Relax) => {},
+            Meaning::Primitive(Primitive::EndCsName) => self.error(ErrorKind::ExtraEndCsName),
+            Meaning::Primitive(p @ (Primitive::OpenIn | Primitive::CloseIn)) => self.open_or_close_in(p),
+            m if m.is_assignment() => {
+                self.prefixed_command(m);
+                if let Some(t) = self.after_assignment.take() {
+                    self.back_input(t);
+                }
+            },
+            Meaning::Primitive(p @ (Primitive::LowerCase | Primitive::UpperCase)) => self.case_shift(p == Primitive::LowerCase),
+            Meaning::Primitive(Primitive::Message) => self.issue_message(),
+            Meaning::Primitive(Primitive::ErrMessage) => {
+                let text: String = self.scan_toks(true).iter().map(|t| self.token_string(t)).collect();
+                self.error(ErrorKind::ErrMessage(text));
+            },
+            Meaning::Primitive(Primitive::Immediate) => match self.get_x_token() {
+                Some((_, Some(Meaning::Primitive(p @ (Primitive::OpenOut | Primitive::Write | Primitive::CloseOut))))) => {
+                    self.do_extension(p, true);
+                },
+                Some((t, _)) => self.back_input(t),
+                None => {},
+            },
+            Meaning::Primitive(p @ (Primitive::OpenOut | Primitive::Write | Primitive::CloseOut | Primitive::Special)) => {
+                self.do_extension(p, false);
+            },
+            Meaning::Primitive(Primitive::IgnoreSpaces) => {
+                if let Some((t, _)) = self.get_x_nonblank() {
+                    self.back_input(t);
+                }
+            },
+            Meaning::Primitive(Primitive::AfterAssignment) => self.after_assignment = self.get_next().map(|t| t.0),
+            Meaning::Primitive(Primitive::AfterGroup) => {
+                if let Some((t, _)) = self.get_next() {
+                    if let Some(after) = self.after_group.last_mut() {
+                        after.push(t);
+                    }
+                }
+            },
+
+            Meaning::Char(TokenType::Letter | TokenType::Other, _)
+                | Meaning::CharDef(_)
+                | Meaning::Primitive(Primitive::HSkip | Primitive::HFil | Primitive::HFill | Primitive::HSs | Primitive::HFilNeg)
+                | Meaning::Primitive(Primitive::VRule | Primitive::DiscretionaryHyphen | Primitive::Char | Primitive::Accent)
+                | Meaning::Primitive(Primitive::ControlSpace | Primitive::UnHBox | Primitive::UnHCopy)
+                | Meaning::Char(TokenType::MathShift, _)
+                if mode.is_vertical() => {
+                self.back_input(tok);
+                self.new_paragraph(true);
+            },
+            Meaning::Primitive(Primitive::VSkip | Primitive::VFil | Primitive::VFill | Primitive::VSs | Primitive::VFilNeg)
+                | Meaning::Primitive(Primitive::HRule | Primitive::UnVBox | Primitive::UnVCopy | Primitive::End | Primitive::Dump)
+                | Meaning::Primitive(Primitive::HAlign)
+                if mode == Mode::Horizontal => {
+                self.back_input(tok);
+                self.end_paragraph();
+            },
+
+            Meaning::Char(TokenType::Letter | TokenType::Other, c) | Meaning::CharDef(c) => self.append_char(c, tok.span()),
+            Meaning::Primitive(Primitive::Char) => {
+                let c = self.scan_char_code();
+                self.append_char(c, tok.span());
+            },
+            Meaning::Primitive(Primitive::Accent) => self.make_accent(tok.span()),
+            Meaning::Char(TokenType::Space, _) if !mode.is_vertical() => {
+                let sf = self.list().space_factor;
+                self.append_space(sf);
+            },
+            Meaning::Char(TokenType::Space, _) => {},
+            Meaning::Primitive(Primitive::ControlSpace) => self.append_space(1000),
+            Meaning::Primitive(Primitive::ItalicCorrection) if !mode.is_vertical() => {
+                let italic = match self.list().nodes.last() {
+                    Some(Node::Box { content: BoxContent::Character(c, f), .. }) => Some((*c, *f)),
+                    _ => None,
+                };
+                if let Some(m) = italic.and_then(|(c, f)| self.font(f).metrics(c)) {
+                    self.tail_append(Node::Kern(m.italic));
+                }
+            },
+            Meaning::Char(TokenType::MathShift, _) => self.init_math(),
+            m if !mode.is_math() && is_math_only(&m) => self.insert_dollar_sign(tok),
+            Meaning::Primitive(Primitive::HAlign) if mode.is_vertical() => self.init_align(false),
+            Meaning::Char(TokenType::AlignTab, _)
+                | Meaning::Primitive(Primitive::Cr | Primitive::CrCr | Primitive::Span | Primitive::Omit | Primitive::NoAlign) => {
+                self.error(ErrorKind::Misplaced(self.meaning_string(Some(&meaning))));
+            },
+            Meaning::Primitive(Primitive::EndTemplate) => self.fin_col(tok),
+            Meaning::Primitive(Primitive::Par) => {
+                if mode == Mode::Horizontal {
+                    self.end_paragraph();
+                }
+            },
+            Meaning::Primitive(p @ (Primitive::HSkip | Primitive::HFil | Primitive::HFill | Primitive::HSs | Primitive::HFilNeg))
+                | Meaning::Primitive(p @ (Primitive::VSkip | Primitive::VFil | Primitive::VFill | Primitive::VSs | Primitive::VFilNeg))
+                if mode.is_vertical() == matches!(p, Primitive::VSkip | Primitive::VFil | Primitive::VFill | Primitive::VSs | Primitive::VFilNeg) => {
+                let glue = match p {
+                    Primitive::HSkip | Primitive::VSkip => self.scan_glue(),
+                    Primitive::HFil | Primitive::VFil => Glue { stretch: 0x10000, stretch_order: 1, ..Glue::default() },
+                    Primitive::HFill | Primitive::VFill => Glue { stretch: 0x10000, stretch_order: 2, ..Glue::default() },
+                    Primitive::HFilNeg | Primitive::VFilNeg => Glue { stretch: -0x10000, stretch_order: 1, ..Glue::default() },
+                    _ => Glue { stretch: 0x10000, stretch_order: 1, shrink: 0x10000, shrink_order: 1, ..Glue::default() },
+                };
+                self.tail_append(glue_node(glue));
+            },
+            Meaning::Primitive(Primitive::Penalty) => {
+                let p = self.scan_int();
+                self.tail_append(Node::Penalty(p));
+            },
+            Meaning::Primitive(p @ (Primitive::UnSkip | Primitive::UnKern | Primitive::UnPenalty)) => {
+                let nodes = &mut self.list().nodes;
+                match (p, nodes.last()) {
+                    (Primitive::UnSkip, Some(Node::Glue { .. }))
+                        | (Primitive::UnKern, Some(Node::Kern(_)))
+                        | (Primitive::UnPenalty, Some(Node::Penalty(_))) => {
+                        nodes.pop();
+                    },
+                    _ => {},
+                }
+            },
+            Meaning::Primitive(Primitive::DiscretionaryHyphen) => {
+                let disc = self.discretionary_hyphen(self.eqtb.font(&Key::CurFont), tok.span());
+                self.tail_append(disc);
+            },
+            Meaning::Primitive(Primitive::Kern) => {
+                let k = self.scan_dimen();
+                self.tail_append(Node::Kern(to_pt(k)));
+            },
+            Meaning::Primitive(p @ (Primitive::HRule | Primitive::VRule))
+                if mode.is_vertical() == (p == Primitive::HRule) => self.append_rule(p, tok.span()),
+            Meaning::Primitive(Primitive::HBox | Primitive::VBox | Primitive::VTop | Primitive::Box | Primitive::Copy | Primitive::LastBox) => {
+                self.back_input(tok);
+                self.scan_box(BoxContext::Append);
+            },
+            Meaning::Primitive(p @ (Primitive::Raise | Primitive::Lower)) if !mode.is_vertical() => {
+                let d = to_pt(self.scan_dimen());
+                self.scan_box(BoxContext::Shift(if p == Primitive::Raise { -d } else { d }));
+            },
+            Meaning::Primitive(p @ (Primitive::MoveLeft | Primitive::MoveRight)) if mode.is_vertical() => {
+                let d = to_pt(self.scan_dimen());
+                self.scan_box(BoxContext::Shift(if p == Primitive::MoveLeft { -d } else { d }));
+            },
+            Meaning::Primitive(p @ (Primitive::UnHBox | Primitive::UnHCopy | Primitive::UnVBox | Primitive::UnVCopy))
+                if mode.is_vertical() == matches!(p, Primitive::UnVBox | Primitive::UnVCopy) => self.unpackage(p),
+            Meaning::Primitive(p @ (Primitive::Indent | Primitive::NoIndent)) if mode.is_vertical() => {
+                self.new_paragraph(p == Primitive::Indent);
+            },
+            Meaning::Primitive(Primitive::Indent) => {
+                let indent = self.parindent_box();
+                self.tail_append(indent);
+            },
+            Meaning::Primitive(Primitive::NoIndent) => {},
+            Meaning::Primitive(Primitive::End) if mode == Mode::Vertical => return false,
+            Meaning::Primitive(Primitive::Dump) if mode == Mode::Vertical => {
+                // like TeX, a format can only be dumped outside of groups
+                match self.eqtb.level() {
+                    0 => self.dumped = Some(self.dump()),
+                    _ => self.error(ErrorKind::DumpInGroup),
+                }
+                return false;
+            },
+
+            m => self.error(ErrorKind::CantUseInMode(self.meaning_string(Some(&m)), mode.name())),
+        }
+
+        self.sweep_math();
+        true
+    }
+
+    /// Inserts a `$` before a command that can only be used in math mode
+    pub(super) fn insert_dollar_sign(&mut self, tok: Token) {
+        let span = tok.span();
+        self.back_input(tok);
+        self.error(ErrorKind::MissingDollar);
+        self.back_input(Token::new_char(TokenType::MathShift, '$', 0..0).at(&span));
+    }
+
+    /// Closes the innermost group when the input ends inside it or something else is read that
+    /// can not be used before it is closed, inserting what should have closed it
+    pub(super) fn off_save(&mut self) {
+        match self.groups.last() {
+            Some(Group::SemiSimple) => {
+                self.error(ErrorKind::MissingEndGroup);
+                self.groups.pop();
+                self.end_group();
+            },
+            Some(Group::Math(MathGroup::Shift | MathGroup::EqNo(_))) => {
+                self.error(ErrorKind::MissingDollar);
+                self.after_math();
+            },
+            Some(Group::Math(MathGroup::Left)) => {
+                self.error(ErrorKind::MissingRight);
+                self.finish_left_right(Delimiter::default());
+            },
+            Some(Group::Cell) => {
+                self.error(ErrorKind::MissingCr);
+                self.end_row_early();
+            },
+            Some(Group::Math(MathGroup::Formula)) => {
+                // only the end of the input closes a formula typeset by itself, so what was read
+                // is thrown away
+                if let Some((tok, _)) = self.get_next() {
+                    let meaning = self.meaning(&tok);
+                    self.error(ErrorKind::CantUseInMode(self.meaning_string(meaning.as_ref()), self.mode().name()));
+                }
+            },
+            Some(_) => {
+                self.error(ErrorKind::MissingRightBrace);
+                self.handle_right_brace();
+            },
+            None => {},
+        }
+    }
+
+    fn handle_right_brace(&mut self) {
+        match self.groups.pop() {
+            Some(Group::Simple) => self.end_group(),
+            Some(group @ (Group::SemiSimple | Group::Math(MathGroup::Shift | MathGroup::EqNo(_) | MathGroup::Left | MathGroup::Formula))) => {
+                self.error(ErrorKind::ExtraRightBrace(match group {
+                    Group::SemiSimple => "\\endgroup",
+                    Group::Math(MathGroup::Left) => "\\right",
+                    _ => "$",
+                }));
+                self.groups.push(group);
+            },
+            Some(Group::Math(MathGroup::Field(target))) => self.end_math_group(target),
+            Some(Group::Align) => self.fin_align(),
+            Some(Group::NoAlign) => self.fin_no_align(),
+            Some(Group::Cell) => {
+                // the `}` is read again after the row is ended
+                self.groups.push(Group::Cell);
+                self.back_input(Token::new_char(TokenType::EndGroup, '}', 0..0).at(&self.span));
+                self.back_input(Token::new_cs(self.cs.intern("cr"), 0..0).at(&self.span));
+                self.error(ErrorKind::MissingCr);
+            },
+            Some(Group::Box(BoxGroup { kind, size, span, context })) => {
+                if self.mode() == Mode::Horizontal {
+                    self.end_paragraph();
+                }
+
+                self.end_group();
+                let list = self.nest.pop().unwrap();
+                let horizontal = list.mode == Mode::RestrictedHorizontal;
+                if let Some(excess) = size.and_then(|s| overfull(&list.nodes, horizontal, s)) {
+                    self.error_at(ErrorKind::OverfullBox { horizontal, excess }, span.clone());
+                }
+
+                let b = match kind {
+                    Primitive::HBox => layout::hpack(list.nodes, size, span),
+                    Primitive::VBox | Primitive::VCenter => layout::vpack(list.nodes, size, span),
+                    _ => layout::vtop(list.nodes, size, span),
+                };
+                if kind == Primitive::VCenter {
+                    self.list().math.push(Noad::Atom(Atom::new(AtomKind::VCenter, Field::Box(b))));
+                    return;
+                }
+                self.box_end(Some(b), context);
+            },
+            None => self.end_group(),
+        }
+    }
+
+    /// A box holding character `c` of font `f`, `None` if the font does not have it
+    pub(super) fn char_box(&self, c: char, f: FontId, span: Span) -> Option<Node> {
+        let m = self.font(f).metrics(c)?;
+        Some(Node::Box { content: BoxContent::Character(c, f), size: [m.width, m.height, m.depth], shift: 0.0, nesting: 0, span })
+    }
+
+    /// A box holding character `c` of font `f`, logging it as missing if the font does not
+    /// have it
+    pub(super) fn new_character(&mut self, c: char, f: FontId, span: Span) -> Option<Node> {
+        let b = self.char_box(c, f, span);
+        if b.is_none() && self.int_param(IntParam::TracingLostChars) > 0 {
+            let text = format!("Missing character: There is no {c} in font {}!", self.font(f).name);
+            self.print_line(&text, false);
+        }
+        b
+    }
+
+    fn append_char(&mut self, c: char, span: Span) {
+        let Some(b) = self.new_character(c, self.eqtb.font(&Key::CurFont), span) else { return };
+        let sf = self.eqtb.int(&Key::SfCode(c));
+        self.tail_append(b);
+        let list = self.list();
+
+        // a space factor above 1000 can not be reached directly from below 1000
+        match sf {
+            0 => {},
+            1001.. if list.space_factor < 1000 => list.space_factor = 1000,
+            sf => list.space_factor = sf,
+        }
+    }
+
+    /// Appends interword glue for space factor `sf`, using `\spaceskip` and `\xspaceskip` if
+    /// they are set, or the spacing of the font stretched or shrunk by the space factor as in TeX
+    fn append_space(&mut self, sf: i32) {
+        let p = self.cur_font().params;
+        let space_skip = self.eqtb.glue(&Key::GlueParam(GlueParam::SpaceSkip));
+        let xspace_skip = self.eqtb.glue(&Key::GlueParam(GlueParam::XSpaceSkip));
+
+        let glue = if sf >= 2000 && xspace_skip != Glue::default() {
+            glue_node(xspace_skip)
+        } else {
+            let mut glue = match space_skip == Glue::default() {
+                true => Node::glue(p.space, p.space_stretch, p.space_shrink),
+                false => glue_node(space_skip),
+            };
+            if let Node::Glue { nat_size, stretchability, shrinkability, .. } = &mut glue {
+                if sf >= 2000 {
+                    *nat_size += p.extra_space;
+                }
+                *stretchability *= sf as f32 / 1000.0;
+                *shrinkability /= sf as f32 / 1000.0;
+            }
+            glue
+        };
+        self.tail_append(glue);
+    }
+
+    /// Performs `\accent`, centring the accent over the character that follows, as TeX does with
+    /// the slants and x-heights of their fonts
+    fn make_accent(&mut self, span: Span) {
+        let c = self.scan_char_code();
+        let Some(accent) = self.new_character(c, self.eqtb.font(&Key::CurFont), span) else { return };
+        let (s, x) = (self.cur_font().params.slant, self.cur_font().params.x_height);
+        let a = accent.size()[0];
+
+        // assignments like font changes may come between the accent and the character
+        let next = loop {
+            match self.get_x_nonblank_nonrelax() {
+                Some((_, Some(m))) if m.is_assignment() => self.prefixed_command(m),
+                t => break t,
+            }
+        };
+        let q = match next {
+            Some((t, Some(Meaning::Char(TokenType::Letter | TokenType::Other, c) | Meaning::CharDef(c)))) => {
+                self.new_character(c, self.eqtb.font(&Key::CurFont), t.span())
+            },
+            Some((t, Some(Meaning::Primitive(Primitive::Char)))) => {
+                let c = self.scan_char_code();
+                self.new_character(c, self.eqtb.font(&Key::CurFont), t.span())
+            },
+            Some((t, _)) => {
+                self.back_input(t);
+                None
+            },
+            None => None,
+        };
+
+        let t = self.cur_font().params.slant;
+        self.use_nodes(if q.is_some() { 4 } else { 1 });
+        let list = self.list();
+        match q {
+            Some(q) => {
+                let [w, h, _] = q.size();
+                let mut accent = accent;
+                if h != x {
+                    let span = layout::span_of(core::slice::from_ref(&accent));
+                    accent = layout::hpack(vec![accent], None, span);
+                    if let Node::Box { shift, .. } = &mut accent {
+                        *shift = x - h;
+                    }
+                }
+
+                let delta = (w - a) / 2.0 + h * t - x * s;
+                list.nodes.extend([Node::Kern(delta), accent, Node::Kern(-a - delta), q]);
+            },
+            None => list.nodes.push(accent),
+        }
+        list.space_factor = 1000;
+    }
+
+    /// Appends a node to the current list
+    pub(super) fn tail_append(&mut self, node: Node) {
+        self.use_nodes(1);
+        self.list().nodes.push(node);
+    }
+
+    /// Accounts for `n` nodes being made, stopping if that is more than the limits allow
+    pub(super) fn use_nodes(&mut self, n: usize) {
+        self.usage.nodes += n;
+        if self.usage.nodes > self.limits.max_nodes {
+            self.overflow("main memory size", self.limits.max_nodes);
+        }
+    }
+
+    /// Accounts for `n` tokens being stored or made, stopping if that is more than the limits
+    /// allow
+    pub(super) fn use_tokens(&mut self, n: usize) {
+        self.usage.tokens += n;
+        if self.usage.tokens > self.limits.max_tokens {
+            self.overflow("token memory size", self.limits.max_tokens);
+        }
+    }
+
+    /// Appends a box to the current list, with interline glue if the list is vertical
+    pub(super) fn append_box(&mut self, b: Node) {
+        let [_, height, depth] = b.size();
+        let baselineskip = self.eqtb.glue(&Key::GlueParam(GlueParam::BaselineSkip));
+        let lineskip = self.eqtb.glue(&Key::GlueParam(GlueParam::LineSkip));
+        let limit = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::LineSkipLimit)));
+
+        self.use_nodes(2);
+        let outer = self.nest.len() == 1;
+        let list = self.list();
+        if !list.mode.is_vertical() {
+            list.nodes.push(b);
+            return;
+        }
+
+        let mut advance = height + depth;
+        if let Some(prev_depth) = list.prev_depth {
+            let d = to_pt(baselineskip.width) - prev_depth - height;
+            let glue = if d < limit { lineskip } else { Glue { width: to_scaled(d), ..baselineskip } };
+            advance += to_pt(glue.width);
+            list.nodes.push(glue_node(glue));
+        }
+
+        list.nodes.push(b);
+        list.prev_depth = Some(depth);
+        if outer {
+            self.use_height(advance);
+        }
+    }
+
+    /// Accounts for the main vertical list growing by `height`, stopping if it fills more pages
+    /// than the limits allow
+    fn use_height(&mut self, height: f32) {
+        let vsize = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::VSize)));
+        self.usage.height += height;
+        if vsize > 0.0 && self.usage.height > vsize * self.limits.max_pages as f32 {
+            self.overflow("pages", self.limits.max_pages);
+        }
+    }
+
+    fn append_rule(&mut self, p: Primitive, span: Span) {
+        let (content, mut size) = match p {
+            Primitive::HRule => (BoxContent::HRule, [RUNNING, 0.4, 0.0]),
+            _ => (BoxContent::VRule, [0.4, RUNNING, RUNNING]),
+        };
+
+        loop {
+            if self.scan_keyword("width") {
+                size[0] = to_pt(self.scan_dimen());
+            } else if self.scan_keyword("height") {
+                size[1] = to_pt(self.scan_dimen());
+            } else if self.scan_keyword("depth") {
+                size[2] = to_pt(self.scan_dimen());
+            } else {
+                break;
+            }
+        }
+
+        self.use_nodes(1);
+        let list = self.list();
+        list.nodes.push(Node::Box { content, size, shift: 0.0, nesting: 0, span });
+        if list.mode.is_vertical() {
+            list.prev_depth = None;
+        }
+    }
+
+    /// Scans a box, either made by `\hbox`, `\vbox` or `\vtop` or taken by `\box`, `\copy` or
+    /// `\lastbox`, and does what `context` says with it
+    pub(super) fn scan_box(&mut self, context: BoxContext) {
+        match self.get_x_nonblank_nonrelax() {
+            Some((t, Some(Meaning::Primitive(p @ (Primitive::HBox | Primitive::VBox | Primitive::VTop))))) => {
+                self.begin_box(p, context, t.span());
+            },
+            Some((_, Some(Meaning::Primitive(p @ (Primitive::Box | Primitive::Copy))))) => {
+                let n = self.scan_register();
+                let b = match p {
+                    Primitive::Box => self.eqtb.take(&Key::Box(n)).and_then(|v| match v {
+                        Value::Box(b) => Some(b),
+                        _ => None,
+                    }),
+                    _ => self.eqtb.box_reg(n).cloned(),
+                };
+                if let Some(b) = b.as_ref().filter(|_| p == Primitive::Copy) {
+                    self.use_nodes(b.count());
+                }
+                self.box_end(b, context);
+            },
+            Some((_, Some(Meaning::Primitive(Primitive::LastBox)))) => {
+                let nodes = &mut self.list().nodes;
+                let b = match nodes.last() {
+                    Some(Node::Box { content: BoxContent::HBox(_) | BoxContent::VBox(_), .. }) => nodes.pop(),
+                    _ => None,
+                };
+                self.box_end(b, context);
+            },
+            t => {
+                self.error(ErrorKind::BoxExpected);
+                if let Some((t, _)) = t {
+                    self.back_input(t);
+                }
+            },
+        }
+    }
+
+    pub(super) fn begin_box(&mut self, kind: Primitive, context: BoxContext, span: Span) {
+        let size = self.scan_keyword("to").then(|| to_pt(self.scan_dimen()));
+        self.scan_left_brace();
+
+        self.begin_group();
+        self.groups.push(Group::Box(BoxGroup { kind, size, span, context }));
+        let (mode, every) = match kind {
+            Primitive::HBox => (Mode::RestrictedHorizontal, ToksParam::EveryHBox),
+            _ => (Mode::InternalVertical, ToksParam::EveryVBox),
+        };
+        self.push_nest(List::new(mode));
+        let toks = self.eqtb.toks(&Key::ToksParam(every));
+        self.back_list(toks.iter().cloned());
+    }
+
+    /// Does what `context` says with a finished box, or with nothing if the box is void
+    fn box_end(&mut self, b: Option<Node>, context: BoxContext) {
+        match context {
+            _ if b.as_ref().is_some_and(|b| b.nesting() > self.limits.max_box_depth) => {
+                self.overflow("box nesting depth", self.limits.max_box_depth);
+            },
+            BoxContext::Append => {
+                if let Some(b) = b {
+                    self.append_box(b);
+                }
+            },
+            BoxContext::Shift(d) => {
+                if let Some(mut b) = b {
+                    if let Node::Box { shift, .. } = &mut b {
+                        *shift = d;
+                    }
+                    self.append_box(b);
+                }
+            },
+            BoxContext::SetBox(n, global) => self.assign(Key::Box(n), b.map(Value::Box), global),
+        }
+    }
+
+    /// Performs `\unhbox`, `\unhcopy`, `\unvbox` or `\unvcopy`, appending the contents of a box
+    /// register to the current list
+    fn unpackage(&mut self, p: Primitive) {
+        let n = self.scan_register();
+        let horizontal = matches!(p, Primitive::UnHBox | Primitive::UnHCopy);
+        let list = match self.eqtb.box_reg(n) {
+            None => return,
+            Some(Node::Box { content: BoxContent::HBox(list), .. }) if horizontal => list.clone(),
+            Some(Node::Box { content: BoxContent::VBox(list), .. }) if !horizontal => list.clone(),
+            Some(_) => {
+                self.error(ErrorKind::IncompatibleUnbox);
+                return;
+            },
+        };
+
+        if matches!(p, Primitive::UnHBox | Primitive::UnVBox) {
+            self.eqtb.take(&Key::Box(n));
+        } else {
+            self.use_nodes(list.iter().map(Node::count).sum());
+        }
+        self.list().nodes.extend(list);
+    }
+
+    fn parindent_box(&self) -> Node {
+        let width = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::ParIndent)));
+        Node::Box { content: BoxContent::HBox(Vec::new()), size: [width, 0.0, 0.0], shift: 0.0, nesting: 1, span: Span::default() }
+    }
+
+    fn new_paragraph(&mut self, indent: bool) {
+        self.use_nodes(2);
+        let parskip = glue_node(self.eqtb.glue(&Key::GlueParam(GlueParam::ParSkip)));
+        let list = self.list();
+        if !list.nodes.is_empty() {
+            list.nodes.push(parskip);
+        }
+
+        let mut list = List::new(Mode::Horizontal);
+        if indent {
+            list.nodes.push(self.parindent_box());
+        }
+        self.push_nest(list);
+
+        let toks = self.eqtb.toks(&Key::ToksParam(ToksParam::EveryPar));
+        self.back_list(toks.iter().cloned());
+    }
+
+    /// Breaks the paragraph being built into lines and appends them to the enclosing list
+    pub(super) fn end_paragraph(&mut self) {
+        self.error_count = 0;
+        let mut nodes = self.nest.pop().unwrap().nodes;
+        if nodes.last().is_some_and(|n| matches!(n, Node::Glue { .. })) {
+            nodes.pop();
+        }
+        if nodes.is_empty() {
+            return;
+        }
+        nodes.push(glue_node(self.eqtb.glue(&Key::GlueParam(GlueParam::ParFillSkip))));
+
+        // the lines are broken to fit between `\leftskip` and `\rightskip`, which are added to
+        // every line unless they are zero
+        let hsize = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::HSize)));
+        let left = self.eqtb.glue(&Key::GlueParam(GlueParam::LeftSkip));
+        let right = self.eqtb.glue(&Key::GlueParam(GlueParam::RightSkip));
+        let width = hsize - to_pt(left.width) - to_pt(right.width);
+        let add_skips = |lines: Vec<Vec<Node>>| -> Vec<Vec<Node>> {
+            lines.into_iter().map(|mut line| {
+                if left != Glue::default() {
+                    line.insert(0, glue_node(left));
+                }
+                if right != Glue::default() {
+                    line.push(glue_node(right));
+                }
+                line
+            }).collect()
+        };
+
+        // try without hyphenating first
+        let pretolerance = self.int_param(IntParam::Pretolerance);
+        let mut lines = Vec::new();
+        if pretolerance >= 0 {
+            lines = add_skips(break_lines(nodes.clone(), width));
+        }
+
+        let fits = |l: &Vec<Node>| line_badness(l, hsize).is_some_and(|b| b as i32 <= pretolerance);
+        if lines.is_empty() || !lines.iter().all(fits) {
+            self.hyphenate(&mut nodes);
+            lines = add_skips(break_lines(nodes, width));
+        }
+
+        for line in lines {
+            let span = layout::span_of(&line);
+            if let Some(excess) = overfull(&line, true, hsize) {
+                self.error_at(ErrorKind::OverfullBox { horizontal: true, excess }, span.clone());
+            }
+            self.append_box(layout::hpack(line, Some(hsize), span));
+        }
+    }
+}
+
+/// Converts glue in scaled points to a glue node
+pub(super) fn glue_node(g: Glue) -> Node {
+    Node::Glue {
+        nat_size: to_pt(g.width),
+        shrinkability: to_pt(g.shrink),
+        stretchability: to_pt(g.stretch),
+        stretch_order: g.stretch_order,
+        shrink_order: g.shrink_order,
+    }
+}
+
+/// Whether `m` is a command that can only be used in math mode
+fn is_math_only(m: &Meaning) -> bool {
+    match m {
+        Meaning::Char(TokenType::Superscript | TokenType::Subscript, _) | Meaning::MathCharDef(_) => true,
+        Meaning::Primitive(p) => matches!(
+            p,
+            Primitive::MathChar
+                | Primitive::Delimiter
+                | Primitive::Radical
+                | Primitive::MathAccent
+                | Primitive::Left
+                | Primitive::Right
+                | Primitive::Over
+                | Primitive::Atop
+                | Primitive::Above
+                | Primitive::OverWithDelims
+                | Primitive::AtopWithDelims
+                | Primitive::AboveWithDelims
+                | Primitive::MathOrd
+                | Primitive::MathOp
+                | Primitive::MathBin
+                | Primitive::MathRel
+                | Primitive::MathOpen
+                | Primitive::MathClose
+                | Primitive::MathPunct
+                | Primitive::MathInner
+                | Primitive::Underline
+                | Primitive::Overline
+                | Primitive::Limits
+                | Primitive::NoLimits
+                | Primitive::DisplayLimits
+                | Primitive::VCenter
+                | Primitive::DisplayStyle
+                | Primitive::TextStyle
+                | Primitive::ScriptStyle
+                | Primitive::ScriptScriptStyle
+                | Primitive::EqNo
+                | Primitive::LEqNo
+                | Primitive::MKern
+                | Primitive::MSkip
+        ),
+        _ => false,
+    }
+}
+
+/// How much a horizontal or vertical list is too big for `size`
+fn overfull(list: &[Node], horizontal: bool, size: f32) -> Option<f32> {
+    match horizontal {
+        true => layout::overfull(list, |n| n.size()[0], size),
+        false => {
+            // the depth of the last box hangs below the box
+            let depth = match list.last() {
+                Some(Node::Box { size: [_, _, d], .. }) => *d,
+                _ => 0.0,
+            };
+            layout::overfull(list, |n| match n {
+                Node::Box { size: [_, h, d], .. } => h + d,
+                n => n.size()[0],
+            }, size + depth)
+        },
+    }
+}
+
+/// The natural width and finite shrinkability of `list`
+fn measure(list: &[Node]) -> (f32, f32) {
+    list.iter().fold((0.0, 0.0), |(width, shrink), n| match n {
+        Node::Glue { nat_size, shrinkability, shrink_order: 0, .. } => (width + nat_size, shrink + shrinkability),
+        n => (width + n.size()[0], shrink),
+    })
+}
+
+/// Breaks a paragraph into lines of width `hsize`, putting as many words on each line as fit.
+/// Lines are broken at glue that follows a box, whose glue is discarded, at discretionaries,
+/// whose pre-break material ends the line, and at penalties below `10000`. A penalty of `-10000`
+/// or less always breaks the line.
+fn break_lines(nodes: Vec<Node>, hsize: f32) -> Vec<Vec<Node>> {
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    let (mut width, mut shrink) = (0.0, 0.0);
+    // the nodes since the last breakpoint, starting with the glue or discretionary at it
+    let mut word: Vec<Node> = Vec::new();
+
+    // `extra` is the width added to the line if it is broken after `word`
+    let mut commit = |line: &mut Vec<Node>, word: &mut Vec<Node>, width: &mut f32, shrink: &mut f32, extra: f32| {
+        let (w, s) = measure(word);
+        let forced = matches!(word.first(), Some(Node::Penalty(p)) if *p <= -10000);
+        if forced || (!line.is_empty() && *width + w + extra - (*shrink + s) > hsize) {
+            if let Some(Node::Discretionary { pre_break }) = word.first() {
+                line.extend(pre_break.iter().cloned());
+            }
+            lines.push(core::mem::take(line));
+
+            let start = word.iter().position(|n| !n.is_discardable() && !matches!(n, Node::Discretionary { .. }));
+            word.drain(..start.unwrap_or(word.len()));
+            (*width, *shrink) = measure(word);
+        } else {
+            *width += w;
+            *shrink += s;
+        }
+        line.append(word);
+    };
+
+    for n in nodes {
+        let extra = match &n {
+            Node::Discretionary { pre_break } => Some(measure(pre_break).0),
+            Node::Glue { .. } if matches!(word.last(), Some(Node::Box { .. })) => Some(0.0),
+            Node::Penalty(p) if *p < 10000 => Some(0.0),
+            _ => None,
+        };
+        if let Some(extra) = extra {
+            commit(&mut line, &mut word, &mut width, &mut shrink, extra);
+        }
+        word.push(n);
+    }
+    commit(&mut line, &mut word, &mut width, &mut shrink, 0.0);
+
+    lines.push(line);
+    lines
+}
+
+/// Breaks the main vertical list into pages of height `vsize`, like TeX's page builder without
+/// insertions or an output routine. Pages are broken at glue that follows a box and at penalties
+/// below `10000`, choosing the breakpoint of least badness plus penalty before the page
+/// overflows. A penalty of `-10000` or less always ends the page. Glue, kerns and penalties at
+/// the top of a page are discarded.
+fn break_pages(mut nodes: Vec<Node>, vsize: f32) -> Vec<Node> {
+    let mut pages = Vec::new();
+
+    loop {
+        let start = nodes.iter().position(|n| !n.is_discardable()).unwrap_or(nodes.len());
+        nodes.drain(..start);
+        if nodes.is_empty() {
+            break;
+        }
+
+        // the height of the page so far, without the depth of its last box
+        let (mut height, mut depth) = (0.0, 0.0);
+        let mut stretch = [0.0; 4];
+        let mut shrink = 0.0;
+        let mut best: Option<(usize, i64)> = None;
+        let mut end = nodes.len();
+
+        for (i, n) in nodes.iter().enumerate() {
+            let penalty = match n {
+                Node::Glue { .. } if i > 0 && !nodes[i - 1].is_discardable() => Some(0),
+                Node::Penalty(p) if *p < 10000 => Some(*p),
+                _ => None,
+            };
+
+            if let Some(p) = penalty {
+                let excess = vsize - height;
+                let badness = if excess >= 0.0 && stretch[1..].iter().any(|s| *s != 0.0) {
+                    0
+                } else if excess >= 0.0 {
+                    layout::badness(excess, stretch[0])
+                } else if -excess <= shrink {
+                    layout::badness(-excess, shrink)
+                } else {
+                    u32::MAX
+                };
+
+                let cost = match badness {
+                    u32::MAX => i64::MAX,
+                    _ if p <= -10000 => p as i64,
+                    b if b < 10000 => b as i64 + p as i64,
+                    _ => 100000,
+                };
+                if cost == i64::MAX {
+                    end = best.map_or(i, |(b, _)| b);
+                    break;
+                }
+                if best.is_none_or(|(_, c)| cost <= c) {
+                    best = Some((i, cost));
+                }
+                if p <= -10000 {
+                    end = i;
+                    break;
+                }
+            }
+
+            match n {
+                Node::Box { size: [_, h, d], .. } => {
+                    height += depth + h;
+                    depth = *d;
+                },
+                n => {
+                    height += depth + n.size()[0];
+                    depth = 0.0;
+                    if let Node::Glue { stretchability, shrinkability, stretch_order, .. } = n {
+                        stretch[*stretch_order as usize] += stretchability;
+                        shrink += shrinkability;
+                    }
+                },
+            }
+        }
+
+        let page: Vec<Node> = nodes.drain(..end).collect();
+        let span = layout::span_of(&page);
+        pages.push(layout::vpack(page, Some(vsize), span));
+    }
+
+    pages
+}
+
+/// The badness of a line set at width `hsize`, `None` if it does not fit
+fn line_badness(line: &[Node], hsize: f32) -> Option<u32> {
+    let mut natural = 0.0;
+    let mut stretch = [0.0; 4];
+    let mut shrink = [0.0; 4];
+
+    for n in line {
+        natural += n.size()[0];
+        if let Node::Glue { stretchability, shrinkability, stretch_order, shrink_order, .. } = n {
+            stretch[*stretch_order as usize] += stretchability;
+            shrink[*shrink_order as usize] += shrinkability;
+        }
+    }
+
+    let excess = hsize - natural;
+    if excess >= 0.0 {
+        let infinite = stretch[1..].iter().any(|s| *s != 0.0);
+        Some(if infinite { 0 } else { layout::badness(excess, stretch[0]) })
+    } else if shrink[1..].iter().any(|s| *s != 0.0) {
+        Some(0)
+    } else {
+        (-excess <= shrink[0]).then(|| layout::badness(-excess, shrink[0]))
+    }
+}
@@ -0,0 +1,254 @@
+use core::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::document::{Document, MathRenderer, Metadata};
+use crate::error::{Error, Severity};
+use crate::layout::Node;
+use super::*;
+
+/// Sets up an [`Engine`]: the format it starts from, the fonts, the page size and the limits it
+/// runs within, and where it finds its files
+///
+/// ```no_run
+/// # use rstex::engine::Engine;
+/// let doc = Engine::builder()
+///     .format_file("plain.tex")
+///     .page_size(300.0, 400.0)
+///     .render(r"Hello, world!\bye")
+///     .unwrap();
+/// println!("{}", doc.to_html());
+/// ```
+#[derive(Default)]
+pub struct EngineBuilder {
+    page_size: Option<(f32, f32)>,
+    base_font: Option<(String, Option<f32>)>,
+    font_dirs: Vec<PathBuf>,
+    format: Option<PathBuf>,
+    latex: bool,
+    math: MathRenderer,
+    limits: Limits,
+    resolver: Option<Box<dyn Resolver>>,
+    sink: Option<Box<dyn Sink>>,
+    terminal: Option<Box<dyn Terminal>>,
+    interaction: Option<InteractionMode>,
+    encoding: Encoding,
+    patterns: Vec<(i32, Patterns)>,
+}
+
+/// Why an engine could not be set up
+#[derive(Debug)]
+pub enum BuildError {
+    /// The format file could not be read
+    Io(PathBuf, io::Error),
+    /// The format file is a format dumped by another version, or damaged
+    Format(PathBuf, BadFormat),
+    /// The base font is neither in the font directories nor built in
+    FontNotFound(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            Self::Format(path, e) => write!(f, "{}: {e}", path.display()),
+            Self::FontNotFound(name) => write!(f, "font {name} not found"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `\hsize` and `\vsize`, in points, overriding the format
+    pub fn page_size(mut self, width: f32, height: f32) -> Self {
+        self.page_size = Some((width, height));
+        self
+    }
+
+    /// Sets the font text is set in before another is selected, at `size` points or its design
+    /// size
+    pub fn base_font(mut self, name: &str, size: Option<f32>) -> Self {
+        self.base_font = Some((name.to_string(), size));
+        self
+    }
+
+    /// Adds a directory to look for TFM files in
+    pub fn font_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.font_dirs.push(dir.into());
+        self
+    }
+
+    /// Loads a format first, either dumped by `\dump` or TeX source like `plain.tex`. Unless
+    /// another resolver is set, files are looked for next to it, then in the current directory.
+    pub fn format_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.format = Some(path.into());
+        self
+    }
+
+    /// Loads the subset of the LaTeX kernel built into rstex, after the format file if one is
+    /// set, so that LaTeX documents can be typeset. See [`Engine::load_latex`].
+    pub fn latex(mut self) -> Self {
+        self.latex = true;
+        self
+    }
+
+    pub fn math_renderer(mut self, math: MathRenderer) -> Self {
+        self.math = math;
+        self
+    }
+
+    /// Sets the limits the document is typeset within, which deny file access by default
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets where `\input` and `\openin` find files
+    pub fn resolver(mut self, resolver: impl Resolver + 'static) -> Self {
+        self.resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// Sets where `\openout` and `\write` put files
+    pub fn sink(mut self, sink: impl Sink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    pub fn terminal(mut self, terminal: impl Terminal + 'static) -> Self {
+        self.terminal = Some(Box::new(terminal));
+        self
+    }
+
+    /// Sets the interaction mode, overriding the format
+    pub fn interaction(mut self, mode: InteractionMode) -> Self {
+        self.interaction = Some(mode);
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets the hyphenation patterns of `language`, replacing those of the format, like ones
+    /// compiled ahead of time and loaded with [`Patterns::from_compiled`]
+    pub fn patterns(mut self, language: i32, patterns: Patterns) -> Self {
+        self.patterns.push((language, patterns));
+        self
+    }
+
+    /// Creates the engine, loading the format, to typeset `main`
+    pub fn build(self, main: SourceFile) -> Result<Engine, BuildError> {
+        let mut engine = Engine::new(main);
+        engine.set_encoding(self.encoding);
+        engine.set_font_dirs(self.font_dirs);
+        engine.set_limits(self.limits);
+        engine.set_math_renderer(self.math);
+        if let Some(terminal) = self.terminal {
+            engine.set_terminal(terminal);
+        }
+        if let Some(sink) = self.sink {
+            engine.set_sink(sink);
+        }
+
+        let format_dir = self.format.as_deref().and_then(Path::parent).filter(|d| !d.as_os_str().is_empty());
+        let resolver = self.resolver.unwrap_or_else(|| {
+            let dirs = format_dir.map(Path::to_path_buf).into_iter().chain([PathBuf::from(".")]).collect();
+            Box::new(DirResolver::new(dirs))
+        });
+        engine.set_resolver(resolver);
+
+        if let Some((name, size)) = self.base_font {
+            let font = engine.load_font(&name, size).ok_or(BuildError::FontNotFound(name))?;
+            engine.set_default_font(font);
+        }
+
+        if let Some(path) = self.format {
+            let bytes = std::fs::read(&path).map_err(|e| BuildError::Io(path.clone(), e))?;
+            match engine.undump(&bytes) {
+                Ok(()) => engine.format_name = path.file_stem().map(|s| s.to_string_lossy().into_owned()),
+                Err(BadFormat::NotAFormat) => {
+                    let text = String::from_utf8(bytes)
+                        .map_err(|_| BuildError::Io(path.clone(), io::Error::from(io::ErrorKind::InvalidData)))?;
+                    engine.load_format(SourceFile { name: path.display().to_string(), text: text.into() });
+                },
+                Err(e) => return Err(BuildError::Format(path, e)),
+            }
+        }
+        if self.latex {
+            engine.load_latex();
+        }
+        for (language, patterns) in self.patterns {
+            engine.set_patterns(language, patterns);
+        }
+
+        if let Some(mode) = self.interaction {
+            engine.set_interaction(mode);
+        }
+        if let Some((width, height)) = self.page_size {
+            engine.eqtb.set(Key::DimenParam(DimenParam::HSize), Some(Value::Int(to_scaled(width))), true);
+            engine.eqtb.set(Key::DimenParam(DimenParam::VSize), Some(Value::Int(to_scaled(height))), true);
+        }
+
+        Ok(engine)
+    }
+
+    /// Creates the engine and typesets `tex` with it
+    pub fn render(self, tex: &str) -> Result<Document, BuildError> {
+        let mut engine = self.build(SourceFile { name: "texput".into(), text: tex.into() })?;
+        Ok(engine.typeset())
+    }
+
+    /// Creates the engine and renders `tex` as a formula by itself, as
+    /// [`Engine::render_formula`] does. It fails if the formula has errors. Only primitives can
+    /// be used unless a format or [`Self::latex`] is set; [`crate::render_math_with`] has the
+    /// LaTeX kernel loaded already.
+    pub fn render_math(self, tex: &str, display: bool) -> Result<String, Error> {
+        let mut engine = self.build(SourceFile { name: "texput".into(), text: tex.into() })?;
+        engine.formula(display)
+    }
+}
+
+impl Engine {
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+
+    /// Renders the main file as a formula as [`Self::render_formula`] does, failing if it has
+    /// errors
+    pub(crate) fn formula(&mut self, display: bool) -> Result<String, Error> {
+        let out = self.render_formula(display);
+        if self.diagnostics.iter().any(|d| d.severity() == Severity::Error) {
+            return Err(Error::Tex(self.diagnostics.clone(), self.files.clone()));
+        }
+        Ok(out)
+    }
+
+    /// Typesets the input, breaking it into pages as [`Self::run_pages`] does
+    pub fn typeset(&mut self) -> Document {
+        let pages = self.run_pages();
+        self.document(pages)
+    }
+
+    /// The document made of `pages`, which the engine has typeset
+    pub fn document(&self, pages: Vec<Node>) -> Document {
+        Document {
+            pages,
+            diagnostics: self.diagnostics.clone(),
+            files: self.files.clone(),
+            fonts: self.fonts.iter().map(|(f, _)| Font::clone(f)).collect(),
+            metadata: Metadata {
+                format: self.format_name.clone(),
+                job_name: self.job_name(),
+                log: self.log.clone(),
+                math: self.math,
+            },
+        }
+    }
+}
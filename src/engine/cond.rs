@@ -0,0 +1,190 @@
+use crate::layout::{BoxContent, Node};
+use crate::lexer::{Token, TokenType};
+use super::build::Mode;
+use super::*;
+
+/// How far a conditional has got, which decides whether `\else`, `\or` and `\fi` may end it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Cond {
+    /// The condition is still being evaluated
+    Evaluating,
+    /// In the branch taken, which `\else` or `\fi` ends
+    Then,
+    /// In the case taken by `\ifcase`, which `\or`, `\else` or `\fi` ends
+    Case,
+    /// After `\else`, which only `\fi` ends
+    Else,
+}
+
+impl Engine {
+    /// Expands a conditional, skipping the text of the branches not taken
+    pub(super) fn conditional(&mut self, p: Primitive, tok: &Token) {
+        self.conds.push(Cond::Evaluating);
+        let level = self.conds.len() - 1;
+
+        if p == Primitive::IfCase {
+            let mut n = self.scan_int();
+            self.conds[level] = Cond::Case;
+            while n != 0 {
+                match self.pass_text(tok) {
+                    Some(Primitive::Or) => n -= 1,
+                    Some(Primitive::Else) => {
+                        self.conds[level] = Cond::Else;
+                        return;
+                    },
+                    _ => {
+                        self.conds.pop();
+                        return;
+                    },
+                }
+            }
+            return;
+        }
+
+        let b = self.test_condition(p);
+        self.conds[level] = Cond::Then;
+        if b {
+            return;
+        }
+
+        loop {
+            match self.pass_text(tok) {
+                Some(Primitive::Or) => self.error(ErrorKind::ExtraCondition(self.cs_string("or"))),
+                Some(Primitive::Else) => {
+                    self.conds[level] = Cond::Else;
+                    return;
+                },
+                _ => {
+                    self.conds.pop();
+                    return;
+                },
+            }
+        }
+    }
+
+    /// Expands `\else`, `\or` or `\fi`, skipping to the `\fi` if a branch has ended
+    pub(super) fn fi_or_else(&mut self, p: Primitive, tok: Token) {
+        let allowed = match self.conds.last() {
+            None => false,
+            Some(Cond::Evaluating) => {
+                // the condition is ended with `\relax` first, as in `\ifnum1=1\fi`
+                let relax = Token::new_cs(self.cs.intern("relax"), 0..0).at(&tok.span());
+                self.back_input(tok);
+                self.back.push((relax, true));
+                return;
+            },
+            Some(Cond::Then) => p != Primitive::Or,
+            Some(Cond::Case) => true,
+            Some(Cond::Else) => p == Primitive::Fi,
+        };
+
+        if !allowed {
+            self.error(ErrorKind::ExtraCondition(self.print_cs(&tok)));
+            return;
+        }
+
+        if p != Primitive::Fi {
+            while !matches!(self.pass_text(&tok), Some(Primitive::Fi) | None) {}
+        }
+        self.conds.pop();
+    }
+
+    /// Skips tokens up to the next `\else`, `\or` or `\fi` that is not part of a nested
+    /// conditional, returning which it was. `None` if the input ended first.
+    fn pass_text(&mut self, start: &Token) -> Option<Primitive> {
+        let mut depth = 0;
+
+        loop {
+            let Some((t, _)) = self.get_next() else {
+                self.error_at(ErrorKind::IncompleteIf(self.print_cs(start)), start.span());
+                return None;
+            };
+
+            match self.meaning(&t) {
+                Some(Meaning::Primitive(p)) if p.is_conditional() => depth += 1,
+                Some(Meaning::Primitive(Primitive::Fi)) if depth > 0 => depth -= 1,
+                Some(Meaning::Primitive(p @ (Primitive::Else | Primitive::Or | Primitive::Fi))) if depth == 0 => {
+                    return Some(p);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn test_condition(&mut self, p: Primitive) -> bool {
+        match p {
+            Primitive::If | Primitive::IfCat => {
+                let (typ_a, a) = self.if_operand();
+                let (typ_b, b) = self.if_operand();
+                match p {
+                    Primitive::If => a == b,
+                    _ => typ_a == typ_b,
+                }
+            },
+            Primitive::IfNum | Primitive::IfDim => {
+                let scan = |e: &mut Self| if p == Primitive::IfNum { e.scan_int() } else { e.scan_dimen() };
+                let a = scan(self);
+                let rel = match self.get_x_nonblank() {
+                    Some((t, _)) if matches!(t.typ, TokenType::Other) && matches!(t.char(), '<' | '=' | '>') => t.char(),
+                    t => {
+                        self.error(ErrorKind::MissingEquals(self.cs_string(p.name())));
+                        if let Some((t, _)) = t {
+                            self.back_input(t);
+                        }
+                        '='
+                    },
+                };
+                let b = scan(self);
+                match rel {
+                    '<' => a < b,
+                    '>' => a > b,
+                    _ => a == b,
+                }
+            },
+            Primitive::IfOdd => self.scan_int() % 2 != 0,
+            Primitive::IfVMode => self.mode().is_vertical(),
+            Primitive::IfHMode => matches!(self.mode(), Mode::Horizontal | Mode::RestrictedHorizontal),
+            Primitive::IfMMode => self.mode().is_math(),
+            Primitive::IfInner => matches!(self.mode(), Mode::InternalVertical | Mode::RestrictedHorizontal | Mode::Math),
+            Primitive::IfVoid | Primitive::IfHBox | Primitive::IfVBox => {
+                let n = self.scan_register();
+                match (p, self.eqtb.box_reg(n)) {
+                    (Primitive::IfVoid, b) => b.is_none(),
+                    (Primitive::IfHBox, Some(Node::Box { content: BoxContent::HBox(_), .. })) => true,
+                    (Primitive::IfVBox, Some(Node::Box { content: BoxContent::VBox(_), .. })) => true,
+                    _ => false,
+                }
+            },
+            Primitive::IfX => {
+                let a = self.get_next().map(|(t, noexpand)| self.ifx_meaning(&t, noexpand));
+                let b = self.get_next().map(|(t, noexpand)| self.ifx_meaning(&t, noexpand));
+                a == b
+            },
+            Primitive::IfEof => {
+                let n = self.scan_four_bit_int();
+                !self.read_files.contains_key(&n)
+            },
+            Primitive::IfTrue => true,
+            _ => false,
+        }
+    }
+
+    /// The category and character code compared by `\if` and `\ifcat`. Control sequences that
+    /// are not `\let` to a character compare equal to each other and to nothing else.
+    fn if_operand(&mut self) -> (Option<TokenType>, Option<char>) {
+        match self.get_x_token() {
+            Some((_, Some(Meaning::Char(typ, c)))) => (Some(typ), Some(c)),
+            Some((t, Some(Meaning::Primitive(Primitive::Relax)))) if t.typ == TokenType::Active => {
+                (Some(TokenType::Active), Some(t.char()))
+            },
+            _ => (None, None),
+        }
+    }
+
+    fn ifx_meaning(&self, t: &Token, noexpand: bool) -> Option<Meaning> {
+        match noexpand {
+            true => Some(Meaning::Primitive(Primitive::Relax)),
+            false => self.meaning(t),
+        }
+    }
+}
@@ -0,0 +1,606 @@
+use core::fmt;
+
+use crate::binary::{Put, Reader};
+use crate::layout::{self, BoxContent, Node, Whatsit};
+use crate::lexer::CsId;
+use super::*;
+
+const MAGIC: &[u8; 4] = b"RSFM";
+
+/// The version of the layout of format files. Formats written with another version are rejected.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// The error for a format file that can not be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadFormat {
+    NotAFormat,
+    /// The format was written with this [`FORMAT_VERSION`]
+    Version(u32),
+    /// The format is truncated or otherwise damaged
+    Corrupt,
+}
+
+impl fmt::Display for BadFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAFormat => write!(f, "not a format file"),
+            Self::Version(v) => write!(f, "format file has version {v}, expected {FORMAT_VERSION}"),
+            Self::Corrupt => write!(f, "format file is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for BadFormat {}
+
+impl Engine {
+    /// Serializes the state a format sets up, as `\dump` does: the table of equivalents with
+    /// every macro, register and code, the control sequence names, the fonts loaded, the
+    /// hyphenation patterns and the interaction mode. Load it with [`Self::undump`].
+    pub fn dump(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.put_u32(FORMAT_VERSION);
+        out.put_u8(self.eqtb.encoding as u8);
+        out.put_u8(self.interaction as u8);
+
+        let names = self.cs.names();
+        out.put_int(names.len());
+        for name in names.iter() {
+            out.put_str(name);
+        }
+
+        out.put_int(self.fonts.len());
+        for (font, cs) in self.fonts.iter() {
+            font.write(&mut out);
+            out.put_int(cs.index());
+        }
+
+        let entries: Vec<_> = self.eqtb.entries().collect();
+        out.put_int(entries.len());
+        for (key, value) in entries {
+            put_key(&mut out, key);
+            put_value(&mut out, value);
+        }
+
+        let mut patterns: Vec<_> = self.patterns.iter().collect();
+        patterns.sort_by_key(|(language, _)| **language);
+        out.put_int(patterns.len());
+        for (language, p) in patterns {
+            out.put_i32(*language);
+            out.put_bytes(&p.compile());
+        }
+
+        out
+    }
+
+    /// Restores the state saved by [`Self::dump`], replacing that of the engine. Nothing is
+    /// changed if the format can not be loaded.
+    pub fn undump(&mut self, format: &[u8]) -> Result<(), BadFormat> {
+        let rest = format.strip_prefix(MAGIC).ok_or(BadFormat::NotAFormat)?;
+        let mut r = Undump { r: Reader::new(rest, BadFormat::Corrupt), ids: Vec::new() };
+        match r.r.u32()? {
+            FORMAT_VERSION => {},
+            v => return Err(BadFormat::Version(v)),
+        }
+
+        let encoding = match r.r.u8()? {
+            0 => Encoding::Classic,
+            1 => Encoding::Unicode,
+            _ => return Err(BadFormat::Corrupt),
+        };
+        let interaction = match r.r.u8()? {
+            0 => InteractionMode::Batch,
+            1 => InteractionMode::NonStop,
+            2 => InteractionMode::Scroll,
+            3 => InteractionMode::ErrorStop,
+            _ => return Err(BadFormat::Corrupt),
+        };
+
+        // the ids of the control sequences may differ from those they were dumped with
+        for _ in 0..r.r.int()? {
+            let name = r.r.str()?;
+            r.ids.push(self.cs.intern(name));
+        }
+
+        let mut fonts = Vec::new();
+        for _ in 0..r.r.int()? {
+            let font = Font::read(&mut r.r)?;
+            fonts.push((Rc::new(font), r.cs()?));
+        }
+
+        let mut eqtb = Eqtb::default();
+        eqtb.encoding = encoding;
+        for _ in 0..r.r.int()? {
+            let key = r.key()?;
+            let value = r.value(fonts.len())?;
+            if !in_range(&key, &value, encoding) {
+                return Err(BadFormat::Corrupt);
+            }
+            eqtb.set(key, Some(value), true);
+        }
+
+        let mut patterns = HashMap::new();
+        for _ in 0..r.r.int()? {
+            let language = r.r.i32()?;
+            let p = Patterns::from_compiled(r.r.bytes()?).map_err(|_| BadFormat::Corrupt)?;
+            patterns.insert(language, p);
+        }
+
+        if !r.r.is_empty() {
+            return Err(BadFormat::Corrupt);
+        }
+
+        self.eqtb = eqtb;
+        self.fonts = fonts;
+        self.patterns = patterns;
+        self.interaction = interaction;
+        self.set_encoding(encoding);
+        for lexer in self.inputs.iter_mut() {
+            lexer.catcodes.clear();
+        }
+        let keys: Vec<Key> = self.eqtb.entries().map(|(k, _)| k.clone()).collect();
+        for key in keys.iter() {
+            self.sync(key);
+        }
+        self.sync(&Key::Int(IntParam::EndLineChar));
+        Ok(())
+    }
+
+    /// The format written by `\dump`, if it has been used
+    pub fn take_dump(&mut self) -> Option<Vec<u8>> {
+        self.dumped.take()
+    }
+}
+
+fn put_key(out: &mut Vec<u8>, key: &Key) {
+    match key {
+        Key::Cs(cs) => {
+            out.put_u8(0);
+            out.put_int(cs.index());
+        },
+        Key::Active(c) => {
+            out.put_u8(1);
+            out.put_char(*c);
+        },
+        Key::Count(n) => put_tagged(out, 2, *n),
+        Key::Dimen(n) => put_tagged(out, 3, *n),
+        Key::Skip(n) => put_tagged(out, 4, *n),
+        Key::MuSkip(n) => put_tagged(out, 5, *n),
+        Key::Toks(n) => put_tagged(out, 6, *n),
+        Key::Box(n) => put_tagged(out, 7, *n),
+        Key::Catcode(c) | Key::LcCode(c) | Key::UcCode(c) | Key::SfCode(c) | Key::MathCode(c) | Key::DelCode(c) => {
+            out.put_u8(match key {
+                Key::Catcode(_) => 8,
+                Key::LcCode(_) => 9,
+                Key::UcCode(_) => 10,
+                Key::SfCode(_) => 11,
+                Key::MathCode(_) => 12,
+                _ => 13,
+            });
+            out.put_char(*c);
+        },
+        // parameters are stored by name, so that they survive being reordered
+        Key::Int(p) => {
+            out.put_u8(14);
+            out.put_str(p.name());
+        },
+        Key::DimenParam(p) => {
+            out.put_u8(15);
+            out.put_str(p.name());
+        },
+        Key::GlueParam(p) => {
+            out.put_u8(16);
+            out.put_str(p.name());
+        },
+        Key::MuGlueParam(p) => {
+            out.put_u8(17);
+            out.put_str(p.name());
+        },
+        Key::ToksParam(p) => {
+            out.put_u8(18);
+            out.put_str(p.name());
+        },
+        Key::CurFont => out.put_u8(19),
+        Key::MathFont(size, fam) => {
+            out.put_u8(20);
+            out.put_u8(*size);
+            out.put_u8(*fam);
+        },
+    }
+}
+
+fn put_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Meaning(m) => {
+            out.put_u8(0);
+            put_meaning(out, m);
+        },
+        Value::Int(i) => {
+            out.put_u8(1);
+            out.put_i32(*i);
+        },
+        Value::Glue(g) => {
+            out.put_u8(2);
+            put_glue(out, g);
+        },
+        Value::Toks(toks) => {
+            out.put_u8(3);
+            out.put_int(toks.len());
+            for t in toks.iter() {
+                put_token(out, t);
+            }
+        },
+        Value::Box(b) => {
+            out.put_u8(4);
+            put_node(out, b);
+        },
+        Value::Font(f) => {
+            out.put_u8(5);
+            out.put_u32(f.0);
+        },
+    }
+}
+
+fn put_meaning(out: &mut Vec<u8>, m: &Meaning) {
+    let mut named = |tag, name: &str| {
+        out.put_u8(tag);
+        out.put_str(name);
+    };
+
+    match m {
+        Meaning::Primitive(p) => return named(2, p.name()),
+        Meaning::IntParam(p) => return named(3, p.name()),
+        Meaning::DimenParam(p) => return named(4, p.name()),
+        Meaning::GlueParam(p) => return named(5, p.name()),
+        Meaning::MuGlueParam(p) => return named(6, p.name()),
+        Meaning::ToksParam(p) => return named(7, p.name()),
+        _ => {},
+    }
+
+    match m {
+        Meaning::Char(typ, c) => {
+            out.put_u8(0);
+            out.put_u8(*typ as u8);
+            out.put_char(*c);
+        },
+        Meaning::Macro(m) => {
+            out.put_u8(1);
+            for list in [&m.params, &m.body] {
+                out.put_int(list.len());
+                for t in list.iter() {
+                    match t {
+                        MacroToken::Token(t) => {
+                            out.put_u8(0);
+                            put_token(out, t);
+                        },
+                        MacroToken::Param(n) => {
+                            out.put_u8(1);
+                            out.put_u8(*n);
+                        },
+                    }
+                }
+            }
+        },
+        Meaning::CountDef(n) => put_tagged(out, 8, *n),
+        Meaning::DimenDef(n) => put_tagged(out, 9, *n),
+        Meaning::SkipDef(n) => put_tagged(out, 10, *n),
+        Meaning::MuSkipDef(n) => put_tagged(out, 11, *n),
+        Meaning::ToksDef(n) => put_tagged(out, 12, *n),
+        Meaning::CharDef(c) => {
+            out.put_u8(13);
+            out.put_char(*c);
+        },
+        Meaning::MathCharDef(n) => {
+            out.put_u8(14);
+            out.put_i32(*n);
+        },
+        Meaning::Font(f) => {
+            out.put_u8(15);
+            out.put_u32(f.0);
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn put_tagged(out: &mut Vec<u8>, tag: u8, n: u8) {
+    out.put_u8(tag);
+    out.put_u8(n);
+}
+
+/// Writes a token without where it came from, since the files it came from are not kept
+fn put_token(out: &mut Vec<u8>, t: &Token) {
+    out.put_u8(t.typ as u8);
+    match t.cs() {
+        Some(cs) => out.put_int(cs.index()),
+        None => out.put_char(t.char()),
+    }
+}
+
+fn put_glue(out: &mut Vec<u8>, g: &Glue) {
+    out.put_i32(g.width);
+    out.put_i32(g.stretch);
+    out.put_u8(g.stretch_order);
+    out.put_i32(g.shrink);
+    out.put_u8(g.shrink_order);
+}
+
+fn put_node(out: &mut Vec<u8>, node: &Node) {
+    let put_list = |out: &mut Vec<u8>, list: &[Node]| {
+        out.put_int(list.len());
+        for n in list {
+            put_node(out, n);
+        }
+    };
+
+    match node {
+        Node::Box { content, size, shift, .. } => {
+            out.put_u8(0);
+            match content {
+                BoxContent::Character(c, f) => {
+                    out.put_u8(0);
+                    out.put_char(*c);
+                    out.put_u32(f.0);
+                },
+                BoxContent::HRule => out.put_u8(1),
+                BoxContent::VRule => out.put_u8(2),
+                BoxContent::HBox(list) => {
+                    out.put_u8(3);
+                    put_list(out, list);
+                },
+                BoxContent::VBox(list) => {
+                    out.put_u8(4);
+                    put_list(out, list);
+                },
+            }
+            for x in size.iter().chain([shift]) {
+                out.put_f32(*x);
+            }
+        },
+        Node::Glue { nat_size, shrinkability, stretchability, stretch_order, shrink_order } => {
+            out.put_u8(1);
+            for x in [nat_size, shrinkability, stretchability] {
+                out.put_f32(*x);
+            }
+            out.put_u8(*stretch_order);
+            out.put_u8(*shrink_order);
+        },
+        Node::Kern(k) => {
+            out.put_u8(2);
+            out.put_f32(*k);
+        },
+        Node::Penalty(p) => {
+            out.put_u8(3);
+            out.put_i32(*p);
+        },
+        Node::Discretionary { pre_break } => {
+            out.put_u8(4);
+            put_list(out, pre_break);
+        },
+        Node::Whatsit(w) => {
+            out.put_u8(5);
+            match w {
+                Whatsit::Open(n, name) => {
+                    put_tagged(out, 0, *n);
+                    out.put_str(name);
+                },
+                Whatsit::Write(n, toks) => {
+                    put_tagged(out, 1, *n);
+                    out.put_int(toks.len());
+                    for t in toks {
+                        put_token(out, t);
+                    }
+                },
+                Whatsit::Close(n) => put_tagged(out, 2, *n),
+                Whatsit::Special(text) => {
+                    out.put_u8(3);
+                    out.put_str(text);
+                },
+            }
+        },
+    }
+}
+
+/// Reads a format, with the ids its control sequences have been interned as
+struct Undump<'b> {
+    r: Reader<'b, BadFormat>,
+    ids: Vec<CsId>,
+}
+
+impl Undump<'_> {
+    fn cs(&mut self) -> Result<CsId, BadFormat> {
+        let i = self.r.int()?;
+        self.ids.get(i).copied().ok_or(BadFormat::Corrupt)
+    }
+
+    fn key(&mut self) -> Result<Key, BadFormat> {
+        Ok(match self.r.u8()? {
+            0 => Key::Cs(self.cs()?),
+            1 => Key::Active(self.r.char()?),
+            2 => Key::Count(self.r.u8()?),
+            3 => Key::Dimen(self.r.u8()?),
+            4 => Key::Skip(self.r.u8()?),
+            5 => Key::MuSkip(self.r.u8()?),
+            6 => Key::Toks(self.r.u8()?),
+            7 => Key::Box(self.r.u8()?),
+            8 => Key::Catcode(self.r.char()?),
+            9 => Key::LcCode(self.r.char()?),
+            10 => Key::UcCode(self.r.char()?),
+            11 => Key::SfCode(self.r.char()?),
+            12 => Key::MathCode(self.r.char()?),
+            13 => Key::DelCode(self.r.char()?),
+            14 => Key::Int(self.named(INT_PARAMS)?),
+            15 => Key::DimenParam(self.named(DIMEN_PARAMS)?),
+            16 => Key::GlueParam(self.named(GLUE_PARAMS)?),
+            17 => Key::MuGlueParam(self.named(MU_GLUE_PARAMS)?),
+            18 => Key::ToksParam(self.named(TOKS_PARAMS)?),
+            19 => Key::CurFont,
+            20 => Key::MathFont(self.r.u8()?, self.r.u8()?),
+            _ => return Err(BadFormat::Corrupt),
+        })
+    }
+
+    /// Looks up a primitive or parameter by its name in `table`
+    fn named<T: Copy>(&mut self, table: &[(&str, T)]) -> Result<T, BadFormat> {
+        let name = self.r.str()?;
+        table.iter().find(|(n, _)| *n == name).map(|(_, p)| *p).ok_or(BadFormat::Corrupt)
+    }
+
+    fn font(&mut self, fonts: usize) -> Result<FontId, BadFormat> {
+        let f = self.r.u32()?;
+        match (f as usize) < fonts {
+            true => Ok(FontId(f)),
+            false => Err(BadFormat::Corrupt),
+        }
+    }
+
+    fn value(&mut self, fonts: usize) -> Result<Value, BadFormat> {
+        Ok(match self.r.u8()? {
+            0 => Value::Meaning(self.meaning(fonts)?),
+            1 => Value::Int(self.r.i32()?),
+            2 => Value::Glue(self.glue()?),
+            3 => Value::Toks((0..self.r.int()?).map(|_| self.token()).collect::<Result<_, _>>()?),
+            4 => Value::Box(self.node(fonts)?),
+            5 => Value::Font(self.font(fonts)?),
+            _ => return Err(BadFormat::Corrupt),
+        })
+    }
+
+    fn meaning(&mut self, fonts: usize) -> Result<Meaning, BadFormat> {
+        Ok(match self.r.u8()? {
+            0 => {
+                let typ = self.token_type()?;
+                Meaning::Char(typ, self.r.char()?)
+            },
+            1 => {
+                let mut lists = [Vec::new(), Vec::new()];
+                for list in lists.iter_mut() {
+                    for _ in 0..self.r.int()? {
+                        list.push(match self.r.u8()? {
+                            0 => MacroToken::Token(self.token()?),
+                            1 => MacroToken::Param(self.r.u8()?),
+                            _ => return Err(BadFormat::Corrupt),
+                        });
+                    }
+                }
+                // parameters are numbered in order, and the body only uses those there are
+                let [params, body] = lists;
+                let numbers = params.iter().filter_map(|t| match t {
+                    MacroToken::Param(n) => Some(*n),
+                    MacroToken::Token(_) => None,
+                });
+                let count = numbers.clone().count() as u8;
+                if !numbers.eq(1..=count) || body.iter().any(|t| matches!(t, MacroToken::Param(n) if !(1..=count).contains(n))) {
+                    return Err(BadFormat::Corrupt);
+                }
+                Meaning::Macro(Rc::new(Macro { params, body }))
+            },
+            2 => Meaning::Primitive(self.named(PRIMITIVES)?),
+            3 => Meaning::IntParam(self.named(INT_PARAMS)?),
+            4 => Meaning::DimenParam(self.named(DIMEN_PARAMS)?),
+            5 => Meaning::GlueParam(self.named(GLUE_PARAMS)?),
+            6 => Meaning::MuGlueParam(self.named(MU_GLUE_PARAMS)?),
+            7 => Meaning::ToksParam(self.named(TOKS_PARAMS)?),
+            8 => Meaning::CountDef(self.r.u8()?),
+            9 => Meaning::DimenDef(self.r.u8()?),
+            10 => Meaning::SkipDef(self.r.u8()?),
+            11 => Meaning::MuSkipDef(self.r.u8()?),
+            12 => Meaning::ToksDef(self.r.u8()?),
+            13 => Meaning::CharDef(self.r.char()?),
+            14 => Meaning::MathCharDef(self.r.i32()?),
+            15 => Meaning::Font(self.font(fonts)?),
+            _ => return Err(BadFormat::Corrupt),
+        })
+    }
+
+    fn token_type(&mut self) -> Result<TokenType, BadFormat> {
+        let code = self.r.u8()?;
+        TokenType::ALL.get(code as usize).copied().ok_or(BadFormat::Corrupt)
+    }
+
+    fn token(&mut self) -> Result<Token, BadFormat> {
+        Ok(match self.token_type()? {
+            TokenType::Escape => Token::new_cs(self.cs()?, 0..0),
+            typ => Token::new_char(typ, self.r.char()?, 0..0),
+        })
+    }
+
+    fn glue(&mut self) -> Result<Glue, BadFormat> {
+        Ok(Glue {
+            width: self.r.i32()?,
+            stretch: self.r.i32()?,
+            stretch_order: self.order()?,
+            shrink: self.r.i32()?,
+            shrink_order: self.order()?,
+        })
+    }
+
+    /// Reads the order of infinity of a stretch or shrink, from 0 for finite to 3 for `filll`
+    fn order(&mut self) -> Result<u8, BadFormat> {
+        match self.r.u8()? {
+            n @ 0..=3 => Ok(n),
+            _ => Err(BadFormat::Corrupt),
+        }
+    }
+
+    fn node(&mut self, fonts: usize) -> Result<Node, BadFormat> {
+        let list = |u: &mut Self| (0..u.r.int()?).map(|_| u.node(fonts)).collect::<Result<Vec<_>, _>>();
+
+        Ok(match self.r.u8()? {
+            0 => {
+                let content = match self.r.u8()? {
+                    0 => {
+                        let c = self.r.char()?;
+                        BoxContent::Character(c, self.font(fonts)?)
+                    },
+                    1 => BoxContent::HRule,
+                    2 => BoxContent::VRule,
+                    3 => BoxContent::HBox(list(self)?),
+                    4 => BoxContent::VBox(list(self)?),
+                    _ => return Err(BadFormat::Corrupt),
+                };
+                let size = [self.r.f32()?, self.r.f32()?, self.r.f32()?];
+                let nesting = match &content {
+                    BoxContent::HBox(list) | BoxContent::VBox(list) => layout::nesting(list),
+                    _ => 0,
+                };
+                Node::Box { content, size, shift: self.r.f32()?, nesting, span: Span::default() }
+            },
+            1 => Node::Glue {
+                nat_size: self.r.f32()?,
+                shrinkability: self.r.f32()?,
+                stretchability: self.r.f32()?,
+                stretch_order: self.order()?,
+                shrink_order: self.order()?,
+            },
+            2 => Node::Kern(self.r.f32()?),
+            3 => Node::Penalty(self.r.i32()?),
+            4 => Node::Discretionary { pre_break: list(self)? },
+            5 => Node::Whatsit(match self.r.u8()? {
+                0 => Whatsit::Open(self.r.u8()?, self.r.str()?.to_string()),
+                1 => {
+                    let n = self.r.u8()?;
+                    Whatsit::Write(n, (0..self.r.int()?).map(|_| self.token()).collect::<Result<_, _>>()?)
+                },
+                2 => Whatsit::Close(self.r.u8()?),
+                3 => Whatsit::Special(self.r.str()?.to_string()),
+                _ => return Err(BadFormat::Corrupt),
+            }),
+            _ => return Err(BadFormat::Corrupt),
+        })
+    }
+}
+
+/// Whether `value` could have been assigned to `key` by TeX, which checks the codes it is given
+/// to be in range
+fn in_range(key: &Key, value: &Value, encoding: Encoding) -> bool {
+    let max = match key {
+        Key::Catcode(_) => 15,
+        Key::LcCode(_) | Key::UcCode(_) => encoding.max_char() as i32,
+        Key::SfCode(_) => 0x7FFF,
+        Key::MathCode(_) if encoding == Encoding::Classic => 0x8000,
+        Key::DelCode(_) if encoding == Encoding::Classic => 0xFFFFFF,
+        Key::MathCode(_) | Key::DelCode(_) => i32::MAX,
+        _ => return true,
+    };
+    let min = if matches!(key, Key::DelCode(_)) { i32::MIN } else { 0 };
+    matches!(value, Value::Int(n) if (min..=max).contains(n))
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::font::FontId;
+use crate::layout::Node;
+use crate::lexer::{CsId, Encoding, Token};
+use super::{DimenParam, Glue, GlueParam, IntParam, Meaning, MuGlueParam, ToksParam};
+
+/// Everything that can be assigned to and is restored at the end of a group
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Cs(CsId),
+    Active(char),
+    Count(u8),
+    Dimen(u8),
+    Skip(u8),
+    MuSkip(u8),
+    Toks(u8),
+    Box(u8),
+    Catcode(char),
+    LcCode(char),
+    UcCode(char),
+    SfCode(char),
+    MathCode(char),
+    DelCode(char),
+    Int(IntParam),
+    DimenParam(DimenParam),
+    GlueParam(GlueParam),
+    MuGlueParam(MuGlueParam),
+    ToksParam(ToksParam),
+    /// The font text is set in
+    CurFont,
+    /// The `\textfont`, `\scriptfont` or `\scriptscriptfont`, for sizes `0` to `2`, of a family
+    MathFont(u8, u8),
+}
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Meaning(Meaning),
+    Int(i32),
+    Glue(Glue),
+    Toks(Rc<[Token]>),
+    Box(Node),
+    Font(FontId),
+}
+
+/// The table of equivalents
+#[derive(Debug, Clone, Default)]
+pub struct Eqtb {
+    /// Decides the initial character codes
+    pub encoding: Encoding,
+    table: HashMap<Key, Value>,
+    /// The values to restore for each open group, `None` meaning the key was unset
+    save_stack: Vec<HashMap<Key, Option<Value>>>,
+    /// The number of values in `save_stack`
+    saved: usize,
+}
+
+impl Eqtb {
+    pub fn get(&self, key: &Key) -> Option<&Value> {
+        self.table.get(key)
+    }
+
+    pub fn meaning(&self, key: &Key) -> Option<Meaning> {
+        match self.table.get(key) {
+            Some(Value::Meaning(m)) => Some(m.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn int(&self, key: &Key) -> i32 {
+        match (self.table.get(key), key) {
+            (Some(Value::Int(i)), _) => *i,
+            (_, Key::Int(p)) => p.default_value(),
+            (_, Key::DimenParam(p)) => p.default_value(),
+            (_, Key::LcCode(c)) => self.default_case(*c, char::to_lowercase),
+            (_, Key::UcCode(c)) => self.default_case(*c, char::to_uppercase),
+            (_, Key::SfCode(c)) if self.encoding.is_letter(*c) && c.is_uppercase() => 999,
+            (_, Key::SfCode(_)) => 1000,
+            (_, Key::MathCode(c)) => self.default_math_code(*c),
+            (_, Key::DelCode(_)) => -1,
+            _ => 0,
+        }
+    }
+
+    /// The initial `\lccode` or `\uccode` of `c`, which is its case mapping if it is a letter. In
+    /// Unicode, letters without a single character mapping map to themselves.
+    fn default_case<I: Iterator<Item = char>>(&self, c: char, map: impl Fn(char) -> I) -> i32 {
+        if !self.encoding.is_letter(c) {
+            return 0;
+        }
+
+        let mut mapped = map(c);
+        match (mapped.next(), mapped.next()) {
+            (Some(m), None) => m as i32,
+            _ => c as i32,
+        }
+    }
+
+    /// The initial `\mathcode` of `c`. Digits are class 7 in family 0, letters class 7 in family
+    /// 1 and everything else class 0 in family 0. Classic math codes are `"cfcc`, Unicode ones
+    /// have the class in bits 28 to 30, the family in bits 21 to 27 and the character below.
+    fn default_math_code(&self, c: char) -> i32 {
+        let fam = if c.is_ascii_digit() {
+            Some(0)
+        } else if self.encoding.is_letter(c) {
+            Some(1)
+        } else {
+            None
+        };
+
+        match (self.encoding, fam) {
+            (Encoding::Classic, Some(fam)) => 0x7000 + fam * 0x100 + c as i32,
+            (Encoding::Unicode, Some(fam)) => (7 << 28) + (fam << 21) + c as i32,
+            (_, None) => c as i32,
+        }
+    }
+
+    pub fn glue(&self, key: &Key) -> Glue {
+        match (self.table.get(key), key) {
+            (Some(Value::Glue(g)), _) => *g,
+            (_, Key::GlueParam(p)) => p.default_value(),
+            (_, Key::MuGlueParam(p)) => p.default_value(),
+            _ => Glue::default(),
+        }
+    }
+
+    pub fn font(&self, key: &Key) -> FontId {
+        match (self.table.get(key), key) {
+            (Some(Value::Font(f)), _) => *f,
+            (_, Key::CurFont) => FontId::DEFAULT,
+            _ => FontId::NULL,
+        }
+    }
+
+    /// The box in a register, `None` if it is void
+    pub fn box_reg(&self, n: u8) -> Option<&Node> {
+        match self.table.get(&Key::Box(n)) {
+            Some(Value::Box(b)) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn box_reg_mut(&mut self, n: u8) -> Option<&mut Node> {
+        match self.table.get_mut(&Key::Box(n)) {
+            Some(Value::Box(b)) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Takes the value of `key`, leaving it unset without saving it, as `\box` voids a register
+    pub fn take(&mut self, key: &Key) -> Option<Value> {
+        self.table.remove(key)
+    }
+
+    pub fn toks(&self, key: &Key) -> Rc<[Token]> {
+        match self.table.get(key) {
+            Some(Value::Toks(t)) => Rc::clone(t),
+            _ => Rc::new([]),
+        }
+    }
+
+    /// Sets `key` to `value`, or unsets it if `value` is `None`
+    pub fn set(&mut self, key: Key, value: Option<Value>, global: bool) {
+        if global {
+            for saved in self.save_stack.iter_mut() {
+                if let Some(s) = saved.get_mut(&key) {
+                    *s = value.clone();
+                }
+            }
+        } else if let Some(saved) = self.save_stack.last_mut() {
+            if !saved.contains_key(&key) {
+                saved.insert(key.clone(), self.table.get(&key).cloned());
+                self.saved += 1;
+            }
+        }
+
+        match value {
+            Some(v) => self.table.insert(key, v),
+            None => self.table.remove(&key),
+        };
+    }
+
+    /// Every key that is set, with its value
+    pub fn entries(&self) -> impl Iterator<Item = (&Key, &Value)> {
+        self.table.iter()
+    }
+
+    pub fn level(&self) -> usize {
+        self.save_stack.len()
+    }
+
+    /// How much the open groups take on the save stack: one entry for each group and for each
+    /// value it restores, as in TeX
+    pub fn save_size(&self) -> usize {
+        self.save_stack.len() + self.saved
+    }
+
+    pub fn begin_group(&mut self) {
+        self.save_stack.push(HashMap::new());
+    }
+
+    /// Closes the innermost group and returns the keys whose values were restored
+    pub fn end_group(&mut self) -> Vec<Key> {
+        let Some(saved) = self.save_stack.pop() else { return Vec::new() };
+        self.saved -= saved.len();
+
+        saved.into_iter().map(|(k, v)| {
+            match v {
+                Some(v) => self.table.insert(k.clone(), v),
+                None => self.table.remove(&k),
+            };
+            k
+        }).collect()
+    }
+}
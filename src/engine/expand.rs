@@ -0,0 +1,372 @@
+use crate::lexer::{Span, Token, TokenType};
+use super::scan::{print_glue, print_scaled, Internal};
+use super::*;
+
+impl Engine {
+    /// Expands `tok` once, putting the result back into the input
+    pub(super) fn expand(&mut self, tok: Token, meaning: Option<Meaning>) {
+        if self.usage.expansions >= self.limits.max_expansions {
+            self.overflow("expansions", self.limits.max_expansions);
+            return;
+        }
+        self.usage.expansions += 1;
+
+        // expansions nest when they scan expanded tokens, like the number after `\ifnum`
+        self.nested(|e| e.expand_once(tok, meaning));
+        if self.back.len() > self.limits.max_input_stack {
+            self.overflow("input stack size", self.limits.max_input_stack);
+        }
+    }
+
+    fn expand_once(&mut self, tok: Token, meaning: Option<Meaning>) {
+        let span = tok.span();
+        match meaning {
+            None => self.error(ErrorKind::UndefinedControlSequence(self.print_cs(&tok))),
+            Some(Meaning::Macro(m)) => self.macro_call(&tok, &m),
+            Some(Meaning::Primitive(Primitive::ExpandAfter)) => {
+                let Some((first, _)) = self.get_next() else { return };
+                if let Some((second, noexpand)) = self.get_next() {
+                    let m = self.meaning(&second);
+                    if noexpand {
+                        self.reread(second, true);
+                    } else if m.as_ref().is_none_or(Meaning::is_expandable) {
+                        self.expand(second, m);
+                    } else {
+                        self.back_input(second);
+                    }
+                }
+                self.back_input(first);
+            },
+            Some(Meaning::Primitive(Primitive::NoExpand)) => {
+                let Some((next, _)) = self.get_next() else { return };
+                let expandable = self.meaning(&next).as_ref().is_none_or(Meaning::is_expandable);
+                self.reread(next, expandable);
+            },
+            Some(Meaning::Primitive(Primitive::CsName)) => {
+                let mut name = String::new();
+                let mut span = span;
+
+                while let Some((t, m)) = self.get_x_token() {
+                    if t.file == span.file {
+                        span.range.end = span.range.end.max(t.range.end);
+                    }
+                    match (t.typ, m) {
+                        (_, Some(Meaning::Primitive(Primitive::EndCsName))) => break,
+                        (TokenType::Escape | TokenType::Active, _) => {
+                            self.error(ErrorKind::MissingEndCsName);
+                            self.back_input(t);
+                            break;
+                        },
+                        _ => name.push(t.char()),
+                    }
+                }
+
+                let cs = self.cs.intern(&name);
+                let key = Key::Cs(cs);
+                if self.eqtb.get(&key).is_none() {
+                    self.assign(key, Some(Value::Meaning(Meaning::Primitive(Primitive::Relax))), false);
+                }
+                self.back_input(Token::new_cs(cs, 0..0).at(&span));
+            },
+            Some(Meaning::Primitive(Primitive::String)) => {
+                let Some((t, _)) = self.get_next() else { return };
+                let s = self.print_cs(&t);
+                self.back_list(str_toks(&s, &span));
+            },
+            Some(Meaning::Primitive(Primitive::Number)) => {
+                let n = self.scan_int();
+                self.back_list(str_toks(&n.to_string(), &span));
+            },
+            Some(Meaning::Primitive(Primitive::RomanNumeral)) => {
+                let n = self.scan_int();
+                self.back_list(str_toks(&roman_numeral(n), &span));
+            },
+            Some(Meaning::Primitive(Primitive::The)) => {
+                let toks = self.the_toks(&span);
+                self.back_list(toks.into_iter());
+            },
+            Some(Meaning::Primitive(Primitive::Meaning)) => {
+                let Some((t, noexpand)) = self.get_next() else { return };
+                let m = if noexpand { Some(Meaning::Primitive(Primitive::Relax)) } else { self.meaning(&t) };
+                let s = self.meaning_string(m.as_ref());
+                self.back_list(str_toks(&s, &span));
+            },
+            Some(Meaning::Primitive(Primitive::JobName)) => {
+                let name = self.job_name();
+                self.back_list(str_toks(&name, &span));
+            },
+            Some(Meaning::Primitive(Primitive::Input)) => {
+                let name = self.scan_file_name();
+                if !self.limits.file_access.can_read() {
+                    self.error(ErrorKind::FileAccessDenied(name));
+                    return;
+                }
+                match self.resolver.resolve(&name) {
+                    Some(file) => self.push_input(file),
+                    None => self.error(ErrorKind::FileNotFound(name)),
+                }
+            },
+            Some(Meaning::Primitive(Primitive::EndInput)) => self.inputs.last_mut().unwrap().end_input(),
+            Some(Meaning::Primitive(p)) if p.is_conditional() => self.conditional(p, &tok),
+            Some(Meaning::Primitive(p @ (Primitive::Else | Primitive::Or | Primitive::Fi))) => self.fi_or_else(p, tok),
+            Some(m) => unreachable!("{m:?} is not expandable"),
+        }
+    }
+
+    /// Replaces a macro and its arguments with its body. Tokens from the body are attributed to
+    /// where the macro was used, while arguments keep their own spans.
+    fn macro_call(&mut self, name: &Token, m: &Macro) {
+        let mut args: Vec<Vec<Token>> = Vec::new();
+        let mut params = m.params.iter().peekable();
+
+        while let Some(p) = params.next() {
+            match p {
+                MacroToken::Token(expected) => {
+                    let next = self.get_next().map(|t| t.0);
+                    if next.as_ref() != Some(expected) {
+                        self.error(ErrorKind::UseDoesntMatchDefinition(self.print_cs(name)));
+                        return;
+                    }
+                },
+                MacroToken::Param(_) => {
+                    let mut delim = Vec::new();
+                    while let Some(MacroToken::Token(t)) = params.peek() {
+                        delim.push(t.clone());
+                        params.next();
+                    }
+
+                    let arg = if delim.is_empty() {
+                        self.scan_undelimited_arg()
+                    } else {
+                        self.scan_delimited_arg(&delim)
+                    };
+
+                    let Some(arg) = arg else {
+                        self.error(ErrorKind::RunawayArgument(self.print_cs(name)));
+                        return;
+                    };
+                    args.push(arg);
+                },
+            }
+        }
+
+        let span = name.span();
+        let mut body = Vec::with_capacity(m.body.len());
+        for t in m.body.iter() {
+            match t {
+                MacroToken::Token(t) => body.push(t.clone().at(&span)),
+                MacroToken::Param(n) => body.extend_from_slice(&args[*n as usize - 1]),
+            }
+        }
+        self.back_list(body.into_iter());
+    }
+
+    fn scan_undelimited_arg(&mut self) -> Option<Vec<Token>> {
+        let first = loop {
+            let (t, _) = self.get_next()?;
+            if t.typ != TokenType::Space {
+                break t;
+            }
+        };
+
+        if first.typ != TokenType::BeginGroup {
+            return Some(vec![first]);
+        }
+
+        let mut arg = Vec::new();
+        let mut depth = 0;
+        loop {
+            let (t, _) = self.get_next()?;
+            match t.typ {
+                TokenType::BeginGroup => depth += 1,
+                TokenType::EndGroup if depth == 0 => return Some(arg),
+                TokenType::EndGroup => depth -= 1,
+                _ => {},
+            }
+            arg.push(t);
+        }
+    }
+
+    fn scan_delimited_arg(&mut self, delim: &[Token]) -> Option<Vec<Token>> {
+        let mut arg = Vec::new();
+        let mut depth = 0;
+
+        let (last, rest) = delim.split_last()?;
+
+        while depth != 0 || !arg.ends_with(delim) {
+            let (t, _) = self.get_next()?;
+            // a brace ending the delimiter, as after `#{`, ends the argument instead of a group
+            if depth == 0 && t.typ == TokenType::BeginGroup && t == *last && arg.ends_with(rest) {
+                arg.push(t);
+                break;
+            }
+            match t.typ {
+                TokenType::BeginGroup => depth += 1,
+                TokenType::EndGroup if depth == 0 => {
+                    self.error(ErrorKind::ArgumentExtraBrace);
+                    continue;
+                },
+                TokenType::EndGroup => depth -= 1,
+                _ => {},
+            }
+            arg.push(t);
+        }
+        arg.truncate(arg.len() - delim.len());
+
+        // strip the braces around an argument like `{...}`
+        if arg.first().is_some_and(|t| t.typ == TokenType::BeginGroup)
+            && arg.last().is_some_and(|t| t.typ == TokenType::EndGroup) {
+            let mut depth = 0;
+            let encloses = arg[..arg.len() - 1].iter().all(|t| {
+                match t.typ {
+                    TokenType::BeginGroup => depth += 1,
+                    TokenType::EndGroup => depth -= 1,
+                    _ => {},
+                }
+                depth > 0
+            });
+
+            if encloses {
+                arg.pop();
+                arg.remove(0);
+            }
+        }
+
+        Some(arg)
+    }
+
+    /// Scans the quantity after `\the` and returns it as a list of tokens
+    pub(super) fn the_toks(&mut self, span: &Span) -> Vec<Token> {
+        let Some((t, m)) = self.get_x_token() else { return Vec::new() };
+
+        match m.as_ref().and_then(|m| self.scan_internal(m)) {
+            Some(Internal::Int(i)) => str_toks(&i.to_string(), span).collect(),
+            Some(Internal::Dimen(d)) => str_toks(&(print_scaled(d) + "pt"), span).collect(),
+            Some(Internal::Glue(g)) => str_toks(&print_glue(g, "pt"), span).collect(),
+            Some(Internal::MuGlue(g)) => str_toks(&print_glue(g, "mu"), span).collect(),
+            Some(Internal::Toks(t)) => {
+                self.use_tokens(t.len());
+                t.iter().map(|t| t.clone().at(span)).collect()
+            },
+            Some(Internal::Font(f)) => vec![Token::new_cs(self.fonts[f.0 as usize].1, 0..0).at(span)],
+            None => {
+                self.error(ErrorKind::CantUseAfterThe(self.print_cs(&t)));
+                str_toks("0", span).collect()
+            },
+        }
+    }
+
+    /// Formats a control sequence name with the current escape character
+    pub(super) fn cs_string(&self, name: &str) -> String {
+        let escape = self.int_param(IntParam::EscapeChar);
+        u32::try_from(escape).ok().and_then(char::from_u32).into_iter().collect::<String>() + name
+    }
+
+    /// Formats a token as it would be shown in a token list
+    pub(super) fn token_string(&self, t: &Token) -> String {
+        let Some(cs) = t.cs() else {
+            return match t.typ {
+                TokenType::Parameter => t.char().to_string().repeat(2),
+                _ => t.char().to_string(),
+            };
+        };
+
+        let name = self.cs.name(cs);
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) => self.cs_string("csname") + &self.cs_string("endcsname"),
+            (Some(c), None) if self.catcode(c) != TokenType::Letter => self.cs_string(&name),
+            _ => self.cs_string(&name) + " ",
+        }
+    }
+
+    /// Formats a token as it is shown in messages, without the space after control words
+    pub(super) fn print_cs(&self, t: &Token) -> String {
+        match t.cs() {
+            Some(cs) if self.cs.name(cs).is_empty() => self.cs_string("csname") + &self.cs_string("endcsname"),
+            Some(cs) => self.cs_string(&self.cs.name(cs)),
+            None => t.char().to_string(),
+        }
+    }
+
+    pub(super) fn meaning_string(&self, m: Option<&Meaning>) -> String {
+        match m {
+            None => "undefined".to_string(),
+            Some(Meaning::Char(typ, c)) => {
+                let desc = match typ {
+                    TokenType::BeginGroup => "begin-group character",
+                    TokenType::EndGroup => "end-group character",
+                    TokenType::MathShift => "math shift character",
+                    TokenType::AlignTab => "alignment tab character",
+                    TokenType::Parameter => "macro parameter character",
+                    TokenType::Superscript => "superscript character",
+                    TokenType::Subscript => "subscript character",
+                    TokenType::Space => "blank space",
+                    TokenType::Letter => "the letter",
+                    _ => "the character",
+                };
+                format!("{desc} {c}")
+            },
+            Some(Meaning::Macro(m)) => {
+                let mut s = "macro:".to_string();
+                for t in m.params.iter() {
+                    s += &self.macro_token_string(t);
+                }
+                s += "->";
+                for t in m.body.iter() {
+                    s += &self.macro_token_string(t);
+                }
+                s
+            },
+            Some(Meaning::Primitive(p)) => self.cs_string(p.name()),
+            Some(Meaning::IntParam(p)) => self.cs_string(p.name()),
+            Some(Meaning::DimenParam(p)) => self.cs_string(p.name()),
+            Some(Meaning::GlueParam(p)) => self.cs_string(p.name()),
+            Some(Meaning::MuGlueParam(p)) => self.cs_string(p.name()),
+            Some(Meaning::ToksParam(p)) => self.cs_string(p.name()),
+            Some(Meaning::CountDef(n)) => self.cs_string("count") + &n.to_string(),
+            Some(Meaning::DimenDef(n)) => self.cs_string("dimen") + &n.to_string(),
+            Some(Meaning::SkipDef(n)) => self.cs_string("skip") + &n.to_string(),
+            Some(Meaning::MuSkipDef(n)) => self.cs_string("muskip") + &n.to_string(),
+            Some(Meaning::ToksDef(n)) => self.cs_string("toks") + &n.to_string(),
+            Some(Meaning::CharDef(c)) => self.cs_string("char") + &format!("\"{:X}", *c as u32),
+            Some(Meaning::MathCharDef(n)) => self.cs_string("mathchar") + &format!("\"{n:X}"),
+            Some(Meaning::Font(f)) => {
+                let font = self.font(*f);
+                match font.size == font.design_size {
+                    true => format!("select font {}", font.name),
+                    false => format!("select font {} at {}pt", font.name, print_scaled(to_scaled(font.size))),
+                }
+            },
+        }
+    }
+
+    fn macro_token_string(&self, t: &MacroToken) -> String {
+        match t {
+            MacroToken::Token(t) => self.token_string(t),
+            MacroToken::Param(n) => format!("#{n}"),
+        }
+    }
+}
+
+/// Converts a string to character tokens attributed to `span`, as `\string` does
+pub(super) fn str_toks<'s>(s: &'s str, span: &'s Span) -> impl DoubleEndedIterator<Item = Token> + 's {
+    s.chars().map(|c| Token::new_char(if c == ' ' { TokenType::Space } else { TokenType::Other }, c, 0..0).at(span))
+}
+
+fn roman_numeral(mut n: i32) -> String {
+    const NUMERALS: &[(i32, &str)] = &[
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+        (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+
+    let mut s = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= *value {
+            s += numeral;
+            n -= value;
+        }
+    }
+
+    s
+}
@@ -0,0 +1,124 @@
+use crate::font::FontId;
+use crate::layout::{BoxContent, Node};
+use crate::lexer::{Span, TokenType};
+use super::*;
+
+impl Engine {
+    /// The language whose patterns are used, as TeX restricts `\language`
+    fn language(&self) -> i32 {
+        match self.int_param(IntParam::Language) {
+            l @ 1..=255 => l,
+            _ => 0,
+        }
+    }
+
+    /// Sets the patterns and exceptions of `language`, replacing any it had, as if they had been
+    /// given with `\patterns` and `\hyphenation`
+    pub fn set_patterns(&mut self, language: i32, patterns: Patterns) {
+        self.patterns.insert(language, patterns);
+    }
+
+    /// Adds the patterns in `\patterns{...}` to the current language
+    pub(super) fn new_patterns(&mut self) {
+        let language = self.language();
+        for word in self.scan_hyph_words() {
+            let patterns = self.patterns.entry(language).or_default();
+            if patterns.add_pattern(&word).is_err() {
+                self.error(ErrorKind::BadPatterns);
+            }
+        }
+    }
+
+    /// Adds the words in `\hyphenation{...}` to the exceptions of the current language
+    pub(super) fn new_hyph_exceptions(&mut self) {
+        let language = self.language();
+        for word in self.scan_hyph_words() {
+            let patterns = self.patterns.entry(language).or_default();
+            if patterns.add_exception(&word).is_err() {
+                self.error(ErrorKind::ImproperHyphenation);
+            }
+        }
+    }
+
+    /// Scans a braced list of space separated words, lowercasing their letters with `\lccode`
+    fn scan_hyph_words(&mut self) -> Vec<String> {
+        self.scan_left_brace();
+
+        let mut words = Vec::new();
+        let mut word = String::new();
+        while let Some((_, m)) = self.get_x_token() {
+            match m {
+                Some(Meaning::Char(TokenType::Letter | TokenType::Other, c)) => word.push(self.lc_code(c).unwrap_or(c)),
+                Some(Meaning::Char(TokenType::Space, _)) if !word.is_empty() => words.push(core::mem::take(&mut word)),
+                Some(Meaning::Char(TokenType::Space, _)) => {},
+                Some(Meaning::Char(TokenType::EndGroup, _)) => break,
+                _ => self.error(ErrorKind::ImproperHyphenation),
+            }
+        }
+
+        if !word.is_empty() {
+            words.push(word);
+        }
+        words
+    }
+
+    /// A discretionary break with the `\hyphenchar` of font `f` before it, or nothing if that is
+    /// not a character of the font
+    pub(super) fn discretionary_hyphen(&self, f: FontId, span: Span) -> Node {
+        let hyphen = char::from_u32(self.font(f).hyphen_char as u32).and_then(|c| self.char_box(c, f, span));
+        Node::Discretionary { pre_break: hyphen.into_iter().collect() }
+    }
+
+    /// Inserts discretionary hyphens into the words of a paragraph. Only words following glue
+    /// are hyphenated, as in TeX, and only if their font has a valid `\hyphenchar`.
+    pub(super) fn hyphenate(&self, nodes: &mut Vec<Node>) {
+        let Some(patterns) = self.patterns.get(&self.language()) else { return };
+        let left = self.int_param(IntParam::LeftHyphenMin).max(1) as usize;
+        let right = self.int_param(IntParam::RightHyphenMin).max(1) as usize;
+        let uchyph = self.int_param(IntParam::UcHyph) > 0;
+
+        // letters are the characters with a nonzero `\lccode`, and are hyphenated in lowercase
+        let letter = |n: &Node| match n {
+            Node::Box { content: BoxContent::Character(c, _), .. } => self.lc_code(*c),
+            _ => None,
+        };
+
+        let mut rest = core::mem::take(nodes).into_iter().peekable();
+        while let Some(n) = rest.next() {
+            let after_glue = matches!(n, Node::Glue { .. });
+            nodes.push(n);
+            if !after_glue {
+                continue;
+            }
+
+            let mut word = Vec::new();
+            while let Some(n) = rest.next_if(|n| letter(n).is_some()) {
+                word.push(n);
+            }
+
+            let text: String = word.iter().filter_map(letter).collect();
+            let Some((first, font)) = word.first().and_then(|n| match n {
+                Node::Box { content: BoxContent::Character(c, f), .. } => Some((*c, *f)),
+                _ => None,
+            }) else {
+                continue;
+            };
+            let hyphen_char = self.font(font).hyphen_char;
+            let positions = match (uchyph || text.starts_with(first)) && char::from_u32(hyphen_char as u32).is_some() {
+                true => patterns.hyphenate(&text, left, right),
+                false => Vec::new(),
+            };
+
+            for (k, n) in word.into_iter().enumerate() {
+                let span = match &n {
+                    Node::Box { span, .. } => span.clone(),
+                    _ => Span::default(),
+                };
+                nodes.push(n);
+                if positions.contains(&(k + 1)) {
+                    nodes.push(self.discretionary_hyphen(font, span));
+                }
+            }
+        }
+    }
+}
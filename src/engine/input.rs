@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use crate::lexer::{Range, Token, TokenType};
+use super::*;
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: Rc<str>,
+}
+
+/// Finds the files requested by `\input` and `\openin`
+pub trait Resolver {
+    fn resolve(&self, name: &str) -> Option<SourceFile>;
+}
+
+/// Looks up files in a list of directories, in order. Names that could lead out of them, like
+/// absolute paths or ones with `..`, are not found.
+#[derive(Debug, Clone)]
+pub struct DirResolver {
+    pub dirs: Vec<PathBuf>,
+}
+
+impl DirResolver {
+    pub fn new(dirs: Vec<PathBuf>) -> Self {
+        Self { dirs }
+    }
+}
+
+impl Resolver for DirResolver {
+    fn resolve(&self, name: &str) -> Option<SourceFile> {
+        if !Path::new(name).components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return None;
+        }
+        candidates(name).find_map(|name| self.dirs.iter().find_map(|dir| {
+            let path = dir.join(&name);
+            let text = std::fs::read_to_string(&path).ok()?;
+            Some(SourceFile { name: path.display().to_string(), text: text.into() })
+        }))
+    }
+}
+
+/// Serves files from memory
+#[derive(Debug, Clone, Default)]
+pub struct MapResolver {
+    pub files: HashMap<String, String>,
+}
+
+impl MapResolver {
+    pub fn new(files: HashMap<String, String>) -> Self {
+        Self { files }
+    }
+}
+
+impl Resolver for MapResolver {
+    fn resolve(&self, name: &str) -> Option<SourceFile> {
+        candidates(name).find_map(|name| {
+            let text = self.files.get(&name)?;
+            Some(SourceFile { name, text: text.as_str().into() })
+        })
+    }
+}
+
+/// The names to try for `name`, adding a `.tex` extension if it has none
+fn candidates(name: &str) -> impl Iterator<Item = String> {
+    let with_ext = Path::new(name).extension().is_none().then(|| format!("{name}.tex"));
+    with_ext.into_iter().chain(core::iter::once(name.to_string()))
+}
+
+/// The lines of a shared source text
+#[derive(Debug, Clone)]
+pub struct SourceLines {
+    text: Rc<str>,
+    pos: usize,
+}
+
+impl SourceLines {
+    pub fn new(text: Rc<str>) -> Self {
+        Self { text, pos: 0 }
+    }
+}
+
+impl Iterator for SourceLines {
+    type Item = Line;
+
+    /// Splits lines like [`str::split_inclusive`] does, keeping their line breaks
+    fn next(&mut self) -> Option<Line> {
+        let rest = self.text.get(self.pos..).filter(|r| !r.is_empty())?;
+        let len = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        let start = self.pos;
+        self.pos += len;
+        Some(Line { text: Rc::clone(&self.text), range: start..start + len })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    text: Rc<str>,
+    range: Range,
+}
+
+impl Line {
+    /// A line holding all of `text`
+    pub fn new(text: Rc<str>) -> Self {
+        let range = 0..text.len();
+        Self { text, range }
+    }
+
+    /// The offset of the line in its text
+    pub fn start(&self) -> usize {
+        self.range.start
+    }
+}
+
+impl AsRef<str> for Line {
+    fn as_ref(&self) -> &str {
+        &self.text[self.range.clone()]
+    }
+}
+
+impl Engine {
+    pub(super) fn scan_file_name(&mut self) -> String {
+        let mut name = String::new();
+        let Some((t, _)) = self.get_x_nonblank() else { return name };
+
+        if t.typ == TokenType::BeginGroup {
+            while let Some((t, _)) = self.get_x_token() {
+                match t.cs() {
+                    _ if t.typ == TokenType::EndGroup => break,
+                    Some(cs) => name += &self.cs.name(cs),
+                    None => name.push(t.char()),
+                }
+            }
+            return name;
+        }
+
+        let mut next = Some(t);
+        while let Some(t) = next {
+            match t.typ {
+                TokenType::Space => break,
+                TokenType::Escape | TokenType::Active => {
+                    self.back_input(t);
+                    break;
+                },
+                _ => name.push(t.char()),
+            }
+            next = self.get_x_token().map(|c| c.0);
+        }
+
+        name
+    }
+
+    pub(super) fn scan_four_bit_int(&mut self) -> u8 {
+        let n = self.scan_int();
+        u8::try_from(n).ok().filter(|n| *n < 16).unwrap_or_else(|| {
+            self.error(ErrorKind::BadNumber(n));
+            0
+        })
+    }
+
+    pub(super) fn open_or_close_in(&mut self, p: Primitive) {
+        let n = self.scan_four_bit_int();
+        self.read_files.remove(&n);
+
+        if p == Primitive::OpenIn {
+            self.scan_optional_equals();
+            let name = self.scan_file_name();
+            if !self.limits.file_access.can_read() {
+                self.error(ErrorKind::FileAccessDenied(name));
+                return;
+            }
+            if let Some(file) = self.resolver.resolve(&name) {
+                let stream = self.add_file(file);
+                self.read_files.insert(n, stream);
+            }
+        }
+    }
+
+    /// Reads a line from stream `n` as `\read` does, continuing on the next lines until braces
+    /// are balanced. Streams that are not open are read from the terminal, showing `prompt`.
+    pub(super) fn read_toks(&mut self, n: i32, prompt: &str) -> Vec<Token> {
+        let mut toks = Vec::new();
+        let mut depth = 0;
+
+        loop {
+            let (file, line) = match u8::try_from(n).ok().and_then(|n| self.read_files.get_mut(&n)) {
+                Some((file, lines)) => (Some(*file), lines.next()),
+                None => match self.terminal_line(if n < 0 { "" } else { prompt }) {
+                    Some(line) => (None, Some(line)),
+                    None => {
+                        self.error(ErrorKind::CannotReadTerminal);
+                        return toks;
+                    },
+                },
+            };
+
+            let Some(line) = line else {
+                self.read_files.remove(&(n as u8));
+                if depth > 0 {
+                    self.error(ErrorKind::FileEndedWithinRead);
+                    return toks;
+                }
+
+                let mut par = Token::new_cs(self.cs.intern("par"), 0..0);
+                par.file = file;
+                toks.push(par);
+                return toks;
+            };
+
+            let start = line.start();
+            let mut lexer = self.new_lexer(core::iter::once(line), file);
+            lexer.set_offset(start);
+
+            for t in lexer.by_ref() {
+                match t.typ {
+                    TokenType::BeginGroup => depth += 1,
+                    TokenType::EndGroup if depth == 0 => continue,
+                    TokenType::EndGroup => depth -= 1,
+                    _ => {},
+                }
+                toks.push(t);
+            }
+            for d in lexer.diagnostics {
+                self.error_at(d.kind, d.span);
+            }
+
+            if depth == 0 {
+                return toks;
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::rc::Rc;
+
+use crate::error::{Diagnostic, ErrorKind, Severity};
+use crate::lexer::{Span, Token};
+use super::input::Line;
+use super::*;
+
+/// How much the user is asked to intervene, set by `\batchmode`, `\nonstopmode`, `\scrollmode`
+/// and `\errorstopmode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InteractionMode {
+    Batch,
+    NonStop,
+    Scroll,
+    /// Stops at every error to ask the [`Terminal`] what to do
+    #[default]
+    ErrorStop,
+}
+
+/// What to do about an error, like the options TeX offers when it stops at one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// Carry on with the recovery TeX does by itself
+    Continue,
+    /// Insert text before the tokens still to be read, then carry on
+    Insert(String),
+    /// Delete the next tokens, then ask again
+    Delete(usize),
+    /// Switch to another mode, then carry on
+    SetMode(InteractionMode),
+    /// Stop processing the document
+    Quit,
+}
+
+/// Answers the questions TeX asks on the terminal, so that a host application can act as the user
+pub trait Terminal {
+    /// Decides what to do about an error, in error stop mode
+    fn error(&mut self, diagnostic: &Diagnostic) -> Response;
+
+    /// Reads a line for `\read` from a stream that is not open, showing `prompt`. `None` if there
+    /// is nothing to read.
+    fn read_line(&mut self, _prompt: &str) -> Option<String> {
+        None
+    }
+
+    /// Shows text printed by `\message`, unless in batch mode
+    fn print(&mut self, _text: &str) {}
+}
+
+impl Engine {
+    pub fn set_terminal(&mut self, terminal: Box<dyn Terminal>) {
+        self.terminal = Some(terminal);
+    }
+
+    pub fn interaction(&self) -> InteractionMode {
+        self.interaction
+    }
+
+    pub fn set_interaction(&mut self, mode: InteractionMode) {
+        self.interaction = mode;
+    }
+
+    /// Everything printed so far
+    pub fn log(&self) -> &str {
+        &self.log
+    }
+
+    /// Prints `text` to the log and terminal, starting a new line wherever `\newlinechar` is
+    pub(super) fn print(&mut self, text: &str) {
+        self.print_to(text, true);
+    }
+
+    /// Prints `text` on a line of its own, to the terminal too if `terminal` is set, as `\write`
+    /// does
+    pub(super) fn print_line(&mut self, text: &str, terminal: bool) {
+        // the log may have had lines of its own, so the two are not always at the same place
+        if !self.log.is_empty() && !self.log.ends_with('\n') {
+            self.log.push('\n');
+        }
+        if terminal && self.terminal_mid_line {
+            self.print_terminal("\n");
+        }
+        self.print_to(&(text.to_string() + "\n"), terminal);
+    }
+
+    /// Prints `text` to the log, and to the terminal if `terminal` is set
+    fn print_to(&mut self, text: &str, terminal: bool) {
+        let text = self.apply_newline_char(text);
+
+        self.log += &text;
+        if terminal {
+            self.print_terminal(&text);
+        }
+    }
+
+    fn print_terminal(&mut self, text: &str) {
+        if self.interaction == InteractionMode::Batch {
+            return;
+        }
+        if let Some(terminal) = self.terminal.as_mut() {
+            terminal.print(text);
+            if let Some(c) = text.chars().last() {
+                self.terminal_mid_line = c != '\n';
+            }
+        }
+    }
+
+    /// Replaces `\newlinechar` in `text` with line breaks
+    pub(super) fn apply_newline_char(&self, text: &str) -> String {
+        let newline = char::from_u32(self.int_param(IntParam::NewLineChar) as u32);
+        text.chars().map(|c| if Some(c) == newline { '\n' } else { c }).collect()
+    }
+
+    /// Performs `\message`, printing its expanded text separated from what came before by a space
+    pub(super) fn issue_message(&mut self) {
+        let text: String = self.scan_toks(true).iter().map(|t| self.token_string(t)).collect();
+        if !self.log.is_empty() && !self.log.ends_with('\n') {
+            self.print(" ");
+        }
+        self.print(&text);
+    }
+
+    /// Reports an error at the last token read
+    pub(super) fn error(&mut self, kind: ErrorKind) {
+        self.error_at(kind, self.span.clone());
+    }
+
+    pub(super) fn error_at(&mut self, kind: ErrorKind, span: Span) {
+        if self.halted {
+            return;
+        }
+
+        let diagnostic = Diagnostic::new(kind, span);
+        if diagnostic.kind.severity() == Severity::Warning {
+            self.diagnostics.push(diagnostic);
+            return;
+        }
+
+        self.error_count += 1;
+        if self.interaction == InteractionMode::ErrorStop {
+            self.ask_terminal(&diagnostic);
+        }
+        self.diagnostics.push(diagnostic);
+
+        if self.error_count == self.limits.max_errors && !self.halted {
+            self.diagnostics.push(Diagnostic::new(ErrorKind::TooManyErrors(self.error_count), self.span.clone()));
+            self.halted = true;
+        }
+    }
+
+    /// Stops processing because a limit on some resource has been reached, as TeX does when its
+    /// memory runs out
+    pub(super) fn overflow(&mut self, resource: &'static str, limit: usize) {
+        if !self.halted {
+            self.diagnostics.push(Diagnostic::new(ErrorKind::CapacityExceeded(resource, limit), self.span.clone()));
+            self.halted = true;
+        }
+    }
+
+    fn ask_terminal(&mut self, diagnostic: &Diagnostic) {
+        let Some(mut terminal) = self.terminal.take() else { return };
+
+        loop {
+            match terminal.error(diagnostic) {
+                Response::Continue => break,
+                Response::Insert(text) => {
+                    let toks = self.terminal_toks(text.into());
+                    self.back_list(toks.into_iter());
+                    break;
+                },
+                Response::Delete(n) => {
+                    for _ in 0..n {
+                        self.get_next();
+                    }
+                },
+                Response::SetMode(mode) => {
+                    self.interaction = mode;
+                    break;
+                },
+                Response::Quit => {
+                    self.halted = true;
+                    break;
+                },
+            }
+        }
+
+        self.terminal = Some(terminal);
+    }
+
+    /// Gets a line typed on the terminal, unless the interaction mode does not allow it
+    pub(super) fn terminal_line(&mut self, prompt: &str) -> Option<Line> {
+        if matches!(self.interaction, InteractionMode::Batch | InteractionMode::NonStop) {
+            return None;
+        }
+
+        let line = self.terminal.as_mut()?.read_line(prompt)?;
+        Some(Line::new(line.into()))
+    }
+
+    /// Converts text typed on the terminal to tokens, with the current catcodes
+    fn terminal_toks(&mut self, text: Rc<str>) -> Vec<Token> {
+        let mut lexer = self.new_lexer(core::iter::once(Line::new(text)), None);
+        // the end of the line is not part of the insertion
+        lexer.end_line_char = None;
+        lexer.collect()
+    }
+}
@@ -0,0 +1,28 @@
+use super::*;
+
+/// A subset of the LaTeX kernel, written in TeX
+const LATEX: &str = include_str!("latex.tex");
+
+impl Engine {
+    /// Runs the subset of the LaTeX kernel built into rstex as the format, which is enough for
+    /// common documents: `\documentclass`, `\newcommand` and `\newenvironment` with optional
+    /// arguments, sectioning, counters, `\label` and `\ref`, font commands, lists, footnotes,
+    /// tabulars and verbatim text. Packages are not loaded.
+    pub fn load_latex(&mut self) {
+        self.load_format(SourceFile { name: "latex.tex".into(), text: LATEX.into() });
+        self.sync_files_switch();
+    }
+
+    /// Sets `\if@filesw`, which decides whether `\begin{document}` reads and writes the `.aux`
+    /// file, to whether the limits allow writing files. Nothing is set unless the LaTeX kernel
+    /// has defined it.
+    pub(super) fn sync_files_switch(&mut self) {
+        let key = Key::Cs(self.cs.intern("if@filesw"));
+        if !matches!(self.eqtb.meaning(&key), Some(Meaning::Primitive(Primitive::IfTrue | Primitive::IfFalse))) {
+            return;
+        }
+
+        let switch = if self.limits.file_access.can_write() { Primitive::IfTrue } else { Primitive::IfFalse };
+        self.eqtb.set(key, Some(Value::Meaning(Meaning::Primitive(switch))), true);
+    }
+}
@@ -0,0 +1,130 @@
+use super::Engine;
+
+/// Bounds on the work the engine does, so that a document can not use up the host's resources.
+/// Reaching one stops processing with [`ErrorKind::CapacityExceeded`], naming the limit.
+///
+/// The defaults are meant for documents from untrusted sources: they allow any reasonable
+/// document, stop runaway ones like `\def\a{\a}\a` within a second or so, and deny all
+/// file access.
+///
+/// [`ErrorKind::CapacityExceeded`]: crate::error::ErrorKind::CapacityExceeded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The number of errors in a paragraph after which processing stops
+    pub max_errors: usize,
+    /// How many files can be open for `\input` at once
+    pub max_input_depth: usize,
+    /// How many tokens can wait to be read again, like the rest of a macro's body while the
+    /// macros it calls are expanded
+    pub max_input_stack: usize,
+    /// How deeply expansions and the scanning of internal quantities can nest, like an `\ifnum`
+    /// whose number is given by a macro whose body has another `\ifnum`, or the register number
+    /// of `\count` given by another `\count`
+    pub max_expansion_depth: usize,
+    /// How many macros and expandable primitives can be expanded in all
+    pub max_expansions: usize,
+    /// How many lists can be built at once, like a box in a paragraph in a box
+    pub max_nest: usize,
+    /// How many groups can be open at once
+    pub max_groups: usize,
+    /// How many groups and the values they restore can be saved at once
+    pub max_save: usize,
+    /// How many boxes deep a box can go
+    pub max_box_depth: usize,
+    /// How many nodes can be made in all, which bounds the memory the lists and boxes take
+    pub max_nodes: usize,
+    /// How many tokens can be stored in macros and registers or made by `\the` in all, which
+    /// bounds the memory token lists take
+    pub max_tokens: usize,
+    /// How many commands can be carried out in all, which bounds the time typesetting takes
+    pub max_steps: usize,
+    /// How many pages the document can fill
+    pub max_pages: usize,
+    /// The files `\input`, `\openin` and `\openout` can open. Formats are trusted, so the files
+    /// they read are not limited.
+    pub file_access: FileAccess,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_errors: 100,
+            max_input_depth: 15,
+            max_input_stack: 1_000_000,
+            max_expansion_depth: 200,
+            max_expansions: 5_000_000,
+            max_nest: 500,
+            max_groups: 255,
+            max_save: 100_000,
+            max_box_depth: 500,
+            max_nodes: 1_000_000,
+            max_tokens: 10_000_000,
+            max_steps: 5_000_000,
+            max_pages: 1000,
+            file_access: FileAccess::None,
+        }
+    }
+}
+
+impl Limits {
+    /// No bound on the time taken or the pages filled and files read and written freely, for
+    /// documents that can be trusted. Memory and nesting stay bounded, as they are in TeX.
+    pub fn trusted() -> Self {
+        Self {
+            max_steps: usize::MAX,
+            max_expansions: usize::MAX,
+            max_pages: usize::MAX,
+            file_access: FileAccess::ReadWrite,
+            ..Self::default()
+        }
+    }
+}
+
+/// What a document can do with files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileAccess {
+    /// No file can be opened
+    #[default]
+    None,
+    /// Files can be read with `\input` and `\openin`, but not written
+    Read,
+    ReadWrite,
+}
+
+impl FileAccess {
+    pub fn can_read(self) -> bool {
+        self != Self::None
+    }
+
+    pub fn can_write(self) -> bool {
+        self == Self::ReadWrite
+    }
+}
+
+/// How much of the limits a document has used
+#[derive(Debug, Clone, Default)]
+pub(super) struct Usage {
+    pub(super) expansion_depth: usize,
+    pub(super) expansions: usize,
+    pub(super) nodes: usize,
+    pub(super) tokens: usize,
+    pub(super) steps: usize,
+    /// The height of the main vertical list, which the pages it fills are counted by
+    pub(super) height: f32,
+}
+
+impl Engine {
+    /// Runs `f` one level deeper in the nesting of expansions and scanning, or stops if that is
+    /// deeper than the limits allow. The nesting is bounded so that the stack can not overflow.
+    pub(super) fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> Option<T> {
+        if self.usage.expansion_depth >= self.limits.max_expansion_depth {
+            self.overflow("expansion depth", self.limits.max_expansion_depth);
+            return None;
+        }
+
+        self.usage.expansion_depth += 1;
+        let result = f(self);
+        self.usage.expansion_depth -= 1;
+        Some(result)
+    }
+}
@@ -0,0 +1,1509 @@
+use crate::font::FontId;
+use crate::layout::{self, BoxContent, Node, RUNNING};
+use crate::lexer::{Span, Token, TokenType};
+use super::build::{glue_node, BoxContext, Group, List, Mode};
+use super::scan::{to_pt, to_scaled};
+use super::*;
+
+// the parameters of math symbol fonts
+const NUM1: i32 = 8;
+const NUM2: i32 = 9;
+const NUM3: i32 = 10;
+const DENOM1: i32 = 11;
+const DENOM2: i32 = 12;
+const SUP1: i32 = 13;
+const SUP2: i32 = 14;
+const SUP3: i32 = 15;
+const SUB1: i32 = 16;
+const SUB2: i32 = 17;
+const SUP_DROP: i32 = 18;
+const SUB_DROP: i32 = 19;
+const DELIM1: i32 = 20;
+const DELIM2: i32 = 21;
+const AXIS_HEIGHT: i32 = 22;
+
+// the parameters of math extension fonts
+const DEFAULT_RULE_THICKNESS: i32 = 8;
+const BIG_OP_SPACING1: i32 = 9;
+const BIG_OP_SPACING2: i32 = 10;
+const BIG_OP_SPACING3: i32 = 11;
+const BIG_OP_SPACING4: i32 = 12;
+const BIG_OP_SPACING5: i32 = 13;
+
+/// The space between atoms of each class, by the class of the atom on the left and then of the
+/// one on the right: `0` for none, `1` for a thin space except in scripts, `2` for a thin space,
+/// `3` for a medium space except in scripts and `4` for a thick space except in scripts. `*`
+/// marks pairs that can not occur, since a binary operator next to them is made ordinary.
+const SPACING: [&[u8; 8]; 8] = [
+    b"02340001",
+    b"22*40001",
+    b"33**3**3",
+    b"44*04004",
+    b"00*00000",
+    b"02340001",
+    b"11*11111",
+    b"12341011",
+];
+
+/// A math style, deciding the size of what is set in it and how high scripts go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Style {
+    /// `0` for display style, `1` for text, `2` for script and `3` for scriptscript
+    pub(super) level: u8,
+    /// Set in styles where superscripts are not raised as high, like denominators
+    cramped: bool,
+}
+
+impl Style {
+    pub(super) const DISPLAY: Self = Self { level: 0, cramped: false };
+    pub(super) const TEXT: Self = Self { level: 1, cramped: false };
+
+    /// The size of the fonts used, `0` for `\textfont`, `1` for `\scriptfont` and `2` for
+    /// `\scriptscriptfont`
+    pub(super) fn size(self) -> u8 {
+        self.level.saturating_sub(1)
+    }
+
+    pub(super) fn sup(self) -> Self {
+        Self { level: if self.level < 2 { 2 } else { 3 }, cramped: self.cramped }
+    }
+
+    pub(super) fn sub(self) -> Self {
+        Self { cramped: true, ..self.sup() }
+    }
+
+    pub(super) fn num(self) -> Self {
+        Self { level: (self.level + 1).min(3), cramped: self.cramped }
+    }
+
+    pub(super) fn denom(self) -> Self {
+        Self { cramped: true, ..self.num() }
+    }
+
+    pub(super) fn cramp(self) -> Self {
+        Self { cramped: true, ..self }
+    }
+}
+
+/// The classes of atoms that decide the spacing between them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Ord,
+    Op,
+    Bin,
+    Rel,
+    Open,
+    Close,
+    Punct,
+    Inner,
+}
+
+/// Where the limits of a large operator go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum OpLimits {
+    /// Above and below in display style, as scripts otherwise
+    Display,
+    /// Above and below, by `\limits`
+    Limits,
+    /// As scripts, by `\nolimits`
+    NoLimits,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum AtomKind {
+    Ord,
+    Op(OpLimits),
+    Bin,
+    Rel,
+    Open,
+    Close,
+    Punct,
+    Inner,
+    /// `\radical`, with its delimiter
+    Radical(Delimiter),
+    /// `\underline`
+    Under,
+    /// `\overline`
+    Over,
+    /// `\mathaccent`, with the family and character of the accent
+    Accent(u8, char),
+    /// `\vcenter`
+    VCenter,
+}
+
+impl AtomKind {
+    fn class(&self) -> Class {
+        match self {
+            Self::Op(_) => Class::Op,
+            Self::Bin => Class::Bin,
+            Self::Rel => Class::Rel,
+            Self::Open => Class::Open,
+            Self::Close => Class::Close,
+            Self::Punct => Class::Punct,
+            Self::Inner => Class::Inner,
+            _ => Class::Ord,
+        }
+    }
+}
+
+/// The nucleus, superscript or subscript of an atom
+#[derive(Debug, Clone, Default)]
+pub(super) enum Field {
+    #[default]
+    Empty,
+    /// A character of a family
+    Char(u8, char, Span),
+    Box(Node),
+    List(Vec<Noad>),
+}
+
+impl Field {
+    pub(super) fn is_empty(&self) -> bool {
+        matches!(self, Self::Empty)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Atom {
+    pub(super) kind: AtomKind,
+    pub(super) nucleus: Field,
+    pub(super) sup: Field,
+    pub(super) sub: Field,
+}
+
+impl Atom {
+    pub(super) fn new(kind: AtomKind, nucleus: Field) -> Self {
+        Self { kind, nucleus, sup: Field::Empty, sub: Field::Empty }
+    }
+}
+
+/// A generalized fraction, made by `\over`, `\atop`, `\above` or their forms with delimiters
+#[derive(Debug, Clone)]
+pub(super) struct Fraction {
+    pub(super) num: Vec<Noad>,
+    pub(super) denom: Vec<Noad>,
+    /// The thickness of the rule, `None` for the default rule thickness of the extension font
+    pub(super) thickness: Option<f32>,
+    pub(super) left: Delimiter,
+    pub(super) right: Delimiter,
+}
+
+/// A delimiter, as the family and character of its small and large variants. Either can be
+/// missing, and a delimiter without either is null.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(super) struct Delimiter {
+    pub(super) small: Option<(u8, char)>,
+    pub(super) large: Option<(u8, char)>,
+}
+
+/// An item of a math list
+#[derive(Debug, Clone)]
+pub(super) enum Noad {
+    Atom(Atom),
+    Fraction(Box<Fraction>),
+    /// `\left`
+    Left(Delimiter),
+    /// `\right`
+    Right(Delimiter),
+    /// `\displaystyle` and the like
+    Style(Style),
+    /// `\mskip`, in math units
+    MuGlue(Glue),
+    /// `\mkern`, in math units
+    MuKern(i32),
+    /// Glue, kerns, penalties and rules, which are passed to the horizontal list as they are
+    Node(Node),
+}
+
+/// What a math group was started by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum MathGroup {
+    /// `$` or `$$`
+    Shift,
+    /// `\eqno`, or `\leqno` if the flag is set
+    EqNo(bool),
+    /// A `{` that starts a field of the last atom
+    Field(Target),
+    /// `\left`
+    Left,
+    /// A formula typeset by itself, which only the end of the input closes
+    Formula,
+}
+
+/// The field of an atom that a subformula is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Target {
+    Nucleus,
+    Sup,
+    Sub,
+}
+
+/// An item of a math list as it is converted to a horizontal list
+enum Item {
+    Atom(Class, Vec<Node>),
+    /// A `\left` or `\right`, which is made once the height of the whole list is known
+    Delimiter(Class, Delimiter),
+    Style(Style),
+    Node(Node),
+}
+
+impl Engine {
+    /// Acts on a command in math mode, returning `false` if it acts as it does in other modes
+    pub(super) fn math_control(&mut self, tok: &Token, meaning: &Meaning) -> bool {
+        let mode = self.mode();
+        match *meaning {
+            Meaning::Char(TokenType::Letter | TokenType::Other, _)
+                | Meaning::CharDef(_)
+                | Meaning::MathCharDef(_)
+                | Meaning::Primitive(Primitive::Char | Primitive::MathChar | Primitive::Delimiter) => {
+                if let Some((code, c)) = self.scan_math_code(meaning) {
+                    self.set_math_char(code, c, tok);
+                }
+            },
+            Meaning::Char(TokenType::BeginGroup, _) => {
+                self.list().math.push(Noad::Atom(Atom::new(AtomKind::Ord, Field::Empty)));
+                self.push_math(MathGroup::Field(Target::Nucleus), Mode::Math);
+            },
+            Meaning::Char(TokenType::Superscript | TokenType::Subscript, _) => {
+                self.sub_sup(tok.typ == TokenType::Subscript);
+            },
+            Meaning::Char(TokenType::MathShift, _) => match self.groups.last() {
+                Some(Group::Math(MathGroup::Shift | MathGroup::EqNo(_))) => self.after_math(),
+                _ => {
+                    self.back_input(tok.clone());
+                    self.off_save();
+                },
+            },
+            Meaning::Char(TokenType::Space, _) => {},
+            Meaning::Primitive(p @ (Primitive::MathOrd | Primitive::MathOp | Primitive::MathBin | Primitive::MathRel))
+                | Meaning::Primitive(p @ (Primitive::MathOpen | Primitive::MathClose | Primitive::MathPunct | Primitive::MathInner))
+                | Meaning::Primitive(p @ (Primitive::Underline | Primitive::Overline)) => {
+                let kind = match p {
+                    Primitive::MathOrd => AtomKind::Ord,
+                    Primitive::MathOp => AtomKind::Op(OpLimits::Display),
+                    Primitive::MathBin => AtomKind::Bin,
+                    Primitive::MathRel => AtomKind::Rel,
+                    Primitive::MathOpen => AtomKind::Open,
+                    Primitive::MathClose => AtomKind::Close,
+                    Primitive::MathPunct => AtomKind::Punct,
+                    Primitive::MathInner => AtomKind::Inner,
+                    Primitive::Underline => AtomKind::Under,
+                    _ => AtomKind::Over,
+                };
+                self.list().math.push(Noad::Atom(Atom::new(kind, Field::Empty)));
+                self.scan_math(Target::Nucleus);
+            },
+            Meaning::Primitive(p @ (Primitive::Limits | Primitive::NoLimits | Primitive::DisplayLimits)) => {
+                match self.list().math.last_mut() {
+                    Some(Noad::Atom(Atom { kind: AtomKind::Op(limits), .. })) => {
+                        *limits = match p {
+                            Primitive::Limits => OpLimits::Limits,
+                            Primitive::NoLimits => OpLimits::NoLimits,
+                            _ => OpLimits::Display,
+                        };
+                    },
+                    _ => self.error(ErrorKind::LimitControls),
+                }
+            },
+            Meaning::Primitive(Primitive::Radical) => {
+                let d = self.scan_delimiter(true);
+                self.list().math.push(Noad::Atom(Atom::new(AtomKind::Radical(d), Field::Empty)));
+                self.scan_math(Target::Nucleus);
+            },
+            Meaning::Primitive(p @ (Primitive::MathAccent | Primitive::Accent)) => {
+                if p == Primitive::Accent {
+                    self.error(ErrorKind::AccentInMathMode);
+                }
+                let code = self.scan_math_char_num();
+                let (_, fam, c) = self.decode_math_code(code);
+                self.list().math.push(Noad::Atom(Atom::new(AtomKind::Accent(fam, c), Field::Empty)));
+                self.scan_math(Target::Nucleus);
+            },
+            Meaning::Primitive(Primitive::VCenter) => self.begin_box(Primitive::VCenter, BoxContext::Append, tok.span()),
+            Meaning::Primitive(p @ (Primitive::DisplayStyle | Primitive::TextStyle | Primitive::ScriptStyle | Primitive::ScriptScriptStyle)) => {
+                let level = match p {
+                    Primitive::DisplayStyle => 0,
+                    Primitive::TextStyle => 1,
+                    Primitive::ScriptStyle => 2,
+                    _ => 3,
+                };
+                self.list().math.push(Noad::Style(Style { level, cramped: false }));
+            },
+            Meaning::Primitive(Primitive::MKern) => {
+                let k = self.scan_mu_dimen();
+                self.list().math.push(Noad::MuKern(k));
+            },
+            Meaning::Primitive(Primitive::MSkip) => {
+                let g = self.scan_mu_glue();
+                self.list().math.push(Noad::MuGlue(g));
+            },
+            Meaning::Primitive(p @ (Primitive::Over | Primitive::Atop | Primitive::Above))
+                | Meaning::Primitive(p @ (Primitive::OverWithDelims | Primitive::AtopWithDelims | Primitive::AboveWithDelims)) => {
+                self.math_fraction(p);
+            },
+            Meaning::Primitive(p @ (Primitive::Left | Primitive::Right)) => self.math_left_right(p, tok),
+            Meaning::Primitive(p @ (Primitive::EqNo | Primitive::LEqNo)) if mode == Mode::DisplayMath => {
+                match self.groups.last() {
+                    Some(Group::Math(MathGroup::Shift)) => {
+                        self.push_math(MathGroup::EqNo(p == Primitive::LEqNo), Mode::Math);
+                        self.assign(Key::Int(IntParam::Fam), Some(Value::Int(-1)), false);
+                        let toks = self.eqtb.toks(&Key::ToksParam(ToksParam::EveryMath));
+                        self.back_list(toks.iter().cloned());
+                    },
+                    _ => {
+                        self.back_input(tok.clone());
+                        self.off_save();
+                    },
+                }
+            },
+            Meaning::Primitive(Primitive::HAlign) if mode == Mode::DisplayMath => {
+                match self.groups.last() {
+                    Some(Group::Math(MathGroup::Shift)) => {
+                        if !self.list().math.is_empty() {
+                            self.error(ErrorKind::ImproperHalign);
+                            self.list().math.clear();
+                        }
+                        self.init_align(true);
+                    },
+                    _ => {
+                        self.back_input(tok.clone());
+                        self.off_save();
+                    },
+                }
+            },
+            Meaning::Primitive(Primitive::Par | Primitive::End | Primitive::Dump | Primitive::EndTemplate)
+                | Meaning::Primitive(Primitive::VSkip | Primitive::VFil | Primitive::VFill | Primitive::VSs | Primitive::VFilNeg)
+                | Meaning::Primitive(Primitive::HRule | Primitive::UnVBox | Primitive::UnVCopy) => {
+                // a formula typeset by itself has no `$` to end it
+                let formula = self.groups.iter().rev().find_map(|g| match g {
+                    Group::Math(g @ (MathGroup::Shift | MathGroup::EqNo(_) | MathGroup::Formula)) => Some(*g),
+                    _ => None,
+                });
+                if formula == Some(MathGroup::Formula) {
+                    self.error(ErrorKind::CantUseInMode(self.meaning_string(Some(meaning)), mode.name()));
+                } else {
+                    self.insert_dollar_sign(tok.clone());
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Moves the nodes appended to a math list, like glue and boxes, to its formula. Boxes
+    /// become ordinary atoms.
+    pub(super) fn sweep_math(&mut self) {
+        if !self.mode().is_math() {
+            return;
+        }
+        let list = self.list();
+        for n in core::mem::take(&mut list.nodes) {
+            list.math.push(match n {
+                Node::Box { content: BoxContent::HBox(_) | BoxContent::VBox(_), .. } => {
+                    Noad::Atom(Atom::new(AtomKind::Ord, Field::Box(n)))
+                },
+                n => Noad::Node(n),
+            });
+        }
+    }
+
+    /// The math code given by a character or by `\char`, `\mathchar`, `\delimiter` or a
+    /// `\mathchardef` command, scanning its number if it has one, along with the character if
+    /// there is one. `None` for other commands.
+    fn scan_math_code(&mut self, m: &Meaning) -> Option<(i32, Option<char>)> {
+        Some(match *m {
+            Meaning::Char(TokenType::Letter | TokenType::Other, c) | Meaning::CharDef(c) => {
+                (self.eqtb.int(&Key::MathCode(c)), Some(c))
+            },
+            Meaning::Primitive(Primitive::Char) => {
+                let c = self.scan_char_code();
+                (self.eqtb.int(&Key::MathCode(c)), Some(c))
+            },
+            Meaning::MathCharDef(n) => (n, None),
+            Meaning::Primitive(Primitive::MathChar) => (self.scan_math_char_num(), None),
+            // the class and small variant of a delimiter
+            Meaning::Primitive(Primitive::Delimiter) => match self.eqtb.encoding {
+                Encoding::Classic => ((self.scan_int() >> 12) & 0x7FFF, None),
+                Encoding::Unicode => (self.scan_int(), None),
+            },
+            _ => return None,
+        })
+    }
+
+    /// Scans the number of a math character, as for `\mathchar`
+    fn scan_math_char_num(&mut self) -> i32 {
+        let n = self.scan_int();
+        let max = match self.eqtb.encoding {
+            Encoding::Classic => 0x7FFF,
+            Encoding::Unicode => i32::MAX - 1,
+        };
+        if !(0..=max).contains(&n) {
+            self.error(ErrorKind::BadMathChar(n));
+            return 0;
+        }
+        n
+    }
+
+    /// Whether a math code makes a character act like an active character
+    fn is_active_math_code(&self, code: i32) -> bool {
+        match self.eqtb.encoding {
+            Encoding::Classic => code == 0x8000,
+            Encoding::Unicode => code == i32::MAX,
+        }
+    }
+
+    /// Splits a math code into its class, family and character. Characters of class 7 are
+    /// ordinary, in family `\fam` if that is a valid family.
+    fn decode_math_code(&self, code: i32) -> (u8, u8, char) {
+        let (class, fam, c) = match self.eqtb.encoding {
+            Encoding::Classic => ((code >> 12) & 7, (code >> 8) & 0xF, code & 0xFF),
+            Encoding::Unicode => ((code >> 28) & 7, (code >> 21) & 0x7F, code & 0x1FFFFF),
+        };
+        let cur_fam = self.int_param(IntParam::Fam);
+        let (class, fam) = match class {
+            7 if (0..16).contains(&cur_fam) => (0, cur_fam),
+            7 => (0, fam),
+            _ => (class, fam),
+        };
+        (class as u8, fam as u8, char::from_u32(c as u32).unwrap_or('\0'))
+    }
+
+    /// Appends an atom for a math character, or reads an active character instead of `c` if its
+    /// math code says so
+    fn set_math_char(&mut self, code: i32, c: Option<char>, tok: &Token) {
+        if self.is_active_math_code(code) {
+            if let Some(c) = c {
+                self.back_input(Token::new_char(TokenType::Active, c, 0..0).at(&tok.span()));
+            }
+            return;
+        }
+
+        let (class, fam, c) = self.decode_math_code(code);
+        let kind = match class {
+            1 => AtomKind::Op(OpLimits::Display),
+            2 => AtomKind::Bin,
+            3 => AtomKind::Rel,
+            4 => AtomKind::Open,
+            5 => AtomKind::Close,
+            6 => AtomKind::Punct,
+            _ => AtomKind::Ord,
+        };
+        self.list().math.push(Noad::Atom(Atom::new(kind, Field::Char(fam, c, tok.span()))));
+    }
+
+    /// Scans a math character or a subformula in braces for a field of the last atom
+    fn scan_math(&mut self, target: Target) {
+        let (code, tok) = loop {
+            let Some((t, m)) = self.get_x_nonblank_nonrelax() else { return };
+            let Some((code, c)) = m.as_ref().and_then(|m| self.scan_math_code(m)) else {
+                self.back_input(t);
+                self.scan_left_brace();
+                self.push_math(MathGroup::Field(target), Mode::Math);
+                return;
+            };
+            match c {
+                Some(c) if self.is_active_math_code(code) => {
+                    self.back_input(Token::new_char(TokenType::Active, c, 0..0).at(&t.span()));
+                },
+                _ => break (code, t),
+            }
+        };
+
+        let (_, fam, c) = self.decode_math_code(code);
+        self.set_field(target, Field::Char(fam, c, tok.span()));
+    }
+
+    /// Sets a field of the last atom of the current list
+    fn set_field(&mut self, target: Target, field: Field) {
+        if let Some(Noad::Atom(atom)) = self.list().math.last_mut() {
+            *match target {
+                Target::Nucleus => &mut atom.nucleus,
+                Target::Sup => &mut atom.sup,
+                Target::Sub => &mut atom.sub,
+            } = field;
+        }
+    }
+
+    /// Scans a superscript or subscript for the last atom, or for an empty one if the last atom
+    /// already has one or there is none
+    fn sub_sup(&mut self, sub: bool) {
+        let target = if sub { Target::Sub } else { Target::Sup };
+        let taken = match self.list().math.last() {
+            Some(Noad::Atom(atom)) => Some(!if sub { &atom.sub } else { &atom.sup }.is_empty()),
+            _ => None,
+        };
+        if taken != Some(false) {
+            self.list().math.push(Noad::Atom(Atom::new(AtomKind::Ord, Field::Empty)));
+            if taken == Some(true) {
+                self.error(ErrorKind::DoubleScript(if sub { "subscript" } else { "superscript" }));
+            }
+        }
+        self.scan_math(target);
+    }
+
+    /// Scans a delimiter, as a character with a `\delcode` or by `\delimiter`, or just its code
+    /// if `radical` is set
+    fn scan_delimiter(&mut self, radical: bool) -> Delimiter {
+        let (code, tok) = if radical {
+            (self.scan_int(), None)
+        } else {
+            match self.get_x_nonblank_nonrelax() {
+                Some((t, Some(Meaning::Char(TokenType::Letter | TokenType::Other, c)))) => (self.eqtb.int(&Key::DelCode(c)), Some(t)),
+                Some((_, Some(Meaning::Primitive(Primitive::Delimiter)))) => (self.scan_int(), None),
+                t => (-1, t.map(|t| t.0)),
+            }
+        };
+        if code < 0 {
+            self.error(ErrorKind::MissingDelimiter);
+            if let Some(t) = tok {
+                self.back_input(t);
+            }
+            return Delimiter::default();
+        }
+
+        let variant = |fam: i32, c: i32| (fam != 0 || c != 0).then(|| (fam as u8, char::from_u32(c as u32).unwrap_or('\0')));
+        match self.eqtb.encoding {
+            Encoding::Classic => Delimiter {
+                small: variant((code >> 20) & 0xF, (code >> 12) & 0xFF),
+                large: variant((code >> 8) & 0xF, code & 0xFF),
+            },
+            Encoding::Unicode => Delimiter { small: variant((code >> 21) & 0x7F, code & 0x1FFFFF), large: None },
+        }
+    }
+
+    /// Starts a fraction with everything so far in the current list as its numerator
+    fn math_fraction(&mut self, p: Primitive) {
+        let delims = matches!(p, Primitive::OverWithDelims | Primitive::AtopWithDelims | Primitive::AboveWithDelims);
+        let (left, right) = match delims {
+            true => (self.scan_delimiter(false), self.scan_delimiter(false)),
+            false => (Delimiter::default(), Delimiter::default()),
+        };
+        let thickness = match p {
+            Primitive::Above | Primitive::AboveWithDelims => Some(to_pt(self.scan_dimen())),
+            Primitive::Atop | Primitive::AtopWithDelims => Some(0.0),
+            _ => None,
+        };
+
+        // a second fraction in the same list is ignored
+        if self.list().incompleat.is_some() {
+            self.error(ErrorKind::AmbiguousFraction);
+            return;
+        }
+        let list = self.list();
+        let num = core::mem::take(&mut list.math);
+        list.incompleat = Some(Fraction { num, denom: Vec::new(), thickness, left, right });
+    }
+
+    /// Performs `\left` or `\right`
+    fn math_left_right(&mut self, p: Primitive, tok: &Token) {
+        if p == Primitive::Right && !matches!(self.groups.last(), Some(Group::Math(MathGroup::Left))) {
+            if matches!(self.groups.last(), Some(Group::Math(MathGroup::Shift | MathGroup::EqNo(_) | MathGroup::Formula))) {
+                self.scan_delimiter(false);
+                self.error(ErrorKind::ExtraRight);
+            } else {
+                self.back_input(tok.clone());
+                self.off_save();
+            }
+            return;
+        }
+
+        let d = self.scan_delimiter(false);
+        if p == Primitive::Left {
+            self.push_math(MathGroup::Left, Mode::Math);
+            self.list().math.push(Noad::Left(d));
+        } else {
+            self.finish_left_right(d);
+        }
+    }
+
+    /// Ends the formula started by `\left` with the delimiter `d`, making it an inner atom
+    pub(super) fn finish_left_right(&mut self, d: Delimiter) {
+        self.groups.pop();
+        let list = self.nest.pop().unwrap();
+        let mlist = fin_mlist(list, Some(Noad::Right(d)));
+        self.end_group();
+        self.list().math.push(Noad::Atom(Atom::new(AtomKind::Inner, Field::List(mlist))));
+    }
+
+    fn push_math(&mut self, group: MathGroup, mode: Mode) {
+        self.push_nest(List::new(mode));
+        self.begin_group();
+        self.groups.push(Group::Math(group));
+    }
+
+    /// Ends a subformula at its `}`, making it the field of the atom it is for
+    pub(super) fn end_math_group(&mut self, target: Target) {
+        self.end_group();
+        let list = self.nest.pop().unwrap();
+        let mut mlist = fin_mlist(list, None);
+        // a single ordinary atom without scripts is as good as its nucleus
+        let field = match mlist.as_mut_slice() {
+            [Noad::Atom(atom)] if atom.kind == AtomKind::Ord && atom.sup.is_empty() && atom.sub.is_empty() => {
+                core::mem::take(&mut atom.nucleus)
+            },
+            _ => Field::List(mlist),
+        };
+        self.set_field(target, field);
+    }
+
+    /// Starts a formula at `$`, or a display at `$$` in a paragraph
+    pub(super) fn init_math(&mut self) {
+        let next = self.get_next();
+        let display = self.mode() == Mode::Horizontal && next.as_ref().is_some_and(|(t, noexpand)| {
+            !noexpand && matches!(self.meaning(t), Some(Meaning::Char(TokenType::MathShift, _)))
+        });
+        if !display {
+            if let Some((t, noexpand)) = next {
+                self.reread(t, noexpand);
+            }
+            self.push_math(MathGroup::Shift, Mode::Math);
+            self.assign(Key::Int(IntParam::Fam), Some(Value::Int(-1)), false);
+            let toks = self.eqtb.toks(&Key::ToksParam(ToksParam::EveryMath));
+            self.back_list(toks.iter().cloned());
+            return;
+        }
+
+        // the paragraph so far is broken into lines, and the display is short if it clears the
+        // end of the last of them
+        let outer = self.nest.len() - 2;
+        let before = self.nest[outer].nodes.len();
+        self.end_paragraph();
+        let mut size = -to_pt(0x3FFFFFFF);
+        let line = self.nest[outer].nodes.iter().skip(before).rev().find(|n| matches!(n, Node::Box { .. }));
+        if let Some(Node::Box { content: BoxContent::HBox(list), size: [width, ..], shift, .. }) = line {
+            let set = layout::glue_set(list, |n| n.size()[0], *width);
+            let mut x = *shift;
+            for n in list {
+                x += set.apply(n);
+                if matches!(n, Node::Box { .. }) {
+                    size = x;
+                }
+            }
+            if size > -to_pt(0x3FFFFFFF) {
+                size += 2.0 * self.cur_font().params.quad;
+            }
+        }
+
+        self.push_math(MathGroup::Shift, Mode::DisplayMath);
+        let hsize = self.eqtb.int(&Key::DimenParam(DimenParam::HSize));
+        self.assign(Key::Int(IntParam::Fam), Some(Value::Int(-1)), false);
+        self.assign(Key::DimenParam(DimenParam::PreDisplaySize), Some(Value::Int(to_scaled(size))), false);
+        self.assign(Key::DimenParam(DimenParam::DisplayWidth), Some(Value::Int(hsize)), false);
+        self.assign(Key::DimenParam(DimenParam::DisplayIndent), Some(Value::Int(0)), false);
+        let toks = self.eqtb.toks(&Key::ToksParam(ToksParam::EveryDisplay));
+        self.back_list(toks.iter().cloned());
+    }
+
+    /// Typesets the main file as a formula by itself, as if it were between `$` signs, in display
+    /// style if `display` is set. It is rendered as the math renderer says: as HTML that sits on
+    /// the baseline of the text around it, styled by [`crate::MATH_STYLE`], or as MathML.
+    pub fn render_formula(&mut self, display: bool) -> String {
+        self.push_math(MathGroup::Formula, Mode::Math);
+        self.assign(Key::Int(IntParam::Fam), Some(Value::Int(-1)), false);
+        let toks = self.eqtb.toks(&Key::ToksParam(ToksParam::EveryMath));
+        self.back_list(toks.iter().cloned());
+        while let Some((tok, meaning)) = self.get_x_token() {
+            if !self.main_control(tok, meaning) {
+                break;
+            }
+        }
+        while !matches!(self.groups.last(), Some(Group::Math(MathGroup::Formula)) | None) {
+            self.off_save();
+        }
+
+        let danger = self.check_math_fonts();
+        let mut mlist = fin_mlist(self.nest.pop().unwrap(), None);
+        self.groups.pop();
+        self.end_group();
+        if danger {
+            mlist.clear();
+        }
+
+        let style = if display { Style::DISPLAY } else { Style::TEXT };
+        match self.math {
+            MathRenderer::MathMl => self.mathml(&mlist, style),
+            MathRenderer::Html => {
+                let nodes = self.mlist_to_hlist(mlist, style, false);
+                let span = layout::span_of(&nodes);
+                let b = layout::hpack(nodes, None, span);
+                crate::html::render_formula(&self.document(vec![b]), display)
+            },
+        }
+    }
+
+    /// Reports a formula that can not be set because the symbol or extension fonts are missing
+    /// or have too few parameters, returning `true` if it can not
+    fn check_math_fonts(&mut self) -> bool {
+        let short = |e: &Self, fam: u8, n: i32| (0..3).any(|size| e.font(e.math_font(fam, size)).params.count() < n);
+        let kind = if short(self, 2, 22) {
+            "symbol"
+        } else if short(self, 3, 13) {
+            "extension"
+        } else {
+            return false;
+        };
+        self.error(ErrorKind::InsufficientFonts(kind));
+        true
+    }
+
+    /// Ends a formula or display at its closing `$`, or an equation number
+    pub(super) fn after_math(&mut self) {
+        let mut danger = self.check_math_fonts();
+        let mut mode = self.mode();
+        let mut mlist = fin_mlist(self.nest.pop().unwrap(), None);
+
+        let mut eqno = None;
+        if let Some(&Group::Math(MathGroup::EqNo(leqno))) = self.groups.last() {
+            self.expect_dollar();
+            if danger {
+                mlist.clear();
+            }
+            let nodes = self.mlist_to_hlist(mlist, Style::TEXT, false);
+            let span = layout::span_of(&nodes);
+            eqno = Some((layout::hpack(nodes, None, span), leqno));
+            self.groups.pop();
+            self.end_group();
+            danger = self.check_math_fonts();
+            mode = self.mode();
+            mlist = fin_mlist(self.nest.pop().unwrap(), None);
+        }
+        if danger {
+            mlist.clear();
+        }
+
+        if mode == Mode::Math {
+            let penalties = self.mode() == Mode::Horizontal;
+            let nodes = self.mlist_to_hlist(mlist, Style::TEXT, penalties);
+            let surround = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::MathSurround)));
+            self.use_nodes(nodes.len());
+            let list = self.list();
+            if surround != 0.0 {
+                list.nodes.push(Node::Kern(surround));
+            }
+            list.nodes.extend(nodes);
+            if surround != 0.0 {
+                list.nodes.push(Node::Kern(surround));
+            }
+            list.space_factor = 1000;
+            self.groups.pop();
+            self.end_group();
+            return;
+        }
+
+        if eqno.is_none() {
+            self.expect_dollar();
+        }
+        let nodes = self.mlist_to_hlist(mlist, Style::DISPLAY, false);
+        self.finish_display(nodes, eqno.filter(|_| !danger));
+    }
+
+    /// Appends a display to the enclosing vertical list, centred in `\displaywidth` with its
+    /// equation number, as TeX places them
+    fn finish_display(&mut self, nodes: Vec<Node>, eqno: Option<(Node, bool)>) {
+        let span = layout::span_of(&nodes);
+        let mut b = layout::hpack(nodes.clone(), None, span.clone());
+        let mut w = b.size()[0];
+        let z = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::DisplayWidth)));
+        let s = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::DisplayIndent)));
+        let p = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::PreDisplaySize)));
+        let leqno = eqno.as_ref().is_some_and(|(_, l)| *l);
+        let (mut e, q) = match &eqno {
+            Some((a, _)) => (a.size()[0], a.size()[0] + self.math_sy(6, 0)),
+            None => (0.0, 0.0),
+        };
+
+        // an equation too wide for the line is squeezed, and its number goes on a line of its own
+        // if it has to
+        if w + q > z {
+            let shrink: f32 = nodes.iter().map(|n| match n {
+                Node::Glue { shrinkability, shrink_order: 0, .. } => *shrinkability,
+                _ => 0.0,
+            }).sum();
+            if e != 0.0 && w - shrink + q <= z {
+                b = layout::hpack(nodes, Some(z - q), span);
+            } else {
+                e = 0.0;
+                if w > z {
+                    b = layout::hpack(nodes, Some(z), span);
+                }
+            }
+            w = b.size()[0];
+        }
+
+        let mut d = (z - w) / 2.0;
+        if e > 0.0 && d < 2.0 * e {
+            d = (z - w - e) / 2.0;
+        }
+
+        self.tail_append(Node::Penalty(self.int_param(IntParam::PreDisplayPenalty)));
+        let (above, mut below) = if d + s <= p || leqno {
+            (GlueParam::AboveDisplaySkip, Some(GlueParam::BelowDisplaySkip))
+        } else {
+            (GlueParam::AboveDisplayShortSkip, Some(GlueParam::BelowDisplayShortSkip))
+        };
+        let mut eqno = eqno.map(|(a, _)| a);
+        if leqno && e == 0.0 {
+            if let Some(a) = eqno.take() {
+                self.append_box(with_shift(a, s));
+                self.tail_append(Node::Penalty(10000));
+            }
+        } else {
+            self.tail_append(glue_node(self.eqtb.glue(&Key::GlueParam(above))));
+        }
+
+        if e != 0.0 {
+            if let Some(a) = eqno.take() {
+                let r = Node::Kern(z - w - e - d);
+                let list = if leqno {
+                    d = 0.0;
+                    vec![a, r, b]
+                } else {
+                    vec![b, r, a]
+                };
+                let span = layout::span_of(&list);
+                b = layout::hpack(list, None, span);
+            }
+        }
+        self.append_box(with_shift(b, s + d));
+
+        if let Some(a) = eqno {
+            let wa = a.size()[0];
+            self.tail_append(Node::Penalty(10000));
+            self.append_box(with_shift(a, s + z - wa));
+            below = None;
+        }
+        self.tail_append(Node::Penalty(self.int_param(IntParam::PostDisplayPenalty)));
+        if let Some(below) = below {
+            self.tail_append(glue_node(self.eqtb.glue(&Key::GlueParam(below))));
+        }
+        self.resume_after_display();
+    }
+
+    /// Goes back to the paragraph after a display
+    pub(super) fn resume_after_display(&mut self) {
+        self.groups.pop();
+        self.end_group();
+        self.push_nest(List::new(Mode::Horizontal));
+        self.scan_optional_space();
+    }
+
+    /// Reads the second `$` that ends a display
+    pub(super) fn expect_dollar(&mut self) {
+        match self.get_x_token() {
+            Some((_, Some(Meaning::Char(TokenType::MathShift, _)))) => {},
+            t => {
+                self.error(ErrorKind::DisplayMathShouldEnd);
+                if let Some((t, _)) = t {
+                    self.back_input(t);
+                }
+            },
+        }
+    }
+
+    pub(super) fn math_font(&self, fam: u8, size: u8) -> FontId {
+        self.eqtb.font(&Key::MathFont(size, fam))
+    }
+
+    /// Parameter `n` of the symbol font, family 2, at `size`
+    fn math_sy(&self, n: i32, size: u8) -> f32 {
+        self.font(self.math_font(2, size)).params.get(n).unwrap_or(0.0)
+    }
+
+    /// Parameter `n` of the extension font, family 3, at `size`
+    fn math_ex(&self, n: i32, size: u8) -> f32 {
+        self.font(self.math_font(3, size)).params.get(n).unwrap_or(0.0)
+    }
+
+    /// Converts glue in math units to glue in points for `style`, where a math unit is an 18th
+    /// of the quad of the symbol font
+    fn math_glue(&self, g: Glue, style: Style) -> Node {
+        let mu = self.math_sy(6, style.size()) / 18.0;
+        let scale = |x: i32, order: u8| if order == 0 { to_pt(x) * mu } else { to_pt(x) };
+        Node::Glue {
+            nat_size: to_pt(g.width) * mu,
+            stretchability: scale(g.stretch, g.stretch_order),
+            shrinkability: scale(g.shrink, g.shrink_order),
+            stretch_order: g.stretch_order,
+            shrink_order: g.shrink_order,
+        }
+    }
+
+    /// Font `f` at `size` points, rounded up to half a point, loading it at that size if it has
+    /// not been
+    fn font_at(&mut self, f: FontId, size: f32) -> FontId {
+        let size = (size * 2.0).ceil() / 2.0;
+        let font = self.font(f);
+        if font.size == size {
+            return f;
+        }
+        if let Some(i) = self.fonts.iter().position(|(g, _)| g.name == font.name && g.size == size) {
+            return FontId(i as u32);
+        }
+        let scaled = font.at_size(size);
+        let cs = self.cs.intern(&format!("FONT{}", font.name));
+        self.fonts.push((Rc::new(scaled), cs));
+        FontId(self.fonts.len() as u32 - 1)
+    }
+
+    /// Converts a math list to a horizontal list in `style`, adding the penalties for breaking
+    /// after binary operators and relations if `penalties` is set, as for a formula in a
+    /// paragraph
+    fn mlist_to_hlist(&mut self, mlist: Vec<Noad>, style: Style, penalties: bool) -> Vec<Node> {
+        // first the atoms are made into boxes, noting the height and depth of the whole list
+        let mut items = Vec::new();
+        let mut cur = style;
+        let (mut max_h, mut max_d) = (0.0f32, 0.0f32);
+        let mut r_type = Class::Op;
+        let mut r_index = None;
+        let demote = |items: &mut Vec<Item>, r_index: Option<usize>| {
+            if let Some(Item::Atom(class, _)) = r_index.and_then(|i| items.get_mut(i)) {
+                *class = Class::Ord;
+            }
+        };
+        for noad in mlist {
+            let (class, nodes) = match noad {
+                Noad::Style(s) => {
+                    cur = s;
+                    items.push(Item::Style(s));
+                    continue;
+                },
+                Noad::MuGlue(g) => {
+                    items.push(Item::Node(self.math_glue(g, cur)));
+                    continue;
+                },
+                Noad::MuKern(k) => {
+                    items.push(Item::Node(Node::Kern(to_pt(k) * self.math_sy(6, cur.size()) / 18.0)));
+                    continue;
+                },
+                Noad::Node(n) => {
+                    if let Node::Box { size: [_, h, d], .. } = n {
+                        max_h = max_h.max(h);
+                        max_d = max_d.max(d);
+                    }
+                    items.push(Item::Node(n));
+                    continue;
+                },
+                Noad::Left(d) => {
+                    r_type = Class::Open;
+                    items.push(Item::Delimiter(Class::Open, d));
+                    continue;
+                },
+                Noad::Right(d) => {
+                    if r_type == Class::Bin {
+                        demote(&mut items, r_index);
+                    }
+                    r_type = Class::Close;
+                    items.push(Item::Delimiter(Class::Close, d));
+                    continue;
+                },
+                Noad::Fraction(f) => (Class::Inner, self.make_fraction(*f, cur)),
+                Noad::Atom(atom) => {
+                    let mut class = atom.kind.class();
+                    if class == Class::Bin && matches!(r_type, Class::Bin | Class::Op | Class::Rel | Class::Open | Class::Punct) {
+                        class = Class::Ord;
+                    }
+                    if matches!(class, Class::Rel | Class::Close | Class::Punct) && r_type == Class::Bin {
+                        demote(&mut items, r_index);
+                    }
+                    (class, self.translate_atom(atom, cur))
+                },
+            };
+
+            let [_, h, d] = extent(&nodes);
+            max_h = max_h.max(h);
+            max_d = max_d.max(d);
+            r_type = class;
+            r_index = Some(items.len());
+            items.push(Item::Atom(class, nodes));
+        }
+        if r_type == Class::Bin {
+            demote(&mut items, r_index);
+        }
+
+        // then the spacing and penalties between them are added
+        let mut hlist = Vec::new();
+        let mut cur = style;
+        let mut r_type = None;
+        let mut items = items.into_iter().peekable();
+        while let Some(item) = items.next() {
+            let (class, nodes) = match item {
+                Item::Style(s) => {
+                    cur = s;
+                    continue;
+                },
+                Item::Node(n) => {
+                    hlist.push(n);
+                    continue;
+                },
+                Item::Atom(class, nodes) => (class, nodes),
+                Item::Delimiter(class, d) => (class, vec![self.make_left_right(d, style, max_h, max_d)]),
+            };
+
+            if let Some(r) = r_type {
+                if let Some(glue) = self.math_spacing(r, class, cur) {
+                    hlist.push(glue);
+                }
+            }
+            hlist.extend(nodes);
+
+            let penalty = match class {
+                Class::Bin => self.int_param(IntParam::BinOpPenalty),
+                Class::Rel => self.int_param(IntParam::RelPenalty),
+                _ => 10000,
+            };
+            let breakable = items.peek().is_some_and(|next| {
+                !matches!(next, Item::Node(Node::Penalty(_)) | Item::Atom(Class::Rel, _))
+            });
+            if penalties && penalty < 10000 && breakable {
+                hlist.push(Node::Penalty(penalty));
+            }
+            r_type = Some(class);
+        }
+        hlist
+    }
+
+    /// The glue between atoms of classes `left` and `right` in `style`, `None` if there is none
+    fn math_spacing(&self, left: Class, right: Class, style: Style) -> Option<Node> {
+        let script = style.level >= 2;
+        let param = match SPACING[left as usize][right as usize] {
+            b'1' if !script => MuGlueParam::ThinMuSkip,
+            b'2' => MuGlueParam::ThinMuSkip,
+            b'3' if !script => MuGlueParam::MedMuSkip,
+            b'4' if !script => MuGlueParam::ThickMuSkip,
+            _ => return None,
+        };
+        let g = self.eqtb.glue(&Key::MuGlueParam(param));
+        (g != Glue::default()).then(|| self.math_glue(g, style))
+    }
+
+    /// Makes an atom into a horizontal list, with its scripts attached
+    fn translate_atom(&mut self, atom: Atom, style: Style) -> Vec<Node> {
+        let Atom { kind, mut nucleus, mut sup, mut sub } = atom;
+        let mut delta = 0.0;
+        match kind {
+            AtomKind::Op(limits) => {
+                let limits = match limits {
+                    OpLimits::Display => style.level == 0,
+                    l => l == OpLimits::Limits,
+                };
+                if let Field::Char(fam, c, span) = nucleus {
+                    let (b, italic) = self.make_op_char(fam, c, span, style, !sub.is_empty() && !limits);
+                    nucleus = Field::Box(b);
+                    delta = italic;
+                }
+                if limits {
+                    return vec![self.make_limits(nucleus, sup, sub, delta, style)];
+                }
+            },
+            AtomKind::Radical(d) => nucleus = Field::Box(self.make_radical(nucleus, d, style)),
+            AtomKind::Over => {
+                let t = self.math_ex(DEFAULT_RULE_THICKNESS, style.size());
+                let x = self.clean_box(nucleus, style.cramp());
+                nucleus = Field::Box(overbar(x, 3.0 * t, t));
+            },
+            AtomKind::Under => nucleus = Field::Box(self.make_under(nucleus, style)),
+            AtomKind::Accent(fam, c) => {
+                // scripts on a single character go with it, under the accent
+                if matches!(nucleus, Field::Char(..)) && !(sup.is_empty() && sub.is_empty()) {
+                    let inner = Atom { kind: AtomKind::Ord, nucleus, sup: core::mem::take(&mut sup), sub: core::mem::take(&mut sub) };
+                    nucleus = Field::List(vec![Noad::Atom(inner)]);
+                }
+                nucleus = Field::Box(self.make_math_accent(fam, c, nucleus, style));
+            },
+            AtomKind::VCenter => {
+                if let Field::Box(Node::Box { size, .. }) = &mut nucleus {
+                    let total = size[1] + size[2];
+                    size[1] = self.math_sy(AXIS_HEIGHT, style.size()) + total / 2.0;
+                    size[2] = total - size[1];
+                }
+            },
+            _ => {},
+        }
+
+        let (mut nodes, is_char) = match nucleus {
+            Field::Char(fam, c, span) => {
+                let f = self.math_font(fam, style.size());
+                match self.new_character(c, f, span) {
+                    Some(b) => {
+                        let italic = self.font(f).metrics(c).map_or(0.0, |m| m.italic);
+                        if sub.is_empty() && italic != 0.0 {
+                            (vec![b, Node::Kern(italic)], true)
+                        } else {
+                            delta = italic;
+                            (vec![b], true)
+                        }
+                    },
+                    None => (Vec::new(), false),
+                }
+            },
+            Field::Empty => (Vec::new(), false),
+            Field::Box(b) => (vec![b], false),
+            Field::List(mlist) => (vec![hbox(self.mlist_to_hlist(mlist, style, false))], false),
+        };
+        if !(sup.is_empty() && sub.is_empty()) {
+            let scripts = self.make_scripts(&nodes, is_char, delta, sup, sub, style);
+            nodes.push(scripts);
+        }
+        nodes
+    }
+
+    /// Makes a field into a box in `style`
+    fn clean_box(&mut self, field: Field, style: Style) -> Node {
+        let mut nodes = match field {
+            Field::Empty => Vec::new(),
+            Field::Box(b) => vec![b],
+            Field::Char(..) => self.mlist_to_hlist(vec![Noad::Atom(Atom::new(AtomKind::Ord, field))], style, false),
+            Field::List(mlist) => self.mlist_to_hlist(mlist, style, false),
+        };
+        match nodes.as_slice() {
+            [Node::Box { content: BoxContent::HBox(_) | BoxContent::VBox(_), shift, .. }] if *shift == 0.0 => {
+                return nodes.pop().unwrap();
+            },
+            // the italic correction of a lone character is not needed
+            [Node::Box { content: BoxContent::Character(..), .. }, Node::Kern(_)] => {
+                nodes.pop();
+            },
+            _ => {},
+        }
+        hbox(nodes)
+    }
+
+    /// Makes the superscript and subscript of an atom whose nucleus has become `nucleus`, into a
+    /// box to go after it. `delta` is the italic correction of the nucleus, which the
+    /// superscript is moved right by.
+    fn make_scripts(&mut self, nucleus: &[Node], is_char: bool, delta: f32, sup: Field, sub: Field, style: Style) -> Node {
+        let size = style.size();
+        let (mut shift_up, mut shift_down) = if is_char {
+            (0.0, 0.0)
+        } else {
+            let [_, h, d] = extent(nucleus);
+            let t = if style.level < 2 { 1 } else { 2 };
+            (h - self.math_sy(SUP_DROP, t), d + self.math_sy(SUB_DROP, t))
+        };
+        let x_height = self.math_sy(5, size).abs();
+        let script_space = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::ScriptSpace)));
+
+        if sup.is_empty() {
+            let x = widen(self.clean_box(sub, style.sub()), script_space);
+            shift_down = shift_down.max(self.math_sy(SUB1, size)).max(x.size()[1] - x_height * 4.0 / 5.0);
+            return with_shift(x, shift_down);
+        }
+
+        let x = widen(self.clean_box(sup, style.sup()), script_space);
+        let clr = match style {
+            Style { cramped: true, .. } => SUP3,
+            Style { level: 0, .. } => SUP1,
+            _ => SUP2,
+        };
+        let [_, _, dx] = x.size();
+        shift_up = shift_up.max(self.math_sy(clr, size)).max(dx + x_height / 4.0);
+        if sub.is_empty() {
+            return with_shift(x, -shift_up);
+        }
+
+        let y = widen(self.clean_box(sub, style.sub()), script_space);
+        let [_, hy, _] = y.size();
+        shift_down = shift_down.max(self.math_sy(SUB2, size));
+        let clr = 4.0 * self.math_ex(DEFAULT_RULE_THICKNESS, size) - ((shift_up - dx) - (hy - shift_down));
+        if clr > 0.0 {
+            shift_down += clr;
+            let clr = x_height * 4.0 / 5.0 - (shift_up - dx);
+            if clr > 0.0 {
+                shift_up += clr;
+                shift_down -= clr;
+            }
+        }
+        let k = (shift_up - dx) - (hy - shift_down);
+        let list = vec![with_shift(x, delta), Node::Kern(k), y];
+        let span = layout::span_of(&list);
+        with_shift(layout::vpack(list, None, span), shift_down)
+    }
+
+    /// Makes the character of a large operator into a box centred on the axis, larger in
+    /// display style, returning it with its italic correction. The italic correction is taken
+    /// off its width if `remove_italic` is set, for an operator with a subscript next to it.
+    fn make_op_char(&mut self, fam: u8, c: char, span: Span, style: Style, remove_italic: bool) -> (Node, f32) {
+        let size = style.size();
+        let mut f = self.math_font(fam, size);
+        if style.level == 0 {
+            let font = self.font(f);
+            let total = |c| font.metrics(c).map_or(0.0, |m| m.height + m.depth);
+            if let Some(larger) = font.successor(c).filter(|_| total(c) > 0.0) {
+                let target = font.size * total(larger) / total(c);
+                f = self.font_at(f, target);
+            }
+        }
+
+        let italic = self.font(f).metrics(c).map_or(0.0, |m| m.italic);
+        let b = self.new_character(c, f, span);
+        let mut x = hbox(b.into_iter().collect());
+        let axis = self.math_sy(AXIS_HEIGHT, size);
+        if let Node::Box { size: [w, h, d], shift, .. } = &mut x {
+            if remove_italic {
+                *w -= italic;
+            }
+            *shift = (*h - *d) / 2.0 - axis;
+        }
+        (x, italic)
+    }
+
+    /// Makes a large operator with limits above and below it, the upper one moved right and the
+    /// lower one left by half of `delta`
+    fn make_limits(&mut self, nucleus: Field, sup: Field, sub: Field, delta: f32, style: Style) -> Node {
+        let size = style.size();
+        let (has_sup, has_sub) = (!sup.is_empty(), !sub.is_empty());
+        let x = self.clean_box(sup, style.sup());
+        let y = self.clean_box(nucleus, style);
+        let z = self.clean_box(sub, style.sub());
+        let w = x.size()[0].max(y.size()[0]).max(z.size()[0]);
+        let x = with_shift(rebox(x, w), delta / 2.0);
+        let y = rebox(y, w);
+        let z = with_shift(rebox(z, w), -delta / 2.0);
+
+        let spacing = |n| self.math_ex(n, size);
+        let [_, mut height, mut depth] = y.size();
+        let mut list = Vec::new();
+        if has_sup {
+            let [_, hx, dx] = x.size();
+            let shift_up = (spacing(BIG_OP_SPACING3) - dx).max(spacing(BIG_OP_SPACING1));
+            height += spacing(BIG_OP_SPACING5) + hx + dx + shift_up;
+            list.extend([Node::Kern(spacing(BIG_OP_SPACING5)), x, Node::Kern(shift_up)]);
+        }
+        list.push(y);
+        if has_sub {
+            let [_, hz, dz] = z.size();
+            let shift_down = (spacing(BIG_OP_SPACING4) - hz).max(spacing(BIG_OP_SPACING2));
+            depth += spacing(BIG_OP_SPACING5) + hz + dz + shift_down;
+            list.extend([Node::Kern(shift_down), z, Node::Kern(spacing(BIG_OP_SPACING5))]);
+        }
+        let span = layout::span_of(&list);
+        let nesting = layout::nesting(&list);
+        Node::Box { content: BoxContent::VBox(list), size: [w, height, depth], shift: 0.0, nesting, span }
+    }
+
+    /// Makes a fraction into a box, with its delimiters
+    fn make_fraction(&mut self, f: Fraction, style: Style) -> Vec<Node> {
+        let size = style.size();
+        let thickness = f.thickness.unwrap_or_else(|| self.math_ex(DEFAULT_RULE_THICKNESS, size));
+        let x = self.clean_box(Field::List(f.num), style.num());
+        let z = self.clean_box(Field::List(f.denom), style.denom());
+        let w = x.size()[0].max(z.size()[0]);
+        let (x, z) = (rebox(x, w), rebox(z, w));
+        let ([_, hx, dx], [_, hz, dz]) = (x.size(), z.size());
+
+        let display = style.level == 0;
+        let (mut shift_up, mut shift_down) = match (display, thickness != 0.0) {
+            (true, _) => (self.math_sy(NUM1, size), self.math_sy(DENOM1, size)),
+            (false, true) => (self.math_sy(NUM2, size), self.math_sy(DENOM2, size)),
+            (false, false) => (self.math_sy(NUM3, size), self.math_sy(DENOM2, size)),
+        };
+        let axis = self.math_sy(AXIS_HEIGHT, size);
+        let list = if thickness == 0.0 {
+            let t = self.math_ex(DEFAULT_RULE_THICKNESS, size);
+            let clr = if display { 7.0 * t } else { 3.0 * t };
+            let delta = (clr - ((shift_up - dx) - (hz - shift_down))) / 2.0;
+            if delta > 0.0 {
+                shift_up += delta;
+                shift_down += delta;
+            }
+            vec![x, Node::Kern((shift_up - dx) - (hz - shift_down)), z]
+        } else {
+            let clr = if display { 3.0 * thickness } else { thickness };
+            let delta = thickness / 2.0;
+            shift_up += (clr - ((shift_up - dx) - (axis + delta))).max(0.0);
+            shift_down += (clr - ((axis - delta) - (hz - shift_down))).max(0.0);
+            vec![
+                x,
+                Node::Kern((shift_up - dx) - (axis + delta)),
+                rule(w, thickness),
+                Node::Kern((axis - delta) - (hz - shift_down)),
+                z,
+            ]
+        };
+        let span = layout::span_of(&list);
+        let nesting = layout::nesting(&list);
+        let v = Node::Box { content: BoxContent::VBox(list), size: [w, shift_up + hx, dz + shift_down], shift: 0.0, nesting, span };
+
+        let delta = self.math_sy(if display { DELIM1 } else { DELIM2 }, size);
+        let left = self.var_delimiter(f.left, size, delta);
+        let right = self.var_delimiter(f.right, size, delta);
+        vec![hbox(vec![left, v, right])]
+    }
+
+    /// A box holding delimiter `d` at least `v` high and deep altogether if it can be, centred on
+    /// the axis. A null delimiter is an empty box `\nulldelimiterspace` wide.
+    fn var_delimiter(&mut self, d: Delimiter, size: u8, v: f32) -> Node {
+        // the small variant is tried at the size of the formula, and the large one at text size
+        let candidates = [d.small.map(|(fam, c)| (self.math_font(fam, size), c)), d.large.map(|(fam, c)| (self.math_font(fam, 0), c))];
+        let mut best: Option<(FontId, char, f32)> = None;
+        for (f, c) in candidates.into_iter().flatten() {
+            let Some(m) = self.font(f).metrics(c) else { continue };
+            let total = m.height + m.depth;
+            if best.is_none_or(|(_, _, t)| total > t) {
+                best = Some((f, c, total));
+            }
+            if total >= v {
+                break;
+            }
+        }
+
+        let Some((mut f, c, total)) = best else {
+            let width = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::NullDelimiterSpace)));
+            return Node::Box { content: BoxContent::HBox(Vec::new()), size: [width, 0.0, 0.0], shift: 0.0, nesting: 1, span: Span::default() };
+        };
+        // without a larger variant the font is scaled up to the size needed
+        if total < v && total > 0.0 {
+            let size = self.font(f).size * v / total;
+            f = self.font_at(f, size);
+        }
+        let b = self.char_box(c, f, Span::default());
+        let b = hbox(b.into_iter().collect());
+        let [_, h, d] = b.size();
+        let axis = self.math_sy(AXIS_HEIGHT, size);
+        with_shift(b, (h - d) / 2.0 - axis)
+    }
+
+    /// Makes the nucleus of a radical into a box under the radical sign
+    fn make_radical(&mut self, nucleus: Field, d: Delimiter, style: Style) -> Node {
+        let size = style.size();
+        let x = self.clean_box(nucleus, style.cramp());
+        let [_, hx, dx] = x.size();
+        let t = self.math_ex(DEFAULT_RULE_THICKNESS, size);
+        let mut clr = match style.level {
+            0 => t + self.math_sy(5, size).abs() / 4.0,
+            _ => t + t.abs() / 4.0,
+        };
+        let y = self.var_delimiter(d, size, hx + dx + clr + t);
+        let [_, hy, dy] = y.size();
+        let delta = dy - (hx + dx + clr);
+        if delta > 0.0 {
+            clr += delta / 2.0;
+        }
+        let y = with_shift(y, -(hx + clr));
+        hbox(vec![y, overbar(x, clr, hy)])
+    }
+
+    /// Makes the nucleus of an `\underline` atom into a box with a rule under it
+    fn make_under(&mut self, nucleus: Field, style: Style) -> Node {
+        let t = self.math_ex(DEFAULT_RULE_THICKNESS, style.size());
+        let x = self.clean_box(nucleus, style);
+        let [w, hx, _] = x.size();
+        let mut y = layout::vpack(vec![x, Node::Kern(3.0 * t), rule(RUNNING, t)], None, Span::default());
+        if let Node::Box { size, .. } = &mut y {
+            let total = size[1] + size[2] + t;
+            *size = [w, hx, total - hx];
+        }
+        y
+    }
+
+    /// Makes the nucleus of a `\mathaccent` atom into a box with the accent centred over it
+    fn make_math_accent(&mut self, fam: u8, c: char, nucleus: Field, style: Style) -> Node {
+        let f = self.math_font(fam, style.size());
+        let x = self.clean_box(nucleus, style.cramp());
+        let Some(accent) = self.new_character(c, f, Span::default()) else { return x };
+        let [w, h, _] = x.size();
+        let delta = h.min(self.font(f).params.x_height);
+
+        let wy = accent.size()[0];
+        let mut y = with_shift(accent, (w - wy) / 2.0);
+        if let Node::Box { size, .. } = &mut y {
+            size[0] = 0.0;
+        }
+        let mut v = layout::vpack(vec![y, Node::Kern(-delta), x], None, Span::default());
+        if let Node::Box { content: BoxContent::VBox(list), size, .. } = &mut v {
+            size[0] = w;
+            if size[1] < h {
+                list.insert(0, Node::Kern(h - size[1]));
+                size[1] = h;
+            }
+        }
+        v
+    }
+
+    /// Makes a `\left` or `\right` delimiter big enough to cover the list it is in, whose
+    /// height and depth are `max_h` and `max_d`
+    fn make_left_right(&mut self, d: Delimiter, style: Style, max_h: f32, max_d: f32) -> Node {
+        let size = style.size();
+        let axis = self.math_sy(AXIS_HEIGHT, size);
+        let delta1 = (max_h - axis).max(max_d + axis);
+        let factor = self.int_param(IntParam::DelimiterFactor) as f32;
+        let shortfall = to_pt(self.eqtb.int(&Key::DimenParam(DimenParam::DelimiterShortfall)));
+        let delta = (delta1 / 500.0 * factor).max(2.0 * delta1 - shortfall);
+        self.var_delimiter(d, size, delta)
+    }
+}
+
+/// The finished formula of a math list, with a fraction started in it completed by the rest of
+/// the list and the `\right` delimiter if there is one
+fn fin_mlist(list: List, right: Option<Noad>) -> Vec<Noad> {
+    let List { math, incompleat, .. } = list;
+    let mut mlist = match incompleat {
+        Some(mut f) => {
+            f.denom = math;
+            // the `\left` of the list stays outside the fraction
+            let left = match f.num.first() {
+                Some(Noad::Left(_)) if right.is_some() => Some(f.num.remove(0)),
+                _ => None,
+            };
+            left.into_iter().chain([Noad::Fraction(Box::new(f))]).collect()
+        },
+        None => math,
+    };
+    mlist.extend(right);
+    mlist
+}
+
+/// The width, height and depth of a horizontal list
+fn extent(list: &[Node]) -> [f32; 3] {
+    hbox(list.to_vec()).size()
+}
+
+fn hbox(list: Vec<Node>) -> Node {
+    let span = layout::span_of(&list);
+    layout::hpack(list, None, span)
+}
+
+/// A rule `thickness` high, as drawn by fractions
+fn rule(width: f32, thickness: f32) -> Node {
+    Node::Box { content: BoxContent::HRule, size: [width, thickness, 0.0], shift: 0.0, nesting: 0, span: Span::default() }
+}
+
+/// Box `b` with a rule `t` thick above it, `k` above the box and with `t` of space above it
+fn overbar(b: Node, k: f32, t: f32) -> Node {
+    layout::vpack(vec![Node::Kern(t), rule(RUNNING, t), Node::Kern(k), b], None, Span::default())
+}
+
+/// Box `b` made `w` wide, with its contents centred
+fn rebox(b: Node, w: f32) -> Node {
+    if b.size()[0] == w {
+        return b;
+    }
+    let (list, span) = match b {
+        Node::Box { content: BoxContent::HBox(list), span, .. } if !list.is_empty() => (list, span),
+        Node::Box { content: BoxContent::HBox(list), size: [_, h, d], shift, nesting, span } => {
+            return Node::Box { content: BoxContent::HBox(list), size: [w, h, d], shift, nesting, span };
+        },
+        b => {
+            let span = layout::span_of(core::slice::from_ref(&b));
+            (vec![b], span)
+        },
+    };
+    let ss = || Node::Glue { nat_size: 0.0, shrinkability: 1.0, stretchability: 1.0, stretch_order: 1, shrink_order: 1 };
+    let list = [ss()].into_iter().chain(list).chain([ss()]).collect();
+    layout::hpack(list, Some(w), span)
+}
+
+fn with_shift(mut b: Node, s: f32) -> Node {
+    if let Node::Box { shift, .. } = &mut b {
+        *shift = s;
+    }
+    b
+}
+
+/// Box `b` made wider by `w`, as scripts are by `\scriptspace`
+fn widen(mut b: Node, w: f32) -> Node {
+    if let Node::Box { size, .. } = &mut b {
+        size[0] += w;
+    }
+    b
+}
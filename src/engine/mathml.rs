@@ -0,0 +1,277 @@
+use core::fmt::Write;
+
+use crate::font::{Font, Shape};
+use crate::html::escape;
+use crate::layout::{BoxContent, Node};
+use super::math::{Atom, AtomKind, Delimiter, Field, Fraction, Noad, OpLimits, Style};
+use super::scan::to_pt;
+use super::*;
+
+impl Engine {
+    /// Writes a math list as MathML, set in `style`. Browsers do the layout, so only the
+    /// structure of the list is kept, and its spacing where it was given explicitly.
+    pub(super) fn mathml(&self, mlist: &[Noad], style: Style) -> String {
+        let display = if style == Style::DISPLAY { " display=\"block\"" } else { "" };
+        let mut out = format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\"{display}>");
+        self.mathml_list(&mut out, mlist, style);
+        out += "</math>";
+        out
+    }
+
+    fn mathml_list(&self, out: &mut String, mlist: &[Noad], mut style: Style) {
+        out.push_str("<mrow>");
+        let mut styles = 0;
+        let mut i = 0;
+        while i < mlist.len() {
+            // a number, or a run of upright letters like the name of a function, is one token
+            if let Some((tag, _)) = self.run_char(&mlist[i], style).filter(|(_, c)| *c != '.') {
+                let mut run: String = mlist[i..].iter()
+                    .map_while(|noad| self.run_char(noad, style).filter(|(t, _)| *t == tag).map(|(_, c)| c))
+                    .collect();
+                if run.ends_with('.') {
+                    run.pop();
+                }
+                let n = run.chars().count();
+                if n > 1 || tag == "mn" {
+                    let _ = write!(out, "<{tag}>{}</{tag}>", escape(&run));
+                    i += n;
+                    continue;
+                }
+            }
+
+            match &mlist[i] {
+                Noad::Atom(atom) => self.mathml_atom(out, atom, style),
+                Noad::Fraction(f) => self.mathml_fraction(out, f, style),
+                Noad::Left(d) | Noad::Right(d) => self.mathml_delimiter(out, *d, style),
+                Noad::Style(s) => {
+                    style = *s;
+                    styles += 1;
+                    let _ = write!(out, "<mstyle displaystyle=\"{}\" scriptlevel=\"{}\">", s.level == 0, s.size());
+                },
+                Noad::MuGlue(g) => space(out, format!("{}em", to_pt(g.width) / 18.0)),
+                Noad::MuKern(k) => space(out, format!("{}em", to_pt(*k) / 18.0)),
+                Noad::Node(Node::Glue { nat_size: w, .. } | Node::Kern(w)) => space(out, format!("{w}pt")),
+                Noad::Node(n @ Node::Box { .. }) => self.mathml_box(out, n),
+                Noad::Node(_) => {},
+            }
+            i += 1;
+        }
+        for _ in 0..styles {
+            out.push_str("</mstyle>");
+        }
+        out.push_str("</mrow>");
+    }
+
+    /// The tag and character of an ordinary atom that is a digit, a decimal point or an upright
+    /// letter and has no scripts, which can be joined with the ones next to it
+    fn run_char(&self, noad: &Noad, style: Style) -> Option<(&'static str, char)> {
+        let Noad::Atom(Atom { kind: AtomKind::Ord, nucleus: Field::Char(fam, c, _), sup, sub }) = noad else {
+            return None;
+        };
+        if !sup.is_empty() || !sub.is_empty() {
+            return None;
+        }
+        let font = self.font(self.math_font(*fam, style.size()));
+        let g = font.glyph(*c);
+        if g.is_ascii_digit() || g == '.' {
+            Some(("mn", g))
+        } else if g.is_alphabetic() && font.style.shape == Shape::Upright && !font.style.bold {
+            Some(("mi", g))
+        } else {
+            None
+        }
+    }
+
+    fn mathml_atom(&self, out: &mut String, atom: &Atom, style: Style) {
+        let limits = match atom.kind {
+            AtomKind::Op(OpLimits::Limits) => true,
+            AtomKind::Op(OpLimits::Display) => style.level == 0,
+            _ => false,
+        };
+        let tag = match (atom.sub.is_empty(), atom.sup.is_empty(), limits) {
+            (true, true, _) => None,
+            (false, true, false) => Some("msub"),
+            (false, true, true) => Some("munder"),
+            (true, false, false) => Some("msup"),
+            (true, false, true) => Some("mover"),
+            (false, false, false) => Some("msubsup"),
+            (false, false, true) => Some("munderover"),
+        };
+        if let Some(tag) = tag {
+            let _ = write!(out, "<{tag}>");
+        }
+
+        match &atom.kind {
+            AtomKind::Radical(_) => {
+                out.push_str("<msqrt>");
+                self.mathml_field(out, &atom.nucleus, style.cramp(), &AtomKind::Ord);
+                out.push_str("</msqrt>");
+            },
+            AtomKind::Over => {
+                out.push_str("<mover accent=\"true\">");
+                self.mathml_field(out, &atom.nucleus, style.cramp(), &AtomKind::Ord);
+                out.push_str("<mo stretchy=\"true\">\u{203e}</mo></mover>");
+            },
+            AtomKind::Under => {
+                out.push_str("<munder accentunder=\"true\">");
+                self.mathml_field(out, &atom.nucleus, style, &AtomKind::Ord);
+                out.push_str("<mo stretchy=\"true\">_</mo></munder>");
+            },
+            AtomKind::Accent(fam, c) => {
+                let font = self.font(self.math_font(*fam, style.size()));
+                out.push_str("<mover accent=\"true\">");
+                self.mathml_field(out, &atom.nucleus, style.cramp(), &AtomKind::Ord);
+                let _ = write!(out, "<mo>{}</mo></mover>", escape(&font.glyph(*c).to_string()));
+            },
+            kind => self.mathml_field(out, &atom.nucleus, style, kind),
+        }
+
+        if !atom.sub.is_empty() {
+            self.mathml_field(out, &atom.sub, style.sub(), &AtomKind::Ord);
+        }
+        if !atom.sup.is_empty() {
+            self.mathml_field(out, &atom.sup, style.sup(), &AtomKind::Ord);
+        }
+        if let Some(tag) = tag {
+            let _ = write!(out, "</{tag}>");
+        }
+    }
+
+    /// Writes a field of an atom of `kind`, whose characters are operators unless it is ordinary
+    fn mathml_field(&self, out: &mut String, field: &Field, style: Style, kind: &AtomKind) {
+        match field {
+            Field::Empty => out.push_str("<mrow></mrow>"),
+            Field::Char(fam, c, _) => {
+                let font = self.font(self.math_font(*fam, style.size()));
+                mathml_char(out, font, *c, *kind != AtomKind::Ord);
+            },
+            Field::Box(n) => self.mathml_box(out, n),
+            Field::List(mlist) => self.mathml_list(out, mlist, style),
+        }
+    }
+
+    fn mathml_fraction(&self, out: &mut String, f: &Fraction, style: Style) {
+        let fenced = f.left != Delimiter::default() || f.right != Delimiter::default();
+        if fenced {
+            out.push_str("<mrow>");
+            self.mathml_delimiter(out, f.left, style);
+        }
+        match f.thickness {
+            Some(t) => {
+                let _ = write!(out, "<mfrac linethickness=\"{t}pt\">");
+            },
+            None => out.push_str("<mfrac>"),
+        }
+        self.mathml_list(out, &f.num, style.num());
+        self.mathml_list(out, &f.denom, style.denom());
+        out.push_str("</mfrac>");
+        if fenced {
+            self.mathml_delimiter(out, f.right, style);
+            out.push_str("</mrow>");
+        }
+    }
+
+    /// Writes a delimiter as a stretchy operator, or nothing if it is null
+    fn mathml_delimiter(&self, out: &mut String, d: Delimiter, style: Style) {
+        let Some((fam, c)) = d.small.or(d.large) else { return };
+        let font = self.font(self.math_font(fam, style.size()));
+        let _ = write!(out, "<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape(&font.glyph(c).to_string()));
+    }
+
+    /// Writes a box, which has lost the structure of the math in it. A vertical box is taken to
+    /// be an alignment, with a row for each horizontal box in it and a column for each box in
+    /// those, and upright words in a horizontal box are text.
+    fn mathml_box(&self, out: &mut String, node: &Node) {
+        let Node::Box { content, .. } = node else { return };
+        match content {
+            BoxContent::VBox(list) => {
+                out.push_str("<mtable>");
+                for row in list {
+                    let Node::Box { content: BoxContent::HBox(cells), .. } = row else { continue };
+                    // rows have an entry for every column of the preamble, even ones never used
+                    let mut cells: Vec<_> = cells.iter().filter(|n| matches!(n, Node::Box { .. })).collect();
+                    while cells.last().is_some_and(|n| is_blank(n)) {
+                        cells.pop();
+                    }
+                    out.push_str("<mtr>");
+                    for cell in cells {
+                        out.push_str("<mtd>");
+                        self.mathml_box(out, cell);
+                        out.push_str("</mtd>");
+                    }
+                    out.push_str("</mtr>");
+                }
+                out.push_str("</mtable>");
+            },
+            BoxContent::HBox(list) => {
+                out.push_str("<mrow>");
+                let mut text = String::new();
+                for n in list {
+                    match n {
+                        Node::Box { content: BoxContent::Character(c, f), .. } => {
+                            let font = self.font(*f);
+                            let g = font.glyph(*c);
+                            let upright = font.style.shape == Shape::Upright;
+                            if upright && (g.is_alphabetic() || !text.is_empty() && ",.;:!?'".contains(g)) {
+                                text.push(g);
+                                continue;
+                            }
+                            flush_text(out, &mut text);
+                            mathml_char(out, font, *c, !g.is_alphanumeric());
+                        },
+                        Node::Glue { nat_size, .. } if *nat_size > 0.0 && !text.is_empty() => text.push(' '),
+                        Node::Box { .. } => {
+                            flush_text(out, &mut text);
+                            self.mathml_box(out, n);
+                        },
+                        _ => {},
+                    }
+                }
+                flush_text(out, &mut text);
+                out.push_str("</mrow>");
+            },
+            BoxContent::Character(c, f) => mathml_char(out, self.font(*f), *c, false),
+            _ => {},
+        }
+    }
+}
+
+/// Writes a character of `font` as an operator if `operator` is set, or else as a number or an
+/// identifier. Single letters are italic in MathML unless they say otherwise.
+fn mathml_char(out: &mut String, font: &Font, c: char, operator: bool) {
+    let g = escape(&font.glyph(c).to_string());
+    if operator {
+        let _ = write!(out, "<mo>{g}</mo>");
+    } else if font.glyph(c).is_ascii_digit() {
+        let _ = write!(out, "<mn>{g}</mn>");
+    } else {
+        let variant = match (font.style.bold, font.style.shape == Shape::Upright) {
+            (false, true) => " mathvariant=\"normal\"",
+            (true, true) => " mathvariant=\"bold\"",
+            (true, false) => " mathvariant=\"bold-italic\"",
+            (false, false) => "",
+        };
+        let _ = write!(out, "<mi{variant}>{g}</mi>");
+    }
+}
+
+/// Whether a box shows nothing
+fn is_blank(node: &Node) -> bool {
+    match node {
+        Node::Box { content: BoxContent::HBox(list) | BoxContent::VBox(list), .. } => {
+            list.iter().all(|n| !matches!(n, Node::Box { .. }) || is_blank(n))
+        },
+        _ => false,
+    }
+}
+
+fn flush_text(out: &mut String, text: &mut String) {
+    if !text.is_empty() {
+        let _ = write!(out, "<mtext>{}</mtext>", escape(text));
+        text.clear();
+    }
+}
+
+fn space(out: &mut String, width: String) {
+    let _ = write!(out, "<mspace width=\"{width}\"/>");
+}
@@ -0,0 +1,597 @@
+mod align;
+mod assign;
+mod build;
+mod builder;
+mod cond;
+mod dump;
+mod eqtb;
+mod expand;
+mod hyphenate;
+mod input;
+mod interaction;
+mod latex;
+mod limits;
+mod math;
+mod mathml;
+mod output;
+mod passes;
+mod plain;
+mod primitive;
+mod scan;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use build::{Group, List, Mode};
+use crate::document::MathRenderer;
+use crate::error::{Diagnostic, ErrorKind};
+use crate::font::{Font, FontId};
+use crate::hyphen::Patterns;
+use crate::lexer::{CsId, CsTable, Encoding, FileId, Lexer, Span, Token, TokenType};
+pub use builder::{BuildError, EngineBuilder};
+pub use dump::{BadFormat, FORMAT_VERSION};
+pub use eqtb::{Eqtb, Key, Value};
+pub use input::{DirResolver, MapResolver, Resolver, SourceFile, SourceLines};
+pub use interaction::{InteractionMode, Response, Terminal};
+pub use limits::{FileAccess, Limits};
+pub use output::{DirSink, MemorySink, Sink};
+pub use passes::MultiPass;
+pub use scan::{print_scaled, to_scaled};
+pub use primitive::{DimenParam, GlueParam, IntParam, MuGlueParam, Primitive, ToksParam};
+pub use primitive::{DIMEN_PARAMS, GLUE_PARAMS, INT_PARAMS, MU_GLUE_PARAMS, PRIMITIVES, TOKS_PARAMS};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Meaning {
+    /// A character token, possibly `\let` to a control sequence
+    Char(TokenType, char),
+    Macro(Rc<Macro>),
+    Primitive(Primitive),
+    IntParam(IntParam),
+    DimenParam(DimenParam),
+    GlueParam(GlueParam),
+    MuGlueParam(MuGlueParam),
+    ToksParam(ToksParam),
+    CountDef(u8),
+    DimenDef(u8),
+    SkipDef(u8),
+    MuSkipDef(u8),
+    ToksDef(u8),
+    /// Defined by `\chardef`
+    CharDef(char),
+    /// Defined by `\mathchardef`
+    MathCharDef(i32),
+    /// A font identifier, like `\tenrm`
+    Font(FontId),
+}
+
+impl Meaning {
+    pub fn is_expandable(&self) -> bool {
+        match self {
+            Self::Macro(_) => true,
+            Self::Primitive(p) => p.is_expandable(),
+            _ => false,
+        }
+    }
+
+    pub fn is_assignment(&self) -> bool {
+        match self {
+            Self::Primitive(p) => matches!(
+                p,
+                Primitive::Def
+                    | Primitive::Gdef
+                    | Primitive::Edef
+                    | Primitive::Xdef
+                    | Primitive::Let
+                    | Primitive::Global
+                    | Primitive::Long
+                    | Primitive::Outer
+                    | Primitive::Count
+                    | Primitive::Dimen
+                    | Primitive::Skip
+                    | Primitive::MuSkip
+                    | Primitive::Toks
+                    | Primitive::CountDef
+                    | Primitive::DimenDef
+                    | Primitive::SkipDef
+                    | Primitive::MuSkipDef
+                    | Primitive::ToksDef
+                    | Primitive::CharDef
+                    | Primitive::MathCharDef
+                    | Primitive::Advance
+                    | Primitive::Multiply
+                    | Primitive::Divide
+                    | Primitive::Font
+                    | Primitive::FontDimen
+                    | Primitive::HyphenChar
+                    | Primitive::SkewChar
+                    | Primitive::TextFont
+                    | Primitive::ScriptFont
+                    | Primitive::ScriptScriptFont
+                    | Primitive::SetBox
+                    | Primitive::Wd
+                    | Primitive::Ht
+                    | Primitive::Dp
+                    | Primitive::FutureLet
+                    | Primitive::Catcode
+                    | Primitive::LcCode
+                    | Primitive::UcCode
+                    | Primitive::SfCode
+                    | Primitive::MathCode
+                    | Primitive::DelCode
+                    | Primitive::Read
+                    | Primitive::BatchMode
+                    | Primitive::NonStopMode
+                    | Primitive::ScrollMode
+                    | Primitive::ErrorStopMode
+                    | Primitive::Patterns
+                    | Primitive::Hyphenation
+            ),
+            Self::IntParam(_)
+                | Self::DimenParam(_)
+                | Self::GlueParam(_)
+                | Self::MuGlueParam(_)
+                | Self::ToksParam(_)
+                | Self::CountDef(_)
+                | Self::DimenDef(_)
+                | Self::SkipDef(_)
+                | Self::MuSkipDef(_)
+                | Self::ToksDef(_)
+                | Self::Font(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    pub params: Vec<MacroToken>,
+    pub body: Vec<MacroToken>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MacroToken {
+    Token(Token),
+    /// `#1` to `#9`
+    Param(u8),
+}
+
+/// Stretchable space, in scaled points
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Glue {
+    pub width: i32,
+    pub stretch: i32,
+    /// `0` for finite stretch, `1` to `3` for `fil`, `fill` and `filll`
+    pub stretch_order: u8,
+    pub shrink: i32,
+    pub shrink_order: u8,
+}
+
+impl Glue {
+    pub fn negate(self) -> Self {
+        Self { width: -self.width, stretch: -self.stretch, shrink: -self.shrink, ..self }
+    }
+}
+
+/// A token along with its current meaning, `None` if it is undefined
+pub type Command = (Token, Option<Meaning>);
+
+pub struct Engine {
+    /// The files being read, with the innermost one last
+    inputs: Vec<Lexer<SourceLines>>,
+    /// Every file that has been opened, indexed by [`FileId`]
+    files: Vec<SourceFile>,
+    /// The streams opened by `\openin`
+    read_files: HashMap<u8, (FileId, SourceLines)>,
+    resolver: Box<dyn Resolver>,
+    /// The names of the files open for `\write`, by stream
+    write_files: HashMap<u8, String>,
+    sink: Box<dyn Sink>,
+    cs: CsTable,
+    /// Tokens to be read before continuing with the lexer, in reverse order. The flag is set if
+    /// the token came from `\noexpand`.
+    back: Vec<(Token, bool)>,
+    pub eqtb: Eqtb,
+    /// The fonts loaded, indexed by [`FontId`], with the control sequence each was loaded as
+    fonts: Vec<(Rc<Font>, CsId)>,
+    /// The lists being built, with the innermost one last
+    nest: Vec<List>,
+    /// The groups that affect typesetting, with the innermost one last
+    groups: Vec<Group>,
+    /// The alignments being built, with the innermost one last
+    aligns: Vec<align::Align>,
+    /// The number of `{` less the number of `}` read since the current alignment entry began,
+    /// offset so that an entry ends at an `&` or `\cr` read when it is zero
+    align_state: i32,
+    /// The conditionals being expanded, with the innermost one last
+    conds: Vec<cond::Cond>,
+    /// The token saved by `\afterassignment`
+    after_assignment: Option<Token>,
+    /// The tokens saved by `\aftergroup` for each open group
+    after_group: Vec<Vec<Token>>,
+    diagnostics: Vec<Diagnostic>,
+    /// Where the last token read came from, for reporting errors
+    span: Span,
+    interaction: InteractionMode,
+    terminal: Option<Box<dyn Terminal>>,
+    /// Everything printed, as TeX writes it to the log file
+    log: String,
+    /// Set when the terminal is not at the start of a line
+    terminal_mid_line: bool,
+    /// The number of errors in the current paragraph
+    error_count: usize,
+    /// Hyphenation patterns, by `\language`
+    patterns: HashMap<i32, Patterns>,
+    /// Set when processing is to stop, because of too many errors or at the user's request
+    halted: bool,
+    /// The format written by `\dump`
+    dumped: Option<Vec<u8>>,
+    /// Where `\font` looks for TFM files
+    font_dirs: Vec<PathBuf>,
+    limits: Limits,
+    usage: limits::Usage,
+    /// How math is to be written in HTML output
+    math: MathRenderer,
+    /// The name of the format loaded, if any
+    format_name: Option<String>,
+}
+
+impl Engine {
+    pub fn new(main: SourceFile) -> Self {
+        let cs = CsTable::default();
+        let mut eqtb = Eqtb::default();
+
+        for (name, p) in PRIMITIVES {
+            eqtb.set(Key::Cs(cs.intern(name)), Some(Value::Meaning(Meaning::Primitive(*p))), true);
+        }
+
+        for (name, p) in INT_PARAMS {
+            eqtb.set(Key::Cs(cs.intern(name)), Some(Value::Meaning(Meaning::IntParam(*p))), true);
+        }
+
+        for (name, p) in DIMEN_PARAMS {
+            eqtb.set(Key::Cs(cs.intern(name)), Some(Value::Meaning(Meaning::DimenParam(*p))), true);
+        }
+
+        for (name, p) in GLUE_PARAMS {
+            eqtb.set(Key::Cs(cs.intern(name)), Some(Value::Meaning(Meaning::GlueParam(*p))), true);
+        }
+
+        for (name, p) in MU_GLUE_PARAMS {
+            eqtb.set(Key::Cs(cs.intern(name)), Some(Value::Meaning(Meaning::MuGlueParam(*p))), true);
+        }
+
+        for (name, p) in TOKS_PARAMS {
+            eqtb.set(Key::Cs(cs.intern(name)), Some(Value::Meaning(Meaning::ToksParam(*p))), true);
+        }
+
+        // the default font is named as TeX names fonts loaded without an identifier
+        let fonts = vec![(Rc::new(Font::null()), cs.intern("nullfont")), (Rc::new(Font::cmr10()), cs.intern("FONTcmr10"))];
+        for (i, (_, name)) in fonts.iter().enumerate() {
+            eqtb.set(Key::Cs(*name), Some(Value::Meaning(Meaning::Font(FontId(i as u32)))), true);
+        }
+
+        let mut engine = Self {
+            inputs: Vec::new(),
+            files: Vec::new(),
+            read_files: HashMap::new(),
+            resolver: Box::new(DirResolver::new(vec![PathBuf::from(".")])),
+            write_files: HashMap::new(),
+            sink: Box::new(DirSink::new(".")),
+            cs,
+            back: Vec::new(),
+            eqtb,
+            fonts,
+            nest: vec![List::new(Mode::Vertical)],
+            groups: Vec::new(),
+            aligns: Vec::new(),
+            align_state: 1_000_000,
+            conds: Vec::new(),
+            after_assignment: None,
+            after_group: Vec::new(),
+            diagnostics: Vec::new(),
+            span: Span::default(),
+            interaction: InteractionMode::default(),
+            terminal: None,
+            log: String::new(),
+            terminal_mid_line: false,
+            error_count: 0,
+            halted: false,
+            dumped: None,
+            font_dirs: Vec::new(),
+            limits: Limits::default(),
+            usage: limits::Usage::default(),
+            math: MathRenderer::default(),
+            format_name: None,
+            patterns: HashMap::new(),
+        };
+        engine.load_plain();
+        engine.push_input(main);
+        engine
+    }
+
+    /// Switches between classic 8-bit character codes and Unicode ones. This changes the initial
+    /// catcodes, `\lccode`s, `\uccode`s, `\sfcode`s and `\mathcode`s of every character that has
+    /// not been assigned to, so it is best done before anything is read.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.eqtb.encoding = encoding;
+        for lexer in self.inputs.iter_mut() {
+            lexer.encoding = encoding;
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.eqtb.encoding
+    }
+
+    pub fn set_resolver(&mut self, resolver: Box<dyn Resolver>) {
+        self.resolver = resolver;
+    }
+
+    /// Sets the directories `\font` looks for TFM files in. Computer Modern fonts that are not
+    /// found there have built in metrics.
+    pub fn set_font_dirs(&mut self, dirs: Vec<PathBuf>) {
+        self.font_dirs = dirs;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.sync_files_switch();
+    }
+
+    pub fn set_math_renderer(&mut self, math: MathRenderer) {
+        self.math = math;
+    }
+
+    /// Replaces the font text is set in until another is selected, which is [`Font::cmr10`]
+    /// initially
+    pub fn set_default_font(&mut self, font: Font) {
+        let cs = self.cs.intern(&format!("FONT{}", font.name));
+        self.fonts[FontId::DEFAULT.0 as usize] = (Rc::new(font), cs);
+        self.eqtb.set(Key::Cs(cs), Some(Value::Meaning(Meaning::Font(FontId::DEFAULT))), true);
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0 as usize]
+    }
+
+    /// Every file that has been read, indexed by [`FileId`]
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    /// The name of the main file without its extension, as `\jobname` gives it
+    pub fn job_name(&self) -> String {
+        let Some(main) = self.files.first() else { return String::new() };
+        let stem = std::path::Path::new(&main.name).file_stem().unwrap_or_default();
+        stem.to_string_lossy().into_owned()
+    }
+
+    /// The font with identifier `id`
+    pub fn font(&self, id: FontId) -> &Font {
+        &self.fonts[id.0 as usize].0
+    }
+
+    /// The font text is set in
+    fn cur_font(&self) -> &Font {
+        self.font(self.eqtb.font(&Key::CurFont))
+    }
+
+    /// The errors and warnings found so far
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn add_file(&mut self, file: SourceFile) -> (FileId, SourceLines) {
+        let id = FileId(self.files.len() as u32);
+        let lines = SourceLines::new(Rc::clone(&file.text));
+        self.files.push(file);
+        (id, lines)
+    }
+
+    /// Starts reading from `file`, until it ends or `\endinput` is used
+    fn push_input(&mut self, file: SourceFile) {
+        if self.inputs.len() >= self.limits.max_input_depth {
+            self.overflow("text input levels", self.limits.max_input_depth);
+            return;
+        }
+
+        let (id, lines) = self.add_file(file);
+        let lexer = self.new_lexer(lines, Some(id));
+        self.inputs.push(lexer);
+    }
+
+    /// Creates a lexer that reads `lines` with the current catcodes and `\endlinechar`
+    fn new_lexer<L: Iterator<Item: AsRef<str>>>(&self, lines: L, file: Option<FileId>) -> Lexer<L> {
+        let mut lexer = Lexer::with_cs_table(lines, self.cs.clone());
+        lexer.file = file;
+        lexer.encoding = self.eqtb.encoding;
+        lexer.end_line_char = self.end_line_char();
+        if let Some(outer) = self.inputs.last() {
+            lexer.catcodes = outer.catcodes.clone();
+        }
+        lexer
+    }
+
+    /// The character given by `\endlinechar`, `None` if it is out of range
+    fn end_line_char(&self) -> Option<char> {
+        let c = self.int_param(IntParam::EndLineChar);
+        u32::try_from(c).ok().filter(|c| *c <= self.eqtb.encoding.max_char()).and_then(char::from_u32)
+    }
+
+    /// Gets the next unexpanded token. Braces are counted for alignments, and an `&`, `\span` or
+    /// `\cr` that ends an alignment entry is replaced by the rest of the entry's template.
+    fn get_next(&mut self) -> Option<(Token, bool)> {
+        loop {
+            let (t, noexpand) = self.next_token()?;
+            match t.typ {
+                TokenType::BeginGroup => self.align_state += 1,
+                TokenType::EndGroup => self.align_state -= 1,
+                _ => {},
+            }
+            if self.align_state == 0 && !noexpand && self.insert_v_template(&t) {
+                continue;
+            }
+            return Some((t, noexpand));
+        }
+    }
+
+    fn next_token(&mut self) -> Option<(Token, bool)> {
+        if self.halted {
+            return None;
+        }
+
+        if let Some(t) = self.back.pop() {
+            self.span = t.0.span();
+            return Some(t);
+        }
+
+        loop {
+            let lexer = self.inputs.last_mut()?;
+            let next = lexer.next();
+            for d in core::mem::take(&mut lexer.diagnostics) {
+                self.error_at(d.kind, d.span);
+            }
+            if let Some(t) = next {
+                self.span = t.span();
+                return Some((t, false));
+            }
+
+            if self.inputs.len() == 1 {
+                return None;
+            }
+            self.inputs.pop();
+        }
+    }
+
+    fn back_input(&mut self, tok: Token) {
+        self.reread(tok, false);
+    }
+
+    /// Puts back a token that has been read, to be read again, uncounting it if it is a brace
+    fn reread(&mut self, tok: Token, noexpand: bool) {
+        match tok.typ {
+            TokenType::BeginGroup => self.align_state -= 1,
+            TokenType::EndGroup => self.align_state += 1,
+            _ => {},
+        }
+        self.back.push((tok, noexpand));
+    }
+
+    fn back_list(&mut self, toks: impl DoubleEndedIterator<Item = Token>) {
+        self.back.extend(toks.rev().map(|t| (t, false)));
+    }
+
+    fn key_of(tok: &Token) -> Option<Key> {
+        match tok.typ {
+            TokenType::Escape => tok.cs().map(Key::Cs),
+            TokenType::Active => Some(Key::Active(tok.char())),
+            _ => None,
+        }
+    }
+
+    pub fn meaning(&self, tok: &Token) -> Option<Meaning> {
+        match Self::key_of(tok) {
+            Some(k) => self.eqtb.meaning(&k),
+            None => Some(Meaning::Char(tok.typ, tok.char())),
+        }
+    }
+
+    pub fn catcode(&self, c: char) -> TokenType {
+        self.inputs.last().unwrap().catcode_of(c)
+    }
+
+    fn int_param(&self, p: IntParam) -> i32 {
+        self.eqtb.int(&Key::Int(p))
+    }
+
+    /// The lowercase form of `c` given by `\lccode`, `None` if it is not a letter
+    fn lc_code(&self, c: char) -> Option<char> {
+        match self.eqtb.int(&Key::LcCode(c)) {
+            0 => None,
+            n => char::from_u32(n as u32),
+        }
+    }
+
+    /// Gets the next token, expanding macros and expandable primitives
+    pub fn get_x_token(&mut self) -> Option<Command> {
+        loop {
+            let (tok, noexpand) = self.get_next()?;
+            if noexpand {
+                return Some((tok, Some(Meaning::Primitive(Primitive::Relax))));
+            }
+
+            let meaning = self.meaning(&tok);
+            match meaning {
+                None => self.expand(tok, None),
+                Some(m) if m.is_expandable() => self.expand(tok, Some(m)),
+                m => return Some((tok, m)),
+            }
+        }
+    }
+
+    /// Assigns `value` to `key`, keeping the lexers in sync with the catcodes and `\endlinechar`
+    fn assign(&mut self, key: Key, value: Option<Value>, global: bool) {
+        self.eqtb.set(key.clone(), value, global);
+        self.sync(&key);
+        if self.eqtb.save_size() > self.limits.max_save {
+            self.overflow("save size", self.limits.max_save);
+        }
+    }
+
+    fn sync(&mut self, key: &Key) {
+        if *key == Key::Int(IntParam::EndLineChar) {
+            let end_line_char = self.end_line_char();
+            for lexer in self.inputs.iter_mut() {
+                lexer.end_line_char = end_line_char;
+            }
+        }
+
+        if let Key::Catcode(c) = key {
+            let code = match self.eqtb.get(key) {
+                Some(Value::Int(i)) => Some(TokenType::from_catcode(*i as u8)),
+                _ => None,
+            };
+
+            for lexer in self.inputs.iter_mut() {
+                match code {
+                    Some(code) => lexer.catcodes.insert(*c, code),
+                    None => lexer.catcodes.remove(c),
+                };
+            }
+        }
+    }
+
+    fn begin_group(&mut self) {
+        if self.eqtb.level() >= self.limits.max_groups {
+            self.overflow("grouping levels", self.limits.max_groups);
+        } else if self.eqtb.save_size() >= self.limits.max_save {
+            self.overflow("save size", self.limits.max_save);
+        }
+        self.eqtb.begin_group();
+        self.after_group.push(Vec::new());
+    }
+
+    fn end_group(&mut self) {
+        if self.eqtb.level() == 0 {
+            self.error(ErrorKind::TooManyRightBraces);
+            return;
+        }
+
+        for key in self.eqtb.end_group() {
+            self.sync(&key);
+        }
+        let after = self.after_group.pop().unwrap_or_default();
+        self.back_list(after.into_iter());
+    }
+}
+
+/// The key of the `\lccode`, `\uccode`, `\sfcode` or `\mathcode` of `c`
+fn code_key(p: Primitive, c: char) -> Key {
+    match p {
+        Primitive::LcCode => Key::LcCode(c),
+        Primitive::UcCode => Key::UcCode(c),
+        Primitive::SfCode => Key::SfCode(c),
+        _ => Key::MathCode(c),
+    }
+}
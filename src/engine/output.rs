@@ -0,0 +1,214 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use crate::layout::{self, Node, Whatsit};
+use crate::lexer::{Token, TokenType};
+use super::*;
+
+/// The stream of `\write` that goes to the terminal and the log
+const TERMINAL: u8 = 16;
+/// The stream of `\write` that only goes to the log
+const LOG_ONLY: u8 = 17;
+
+/// Receives the files written with `\openout` and `\write`
+pub trait Sink {
+    /// Starts the file `name`, emptying it if it exists
+    fn open(&mut self, name: &str) -> io::Result<()>;
+    /// Adds `line` and a line break to the file `name`, which is open
+    fn write_line(&mut self, name: &str, line: &str) -> io::Result<()>;
+    /// Finishes the file `name`
+    fn close(&mut self, name: &str) -> io::Result<()>;
+}
+
+/// Writes files in a directory. Names that would reach outside it, like `../x.tex`, are refused.
+#[derive(Debug)]
+pub struct DirSink {
+    pub dir: PathBuf,
+    files: HashMap<String, BufWriter<File>>,
+}
+
+impl DirSink {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), files: HashMap::new() }
+    }
+}
+
+impl Sink for DirSink {
+    fn open(&mut self, name: &str) -> io::Result<()> {
+        if !Path::new(name).components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        let file = File::create(self.dir.join(name))?;
+        self.files.insert(name.to_string(), BufWriter::new(file));
+        Ok(())
+    }
+
+    fn write_line(&mut self, name: &str, line: &str) -> io::Result<()> {
+        let file = self.files.get_mut(name).ok_or(io::ErrorKind::NotFound)?;
+        writeln!(file, "{line}")
+    }
+
+    fn close(&mut self, name: &str) -> io::Result<()> {
+        match self.files.remove(name) {
+            Some(mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Keeps the files written in memory. Clones share the files, so a host can keep one to read
+/// them back after giving another to the engine.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    files: Rc<RefCell<HashMap<String, String>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The text of the file `name`, as far as it has been written
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.files.borrow().get(name).cloned()
+    }
+
+    /// Every file written, by name
+    pub fn files(&self) -> HashMap<String, String> {
+        self.files.borrow().clone()
+    }
+}
+
+impl Sink for MemorySink {
+    fn open(&mut self, name: &str) -> io::Result<()> {
+        self.files.borrow_mut().insert(name.to_string(), String::new());
+        Ok(())
+    }
+
+    fn write_line(&mut self, name: &str, line: &str) -> io::Result<()> {
+        let mut files = self.files.borrow_mut();
+        let text = files.get_mut(name).ok_or(io::ErrorKind::NotFound)?;
+        *text += line;
+        text.push('\n');
+        Ok(())
+    }
+
+    fn close(&mut self, _name: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Engine {
+    /// Sets where `\openout` and `\write` put files, which is the current directory by default
+    pub fn set_sink(&mut self, sink: Box<dyn Sink>) {
+        self.sink = sink;
+    }
+
+    /// Performs `\openout`, `\write`, `\closeout` or `\special`, at once if `immediate` is set and
+    /// otherwise by appending a whatsit, which acts when its page is shipped out
+    pub(super) fn do_extension(&mut self, p: Primitive, immediate: bool) {
+        let whatsit = match p {
+            Primitive::OpenOut => {
+                let n = self.scan_four_bit_int();
+                self.scan_optional_equals();
+                let name = self.scan_file_name();
+                if !self.limits.file_access.can_write() {
+                    self.error(ErrorKind::FileAccessDenied(name));
+                    return;
+                }
+                Whatsit::Open(n, out_name(&name))
+            },
+            Primitive::Write => {
+                let n = match self.scan_int() {
+                    ..0 => LOG_ONLY,
+                    n @ 0..16 => n as u8,
+                    _ => TERMINAL,
+                };
+                Whatsit::Write(n, self.scan_toks(false))
+            },
+            Primitive::CloseOut => Whatsit::Close(self.scan_four_bit_int()),
+            // unlike that of `\write`, the text of `\special` is expanded at once
+            _ => Whatsit::Special(self.scan_toks(true).iter().map(|t| self.token_string(t)).collect()),
+        };
+
+        if immediate {
+            self.out_what(&whatsit);
+        } else {
+            self.tail_append(Node::Whatsit(whatsit));
+        }
+    }
+
+    /// Does what the whatsits on a page say, in order, as TeX does when it ships the page out
+    pub(super) fn ship_out(&mut self, page: &Node) {
+        layout::walk(page, 0.0, 0.0, &mut |n, _, _| {
+            if let Node::Whatsit(w) = n {
+                self.out_what(w);
+            }
+        });
+    }
+
+    /// Closes the files still open at the end of the job
+    pub(super) fn close_files(&mut self) {
+        let mut open: Vec<_> = self.write_files.keys().copied().collect();
+        open.sort();
+        for n in open {
+            self.close_out(n);
+        }
+    }
+
+    fn out_what(&mut self, whatsit: &Whatsit) {
+        match whatsit {
+            Whatsit::Open(n, name) => {
+                self.close_out(*n);
+                match self.sink.open(name) {
+                    Ok(()) => {
+                        self.write_files.insert(*n, name.clone());
+                    },
+                    Err(_) => self.error(ErrorKind::CantWriteFile(name.clone())),
+                }
+            },
+            Whatsit::Write(n, toks) => {
+                let text = self.expand_write(toks);
+                match self.write_files.get(n).cloned() {
+                    Some(name) => {
+                        let text = self.apply_newline_char(&text);
+                        if self.sink.write_line(&name, &text).is_err() {
+                            self.error(ErrorKind::CantWriteFile(name));
+                        }
+                    },
+                    None => self.print_line(&text, *n != LOG_ONLY),
+                }
+            },
+            Whatsit::Close(n) => self.close_out(*n),
+            Whatsit::Special(_) => {},
+        }
+    }
+
+    fn close_out(&mut self, n: u8) {
+        if let Some(name) = self.write_files.remove(&n) {
+            if self.sink.close(&name).is_err() {
+                self.error(ErrorKind::CantWriteFile(name));
+            }
+        }
+    }
+
+    /// Expands the text of a `\write`, reading it back between braces as TeX does
+    fn expand_write(&mut self, toks: &[Token]) -> String {
+        self.back_input(Token::new_char(TokenType::EndGroup, '}', 0..0));
+        self.back_list(toks.iter().cloned());
+        self.back_input(Token::new_char(TokenType::BeginGroup, '{', 0..0));
+        self.scan_toks(true).iter().map(|t| self.token_string(t)).collect()
+    }
+}
+
+/// The name a file opened by `\openout` is written as, adding a `.tex` extension if it has none
+fn out_name(name: &str) -> String {
+    match Path::new(name).extension() {
+        Some(_) => name.to_string(),
+        None => format!("{name}.tex"),
+    }
+}
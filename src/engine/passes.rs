@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use crate::document::Document;
+use super::*;
+
+/// A document typeset as many times as its cross references needed
+#[derive(Debug, Clone)]
+pub struct MultiPass {
+    /// The document as the last run typeset it
+    pub document: Document,
+    /// How many times the document was typeset
+    pub runs: usize,
+    /// Whether the last run wrote the same files as the run before it, rather than the runs
+    /// running out first
+    pub settled: bool,
+    /// The files the last run wrote, by name
+    pub files: HashMap<String, String>,
+}
+
+/// Finds the files written by the previous run first, then those the document could read anyway
+struct AuxResolver {
+    aux: MapResolver,
+    inner: Option<Rc<dyn Resolver>>,
+}
+
+impl Resolver for AuxResolver {
+    fn resolve(&self, name: &str) -> Option<SourceFile> {
+        self.aux.resolve(name).or_else(|| self.inner.as_ref()?.resolve(name))
+    }
+}
+
+impl EngineBuilder {
+    /// Typesets `tex` repeatedly, as LaTeX documents need to resolve their cross references, until
+    /// a run writes the same files as the one before it or `max_runs` runs have been made. Each
+    /// run reads the files the one before it wrote, like `.aux` and `.toc` files.
+    ///
+    /// The files are kept in memory, never on disk, so they can be read and written even if the
+    /// limits do not allow the document to open files. The format is only loaded once.
+    pub fn render_passes(self, tex: &str, max_runs: usize) -> Result<MultiPass, BuildError> {
+        let main = SourceFile { name: "texput".into(), text: tex.into() };
+        let mut engine = self.build(main.clone())?;
+        let format = engine.dump();
+
+        let resolver = core::mem::replace(&mut engine.resolver, Box::new(MapResolver::default()));
+        let inner = engine.limits.file_access.can_read().then(|| Rc::from(resolver));
+        engine.limits.file_access = FileAccess::ReadWrite;
+        engine.sync_files_switch();
+
+        let mut aux = HashMap::new();
+        let mut runs = 0;
+        loop {
+            runs += 1;
+            let sink = MemorySink::new();
+            engine.set_sink(Box::new(sink.clone()));
+            engine.set_resolver(Box::new(AuxResolver { aux: MapResolver::new(aux.clone()), inner: inner.clone() }));
+
+            let document = engine.typeset();
+            let files = sink.files();
+            let settled = files == aux;
+            if settled || runs >= max_runs {
+                return Ok(MultiPass { document, runs, settled, files });
+            }
+
+            aux = files;
+            engine = engine.rerun(&format, main.clone());
+        }
+    }
+}
+
+impl Engine {
+    /// A new engine to typeset `main` from the state in `format`, dumped by this engine, with the
+    /// same settings and terminal
+    fn rerun(&mut self, format: &[u8], main: SourceFile) -> Engine {
+        let mut engine = Engine::new(main);
+        engine.undump(format).expect("the format was dumped by the same engine");
+        engine.font_dirs = self.font_dirs.clone();
+        engine.set_limits(self.limits.clone());
+        engine.math = self.math;
+        engine.format_name = self.format_name.clone();
+        engine.terminal = self.terminal.take();
+        engine
+    }
+}
@@ -0,0 +1,20 @@
+use super::*;
+
+/// The macros of plain TeX that are built in, written in TeX
+const PLAIN: &str = include_str!("plain.tex");
+
+impl Engine {
+    /// Defines the macros of plain TeX that documents typeset without a format have, its accents
+    /// and special letters. They are read before any file, and not as one.
+    pub(super) fn load_plain(&mut self) {
+        let lexer = self.new_lexer(SourceLines::new(PLAIN.into()), None);
+        self.inputs.push(lexer);
+        while let Some((tok, meaning)) = self.get_x_token() {
+            if !self.main_control(tok, meaning) {
+                break;
+            }
+        }
+        self.inputs.clear();
+        self.usage = limits::Usage::default();
+    }
+}
@@ -0,0 +1,818 @@
+use super::Glue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Primitive {
+    Relax,
+    Def,
+    Gdef,
+    Edef,
+    Xdef,
+    Let,
+    Global,
+    Long,
+    Outer,
+    ExpandAfter,
+    NoExpand,
+    CsName,
+    EndCsName,
+    String,
+    Number,
+    RomanNumeral,
+    The,
+    Meaning,
+    JobName,
+    Count,
+    Dimen,
+    Skip,
+    Toks,
+    CountDef,
+    DimenDef,
+    SkipDef,
+    ToksDef,
+    Catcode,
+    LcCode,
+    UcCode,
+    SfCode,
+    MathCode,
+    LowerCase,
+    UpperCase,
+    Message,
+    BeginGroup,
+    EndGroup,
+    Input,
+    EndInput,
+    OpenIn,
+    Read,
+    CloseIn,
+    Par,
+    HSkip,
+    VSkip,
+    HFil,
+    HFill,
+    HSs,
+    VFil,
+    VFill,
+    VSs,
+    Kern,
+    HRule,
+    VRule,
+    HBox,
+    VBox,
+    Indent,
+    NoIndent,
+    End,
+    Dump,
+    BatchMode,
+    NonStopMode,
+    ScrollMode,
+    ErrorStopMode,
+    Patterns,
+    Hyphenation,
+    /// `\-`
+    DiscretionaryHyphen,
+    If,
+    IfCat,
+    IfNum,
+    IfDim,
+    IfOdd,
+    IfVMode,
+    IfHMode,
+    IfMMode,
+    IfInner,
+    IfVoid,
+    IfHBox,
+    IfVBox,
+    IfX,
+    IfEof,
+    IfTrue,
+    IfFalse,
+    IfCase,
+    Else,
+    Or,
+    Fi,
+    Advance,
+    Multiply,
+    Divide,
+    CharDef,
+    MathCharDef,
+    MuSkip,
+    MuSkipDef,
+    Char,
+    Font,
+    FontDimen,
+    HyphenChar,
+    SkewChar,
+    TextFont,
+    ScriptFont,
+    ScriptScriptFont,
+    DelCode,
+    SetBox,
+    Box,
+    Copy,
+    LastBox,
+    Wd,
+    Ht,
+    Dp,
+    UnHBox,
+    UnVBox,
+    UnHCopy,
+    UnVCopy,
+    VTop,
+    Raise,
+    Lower,
+    MoveLeft,
+    MoveRight,
+    Penalty,
+    HFilNeg,
+    VFilNeg,
+    UnSkip,
+    UnKern,
+    UnPenalty,
+    LastSkip,
+    LastKern,
+    LastPenalty,
+    Accent,
+    IgnoreSpaces,
+    AfterAssignment,
+    AfterGroup,
+    FutureLet,
+    Immediate,
+    OpenOut,
+    Write,
+    CloseOut,
+    Special,
+    ErrMessage,
+    HAlign,
+    Cr,
+    CrCr,
+    NoAlign,
+    Omit,
+    Span,
+    /// Ends the template of an alignment entry, inserted after the `v` part
+    EndTemplate,
+    MathChar,
+    Delimiter,
+    Radical,
+    MathAccent,
+    Left,
+    Right,
+    Over,
+    Atop,
+    Above,
+    OverWithDelims,
+    AtopWithDelims,
+    AboveWithDelims,
+    MathOrd,
+    MathOp,
+    MathBin,
+    MathRel,
+    MathOpen,
+    MathClose,
+    MathPunct,
+    MathInner,
+    Underline,
+    Overline,
+    Limits,
+    NoLimits,
+    DisplayLimits,
+    VCenter,
+    DisplayStyle,
+    TextStyle,
+    ScriptStyle,
+    ScriptScriptStyle,
+    EqNo,
+    LEqNo,
+    MKern,
+    MSkip,
+    /// `\ `
+    ControlSpace,
+    /// `\/`
+    ItalicCorrection,
+}
+
+pub const PRIMITIVES: &[(&str, Primitive)] = &[
+    ("relax", Primitive::Relax),
+    ("def", Primitive::Def),
+    ("gdef", Primitive::Gdef),
+    ("edef", Primitive::Edef),
+    ("xdef", Primitive::Xdef),
+    ("let", Primitive::Let),
+    ("global", Primitive::Global),
+    ("long", Primitive::Long),
+    ("outer", Primitive::Outer),
+    ("expandafter", Primitive::ExpandAfter),
+    ("noexpand", Primitive::NoExpand),
+    ("csname", Primitive::CsName),
+    ("endcsname", Primitive::EndCsName),
+    ("string", Primitive::String),
+    ("number", Primitive::Number),
+    ("romannumeral", Primitive::RomanNumeral),
+    ("the", Primitive::The),
+    ("meaning", Primitive::Meaning),
+    ("jobname", Primitive::JobName),
+    ("count", Primitive::Count),
+    ("dimen", Primitive::Dimen),
+    ("skip", Primitive::Skip),
+    ("toks", Primitive::Toks),
+    ("countdef", Primitive::CountDef),
+    ("dimendef", Primitive::DimenDef),
+    ("skipdef", Primitive::SkipDef),
+    ("toksdef", Primitive::ToksDef),
+    ("catcode", Primitive::Catcode),
+    ("lccode", Primitive::LcCode),
+    ("uccode", Primitive::UcCode),
+    ("sfcode", Primitive::SfCode),
+    ("mathcode", Primitive::MathCode),
+    ("lowercase", Primitive::LowerCase),
+    ("uppercase", Primitive::UpperCase),
+    ("message", Primitive::Message),
+    ("begingroup", Primitive::BeginGroup),
+    ("endgroup", Primitive::EndGroup),
+    ("input", Primitive::Input),
+    ("endinput", Primitive::EndInput),
+    ("openin", Primitive::OpenIn),
+    ("read", Primitive::Read),
+    ("closein", Primitive::CloseIn),
+    ("par", Primitive::Par),
+    ("hskip", Primitive::HSkip),
+    ("vskip", Primitive::VSkip),
+    ("hfil", Primitive::HFil),
+    ("hfill", Primitive::HFill),
+    ("hss", Primitive::HSs),
+    ("vfil", Primitive::VFil),
+    ("vfill", Primitive::VFill),
+    ("vss", Primitive::VSs),
+    ("kern", Primitive::Kern),
+    ("hrule", Primitive::HRule),
+    ("vrule", Primitive::VRule),
+    ("hbox", Primitive::HBox),
+    ("vbox", Primitive::VBox),
+    ("indent", Primitive::Indent),
+    ("noindent", Primitive::NoIndent),
+    ("end", Primitive::End),
+    ("dump", Primitive::Dump),
+    ("batchmode", Primitive::BatchMode),
+    ("nonstopmode", Primitive::NonStopMode),
+    ("scrollmode", Primitive::ScrollMode),
+    ("errorstopmode", Primitive::ErrorStopMode),
+    ("patterns", Primitive::Patterns),
+    ("hyphenation", Primitive::Hyphenation),
+    ("-", Primitive::DiscretionaryHyphen),
+    ("if", Primitive::If),
+    ("ifcat", Primitive::IfCat),
+    ("ifnum", Primitive::IfNum),
+    ("ifdim", Primitive::IfDim),
+    ("ifodd", Primitive::IfOdd),
+    ("ifvmode", Primitive::IfVMode),
+    ("ifhmode", Primitive::IfHMode),
+    ("ifmmode", Primitive::IfMMode),
+    ("ifinner", Primitive::IfInner),
+    ("ifvoid", Primitive::IfVoid),
+    ("ifhbox", Primitive::IfHBox),
+    ("ifvbox", Primitive::IfVBox),
+    ("ifx", Primitive::IfX),
+    ("ifeof", Primitive::IfEof),
+    ("iftrue", Primitive::IfTrue),
+    ("iffalse", Primitive::IfFalse),
+    ("ifcase", Primitive::IfCase),
+    ("else", Primitive::Else),
+    ("or", Primitive::Or),
+    ("fi", Primitive::Fi),
+    ("advance", Primitive::Advance),
+    ("multiply", Primitive::Multiply),
+    ("divide", Primitive::Divide),
+    ("chardef", Primitive::CharDef),
+    ("mathchardef", Primitive::MathCharDef),
+    ("muskip", Primitive::MuSkip),
+    ("muskipdef", Primitive::MuSkipDef),
+    ("char", Primitive::Char),
+    ("font", Primitive::Font),
+    ("fontdimen", Primitive::FontDimen),
+    ("hyphenchar", Primitive::HyphenChar),
+    ("skewchar", Primitive::SkewChar),
+    ("textfont", Primitive::TextFont),
+    ("scriptfont", Primitive::ScriptFont),
+    ("scriptscriptfont", Primitive::ScriptScriptFont),
+    ("delcode", Primitive::DelCode),
+    ("setbox", Primitive::SetBox),
+    ("box", Primitive::Box),
+    ("copy", Primitive::Copy),
+    ("lastbox", Primitive::LastBox),
+    ("wd", Primitive::Wd),
+    ("ht", Primitive::Ht),
+    ("dp", Primitive::Dp),
+    ("unhbox", Primitive::UnHBox),
+    ("unvbox", Primitive::UnVBox),
+    ("unhcopy", Primitive::UnHCopy),
+    ("unvcopy", Primitive::UnVCopy),
+    ("vtop", Primitive::VTop),
+    ("raise", Primitive::Raise),
+    ("lower", Primitive::Lower),
+    ("moveleft", Primitive::MoveLeft),
+    ("moveright", Primitive::MoveRight),
+    ("penalty", Primitive::Penalty),
+    ("hfilneg", Primitive::HFilNeg),
+    ("vfilneg", Primitive::VFilNeg),
+    ("unskip", Primitive::UnSkip),
+    ("unkern", Primitive::UnKern),
+    ("unpenalty", Primitive::UnPenalty),
+    ("lastskip", Primitive::LastSkip),
+    ("lastkern", Primitive::LastKern),
+    ("lastpenalty", Primitive::LastPenalty),
+    ("accent", Primitive::Accent),
+    ("ignorespaces", Primitive::IgnoreSpaces),
+    ("afterassignment", Primitive::AfterAssignment),
+    ("aftergroup", Primitive::AfterGroup),
+    ("futurelet", Primitive::FutureLet),
+    ("immediate", Primitive::Immediate),
+    ("openout", Primitive::OpenOut),
+    ("write", Primitive::Write),
+    ("closeout", Primitive::CloseOut),
+    ("special", Primitive::Special),
+    ("errmessage", Primitive::ErrMessage),
+    ("halign", Primitive::HAlign),
+    ("cr", Primitive::Cr),
+    ("crcr", Primitive::CrCr),
+    ("noalign", Primitive::NoAlign),
+    ("omit", Primitive::Omit),
+    ("span", Primitive::Span),
+    ("endtemplate", Primitive::EndTemplate),
+    ("mathchar", Primitive::MathChar),
+    ("delimiter", Primitive::Delimiter),
+    ("radical", Primitive::Radical),
+    ("mathaccent", Primitive::MathAccent),
+    ("left", Primitive::Left),
+    ("right", Primitive::Right),
+    ("over", Primitive::Over),
+    ("atop", Primitive::Atop),
+    ("above", Primitive::Above),
+    ("overwithdelims", Primitive::OverWithDelims),
+    ("atopwithdelims", Primitive::AtopWithDelims),
+    ("abovewithdelims", Primitive::AboveWithDelims),
+    ("mathord", Primitive::MathOrd),
+    ("mathop", Primitive::MathOp),
+    ("mathbin", Primitive::MathBin),
+    ("mathrel", Primitive::MathRel),
+    ("mathopen", Primitive::MathOpen),
+    ("mathclose", Primitive::MathClose),
+    ("mathpunct", Primitive::MathPunct),
+    ("mathinner", Primitive::MathInner),
+    ("underline", Primitive::Underline),
+    ("overline", Primitive::Overline),
+    ("limits", Primitive::Limits),
+    ("nolimits", Primitive::NoLimits),
+    ("displaylimits", Primitive::DisplayLimits),
+    ("vcenter", Primitive::VCenter),
+    ("displaystyle", Primitive::DisplayStyle),
+    ("textstyle", Primitive::TextStyle),
+    ("scriptstyle", Primitive::ScriptStyle),
+    ("scriptscriptstyle", Primitive::ScriptScriptStyle),
+    ("eqno", Primitive::EqNo),
+    ("leqno", Primitive::LEqNo),
+    ("mkern", Primitive::MKern),
+    ("mskip", Primitive::MSkip),
+    (" ", Primitive::ControlSpace),
+    ("/", Primitive::ItalicCorrection),
+];
+
+impl Primitive {
+    pub fn name(self) -> &'static str {
+        PRIMITIVES.iter().find(|(_, p)| *p == self).unwrap().0
+    }
+
+    pub fn is_expandable(self) -> bool {
+        matches!(
+            self,
+            Self::ExpandAfter
+                | Self::NoExpand
+                | Self::CsName
+                | Self::String
+                | Self::Number
+                | Self::RomanNumeral
+                | Self::The
+                | Self::Meaning
+                | Self::JobName
+                | Self::Input
+                | Self::EndInput
+        ) || self.is_conditional()
+            || matches!(self, Self::Else | Self::Or | Self::Fi)
+    }
+
+    /// Whether the primitive starts a conditional, like `\ifnum`
+    pub fn is_conditional(self) -> bool {
+        matches!(
+            self,
+            Self::If
+                | Self::IfCat
+                | Self::IfNum
+                | Self::IfDim
+                | Self::IfOdd
+                | Self::IfVMode
+                | Self::IfHMode
+                | Self::IfMMode
+                | Self::IfInner
+                | Self::IfVoid
+                | Self::IfHBox
+                | Self::IfVBox
+                | Self::IfX
+                | Self::IfEof
+                | Self::IfTrue
+                | Self::IfFalse
+                | Self::IfCase
+        )
+    }
+}
+
+/// Integer parameters, like `\escapechar`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntParam {
+    Mag,
+    EscapeChar,
+    EndLineChar,
+    NewLineChar,
+    Pretolerance,
+    LeftHyphenMin,
+    RightHyphenMin,
+    UcHyph,
+    Language,
+    Tolerance,
+    HBadness,
+    VBadness,
+    LinePenalty,
+    HyphenPenalty,
+    ExHyphenPenalty,
+    ClubPenalty,
+    WidowPenalty,
+    DisplayWidowPenalty,
+    BrokenPenalty,
+    BinOpPenalty,
+    RelPenalty,
+    PreDisplayPenalty,
+    PostDisplayPenalty,
+    InterLinePenalty,
+    DoubleHyphenDemerits,
+    FinalHyphenDemerits,
+    AdjDemerits,
+    DelimiterFactor,
+    Looseness,
+    Time,
+    Day,
+    Month,
+    Year,
+    ShowBoxBreadth,
+    ShowBoxDepth,
+    Pausing,
+    TracingOnline,
+    TracingMacros,
+    TracingStats,
+    TracingParagraphs,
+    TracingPages,
+    TracingOutput,
+    TracingLostChars,
+    TracingCommands,
+    TracingRestores,
+    OutputPenalty,
+    MaxDeadCycles,
+    HangAfter,
+    FloatingPenalty,
+    GlobalDefs,
+    Fam,
+    DefaultHyphenChar,
+    DefaultSkewChar,
+    HoldingInserts,
+    ErrorContextLines,
+}
+
+pub const INT_PARAMS: &[(&str, IntParam)] = &[
+    ("mag", IntParam::Mag),
+    ("escapechar", IntParam::EscapeChar),
+    ("endlinechar", IntParam::EndLineChar),
+    ("newlinechar", IntParam::NewLineChar),
+    ("pretolerance", IntParam::Pretolerance),
+    ("lefthyphenmin", IntParam::LeftHyphenMin),
+    ("righthyphenmin", IntParam::RightHyphenMin),
+    ("uchyph", IntParam::UcHyph),
+    ("language", IntParam::Language),
+    ("tolerance", IntParam::Tolerance),
+    ("hbadness", IntParam::HBadness),
+    ("vbadness", IntParam::VBadness),
+    ("linepenalty", IntParam::LinePenalty),
+    ("hyphenpenalty", IntParam::HyphenPenalty),
+    ("exhyphenpenalty", IntParam::ExHyphenPenalty),
+    ("clubpenalty", IntParam::ClubPenalty),
+    ("widowpenalty", IntParam::WidowPenalty),
+    ("displaywidowpenalty", IntParam::DisplayWidowPenalty),
+    ("brokenpenalty", IntParam::BrokenPenalty),
+    ("binoppenalty", IntParam::BinOpPenalty),
+    ("relpenalty", IntParam::RelPenalty),
+    ("predisplaypenalty", IntParam::PreDisplayPenalty),
+    ("postdisplaypenalty", IntParam::PostDisplayPenalty),
+    ("interlinepenalty", IntParam::InterLinePenalty),
+    ("doublehyphendemerits", IntParam::DoubleHyphenDemerits),
+    ("finalhyphendemerits", IntParam::FinalHyphenDemerits),
+    ("adjdemerits", IntParam::AdjDemerits),
+    ("delimiterfactor", IntParam::DelimiterFactor),
+    ("looseness", IntParam::Looseness),
+    ("time", IntParam::Time),
+    ("day", IntParam::Day),
+    ("month", IntParam::Month),
+    ("year", IntParam::Year),
+    ("showboxbreadth", IntParam::ShowBoxBreadth),
+    ("showboxdepth", IntParam::ShowBoxDepth),
+    ("pausing", IntParam::Pausing),
+    ("tracingonline", IntParam::TracingOnline),
+    ("tracingmacros", IntParam::TracingMacros),
+    ("tracingstats", IntParam::TracingStats),
+    ("tracingparagraphs", IntParam::TracingParagraphs),
+    ("tracingpages", IntParam::TracingPages),
+    ("tracingoutput", IntParam::TracingOutput),
+    ("tracinglostchars", IntParam::TracingLostChars),
+    ("tracingcommands", IntParam::TracingCommands),
+    ("tracingrestores", IntParam::TracingRestores),
+    ("outputpenalty", IntParam::OutputPenalty),
+    ("maxdeadcycles", IntParam::MaxDeadCycles),
+    ("hangafter", IntParam::HangAfter),
+    ("floatingpenalty", IntParam::FloatingPenalty),
+    ("globaldefs", IntParam::GlobalDefs),
+    ("fam", IntParam::Fam),
+    ("defaulthyphenchar", IntParam::DefaultHyphenChar),
+    ("defaultskewchar", IntParam::DefaultSkewChar),
+    ("holdinginserts", IntParam::HoldingInserts),
+    ("errorcontextlines", IntParam::ErrorContextLines),
+];
+
+impl IntParam {
+    pub fn name(self) -> &'static str {
+        INT_PARAMS.iter().find(|(_, p)| *p == self).unwrap().0
+    }
+
+    /// The values set by plain TeX, with the time and date TeX uses when it has no clock
+    pub fn default_value(self) -> i32 {
+        match self {
+            Self::Mag => 1000,
+            Self::EscapeChar => '\\' as i32,
+            Self::EndLineChar => '\r' as i32,
+            Self::NewLineChar => -1,
+            Self::Pretolerance => 100,
+            Self::LeftHyphenMin => 2,
+            Self::RightHyphenMin => 3,
+            Self::UcHyph => 1,
+            Self::Language => 0,
+            Self::Tolerance => 200,
+            Self::HBadness => 1000,
+            Self::VBadness => 1000,
+            Self::LinePenalty => 10,
+            Self::HyphenPenalty => 50,
+            Self::ExHyphenPenalty => 50,
+            Self::ClubPenalty => 150,
+            Self::WidowPenalty => 150,
+            Self::DisplayWidowPenalty => 50,
+            Self::BrokenPenalty => 100,
+            Self::BinOpPenalty => 700,
+            Self::RelPenalty => 500,
+            Self::PreDisplayPenalty => 10000,
+            Self::PostDisplayPenalty => 0,
+            Self::InterLinePenalty => 0,
+            Self::DoubleHyphenDemerits => 10000,
+            Self::FinalHyphenDemerits => 5000,
+            Self::AdjDemerits => 10000,
+            Self::DelimiterFactor => 901,
+            Self::Looseness => 0,
+            Self::Time => 720,
+            Self::Day => 4,
+            Self::Month => 7,
+            Self::Year => 1776,
+            Self::ShowBoxBreadth => 5,
+            Self::ShowBoxDepth => 3,
+            Self::Pausing => 0,
+            Self::TracingOnline => 0,
+            Self::TracingMacros => 0,
+            Self::TracingStats => 0,
+            Self::TracingParagraphs => 0,
+            Self::TracingPages => 0,
+            Self::TracingOutput => 0,
+            Self::TracingLostChars => 1,
+            Self::TracingCommands => 0,
+            Self::TracingRestores => 0,
+            Self::OutputPenalty => 0,
+            Self::MaxDeadCycles => 25,
+            Self::HangAfter => 1,
+            Self::FloatingPenalty => 0,
+            Self::GlobalDefs => 0,
+            Self::Fam => 0,
+            Self::DefaultHyphenChar => '-' as i32,
+            Self::DefaultSkewChar => -1,
+            Self::HoldingInserts => 0,
+            Self::ErrorContextLines => 5,
+        }
+    }
+}
+
+/// Dimension parameters, like `\hsize`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DimenParam {
+    HSize,
+    VSize,
+    ParIndent,
+    LineSkipLimit,
+    MathSurround,
+    MaxDepth,
+    SplitMaxDepth,
+    BoxMaxDepth,
+    HFuzz,
+    VFuzz,
+    DelimiterShortfall,
+    NullDelimiterSpace,
+    ScriptSpace,
+    PreDisplaySize,
+    DisplayWidth,
+    DisplayIndent,
+    OverfullRule,
+    HangIndent,
+    HOffset,
+    VOffset,
+    EmergencyStretch,
+}
+
+pub const DIMEN_PARAMS: &[(&str, DimenParam)] = &[
+    ("hsize", DimenParam::HSize),
+    ("vsize", DimenParam::VSize),
+    ("parindent", DimenParam::ParIndent),
+    ("lineskiplimit", DimenParam::LineSkipLimit),
+    ("mathsurround", DimenParam::MathSurround),
+    ("maxdepth", DimenParam::MaxDepth),
+    ("splitmaxdepth", DimenParam::SplitMaxDepth),
+    ("boxmaxdepth", DimenParam::BoxMaxDepth),
+    ("hfuzz", DimenParam::HFuzz),
+    ("vfuzz", DimenParam::VFuzz),
+    ("delimitershortfall", DimenParam::DelimiterShortfall),
+    ("nulldelimiterspace", DimenParam::NullDelimiterSpace),
+    ("scriptspace", DimenParam::ScriptSpace),
+    ("predisplaysize", DimenParam::PreDisplaySize),
+    ("displaywidth", DimenParam::DisplayWidth),
+    ("displayindent", DimenParam::DisplayIndent),
+    ("overfullrule", DimenParam::OverfullRule),
+    ("hangindent", DimenParam::HangIndent),
+    ("hoffset", DimenParam::HOffset),
+    ("voffset", DimenParam::VOffset),
+    ("emergencystretch", DimenParam::EmergencyStretch),
+];
+
+impl DimenParam {
+    pub fn name(self) -> &'static str {
+        DIMEN_PARAMS.iter().find(|(_, p)| *p == self).unwrap().0
+    }
+
+    /// The values set by plain TeX, in scaled points
+    pub fn default_value(self) -> i32 {
+        match self {
+            Self::HSize => 30785863,
+            Self::VSize => 42152922,
+            Self::ParIndent => 20 * 0x10000,
+            Self::LineSkipLimit => 0,
+            Self::MathSurround => 0,
+            Self::MaxDepth => 4 * 0x10000,
+            Self::SplitMaxDepth => 0x3fffffff,
+            Self::BoxMaxDepth => 0x3fffffff,
+            Self::HFuzz => 6554,
+            Self::VFuzz => 6554,
+            Self::DelimiterShortfall => 5 * 0x10000,
+            Self::NullDelimiterSpace => 78643,
+            Self::ScriptSpace => 0x8000,
+            Self::PreDisplaySize => 0,
+            Self::DisplayWidth => 0,
+            Self::DisplayIndent => 0,
+            Self::OverfullRule => 5 * 0x10000,
+            Self::HangIndent => 0,
+            Self::HOffset => 0,
+            Self::VOffset => 0,
+            Self::EmergencyStretch => 0,
+        }
+    }
+}
+
+/// Glue parameters, like `\baselineskip`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GlueParam {
+    BaselineSkip,
+    LineSkip,
+    ParSkip,
+    ParFillSkip,
+    AboveDisplaySkip,
+    BelowDisplaySkip,
+    AboveDisplayShortSkip,
+    BelowDisplayShortSkip,
+    LeftSkip,
+    RightSkip,
+    TopSkip,
+    SplitTopSkip,
+    TabSkip,
+    SpaceSkip,
+    XSpaceSkip,
+}
+
+pub const GLUE_PARAMS: &[(&str, GlueParam)] = &[
+    ("baselineskip", GlueParam::BaselineSkip),
+    ("lineskip", GlueParam::LineSkip),
+    ("parskip", GlueParam::ParSkip),
+    ("parfillskip", GlueParam::ParFillSkip),
+    ("abovedisplayskip", GlueParam::AboveDisplaySkip),
+    ("belowdisplayskip", GlueParam::BelowDisplaySkip),
+    ("abovedisplayshortskip", GlueParam::AboveDisplayShortSkip),
+    ("belowdisplayshortskip", GlueParam::BelowDisplayShortSkip),
+    ("leftskip", GlueParam::LeftSkip),
+    ("rightskip", GlueParam::RightSkip),
+    ("topskip", GlueParam::TopSkip),
+    ("splittopskip", GlueParam::SplitTopSkip),
+    ("tabskip", GlueParam::TabSkip),
+    ("spaceskip", GlueParam::SpaceSkip),
+    ("xspaceskip", GlueParam::XSpaceSkip),
+];
+
+impl GlueParam {
+    pub fn name(self) -> &'static str {
+        GLUE_PARAMS.iter().find(|(_, p)| *p == self).unwrap().0
+    }
+
+    /// The values set by plain TeX
+    pub fn default_value(self) -> Glue {
+        match self {
+            Self::BaselineSkip => Glue { width: 12 * 0x10000, ..Glue::default() },
+            Self::LineSkip => Glue { width: 0x10000, ..Glue::default() },
+            Self::ParSkip => Glue { stretch: 0x10000, ..Glue::default() },
+            Self::ParFillSkip => Glue { stretch: 0x10000, stretch_order: 1, ..Glue::default() },
+            Self::AboveDisplaySkip => Glue { width: 12 * 0x10000, stretch: 3 * 0x10000, shrink: 9 * 0x10000, ..Glue::default() },
+            Self::BelowDisplaySkip => Glue { width: 12 * 0x10000, stretch: 3 * 0x10000, shrink: 9 * 0x10000, ..Glue::default() },
+            Self::AboveDisplayShortSkip => Glue { stretch: 3 * 0x10000, ..Glue::default() },
+            Self::BelowDisplayShortSkip => Glue { width: 7 * 0x10000, stretch: 3 * 0x10000, shrink: 4 * 0x10000, ..Glue::default() },
+            Self::LeftSkip => Glue::default(),
+            Self::RightSkip => Glue::default(),
+            Self::TopSkip => Glue { width: 10 * 0x10000, ..Glue::default() },
+            Self::SplitTopSkip => Glue { width: 10 * 0x10000, ..Glue::default() },
+            Self::TabSkip => Glue::default(),
+            Self::SpaceSkip => Glue::default(),
+            Self::XSpaceSkip => Glue::default(),
+        }
+    }
+}
+
+/// Glue parameters in math units, like `\thinmuskip`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MuGlueParam {
+    ThinMuSkip,
+    MedMuSkip,
+    ThickMuSkip,
+}
+
+pub const MU_GLUE_PARAMS: &[(&str, MuGlueParam)] = &[
+    ("thinmuskip", MuGlueParam::ThinMuSkip),
+    ("medmuskip", MuGlueParam::MedMuSkip),
+    ("thickmuskip", MuGlueParam::ThickMuSkip),
+];
+
+impl MuGlueParam {
+    pub fn name(self) -> &'static str {
+        MU_GLUE_PARAMS.iter().find(|(_, p)| *p == self).unwrap().0
+    }
+
+    /// The values set by plain TeX, with `mu` in place of `pt`
+    pub fn default_value(self) -> Glue {
+        match self {
+            Self::ThinMuSkip => Glue { width: 3 * 0x10000, ..Glue::default() },
+            Self::MedMuSkip => Glue { width: 4 * 0x10000, stretch: 2 * 0x10000, shrink: 4 * 0x10000, ..Glue::default() },
+            Self::ThickMuSkip => Glue { width: 5 * 0x10000, stretch: 5 * 0x10000, ..Glue::default() },
+        }
+    }
+}
+
+/// Token list parameters, like `\everypar`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ToksParam {
+    Output,
+    EveryPar,
+    EveryMath,
+    EveryDisplay,
+    EveryHBox,
+    EveryVBox,
+    EveryJob,
+    EveryCr,
+    ErrHelp,
+}
+
+pub const TOKS_PARAMS: &[(&str, ToksParam)] = &[
+    ("output", ToksParam::Output),
+    ("everypar", ToksParam::EveryPar),
+    ("everymath", ToksParam::EveryMath),
+    ("everydisplay", ToksParam::EveryDisplay),
+    ("everyhbox", ToksParam::EveryHBox),
+    ("everyvbox", ToksParam::EveryVBox),
+    ("everyjob", ToksParam::EveryJob),
+    ("everycr", ToksParam::EveryCr),
+    ("errhelp", ToksParam::ErrHelp),
+];
+
+impl ToksParam {
+    pub fn name(self) -> &'static str {
+        TOKS_PARAMS.iter().find(|(_, p)| *p == self).unwrap().0
+    }
+}
@@ -0,0 +1,606 @@
+use std::rc::Rc;
+
+use crate::font::FontId;
+use crate::layout::Node;
+use crate::lexer::{Token, TokenType};
+use super::*;
+
+/// One scaled point
+pub const UNITY: i32 = 0x10000;
+const MAX_DIMEN: i32 = 0x3fffffff;
+
+/// A value read from an internal quantity, like `\count0` or `\escapechar`
+pub enum Internal {
+    Int(i32),
+    /// In scaled points
+    Dimen(i32),
+    Glue(Glue),
+    /// Glue in math units
+    MuGlue(Glue),
+    Toks(Rc<[Token]>),
+    Font(FontId),
+}
+
+impl Engine {
+    pub(super) fn get_x_nonblank(&mut self) -> Option<Command> {
+        loop {
+            let c = self.get_x_token()?;
+            if c.0.typ != TokenType::Space {
+                return Some(c);
+            }
+        }
+    }
+
+    pub(super) fn get_x_nonblank_nonrelax(&mut self) -> Option<Command> {
+        loop {
+            let c = self.get_x_nonblank()?;
+            if !matches!(c.1, Some(Meaning::Primitive(Primitive::Relax))) {
+                return Some(c);
+            }
+        }
+    }
+
+    pub(super) fn scan_optional_space(&mut self) {
+        if let Some((t, _)) = self.get_x_token() {
+            if t.typ != TokenType::Space {
+                self.back_input(t);
+            }
+        }
+    }
+
+    pub(super) fn scan_optional_equals(&mut self) {
+        if let Some((t, _)) = self.get_x_nonblank() {
+            if !t.is_other('=') {
+                self.back_input(t);
+            }
+        }
+    }
+
+    /// Scans for a case-insensitive keyword, leaving the input untouched if it is not there
+    pub(super) fn scan_keyword(&mut self, keyword: &str) -> bool {
+        let mut scanned = Vec::new();
+        let mut chars = keyword.chars().peekable();
+
+        while let Some(k) = chars.peek() {
+            let Some((t, _)) = self.get_x_token() else { return false };
+
+            if matches!(t.typ, TokenType::Letter | TokenType::Other) && t.char().eq_ignore_ascii_case(k) {
+                scanned.push(t);
+                chars.next();
+            } else if t.typ != TokenType::Space || !scanned.is_empty() {
+                self.back_input(t);
+                self.back_list(scanned.into_iter());
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Scans the value of an internal quantity with the given meaning
+    pub(super) fn scan_internal(&mut self, m: &Meaning) -> Option<Internal> {
+        self.nested(|e| e.scan_internal_value(m)).flatten()
+    }
+
+    fn scan_internal_value(&mut self, m: &Meaning) -> Option<Internal> {
+        Some(match m {
+            Meaning::Primitive(Primitive::Count) => {
+                let n = self.scan_register();
+                Internal::Int(self.eqtb.int(&Key::Count(n)))
+            },
+            Meaning::Primitive(Primitive::Dimen) => {
+                let n = self.scan_register();
+                Internal::Dimen(self.eqtb.int(&Key::Dimen(n)))
+            },
+            Meaning::Primitive(Primitive::Skip) => {
+                let n = self.scan_register();
+                Internal::Glue(self.eqtb.glue(&Key::Skip(n)))
+            },
+            Meaning::Primitive(Primitive::Toks) => {
+                let n = self.scan_register();
+                Internal::Toks(self.eqtb.toks(&Key::Toks(n)))
+            },
+            Meaning::Primitive(Primitive::Catcode) => {
+                let c = self.scan_char_code();
+                Internal::Int(self.catcode(c) as i32)
+            },
+            Meaning::Primitive(p @ (Primitive::LcCode | Primitive::UcCode | Primitive::SfCode | Primitive::MathCode)) => {
+                let c = self.scan_char_code();
+                Internal::Int(self.eqtb.int(&code_key(*p, c)))
+            },
+            Meaning::Primitive(Primitive::MuSkip) => {
+                let n = self.scan_register();
+                Internal::MuGlue(self.eqtb.glue(&Key::MuSkip(n)))
+            },
+            Meaning::Primitive(Primitive::DelCode) => {
+                let c = self.scan_char_code();
+                Internal::Int(self.eqtb.int(&Key::DelCode(c)))
+            },
+            Meaning::Primitive(p @ (Primitive::Wd | Primitive::Ht | Primitive::Dp)) => {
+                let n = self.scan_register();
+                let i = match p {
+                    Primitive::Wd => 0,
+                    Primitive::Ht => 1,
+                    _ => 2,
+                };
+                Internal::Dimen(self.eqtb.box_reg(n).map_or(0, |b| to_scaled(b.size()[i])))
+            },
+            Meaning::Primitive(Primitive::FontDimen) => {
+                let (n, f) = self.scan_font_dimen();
+                Internal::Dimen(self.font(f).params.get(n).map_or(0, to_scaled))
+            },
+            Meaning::Primitive(Primitive::HyphenChar) => {
+                let f = self.scan_font_ident();
+                Internal::Int(self.font(f).hyphen_char)
+            },
+            Meaning::Primitive(Primitive::SkewChar) => {
+                let f = self.scan_font_ident();
+                Internal::Int(self.font(f).skew_char)
+            },
+            Meaning::Primitive(Primitive::Font) => Internal::Font(self.eqtb.font(&Key::CurFont)),
+            Meaning::Primitive(p @ (Primitive::TextFont | Primitive::ScriptFont | Primitive::ScriptScriptFont)) => {
+                let fam = self.scan_four_bit_int();
+                Internal::Font(self.eqtb.font(&math_font_key(*p, fam)))
+            },
+            Meaning::Primitive(Primitive::LastSkip) => match self.list().nodes.last() {
+                Some(Node::Glue { nat_size, stretchability, shrinkability, stretch_order, shrink_order }) => Internal::Glue(Glue {
+                    width: to_scaled(*nat_size),
+                    stretch: to_scaled(*stretchability),
+                    stretch_order: *stretch_order,
+                    shrink: to_scaled(*shrinkability),
+                    shrink_order: *shrink_order,
+                }),
+                _ => Internal::Glue(Glue::default()),
+            },
+            Meaning::Primitive(Primitive::LastKern) => match self.list().nodes.last() {
+                Some(Node::Kern(k)) => Internal::Dimen(to_scaled(*k)),
+                _ => Internal::Dimen(0),
+            },
+            Meaning::Primitive(Primitive::LastPenalty) => match self.list().nodes.last() {
+                Some(Node::Penalty(p)) => Internal::Int(*p),
+                _ => Internal::Int(0),
+            },
+            Meaning::CountDef(n) => Internal::Int(self.eqtb.int(&Key::Count(*n))),
+            Meaning::DimenDef(n) => Internal::Dimen(self.eqtb.int(&Key::Dimen(*n))),
+            Meaning::SkipDef(n) => Internal::Glue(self.eqtb.glue(&Key::Skip(*n))),
+            Meaning::MuSkipDef(n) => Internal::MuGlue(self.eqtb.glue(&Key::MuSkip(*n))),
+            Meaning::ToksDef(n) => Internal::Toks(self.eqtb.toks(&Key::Toks(*n))),
+            Meaning::IntParam(p) => Internal::Int(self.int_param(*p)),
+            Meaning::DimenParam(p) => Internal::Dimen(self.eqtb.int(&Key::DimenParam(*p))),
+            Meaning::GlueParam(p) => Internal::Glue(self.eqtb.glue(&Key::GlueParam(*p))),
+            Meaning::MuGlueParam(p) => Internal::MuGlue(self.eqtb.glue(&Key::MuGlueParam(*p))),
+            Meaning::ToksParam(p) => Internal::Toks(self.eqtb.toks(&Key::ToksParam(*p))),
+            Meaning::CharDef(c) => Internal::Int(*c as i32),
+            Meaning::MathCharDef(n) => Internal::Int(*n),
+            Meaning::Font(f) => Internal::Font(*f),
+            _ => return None,
+        })
+    }
+
+    pub(super) fn scan_register(&mut self) -> u8 {
+        let n = self.scan_int();
+        u8::try_from(n).unwrap_or_else(|_| {
+            self.error(ErrorKind::BadRegisterCode(n));
+            0
+        })
+    }
+
+    /// Scans a font identifier, like `\tenrm`, `\font` or `\textfont1`
+    pub(super) fn scan_font_ident(&mut self) -> FontId {
+        match self.get_x_nonblank() {
+            Some((_, Some(Meaning::Font(f)))) => f,
+            Some((_, Some(Meaning::Primitive(Primitive::Font)))) => self.eqtb.font(&Key::CurFont),
+            Some((_, Some(Meaning::Primitive(p @ (Primitive::TextFont | Primitive::ScriptFont | Primitive::ScriptScriptFont))))) => {
+                let fam = self.scan_four_bit_int();
+                self.eqtb.font(&math_font_key(p, fam))
+            },
+            t => {
+                self.error(ErrorKind::MissingFontIdentifier);
+                if let Some((t, _)) = t {
+                    self.back_input(t);
+                }
+                FontId::NULL
+            },
+        }
+    }
+
+    /// Scans the parameter number and font after `\fontdimen`
+    pub(super) fn scan_font_dimen(&mut self) -> (i32, FontId) {
+        let n = self.scan_int();
+        let f = self.scan_font_ident();
+        if self.font(f).params.get(n).is_none() {
+            self.error(ErrorKind::FontDimenMissing(self.font(f).name.clone(), self.font(f).params.count()));
+        }
+        (n, f)
+    }
+
+    pub(super) fn scan_char_code(&mut self) -> char {
+        let n = self.scan_int();
+        let max = self.eqtb.encoding.max_char();
+        u32::try_from(n).ok().filter(|n| *n <= max).and_then(char::from_u32).unwrap_or_else(|| {
+            self.error(ErrorKind::BadCharacterCode(n));
+            '\0'
+        })
+    }
+
+    /// Scans signs and spaces, returning whether the result is negative along with the next token
+    fn scan_signs(&mut self) -> Option<(bool, Command)> {
+        let mut negative = false;
+
+        loop {
+            let (t, m) = self.get_x_nonblank()?;
+            if t.is_other('-') {
+                negative = !negative;
+            } else if !t.is_other('+') {
+                return Some((negative, (t, m)));
+            }
+        }
+    }
+
+    fn scan_digits(&mut self, radix: u32, first: Option<u32>) -> i32 {
+        let mut value = first.unwrap_or(0) as i64;
+        let mut too_big = false;
+
+        while let Some((t, _)) = self.get_x_token() {
+            let digit = match t.typ {
+                TokenType::Other => t.char().to_digit(radix),
+                TokenType::Letter if radix == 16 => t.char().to_digit(16).filter(|_| t.char().is_ascii_uppercase()),
+                _ => None,
+            };
+
+            match digit {
+                Some(d) => {
+                    value = value * radix as i64 + d as i64;
+                    if value > i32::MAX as i64 {
+                        value = i32::MAX as i64;
+                        too_big = true;
+                    }
+                },
+                None => {
+                    if t.typ != TokenType::Space {
+                        self.back_input(t);
+                    }
+                    break;
+                },
+            }
+        }
+
+        if too_big {
+            self.error(ErrorKind::NumberTooBig);
+        }
+
+        value as i32
+    }
+
+    pub(super) fn scan_int(&mut self) -> i32 {
+        let Some((negative, (t, m))) = self.scan_signs() else { return 0 };
+
+        let value = if t.is_other('`') {
+            let code = self.get_next().map_or(0, |(t, _)| {
+                // a brace given as a character code does not count towards alignments
+                match t.typ {
+                    TokenType::BeginGroup => self.align_state -= 1,
+                    TokenType::EndGroup => self.align_state += 1,
+                    _ => {},
+                }
+                match t.cs().map(|cs| self.cs.name(cs)) {
+                    Some(name) if name.chars().count() == 1 => name.chars().next().unwrap() as i32,
+                    Some(_) => {
+                        self.error(ErrorKind::ImproperAlphabeticConstant);
+                        '0' as i32
+                    },
+                    None => t.char() as i32,
+                }
+            });
+            self.scan_optional_space();
+            code
+        } else if t.is_other('\'') {
+            self.scan_digits(8, None)
+        } else if t.is_other('"') {
+            self.scan_digits(16, None)
+        } else if let Some(d) = t.char().to_digit(10).filter(|_| t.typ == TokenType::Other) {
+            self.scan_digits(10, Some(d))
+        } else {
+            match m.and_then(|m| self.scan_internal(&m)) {
+                Some(Internal::Int(i) | Internal::Dimen(i) | Internal::Glue(Glue { width: i, .. })) => i,
+                _ => {
+                    self.error(ErrorKind::MissingNumber);
+                    self.back_input(t);
+                    0
+                },
+            }
+        };
+
+        if negative { -value } else { value }
+    }
+
+    /// Scans a dimension in scaled points
+    pub(super) fn scan_dimen(&mut self) -> i32 {
+        self.scan_dimen_inf(false, false).0
+    }
+
+    /// Scans a dimension in math units, as for `\mkern`
+    pub(super) fn scan_mu_dimen(&mut self) -> i32 {
+        self.scan_dimen_inf(false, true).0
+    }
+
+    /// Scans a dimension, allowing `fil`, `fill` and `filll` units if `inf` is set and taking
+    /// `mu` as the only other unit if `mu` is. Returns the dimension along with its order of
+    /// infinity.
+    fn scan_dimen_inf(&mut self, inf: bool, mu: bool) -> (i32, u8) {
+        let Some((negative, (t, m))) = self.scan_signs() else { return (0, 0) };
+        let sign = |v: i32| if negative { -v } else { v };
+
+        let (mut value, mut frac) = if t.is_other('.') || t.is_other(',') {
+            (0, self.scan_fraction())
+        } else if let Some(d) = t.char().to_digit(10).filter(|_| t.typ == TokenType::Other) {
+            let value = self.scan_int_part(d);
+            match self.get_x_token() {
+                Some((t, _)) if t.is_other('.') || t.is_other(',') => (value, self.scan_fraction()),
+                Some((t, _)) => {
+                    self.back_input(t);
+                    (value, 0)
+                },
+                None => (value, 0),
+            }
+        } else {
+            match m.as_ref().and_then(|m| self.scan_internal(m)) {
+                Some(Internal::MuGlue(Glue { width: d, .. })) if mu => return (sign(d), 0),
+                Some(Internal::Dimen(d) | Internal::Glue(Glue { width: d, .. })) if !mu => return (sign(d), 0),
+                Some(Internal::Int(i)) => (i, 0),
+                _ => {
+                    self.back_input(t);
+                    (self.scan_int(), 0)
+                },
+            }
+        };
+
+        if inf && self.scan_keyword("fil") {
+            let mut order = 1;
+            while self.scan_keyword("l") {
+                if order == 3 {
+                    self.error(ErrorKind::IllegalUnit("replaced by filll"));
+                } else {
+                    order += 1;
+                }
+            }
+
+            self.scan_optional_space();
+            return (sign(self.attach_fraction(value, frac)), order);
+        }
+
+        // units that are internal dimensions
+        if let Some((t, m)) = self.get_x_nonblank() {
+            match m.as_ref().and_then(|m| self.scan_internal(m)) {
+                Some(Internal::MuGlue(Glue { width: v, .. })) if mu => return (sign(nx_plus_frac(value, frac, v)), 0),
+                Some(Internal::Dimen(v) | Internal::Int(v) | Internal::Glue(Glue { width: v, .. })) if !mu => {
+                    return (sign(nx_plus_frac(value, frac, v)), 0);
+                },
+                _ => self.back_input(t),
+            }
+        }
+
+        if mu {
+            if !self.scan_keyword("mu") {
+                self.error(ErrorKind::IllegalUnit("mu inserted"));
+            }
+            self.scan_optional_space();
+            return (sign(self.attach_fraction(value, frac)), 0);
+        }
+
+        let relative = if self.scan_keyword("em") {
+            Some(to_scaled(self.cur_font().params.quad))
+        } else if self.scan_keyword("ex") {
+            Some(to_scaled(self.cur_font().params.x_height))
+        } else {
+            None
+        };
+
+        if let Some(v) = relative {
+            self.scan_optional_space();
+            return (sign(nx_plus_frac(value, frac, v)), 0);
+        }
+
+        if self.scan_keyword("true") {
+            let mag = self.int_param(IntParam::Mag);
+            if mag != 1000 && mag > 0 {
+                let (v, rem) = xn_over_d(value, 1000, mag);
+                let f = (1000 * frac as i64 + UNITY as i64 * rem as i64) / mag as i64;
+                value = v + (f / UNITY as i64) as i32;
+                frac = (f % UNITY as i64) as i32;
+            }
+        }
+
+        const UNITS: &[(&str, i32, i32)] = &[
+            ("in", 7227, 100),
+            ("pc", 12, 1),
+            ("cm", 7227, 254),
+            ("mm", 7227, 2540),
+            ("bp", 7227, 7200),
+            ("dd", 1238, 1157),
+            ("cc", 14856, 1157),
+        ];
+
+        if self.scan_keyword("pt") {
+        } else if let Some((num, denom)) = UNITS.iter().find(|u| self.scan_keyword(u.0)).map(|u| (u.1, u.2)) {
+            let (v, rem) = xn_over_d(value, num, denom);
+            let f = (num as i64 * frac as i64 + UNITY as i64 * rem as i64) / denom as i64;
+            value = v + (f / UNITY as i64) as i32;
+            frac = (f % UNITY as i64) as i32;
+        } else if self.scan_keyword("sp") {
+            self.scan_optional_space();
+            return (sign(value), 0);
+        } else {
+            self.error(ErrorKind::IllegalUnit("pt inserted"));
+        }
+
+        self.scan_optional_space();
+        (sign(self.attach_fraction(value, frac)), 0)
+    }
+
+    /// Scans glue, with optional `plus` and `minus` components
+    pub(super) fn scan_glue(&mut self) -> Glue {
+        self.scan_glue_in(false)
+    }
+
+    /// Scans glue in math units, as for `\muskip`
+    pub(super) fn scan_mu_glue(&mut self) -> Glue {
+        self.scan_glue_in(true)
+    }
+
+    fn scan_glue_in(&mut self, mu: bool) -> Glue {
+        let Some((negative, (t, m))) = self.scan_signs() else { return Glue::default() };
+
+        match m.as_ref().and_then(|m| self.scan_internal(m)) {
+            Some(Internal::Glue(g)) if !mu => return if negative { g.negate() } else { g },
+            Some(Internal::MuGlue(g)) if mu => return if negative { g.negate() } else { g },
+            _ => {},
+        }
+
+        // put the signs back in front of the width
+        self.back_input(t);
+        if negative {
+            self.back_input(Token::new_char(TokenType::Other, '-', 0..0));
+        }
+
+        let mut glue = Glue { width: self.scan_dimen_inf(false, mu).0, ..Glue::default() };
+        if self.scan_keyword("plus") {
+            (glue.stretch, glue.stretch_order) = self.scan_dimen_inf(true, mu);
+        }
+        if self.scan_keyword("minus") {
+            (glue.shrink, glue.shrink_order) = self.scan_dimen_inf(true, mu);
+        }
+
+        glue
+    }
+
+    fn scan_int_part(&mut self, first: u32) -> i32 {
+        let mut value = first as i64;
+
+        while let Some((t, _)) = self.get_x_token() {
+            match t.char().to_digit(10).filter(|_| t.typ == TokenType::Other) {
+                Some(d) => value = (value * 10 + d as i64).min(i32::MAX as i64),
+                None => {
+                    self.back_input(t);
+                    break;
+                },
+            }
+        }
+
+        value as i32
+    }
+
+    /// Scans the digits after a decimal point as a fraction of `UNITY`
+    fn scan_fraction(&mut self) -> i32 {
+        let mut digits = Vec::new();
+
+        while let Some((t, _)) = self.get_x_token() {
+            match t.char().to_digit(10).filter(|_| t.typ == TokenType::Other) {
+                Some(d) if digits.len() < 17 => digits.push(d as i32),
+                Some(_) => {},
+                None => {
+                    if t.typ != TokenType::Space {
+                        self.back_input(t);
+                    }
+                    break;
+                },
+            }
+        }
+
+        round_decimals(&digits)
+    }
+
+    fn attach_fraction(&mut self, value: i32, frac: i32) -> i32 {
+        if value >= 0o40000 {
+            self.error(ErrorKind::DimensionTooLarge);
+            MAX_DIMEN
+        } else {
+            value * UNITY + frac
+        }
+    }
+}
+
+/// The key of `\textfont`, `\scriptfont` or `\scriptscriptfont` for family `fam`
+pub(super) fn math_font_key(p: Primitive, fam: u8) -> Key {
+    let size = match p {
+        Primitive::TextFont => 0,
+        Primitive::ScriptFont => 1,
+        _ => 2,
+    };
+    Key::MathFont(size, fam)
+}
+
+/// Computes `(value + frac / UNITY) * unit`
+fn nx_plus_frac(value: i32, frac: i32, unit: i32) -> i32 {
+    let (scaled, _) = xn_over_d(unit, frac, UNITY);
+    (value as i64 * unit as i64 + scaled as i64).clamp(-MAX_DIMEN as i64, MAX_DIMEN as i64) as i32
+}
+
+/// Converts points to scaled points
+pub fn to_scaled(pt: f32) -> i32 {
+    (pt * UNITY as f32).round() as i32
+}
+
+/// Converts scaled points to points
+pub fn to_pt(sp: i32) -> f32 {
+    sp as f32 / UNITY as f32
+}
+
+fn round_decimals(digits: &[i32]) -> i32 {
+    let mut a = 0;
+    for d in digits.iter().rev() {
+        a = (a + d * 0x20000) / 10;
+    }
+
+    (a + 1) / 2
+}
+
+/// Computes `x * n / d` and its remainder, as TeX does
+fn xn_over_d(x: i32, n: i32, d: i32) -> (i32, i32) {
+    let t = x.unsigned_abs() as i64 * n as i64;
+    let (q, r) = ((t / d as i64) as i32, (t % d as i64) as i32);
+    if x < 0 { (-q, -r) } else { (q, r) }
+}
+
+/// Formats glue as TeX does, with finite components in `unit`
+pub fn print_glue(g: Glue, unit: &str) -> String {
+    let order = |o: u8| [unit, "fil", "fill", "filll"][o as usize];
+    let mut out = print_scaled(g.width) + unit;
+    if g.stretch != 0 {
+        out += &format!(" plus {}{}", print_scaled(g.stretch), order(g.stretch_order));
+    }
+    if g.shrink != 0 {
+        out += &format!(" minus {}{}", print_scaled(g.shrink), order(g.shrink_order));
+    }
+    out
+}
+
+/// Formats scaled points as TeX does, without the unit
+pub fn print_scaled(mut s: i32) -> String {
+    let mut out = String::new();
+    if s < 0 {
+        out.push('-');
+        s = -s;
+    }
+
+    out += &(s / UNITY).to_string();
+    out.push('.');
+
+    let mut s = 10 * (s % UNITY) + 5;
+    let mut delta = 10;
+    loop {
+        if delta > UNITY {
+            s += 0x8000 - 50000;
+        }
+
+        out.push(char::from_digit((s / UNITY) as u32, 10).unwrap());
+        s = 10 * (s % UNITY);
+        delta *= 10;
+
+        if s <= delta {
+            break;
+        }
+    }
+
+    out
+}
@@ -0,0 +1,280 @@
+use core::fmt;
+
+use crate::engine::{print_scaled, to_scaled, BuildError, SourceFile};
+use crate::lexer::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Everything that can go wrong while processing a document
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    InvalidCharacter(char),
+    /// The control sequence, as it was written
+    UndefinedControlSequence(String),
+    MissingEndCsName,
+    FileNotFound(String),
+    /// A file the limits do not allow to be opened
+    FileAccessDenied(String),
+    /// A file opened by `\openout` that could not be written
+    CantWriteFile(String),
+    /// The macro whose parameter text did not match
+    UseDoesntMatchDefinition(String),
+    /// The macro whose argument was still being scanned when the file ended
+    RunawayArgument(String),
+    ArgumentExtraBrace,
+    CantUseAfterThe(String),
+    /// The command, and the command it came after
+    CantUseAfter(String, String),
+    BadRegisterCode(i32),
+    BadCharacterCode(i32),
+    BadNumber(i32),
+    BadMathChar(i32),
+    NumberTooBig,
+    ImproperAlphabeticConstant,
+    MissingNumber,
+    /// What was done instead, like `pt inserted`
+    IllegalUnit(&'static str),
+    DimensionTooLarge,
+    CantUsePrefix(String),
+    /// The code and the largest code allowed
+    InvalidCode(i32, i32),
+    MissingTo,
+    MissingLeftBrace,
+    MissingRightBrace,
+    MissingControlSequence,
+    NonConsecutiveParameters,
+    IllegalParameterNumber,
+    CannotReadTerminal,
+    FileEndedWithinRead,
+    TooManyRightBraces,
+    /// A `}` closing a group started by something else, with what ends that group, like
+    /// `\endgroup` or `$`
+    ExtraRightBrace(&'static str),
+    ExtraEndGroup,
+    ExtraEndCsName,
+    /// An `\else`, `\or` or `\fi` that does not belong to a conditional
+    ExtraCondition(String),
+    /// The conditional the input ended in
+    IncompleteIf(String),
+    /// The comparison a relation was missing from, like `\ifnum`
+    MissingEquals(String),
+    MissingEndGroup,
+    MissingDollar,
+    DisplayMathShouldEnd,
+    /// The kind of math font, `symbol` or `extension`, that has too few parameters
+    InsufficientFonts(&'static str),
+    /// `superscript` or `subscript`
+    DoubleScript(&'static str),
+    LimitControls,
+    MissingDelimiter,
+    ExtraRight,
+    MissingRight,
+    AmbiguousFraction,
+    /// What was misplaced, like `alignment tab character &` or `\cr`
+    Misplaced(String),
+    MissingSharp,
+    OnlyOneSharp,
+    MissingCr,
+    ExtraAlignmentTab,
+    ImproperHalign,
+    AccentInMathMode,
+    MissingFontIdentifier,
+    /// The font and the number of parameters it has
+    FontDimenMissing(String, i32),
+    /// The control sequence being defined and the file name
+    FontNotLoadable(String, String),
+    BoxExpected,
+    IncompatibleUnbox,
+    ArithmeticOverflow,
+    DumpInGroup,
+    /// The text of an `\errmessage`
+    ErrMessage(String),
+    /// The meaning of the command, and the mode it was used in
+    CantUseInMode(String, &'static str),
+    /// A box whose contents do not fit, with `horizontal` set for an `\hbox`, and by how many
+    /// points it is too big
+    OverfullBox { horizontal: bool, excess: f32 },
+    BadPatterns,
+    ImproperHyphenation,
+    /// Processing was stopped after this many errors in a paragraph
+    TooManyErrors(usize),
+    /// Processing was stopped because a resource, like the levels of `\input`, ran out at the
+    /// given limit
+    CapacityExceeded(&'static str, usize),
+}
+
+impl ErrorKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::OverfullBox { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// The messages TeX uses
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidCharacter(_) => write!(f, "Text line contains an invalid character."),
+            Self::UndefinedControlSequence(_) => write!(f, "Undefined control sequence."),
+            Self::MissingEndCsName => write!(f, "Missing \\endcsname inserted."),
+            Self::FileNotFound(name) => write!(f, "I can't find file `{name}'."),
+            Self::FileAccessDenied(name) => write!(f, "I'm not allowed to open file `{name}'."),
+            Self::CantWriteFile(name) => write!(f, "I can't write on file `{name}'."),
+            Self::UseDoesntMatchDefinition(name) => write!(f, "Use of {name} doesn't match its definition."),
+            Self::RunawayArgument(name) => write!(f, "File ended while scanning use of {name}."),
+            Self::ArgumentExtraBrace => write!(f, "Argument has an extra }}."),
+            Self::CantUseAfterThe(name) => write!(f, "You can't use `{name}' after \\the."),
+            Self::CantUseAfter(name, after) => write!(f, "You can't use `{name}' after {after}."),
+            Self::BadRegisterCode(n) => write!(f, "Bad register code ({n})."),
+            Self::BadCharacterCode(n) => write!(f, "Bad character code ({n})."),
+            Self::BadNumber(n) => write!(f, "Bad number ({n})."),
+            Self::BadMathChar(n) => write!(f, "Bad mathchar ({n})."),
+            Self::NumberTooBig => write!(f, "Number too big."),
+            Self::ImproperAlphabeticConstant => write!(f, "Improper alphabetic constant."),
+            Self::MissingNumber => write!(f, "Missing number, treated as zero."),
+            Self::IllegalUnit(fix) => write!(f, "Illegal unit of measure ({fix})."),
+            Self::DimensionTooLarge => write!(f, "Dimension too large."),
+            Self::CantUsePrefix(name) => write!(f, "You can't use a prefix with `{name}'."),
+            Self::InvalidCode(n, max) => write!(f, "Invalid code ({n}), should be at most {max}."),
+            Self::MissingTo => write!(f, "Missing `to' inserted."),
+            Self::MissingLeftBrace => write!(f, "Missing {{ inserted."),
+            Self::MissingRightBrace => write!(f, "Missing }} inserted."),
+            Self::MissingControlSequence => write!(f, "Missing control sequence inserted."),
+            Self::NonConsecutiveParameters => write!(f, "Parameters must be numbered consecutively."),
+            Self::IllegalParameterNumber => write!(f, "Illegal parameter number in definition."),
+            Self::CannotReadTerminal => write!(f, "*** (cannot \\read from terminal in nonstop modes)"),
+            Self::FileEndedWithinRead => write!(f, "File ended within \\read."),
+            Self::TooManyRightBraces => write!(f, "Too many }}'s."),
+            Self::ExtraRightBrace(end) => write!(f, "Extra }}, or forgotten {end}."),
+            Self::ExtraEndGroup => write!(f, "Extra \\endgroup."),
+            Self::ExtraEndCsName => write!(f, "Extra \\endcsname."),
+            Self::ExtraCondition(name) => write!(f, "Extra {name}."),
+            Self::IncompleteIf(name) => write!(f, "Incomplete {name}; all text was ignored after it."),
+            Self::MissingEquals(name) => write!(f, "Missing = inserted for {name}."),
+            Self::MissingEndGroup => write!(f, "Missing \\endgroup inserted."),
+            Self::MissingDollar => write!(f, "Missing $ inserted."),
+            Self::DisplayMathShouldEnd => write!(f, "Display math should end with $$."),
+            Self::InsufficientFonts(kind) => write!(f, "Math formula deleted: Insufficient {kind} fonts."),
+            Self::DoubleScript(kind) => write!(f, "Double {kind}."),
+            Self::LimitControls => write!(f, "Limit controls must follow a math operator."),
+            Self::MissingDelimiter => write!(f, "Missing delimiter (. inserted)."),
+            Self::ExtraRight => write!(f, "Extra \\right."),
+            Self::MissingRight => write!(f, "Missing \\right. inserted."),
+            Self::AmbiguousFraction => write!(f, "Ambiguous; you need another {{ and }}."),
+            Self::Misplaced(what) => write!(f, "Misplaced {what}."),
+            Self::MissingSharp => write!(f, "Missing # inserted in alignment preamble."),
+            Self::OnlyOneSharp => write!(f, "Only one # is allowed per tab."),
+            Self::MissingCr => write!(f, "Missing \\cr inserted."),
+            Self::ExtraAlignmentTab => write!(f, "Extra alignment tab has been changed to \\cr."),
+            Self::ImproperHalign => write!(f, "Improper \\halign inside $$'s."),
+            Self::AccentInMathMode => write!(f, "Please use \\mathaccent for accents in math mode."),
+            Self::MissingFontIdentifier => write!(f, "Missing font identifier."),
+            Self::FontDimenMissing(font, n) => write!(f, "Font {font} has only {n} fontdimen parameters."),
+            Self::FontNotLoadable(cs, name) => write!(f, "Font {cs}={name} not loadable: Metric (TFM) file not found."),
+            Self::BoxExpected => write!(f, "A <box> was supposed to be here."),
+            Self::IncompatibleUnbox => write!(f, "Incompatible list can't be unboxed."),
+            Self::ArithmeticOverflow => write!(f, "Arithmetic overflow."),
+            Self::DumpInGroup => write!(f, "You can't dump inside a group."),
+            Self::ErrMessage(text) => write!(f, "{text}."),
+            Self::CantUseInMode(name, mode) => write!(f, "You can't use `{name}' in {mode}."),
+            Self::BadPatterns => write!(f, "Bad \\patterns."),
+            Self::ImproperHyphenation => write!(f, "Improper \\hyphenation will be flushed."),
+            Self::TooManyErrors(n) => write!(f, "(That makes {n} errors; please try again.)"),
+            Self::CapacityExceeded(resource, n) => write!(f, "TeX capacity exceeded, sorry [{resource}={n}]."),
+            Self::OverfullBox { horizontal, excess } => {
+                let (kind, dir) = if *horizontal { ('h', "wide") } else { ('v', "high") };
+                write!(f, "Overfull \\{kind}box ({}pt too {dir})", print_scaled(to_scaled(*excess)))
+            },
+        }
+    }
+}
+
+/// An error or warning, along with where in the input it happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: ErrorKind,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(kind: ErrorKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+
+    /// Formats the diagnostic like TeX does, with the offending line broken after the span:
+    ///
+    /// ```text
+    /// ! Undefined control sequence.
+    /// l.2 Some \foo
+    ///              bar
+    /// ```
+    pub fn render(&self, files: &[SourceFile]) -> String {
+        let mut out = match self.severity() {
+            Severity::Error => format!("! {}", self.kind),
+            Severity::Warning => self.kind.to_string(),
+        };
+
+        let Some(file) = self.span.file.and_then(|f| files.get(f.0 as usize)) else { return out };
+        let text = &file.text;
+        let Some(before) = text.get(..self.span.range.end) else { return out };
+
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[self.span.range.end..].find('\n').map_or(text.len(), |i| self.span.range.end + i);
+        let line = before.matches('\n').count() + 1;
+
+        match self.severity() {
+            Severity::Error => {
+                let prefix = format!("l.{line} {}", &text[line_start..self.span.range.end]);
+                let rest = text[self.span.range.end..line_end].trim_end();
+                out += &format!("\n{prefix}\n{}{rest}", " ".repeat(prefix.chars().count()));
+                out.truncate(out.trim_end().len());
+            },
+            Severity::Warning => {
+                let first = text[..self.span.range.start.min(text.len())].matches('\n').count() + 1;
+                out += &format!(" at lines {first}--{line}");
+            },
+        }
+
+        out
+    }
+}
+
+/// Why a formula could not be rendered
+#[derive(Debug)]
+pub enum Error {
+    /// The engine could not be set up
+    Build(BuildError),
+    /// The formula has errors, which are given with the files read, as their spans refer to them
+    Tex(Vec<Diagnostic>, Vec<SourceFile>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Build(e) => e.fmt(f),
+            Self::Tex(diagnostics, files) => {
+                let errors = diagnostics.iter().filter(|d| d.severity() == Severity::Error);
+                let rendered: Vec<_> = errors.map(|d| d.render(files)).collect();
+                f.write_str(&rendered.join("\n"))
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<BuildError> for Error {
+    fn from(e: BuildError) -> Self {
+        Self::Build(e)
+    }
+}
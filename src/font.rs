@@ -0,0 +1,666 @@
+use std::collections::HashMap;
+
+use crate::binary::{Put, Reader};
+
+/// Dimensions of a character, in points
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CharMetrics {
+    pub width: f32,
+    pub height: f32,
+    pub depth: f32,
+    pub italic: f32,
+}
+
+impl CharMetrics {
+    /// Metrics given in ems of a 10pt font, scaled by `f`
+    fn ems(width: f32, height: f32, depth: f32, f: f32) -> Self {
+        Self { width: width * 10.0 * f, height: height * 10.0 * f, depth: depth * 10.0 * f, italic: 0.0 }
+    }
+}
+
+/// The `\fontdimen` parameters of a font, in points
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FontParams {
+    pub slant: f32,
+    pub space: f32,
+    pub space_stretch: f32,
+    pub space_shrink: f32,
+    pub x_height: f32,
+    pub quad: f32,
+    pub extra_space: f32,
+    /// Parameters 8 and up, which only math fonts have. Symbol fonts have up to `\fontdimen22`,
+    /// giving the placement of fractions, scripts and delimiters, and extension fonts up to
+    /// `\fontdimen13`, giving rule thicknesses and the spacing around large operators.
+    pub math: [f32; 15],
+    /// How many of the parameters in `math` the font has
+    pub math_count: u8,
+}
+
+impl FontParams {
+    /// Parameter `n` as numbered by `\fontdimen`, `None` if there is no such parameter
+    pub fn get(&self, n: i32) -> Option<f32> {
+        Some(match n {
+            1 => self.slant,
+            2 => self.space,
+            3 => self.space_stretch,
+            4 => self.space_shrink,
+            5 => self.x_height,
+            6 => self.quad,
+            7 => self.extra_space,
+            8.. if n < self.count() + 1 => self.math[n as usize - 8],
+            _ => return None,
+        })
+    }
+
+    pub fn get_mut(&mut self, n: i32) -> Option<&mut f32> {
+        Some(match n {
+            1 => &mut self.slant,
+            2 => &mut self.space,
+            3 => &mut self.space_stretch,
+            4 => &mut self.space_shrink,
+            5 => &mut self.x_height,
+            6 => &mut self.quad,
+            7 => &mut self.extra_space,
+            8.. if n < self.count() + 1 => &mut self.math[n as usize - 8],
+            _ => return None,
+        })
+    }
+
+    /// The number of parameters the font has
+    pub fn count(&self) -> i32 {
+        7 + self.math_count as i32
+    }
+
+    fn scale(self, f: f32) -> Self {
+        Self {
+            slant: self.slant,
+            space: self.space * f,
+            space_stretch: self.space_stretch * f,
+            space_shrink: self.space_shrink * f,
+            x_height: self.x_height * f,
+            quad: self.quad * f,
+            extra_space: self.extra_space * f,
+            math: self.math.map(|x| x * f),
+            math_count: self.math_count,
+        }
+    }
+}
+
+/// Refers to a font loaded by the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FontId(pub u32);
+
+impl FontId {
+    /// `\nullfont`
+    pub const NULL: Self = Self(0);
+    /// The font text is set in until another one is selected, [`Font::cmr10`]
+    pub const DEFAULT: Self = Self(1);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Shape {
+    #[default]
+    Upright,
+    Slanted,
+    Italic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Family {
+    #[default]
+    Serif,
+    Sans,
+    Monospace,
+}
+
+/// How a font looks, for output formats that use system fonts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FontStyle {
+    pub bold: bool,
+    pub shape: Shape,
+    pub family: Family,
+}
+
+impl FontStyle {
+    /// The style of a Computer Modern family, like `bx` or `sltt`
+    fn of_cm_family(family: &str) -> Self {
+        Self {
+            bold: matches!(family, "bx" | "b" | "ssbx" | "mib" | "bsy" | "bxsl" | "bxti"),
+            shape: match family {
+                "ti" | "mi" | "mib" | "itt" | "fi" | "bxti" => Shape::Italic,
+                "sl" | "sltt" | "ssi" | "ssqi" | "bxsl" => Shape::Slanted,
+                _ => Shape::Upright,
+            },
+            family: match family {
+                "tt" | "sltt" | "itt" | "vtt" | "tcsc" => Family::Monospace,
+                "ss" | "ssi" | "ssq" | "ssqi" | "ssbx" | "ssdc" => Family::Sans,
+                _ => Family::Serif,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    pub name: String,
+    /// The size the font is used at, in points
+    pub size: f32,
+    /// The size the font was designed for, in points
+    pub design_size: f32,
+    pub params: FontParams,
+    pub style: FontStyle,
+    /// The character used for hyphens, as set by `\hyphenchar`. Words are not hyphenated if it
+    /// is not a valid character.
+    pub hyphen_char: i32,
+    /// As set by `\skewchar`
+    pub skew_char: i32,
+    chars: HashMap<char, CharMetrics>,
+    /// The metrics of the characters not in `chars`, `None` if the font has no other characters
+    fallback: Option<CharMetrics>,
+}
+
+impl Font {
+    /// Computer Modern Roman at 10pt, with metrics approximating `cmr10.tfm`
+    pub fn cmr10() -> Self {
+        // (characters, width, height, depth) in ems
+        const METRICS: &[(&str, f32, f32, f32)] = &[
+            ("acegmnorsuvwxz", 0.0, 0.430555, 0.0),
+            ("bdhkl", 0.0, 0.694445, 0.0),
+            ("i", 0.0, 0.669444, 0.0),
+            ("t", 0.0, 0.615079, 0.0),
+            ("f", 0.0, 0.694445, 0.0),
+            ("j", 0.0, 0.669444, 0.194445),
+            ("gpqy", 0.0, 0.430555, 0.194445),
+            ("ABCDEFGHIJKLMNOPRSTUVWXYZ", 0.0, 0.683333, 0.0),
+            ("Q", 0.0, 0.683333, 0.194445),
+            ("0123456789", 0.5, 0.644444, 0.0),
+            ("!'`", 0.277779, 0.694445, 0.0),
+            (",;", 0.277779, 0.105556, 0.194445),
+            (".:", 0.277779, 0.105556, 0.0),
+            ("()[]", 0.0, 0.75, 0.25),
+            ("?@/", 0.0, 0.694445, 0.0),
+            ("*", 0.5, 0.75, 0.0),
+            ("+=", 0.777781, 0.583334, 0.083334),
+            ("-", 0.333334, 0.430555, 0.0),
+            ("#%&", 0.0, 0.694445, 0.055556),
+            ("$", 0.5, 0.75, 0.055556),
+            ("\"", 0.5, 0.694445, 0.0),
+            ("<>", 0.0, 0.5, 0.0),
+            ("^~", 0.5, 0.694445, 0.0),
+            ("_|", 0.0, 0.430555, 0.0),
+            ("{}\\", 0.5, 0.694445, 0.0),
+        ];
+
+        const WIDTHS: &[(char, f32)] = &[
+            ('a', 0.5), ('b', 0.555557), ('c', 0.444446), ('d', 0.555557), ('e', 0.444446),
+            ('f', 0.305557), ('g', 0.5), ('h', 0.555557), ('i', 0.277779), ('j', 0.305557),
+            ('k', 0.527781), ('l', 0.277779), ('m', 0.833336), ('n', 0.555557), ('o', 0.5),
+            ('p', 0.555557), ('q', 0.527781), ('r', 0.391668), ('s', 0.394445), ('t', 0.38889),
+            ('u', 0.555557), ('v', 0.527781), ('w', 0.722224), ('x', 0.527781), ('y', 0.527781),
+            ('z', 0.444446),
+            ('A', 0.75), ('B', 0.708336), ('C', 0.722224), ('D', 0.763891), ('E', 0.680557),
+            ('F', 0.652781), ('G', 0.784724), ('H', 0.75), ('I', 0.361112), ('J', 0.51389),
+            ('K', 0.777781), ('L', 0.625002), ('M', 0.916669), ('N', 0.75), ('O', 0.777781),
+            ('P', 0.680557), ('Q', 0.777781), ('R', 0.736113), ('S', 0.555557), ('T', 0.722224),
+            ('U', 0.75), ('V', 0.75), ('W', 1.027781), ('X', 0.75), ('Y', 0.75), ('Z', 0.611113),
+            ('(', 0.38889), (')', 0.38889), ('[', 0.277779), (']', 0.277779), ('?', 0.472224),
+            ('@', 0.777781), ('/', 0.5), ('#', 0.833336), ('%', 0.833336), ('&', 0.777781),
+            ('<', 0.277779), ('>', 0.472224), ('_', 0.277779), ('|', 1.0),
+        ];
+
+        let mut chars = HashMap::new();
+        for (cs, width, height, depth) in METRICS {
+            for c in cs.chars() {
+                chars.insert(c, CharMetrics { width: *width * 10.0, height: *height * 10.0, depth: *depth * 10.0, italic: 0.0 });
+            }
+        }
+        for (c, width) in WIDTHS {
+            chars.entry(*c).or_default().width = width * 10.0;
+        }
+
+        let params = FontParams {
+                slant: 0.0,
+                space: 3.33334,
+                space_stretch: 1.66667,
+                space_shrink: 1.11111,
+                x_height: 4.30555,
+                quad: 10.00002,
+                extra_space: 1.11111,
+                ..FontParams::default()
+        };
+
+        Self {
+            name: "cmr10".to_string(),
+            size: 10.0,
+            design_size: 10.0,
+            params,
+            style: FontStyle::default(),
+            hyphen_char: '-' as i32,
+            skew_char: -1,
+            chars,
+            // an average letter
+            fallback: Some(CharMetrics { width: params.quad / 2.0, height: params.x_height, depth: 0.0, italic: 0.0 }),
+        }
+    }
+
+    /// The font with no characters, `\nullfont`
+    pub fn null() -> Self {
+        Self {
+            name: "nullfont".to_string(),
+            size: 0.0,
+            design_size: 0.0,
+            params: FontParams::default(),
+            style: FontStyle::default(),
+            hyphen_char: '-' as i32,
+            skew_char: -1,
+            chars: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Loads a Computer Modern font like `cmbx10` or `cmsl9`, at `size` points or its design size.
+    /// The metrics are those of [`Self::cmr10`] scaled to the size, widened for bold fonts and
+    /// made fixed width for typewriter fonts. `None` if the name is not one of Computer Modern.
+    pub fn computer_modern(name: &str, size: Option<f32>) -> Option<Self> {
+        let (family, design) = cm_name(name)?;
+        let style = FontStyle::of_cm_family(family);
+        let mono = style.family == Family::Monospace;
+
+        let mut font = Self::cmr10();
+        let f = size.unwrap_or(design) / 10.0;
+        let widen = if style.bold { 1.15 } else { 1.0 };
+        for m in font.chars.values_mut().chain(font.fallback.as_mut()) {
+            m.width = if mono { 5.25 } else { m.width * widen } * f;
+            m.height *= f;
+            m.depth *= f;
+        }
+
+        font.params = font.params.scale(f * widen);
+        font.params.slant = match style.shape {
+            Shape::Upright => 0.0,
+            Shape::Slanted => 0.166667,
+            Shape::Italic => 0.25,
+        };
+        if mono {
+            font.params.space = 5.25 * f;
+            font.params.space_stretch = 0.0;
+            font.params.space_shrink = 0.0;
+            font.params.extra_space = 5.25 * f;
+        }
+
+        // math symbol and extension fonts have their own characters and parameters, from
+        // `cmsy10.tfm` and `cmex10.tfm` in ems
+        let math: &[f32] = match family {
+            "sy" | "bsy" => &[
+                0.676508, 0.393732, 0.443731, 0.685951, 0.344841, 0.412892, 0.362892, 0.288889,
+                0.15, 0.247217, 0.386108, 0.05, 2.39, 1.01, 0.25,
+            ],
+            "ex" => &[0.04, 0.111112, 0.166667, 0.2, 0.6, 0.1],
+            _ => &[],
+        };
+        for (p, x) in font.params.math.iter_mut().zip(math) {
+            *p = x * 10.0 * f;
+        }
+        font.params.math_count = math.len() as u8;
+        let (metrics, fallback) = match family {
+            "sy" | "bsy" => (SYMBOL_METRICS, None),
+            "ex" => (EXTENSION_METRICS, Some((0.5, 0.04, 1.16))),
+            _ => (&[][..], None),
+        };
+        if !metrics.is_empty() {
+            font.chars.clear();
+            font.fallback = None;
+            for &(first, last, width, height, depth) in metrics {
+                for c in first..=last {
+                    font.chars.insert(char::from(c), CharMetrics::ems(width * widen, height, depth, f));
+                }
+            }
+        }
+        if let Some((width, height, depth)) = fallback {
+            font.fallback = Some(CharMetrics::ems(width, height, depth, f));
+        }
+
+        font.name = name.to_string();
+        font.size = size.unwrap_or(design);
+        font.design_size = design;
+        font.style = style;
+        Some(font)
+    }
+
+    /// Loads a font from the bytes of a TeX font metric file, at `size` points or its design
+    /// size. Only the dimensions of the characters and the parameters are used, and the style is
+    /// guessed from the name for Computer Modern fonts. `None` if the file is malformed.
+    pub fn from_tfm(bytes: &[u8], name: &str, size: Option<f32>) -> Option<Self> {
+        let word = |i: usize| bytes.get(i * 4..i * 4 + 4).map(|w| u32::from_be_bytes(w.try_into().unwrap()));
+        // a fix_word has 20 bits after the point
+        let fix = |w: u32| w as i32 as f32 / (1 << 20) as f32;
+
+        let header = word(0)?;
+        let (lf, lh) = ((header >> 16) as usize, (header & 0xffff) as usize);
+        let [bc, ec, nw, nh, nd, ni, nl, nk, ne, np] = [1, 2, 3, 4, 5].map(|i| {
+            let w = word(i).unwrap_or(0);
+            [(w >> 16) as usize, (w & 0xffff) as usize]
+        }).as_flattened().try_into().unwrap();
+        if lf * 4 > bytes.len() || lh < 2 || ec < bc.saturating_sub(1) || ec > 255 {
+            return None;
+        }
+
+        let char_info = 6 + lh;
+        let widths = char_info + ec + 1 - bc;
+        let heights = widths + nw;
+        let depths = heights + nh;
+        let italics = depths + nd;
+        let params = italics + ni + nl + nk + ne;
+        if params + np > lf {
+            return None;
+        }
+
+        let design = fix(word(7)?);
+        if design <= 0.0 {
+            return None;
+        }
+        let size = size.unwrap_or(design);
+        let dimen = |table: usize, i: usize| fix(word(table + i).unwrap_or(0)) * size;
+
+        let mut chars = HashMap::new();
+        for c in bc..=ec {
+            let info = word(char_info + c - bc)?;
+            let w = (info >> 24) as usize;
+            // characters with a zero width index do not exist
+            if w == 0 {
+                continue;
+            }
+            let metrics = CharMetrics {
+                width: dimen(widths, w),
+                height: dimen(heights, (info >> 20 & 0xf) as usize),
+                depth: dimen(depths, (info >> 16 & 0xf) as usize),
+                italic: dimen(italics, (info >> 10 & 0x3f) as usize),
+            };
+            chars.insert(char::from(c as u8), metrics);
+        }
+
+        let mut font_params = FontParams { math_count: np.clamp(7, 22) as u8 - 7, ..FontParams::default() };
+        for n in 1..=np.min(22) {
+            let x = fix(word(params + n - 1)?);
+            // the slant is a ratio, the other parameters are lengths
+            *font_params.get_mut(n as i32).unwrap() = if n == 1 { x } else { x * size };
+        }
+
+        Some(Self {
+            name: name.to_string(),
+            size,
+            design_size: design,
+            params: font_params,
+            style: cm_name(name).map(|(family, _)| FontStyle::of_cm_family(family)).unwrap_or_default(),
+            hyphen_char: '-' as i32,
+            skew_char: -1,
+            chars,
+            fallback: None,
+        })
+    }
+
+    /// Appends the font to a format file
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        let put_metrics = |out: &mut Vec<u8>, m: &CharMetrics| {
+            for x in [m.width, m.height, m.depth, m.italic] {
+                out.put_f32(x);
+            }
+        };
+
+        out.put_str(&self.name);
+        out.put_f32(self.size);
+        out.put_f32(self.design_size);
+        out.put_u8(self.params.math_count);
+        for n in 1..=self.params.count() {
+            out.put_f32(self.params.get(n).unwrap());
+        }
+        out.put_u8(self.style.bold as u8);
+        out.put_u8(self.style.shape as u8);
+        out.put_u8(self.style.family as u8);
+        out.put_i32(self.hyphen_char);
+        out.put_i32(self.skew_char);
+
+        let mut chars: Vec<_> = self.chars.iter().collect();
+        chars.sort_by_key(|(c, _)| **c);
+        out.put_int(chars.len());
+        for (c, m) in chars {
+            out.put_char(*c);
+            put_metrics(out, m);
+        }
+        match &self.fallback {
+            Some(m) => {
+                out.put_u8(1);
+                put_metrics(out, m);
+            },
+            None => out.put_u8(0),
+        }
+    }
+
+    /// Reads a font written by [`Self::write`]
+    pub(crate) fn read<E: Copy>(r: &mut Reader<E>) -> Result<Self, E> {
+        let metrics = |r: &mut Reader<E>| Ok(CharMetrics { width: r.f32()?, height: r.f32()?, depth: r.f32()?, italic: r.f32()? });
+
+        let name = r.str()?.to_string();
+        let size = r.f32()?;
+        let design_size = r.f32()?;
+        let mut params = FontParams { math_count: r.u8()?.min(15), ..FontParams::default() };
+        for n in 1..=params.count() {
+            *params.get_mut(n).unwrap() = r.f32()?;
+        }
+        let style = FontStyle {
+            bold: r.u8()? != 0,
+            shape: match r.u8()? {
+                0 => Shape::Upright,
+                1 => Shape::Slanted,
+                2 => Shape::Italic,
+                _ => return Err(r.err()),
+            },
+            family: match r.u8()? {
+                0 => Family::Serif,
+                1 => Family::Sans,
+                2 => Family::Monospace,
+                _ => return Err(r.err()),
+            },
+        };
+        let hyphen_char = r.i32()?;
+        let skew_char = r.i32()?;
+
+        let mut chars = HashMap::new();
+        for _ in 0..r.int()? {
+            let c = r.char()?;
+            chars.insert(c, metrics(r)?);
+        }
+        let fallback = match r.u8()? {
+            0 => None,
+            _ => Some(metrics(r)?),
+        };
+
+        Ok(Self { name, size, design_size, params, style, hyphen_char, skew_char, chars, fallback })
+    }
+
+    /// The metrics of `c`, `None` if the font does not have it
+    pub fn metrics(&self, c: char) -> Option<CharMetrics> {
+        self.chars.get(&c).copied().or(self.fallback)
+    }
+
+    /// The larger version of `c` used in displays, for the operators of math extension fonts
+    pub fn successor(&self, c: char) -> Option<char> {
+        if !matches!(cm_name(&self.name), Some(("ex", _))) {
+            return None;
+        }
+        match c as u32 {
+            n @ (0x48..=0x4f | 0x60) if n % 2 == 0 => char::from_u32(n + 1),
+            n @ 0x50..=0x57 => char::from_u32(n + 8),
+            _ => None,
+        }
+    }
+
+    /// How far below the reference point of `c` output formats should put the baseline of its
+    /// glyph. Delimiters and radicals in math fonts hang below the baseline, where system fonts
+    /// do not put them, so they are moved down to the middle of their box.
+    pub fn baseline_shift(&self, c: char) -> f32 {
+        let hangs = match cm_name(&self.name) {
+            Some(("ex", _)) => true,
+            Some(("sy" | "bsy", _)) => c == '\u{70}',
+            _ => false,
+        };
+        match self.metrics(c) {
+            Some(m) if hangs && m.depth > m.height => (m.depth - m.height) / 2.0 + 0.25 * self.size,
+            _ => 0.0,
+        }
+    }
+
+    /// The same font at `size` points, with its metrics and parameters scaled to match
+    pub fn at_size(&self, size: f32) -> Self {
+        let f = if self.size > 0.0 { size / self.size } else { 1.0 };
+        let mut font = self.clone();
+        for m in font.chars.values_mut().chain(font.fallback.as_mut()) {
+            *m = CharMetrics { width: m.width * f, height: m.height * f, depth: m.depth * f, italic: m.italic * f };
+        }
+        font.params = font.params.scale(f);
+        font.size = size;
+        font
+    }
+
+    /// The Unicode character for the glyph at position `c` of the font. Positions below 32, 127
+    /// and a few others hold accents and special letters in Computer Modern text fonts, and the
+    /// math fonts use encodings of their own.
+    pub fn glyph(&self, c: char) -> char {
+        const LOW: &str = "ΓΔΘΛΞΠΣΥΦΨΩ\u{fb00}\u{fb01}\u{fb02}\u{fb03}\u{fb04}ıȷ`´ˇ˘¯˚¸ßæœøÆŒØ";
+        // (first position, characters) for the math italic, symbol, extension and blackboard bold
+        // encodings
+        const MATH_ITALIC: &[(u32, &str)] = &[
+            (0x00, "ΓΔΘΛΞΠΣΥΦΨΩαβγδϵζηθικλμνξπρστυϕχψωεϑϖϱςφ↼↽⇀⇁"),
+            (0x2e, "▹◃"),
+            (0x3a, ".,</>⋆∂"),
+            (0x5b, "♭♮♯⌣⌢ℓ"),
+            (0x7b, "ıȷ℘→⁀"),
+        ];
+        const SYMBOL: &[(u32, &str)] = &[
+            (0x00, "−·×∗÷⋄±∓⊕⊖⊗⊘⊙◯∘∙≍≡⊆⊇≤≥⪯⪰∼≈⊂⊃≪≫≺≻←→↑↓↔↗↘≃⇐⇒⇑⇓⇔↖↙∝′∞∈∋△▽/|∀∃¬∅ℜℑ⊤⊥ℵ"),
+            (0x41, "𝒜ℬ𝒞𝒟ℰℱ𝒢ℋℐ𝒥𝒦ℒℳ𝒩𝒪𝒫𝒬ℛ𝒮𝒯𝒰𝒱𝒲𝒳𝒴𝒵"),
+            (0x5b, "∪∩⊎∧∨⊢⊣⌊⌋⌈⌉{}⟨⟩|‖↕⇕\\≀√⨿∇∫⊔⊓⊑⊒§†‡¶♣♢♡♠"),
+        ];
+        const BLACKBOARD: &[(u32, &str)] = &[(0x41, "𝔸𝔹ℂ𝔻𝔼𝔽𝔾ℍ𝕀𝕁𝕂𝕃𝕄ℕ𝕆ℙℚℝ𝕊𝕋𝕌𝕍𝕎𝕏𝕐ℤ")];
+        const EXTENSION: &[(u32, &str)] = &[
+            (0x00, "()[]⌊⌋⌈⌉{}⟨⟩|‖/\\"),
+            (0x10, "()()[]⌊⌋⌈⌉{}⟨⟩/\\"),
+            (0x20, "()[]⌊⌋⌈⌉{}⟨⟩/\\/\\"),
+            (0x30, "⎛⎞⎡⎤⎣⎦⎢⎥⎧⎫⎩⎭⎨⎬⎪⏐"),
+            (0x40, "⎝⎠⎜⎟⟨⟩⊔⊔∮∮⊙⊙⊕⊕⊗⊗"),
+            (0x50, "∑∏∫⋃⋂⊎⋀⋁∑∏∫⋃⋂⊎⋀⋁"),
+            (0x60, "∐∐ˆˆˆ˜˜˜[]⌊⌋⌈⌉{}"),
+            (0x70, "√√√√⎷│┌‖↑↓◜◝◟◞⇑⇓"),
+        ];
+
+        let table = match cm_name(&self.name) {
+            Some(("mi" | "mib", _)) => MATH_ITALIC,
+            Some(("sy" | "bsy", _)) => SYMBOL,
+            Some(("ex", _)) => EXTENSION,
+            Some(("bm", _)) => BLACKBOARD,
+            _ => match c as u32 {
+                n @ 0..=31 => return LOW.chars().nth(n as usize).unwrap_or(c),
+                // typewriter fonts have the ASCII characters where the others have accents
+                0x5e if self.style.family != Family::Monospace => return 'ˆ',
+                0x5f if self.style.family != Family::Monospace => return '˙',
+                0x7d if self.style.family != Family::Monospace => return '˝',
+                0x7e if self.style.family != Family::Monospace => return '˜',
+                127 => return '¨',
+                _ => return c,
+            },
+        };
+        let n = c as u32;
+        table.iter()
+            .find_map(|(first, chars)| chars.chars().nth(n.checked_sub(*first)? as usize))
+            .unwrap_or(c)
+    }
+}
+
+// (first position, last position, width, height, depth) in ems, approximating `cmsy10.tfm`
+const SYMBOL_METRICS: &[(u8, u8, f32, f32, f32)] = &[
+    (0x00, 0x00, 0.777781, 0.583334, 0.083334),
+    (0x01, 0x01, 0.277779, 0.444446, 0.0),
+    (0x02, 0x02, 0.777781, 0.491109, 0.0),
+    (0x03, 0x03, 0.5, 0.465279, 0.0),
+    (0x04, 0x05, 0.777781, 0.5, 0.0),
+    (0x06, 0x0d, 0.777781, 0.583334, 0.083334),
+    (0x0e, 0x0f, 0.5, 0.444446, 0.0),
+    (0x10, 0x11, 0.777781, 0.463891, 0.0),
+    (0x12, 0x17, 0.777781, 0.636113, 0.136113),
+    (0x18, 0x19, 0.777781, 0.483403, 0.0),
+    (0x1a, 0x1b, 0.777781, 0.538891, 0.038891),
+    (0x1c, 0x1d, 1.000003, 0.538891, 0.038891),
+    (0x1e, 0x1f, 0.777781, 0.538891, 0.038891),
+    (0x20, 0x21, 1.000003, 0.366669, 0.0),
+    (0x22, 0x23, 0.5, 0.694445, 0.194445),
+    (0x24, 0x29, 1.000003, 0.366669, 0.0),
+    (0x2a, 0x2b, 0.611112, 0.694445, 0.194445),
+    (0x2c, 0x2f, 1.000003, 0.366669, 0.0),
+    (0x30, 0x30, 0.275, 0.558336, 0.0),
+    (0x31, 0x31, 1.000003, 0.430555, 0.0),
+    (0x32, 0x33, 0.666669, 0.538891, 0.038891),
+    (0x34, 0x35, 0.888891, 0.694445, 0.0),
+    (0x36, 0x37, 0.0, 0.694445, 0.194445),
+    (0x38, 0x39, 0.555557, 0.694445, 0.0),
+    (0x3a, 0x3a, 0.666669, 0.430555, 0.0),
+    (0x3b, 0x3b, 0.5, 0.75, 0.055556),
+    (0x3c, 0x3f, 0.722224, 0.683333, 0.0),
+    (0x40, 0x40, 0.611112, 0.694445, 0.0),
+    (0x41, 0x5a, 0.75, 0.683333, 0.0),
+    (0x5b, 0x5f, 0.666669, 0.555557, 0.0),
+    (0x60, 0x61, 0.611112, 0.694445, 0.0),
+    (0x62, 0x65, 0.444446, 0.75, 0.25),
+    (0x66, 0x67, 0.5, 0.75, 0.25),
+    (0x68, 0x69, 0.38889, 0.75, 0.25),
+    (0x6a, 0x6a, 0.277779, 0.75, 0.25),
+    (0x6b, 0x6f, 0.5, 0.75, 0.25),
+    (0x70, 0x70, 0.833336, 0.04, 0.96),
+    (0x71, 0x71, 0.75, 0.683333, 0.0),
+    (0x72, 0x72, 0.833336, 0.683333, 0.0),
+    (0x73, 0x73, 0.416669, 0.694445, 0.111112),
+    (0x74, 0x77, 0.666669, 0.555557, 0.0),
+    (0x78, 0x7b, 0.444446, 0.694445, 0.194445),
+    (0x7c, 0x7f, 0.777781, 0.694445, 0.13),
+];
+
+// (first position, last position, width, height, depth) in ems, approximating `cmex10.tfm`
+const EXTENSION_METRICS: &[(u8, u8, f32, f32, f32)] = &[
+    (0x00, 0x0f, 0.458336, 0.04, 1.160013),
+    (0x10, 0x1f, 0.597224, 0.04, 1.760019),
+    (0x20, 0x2f, 0.736113, 0.04, 2.360025),
+    (0x30, 0x47, 0.875003, 0.0, 1.800018),
+    (0x48, 0x51, 1.055559, 0.0, 1.000013),
+    (0x52, 0x52, 0.555557, 0.0, 1.111123),
+    (0x53, 0x57, 0.833336, 0.0, 1.000013),
+    (0x58, 0x59, 1.444446, 0.0, 1.400014),
+    (0x5a, 0x5a, 0.555557, 0.0, 2.222246),
+    (0x5b, 0x5f, 1.111112, 0.0, 1.400014),
+    (0x60, 0x60, 0.944446, 0.0, 1.000013),
+    (0x61, 0x61, 1.277781, 0.0, 1.400014),
+    (0x62, 0x67, 0.555557, 0.75, 0.0),
+    (0x68, 0x6f, 0.472224, 0.04, 2.960031),
+    (0x70, 0x70, 1.000003, 0.04, 0.960013),
+    (0x71, 0x74, 1.000003, 0.04, 1.560019),
+    (0x75, 0x7f, 0.666669, 0.0, 0.600006),
+];
+
+/// Splits the name of a Computer Modern font, like `cmbx12`, into its family and design size.
+/// The AMS blackboard bold font `msbm10` that goes with them has the family `bm`. `None` if it
+/// is neither.
+fn cm_name(name: &str) -> Option<(&str, f32)> {
+    const FAMILIES: &[&str] = &[
+        "r", "mi", "sy", "ex", "bx", "b", "tt", "sltt", "itt", "vtt", "tcsc", "sl", "ti", "u",
+        "ss", "ssi", "ssq", "ssqi", "ssbx", "ssdc", "mib", "bsy", "csc", "dunh", "fib", "fi", "ff",
+        "bxsl", "bxti",
+    ];
+
+    let (ams, rest) = match name.strip_prefix("cm") {
+        Some(rest) => (false, rest),
+        None => (true, name.strip_prefix("ms")?),
+    };
+    let digits = rest.find(|c: char| c.is_ascii_digit())?;
+    let (family, design) = rest.split_at(digits);
+    let design: f32 = design.parse().ok().filter(|d| *d > 0.0)?;
+    let known = if ams { family == "bm" } else { FAMILIES.contains(&family) };
+    known.then_some((family, design))
+}
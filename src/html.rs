@@ -0,0 +1,267 @@
+use std::fmt::Write;
+
+use crate::document::Document;
+use crate::font::{Family, Font, Shape};
+use crate::layout::{self, BoxContent, Node};
+use crate::lexer::Span;
+
+const STYLE: &str = "<style>\
+.rstex-page{position:relative;font-family:\"Latin Modern Roman\",\"Computer Modern\",serif;font-size:10pt}\
+.rstex-page span,.rstex-page div{position:absolute}\
+.rstex-page span{line-height:0;white-space:pre}\
+.rstex-rule{background:currentColor}\
+.rstex-page+.rstex-page{margin-top:10pt}\
+</style>";
+
+/// Renders the pages of a document as absolutely positioned HTML, one `rstex-page` element each.
+/// Every glyph and rule has a `data-src` attribute of the form `file:start-end`, giving the byte
+/// range it came from in the file at index `file` of the page's `data-files` list.
+pub fn render(doc: &Document) -> String {
+    let files = doc.files.iter().map(|f| format!("\"{}\"", json_escape(&f.name))).collect::<Vec<_>>();
+    let files = escape(&format!("[{}]", files.join(",")));
+
+    let mut out = STYLE.to_string();
+    for page in doc.pages.iter() {
+        let [width, height, depth] = page.size();
+        let _ = write!(
+            out,
+            "<div class=\"rstex-page\" style=\"width:{width}pt;height:{}pt\" data-files=\"{files}\">",
+            height + depth,
+        );
+        let mut page = page.clone();
+        compose_accents(&mut page, doc);
+        layout::walk(&page, 0.0, height, &mut |n, x, y| render_box(&mut out, n, x, y, doc));
+        out += "</div>";
+    }
+    out
+}
+
+/// The `<style>` element formulas rendered as HTML need, to be put in the page once
+pub const MATH_STYLE: &str = "<style>\
+.rstex-math{display:inline-block;position:relative;font-family:\"Latin Modern Roman\",\"Computer Modern\",serif;font-size:10pt}\
+.rstex-math span,.rstex-math div{position:absolute}\
+.rstex-math span{line-height:0;white-space:pre}\
+.rstex-rule{background:currentColor}\
+.rstex-display{display:block;margin:1em auto}\
+</style>";
+
+/// Renders the only page of a document, a formula typeset by itself, as an inline block whose
+/// baseline is that of the text around it, or centred on a line of its own if `display` is set.
+/// It is styled by [`MATH_STYLE`].
+pub(crate) fn render_formula(doc: &Document, display: bool) -> String {
+    let mut out = String::new();
+    let Some(formula) = doc.pages.first() else { return out };
+    let [width, height, depth] = formula.size();
+    let _ = write!(
+        out,
+        "<span class=\"rstex-math{}\" style=\"width:{width}pt;height:{}pt;vertical-align:-{depth}pt\">",
+        if display { " rstex-display" } else { "" },
+        height + depth,
+    );
+    let mut formula = formula.clone();
+    compose_accents(&mut formula, doc);
+    layout::walk(&formula, 0.0, height, &mut |n, x, y| render_box(&mut out, n, x, y, doc));
+    out += "</span>";
+    out
+}
+
+/// Replaces the accents made by `\accent` over or under letters that Unicode has precomposed
+/// forms of by the precomposed letters, which come from both the accent and the letter. So are
+/// the marks `\oalign` sets under letters, as `\d`, `\b` and `\c` do.
+fn compose_accents(node: &mut Node, doc: &Document) {
+    let Node::Box { content: BoxContent::HBox(list) | BoxContent::VBox(list), .. } = node else { return };
+    for n in list.iter_mut() {
+        match stacked(n, doc) {
+            Some(letter) => *n = letter,
+            None => compose_accents(n, doc),
+        }
+    }
+
+    let mut i = 0;
+    while i + 4 <= list.len() {
+        if let Some(letter) = accented(&list[i..i + 4], doc) {
+            list.splice(i..i + 4, [letter]);
+        }
+        i += 1;
+    }
+}
+
+/// The precomposed letter for an accent, as `\accent` sets it: a kern, the accent, possibly
+/// raised in a box, a kern back to where it started and the letter. The precomposed letter is in
+/// the letter's font, where positions above 127 are the Unicode characters themselves.
+fn accented(w: &[Node], doc: &Document) -> Option<Node> {
+    let [Node::Kern(k1), a, Node::Kern(k2), letter] = w else { return None };
+    let accent = match a {
+        Node::Box { content: BoxContent::HBox(inner), .. } if inner.len() == 1 => &inner[0],
+        a => a,
+    };
+    let (
+        Node::Box { content: BoxContent::Character(m, mf), .. },
+        Node::Box { content: BoxContent::Character(c, f), size, shift, .. },
+    ) = (accent, letter) else { return None };
+    if (k1 + a.size()[0] + k2).abs() > 0.001 {
+        return None;
+    }
+    let composed = compose(doc.font(*f).glyph(*c), doc.font(*mf).glyph(*m))?;
+
+    Some(Node::Box {
+        content: BoxContent::Character(composed, *f),
+        size: *size,
+        shift: *shift,
+        nesting: 0,
+        span: layout::span_of(&[accent.clone(), letter.clone()]),
+    })
+}
+
+/// The precomposed letter for a mark set under a letter by `\oalign`: a box of two rows, the
+/// first holding just the letter and the second just the mark, made with `\vtop` so that its
+/// baseline is the letter's. The precomposed letter takes the place of the whole box.
+fn stacked(node: &Node, doc: &Document) -> Option<Node> {
+    let Node::Box { content: BoxContent::VBox(rows), size, shift, .. } = node else { return None };
+    let mut rows = rows.iter().filter(|n| matches!(n, Node::Box { .. }));
+    let (Some(letter), Some(mark), None) = (rows.next(), rows.next(), rows.next()) else { return None };
+    if (letter.size()[1] - size[1]).abs() > 0.001 {
+        return None;
+    }
+    let (letter, mark) = (only_character(letter)?, only_character(mark)?);
+    let (
+        Node::Box { content: BoxContent::Character(c, f), .. },
+        Node::Box { content: BoxContent::Character(m, mf), .. },
+    ) = (letter, mark) else { return None };
+    let (c, m) = (doc.font(*f).glyph(*c), doc.font(*mf).glyph(*m));
+    let composed = compose_below(c, m).or_else(|| compose(c, m))?;
+
+    Some(Node::Box {
+        content: BoxContent::Character(composed, *f),
+        size: *size,
+        shift: *shift,
+        nesting: 0,
+        span: layout::span_of(&[node.clone(), letter.clone(), mark.clone()]),
+    })
+}
+
+/// The one character in `node`, through the boxes around it and the glue and kerns beside it
+fn only_character(node: &Node) -> Option<&Node> {
+    match node {
+        Node::Box { content: BoxContent::Character(..), .. } => Some(node),
+        Node::Box { content: BoxContent::HBox(list) | BoxContent::VBox(list), .. } => {
+            let mut boxes = list.iter().filter(|n| matches!(n, Node::Box { .. }));
+            match (boxes.next(), boxes.next()) {
+                (Some(n), None) => only_character(n),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+/// The precomposed character for `letter` with `mark` under it, for the marks that are accents
+/// of their own when they are over letters
+fn compose_below(letter: char, mark: char) -> Option<char> {
+    // (mark, letters, the letters with the mark under them)
+    const COMPOSED: &[(char, &str, &str)] = &[
+        ('.', "ABDEHIKLMNORSTUVWYZabdehiklmnorstuvwyz", "ẠḄḌẸḤỊḲḶṂṆỌṚṢṬỤṾẈỴẒạḅḍẹḥịḳḷṃṇọṛṣṭụṿẉỵẓ"),
+        ('¯', "BDKLNRTZbdhklnrtz", "ḆḎḴḺṈṞṮẔḇḏẖḵḻṉṟṯẕ"),
+    ];
+
+    let (_, letters, composed) = COMPOSED.iter().find(|(m, ..)| *m == mark)?;
+    composed.chars().nth(letters.chars().position(|c| c == letter)?)
+}
+
+/// The precomposed character for `letter` with `accent` over or under it, if Unicode has one
+fn compose(letter: char, accent: char) -> Option<char> {
+    // (accent, letters, the letters with the accent)
+    const COMPOSED: &[(char, &str, &str)] = &[
+        ('`', "AEINOUWYaeinouwy", "ÀÈÌǸÒÙẀỲàèìǹòùẁỳ"),
+        ('´', "ACEGIKLMNOPRSUWYZacegiklmnoprsuwyz", "ÁĆÉǴÍḰĹḾŃÓṔŔŚÚẂÝŹáćéǵíḱĺḿńóṕŕśúẃýź"),
+        ('ˆ', "ACEGHIJOSUWYZaceghijosuwyz", "ÂĈÊĜĤÎĴÔŜÛŴŶẐâĉêĝĥîĵôŝûŵŷẑ"),
+        ('˜', "AEINOUVYaeinouvy", "ÃẼĨÑÕŨṼỸãẽĩñõũṽỹ"),
+        ('¯', "AEGIOUYaegiouy", "ĀĒḠĪŌŪȲāēḡīōūȳ"),
+        ('˘', "AEGIOUaegiou", "ĂĔĞĬŎŬăĕğĭŏŭ"),
+        ('˙', "ABCDEFGHIMNOPRSTWXYZabcdefghmnoprstwxyz", "ȦḂĊḊĖḞĠḢİṀṄȮṖṘṠṪẆẊẎŻȧḃċḋėḟġḣṁṅȯṗṙṡṫẇẋẏż"),
+        ('¨', "AEHIOUWXYaehiotuwxy", "ÄËḦÏÖÜẄẌŸäëḧïöẗüẅẍÿ"),
+        ('˚', "AUauwy", "ÅŮåůẘẙ"),
+        ('˝', "OUou", "ŐŰőű"),
+        ('ˇ', "ACDEGHIKLNORSTUZacdeghijklnorstuz", "ǍČĎĚǦȞǏǨĽŇǑŘŠŤǓŽǎčďěǧȟǐǰǩľňǒřšťǔž"),
+        ('¸', "CDEGHKLNRSTcdeghklnrst", "ÇḐȨĢḨĶĻŅŖŞŢçḑȩģḩķļņŗşţ"),
+    ];
+
+    // accents go over the dotless i and j, and typewriter fonts have ASCII ones
+    let letter = match letter {
+        'ı' => 'i',
+        'ȷ' => 'j',
+        c => c,
+    };
+    let accent = match accent {
+        '^' => 'ˆ',
+        '~' => '˜',
+        c => c,
+    };
+    let (_, letters, composed) = COMPOSED.iter().find(|(a, ..)| *a == accent)?;
+    composed.chars().nth(letters.chars().position(|c| c == letter)?)
+}
+
+/// Renders a character or rule with its reference point at `(x, y)`
+fn render_box(out: &mut String, node: &Node, x: f32, y: f32, doc: &Document) {
+    let Node::Box { content, size: [width, height, depth], span, .. } = node else { return };
+
+    match content {
+        BoxContent::Character(c, f) => {
+            let font = doc.font(*f);
+            let _ = write!(
+                out,
+                "<span style=\"left:{x}pt;top:{}pt{}\"{}>{}</span>",
+                y + font.baseline_shift(*c),
+                font_style(font),
+                src(span),
+                escape(&font.glyph(*c).to_string()),
+            );
+        },
+        _ => {
+            let _ = write!(
+                out,
+                "<div class=\"rstex-rule\" style=\"left:{x}pt;top:{}pt;width:{width}pt;height:{}pt\"{}></div>",
+                y - height,
+                height + depth,
+                src(span),
+            );
+        },
+    }
+}
+
+/// The styles that set a character in `font`, on top of the 10pt roman of the page
+fn font_style(font: &Font) -> String {
+    let mut style = String::new();
+    if font.size != 10.0 {
+        let _ = write!(style, ";font-size:{}pt", font.size);
+    }
+    if font.style.bold {
+        style += ";font-weight:bold";
+    }
+    match font.style.shape {
+        Shape::Upright => {},
+        Shape::Slanted => style += ";font-style:oblique",
+        Shape::Italic => style += ";font-style:italic",
+    }
+    match font.style.family {
+        Family::Serif => {},
+        Family::Sans => style += ";font-family:'Latin Modern Sans',sans-serif",
+        Family::Monospace => style += ";font-family:'Latin Modern Mono',monospace",
+    }
+    style
+}
+
+fn src(span: &Span) -> String {
+    match span.file {
+        Some(file) => format!(" data-src=\"{}:{}-{}\"", file.0, span.range.start, span.range.end),
+        None => String::new(),
+    }
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn json_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
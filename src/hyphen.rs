@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use crate::binary::{Put, Reader};
+
+/// Hyphenation patterns and exceptions for one language, used with Liang's algorithm
+#[derive(Debug, Clone)]
+pub struct Patterns {
+    trie: Vec<TrieNode>,
+    /// Words with their hyphen positions, as given by `\hyphenation`
+    exceptions: HashMap<String, Vec<usize>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct TrieNode {
+    /// Sorted by character
+    children: Vec<(char, u32)>,
+    /// The inter-letter values of the pattern ending here, empty if none does
+    values: Vec<u8>,
+}
+
+/// The error for a malformed pattern or exception
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BadPattern;
+
+const MAGIC: &[u8; 4] = b"RSHY";
+
+impl Default for Patterns {
+    fn default() -> Self {
+        Self { trie: vec![TrieNode::default()], exceptions: HashMap::new() }
+    }
+}
+
+impl Patterns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pattern like `.ach4` or `a1b`, where digits give the values between letters and `.`
+    /// marks the edge of a word
+    pub fn add_pattern(&mut self, pattern: &str) -> Result<(), BadPattern> {
+        let mut letters = Vec::new();
+        let mut values = vec![0];
+
+        for c in pattern.chars() {
+            match c.to_digit(10) {
+                Some(d) if *values.last().unwrap() == 0 => *values.last_mut().unwrap() = d as u8,
+                Some(_) => return Err(BadPattern),
+                None => {
+                    letters.push(c);
+                    values.push(0);
+                },
+            }
+        }
+
+        if letters.is_empty() {
+            return Err(BadPattern);
+        }
+
+        let mut node = 0;
+        for c in letters {
+            node = match self.trie[node].children.binary_search_by_key(&c, |(c, _)| *c) {
+                Ok(i) => self.trie[node].children[i].1 as usize,
+                Err(i) => {
+                    let child = self.trie.len();
+                    self.trie.push(TrieNode::default());
+                    self.trie[node].children.insert(i, (c, child as u32));
+                    child
+                },
+            };
+        }
+
+        self.trie[node].values = values;
+        Ok(())
+    }
+
+    /// Adds a word with its hyphens marked, like `ta-ble`
+    pub fn add_exception(&mut self, word: &str) -> Result<(), BadPattern> {
+        let mut letters = String::new();
+        let mut positions = Vec::new();
+
+        for c in word.chars() {
+            match c {
+                '-' if letters.is_empty() => return Err(BadPattern),
+                '-' => positions.push(letters.chars().count()),
+                c => letters.extend(c.to_lowercase()),
+            }
+        }
+
+        self.exceptions.insert(letters, positions);
+        Ok(())
+    }
+
+    /// The positions in `word`, in characters, where it may be hyphenated, leaving at least `left`
+    /// characters before the first hyphen and `right` after the last
+    pub fn hyphenate(&self, word: &str, left: usize, right: usize) -> Vec<usize> {
+        let word: String = word.chars().flat_map(char::to_lowercase).collect();
+        let len = word.chars().count();
+        let allowed = |k: &usize| *k >= left.max(1) && k + right.max(1) <= len;
+
+        if let Some(positions) = self.exceptions.get(&word) {
+            return positions.iter().copied().filter(allowed).collect();
+        }
+
+        let padded: Vec<char> = core::iter::once('.').chain(word.chars()).chain(core::iter::once('.')).collect();
+        // values[i] is the value before padded[i]
+        let mut values = vec![0; padded.len() + 1];
+
+        for start in 0..padded.len() {
+            let mut node = 0;
+            for c in padded[start..].iter() {
+                let Ok(child) = self.trie[node].children.binary_search_by_key(c, |(c, _)| *c) else { break };
+                node = self.trie[node].children[child].1 as usize;
+
+                for (j, v) in self.trie[node].values.iter().enumerate() {
+                    values[start + j] = values[start + j].max(*v);
+                }
+            }
+        }
+
+        // a hyphen after k letters of the word is before padded[k + 1]
+        (1..len).filter(allowed).filter(|k| values[k + 1] % 2 == 1).collect()
+    }
+
+    /// Serializes the trie and exceptions, to be loaded back with [`Self::from_compiled`]
+    pub fn compile(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+
+        out.put_int(self.trie.len());
+        for node in self.trie.iter() {
+            out.put_int(node.children.len());
+            for (c, child) in node.children.iter() {
+                out.put_char(*c);
+                out.put_u32(*child);
+            }
+            out.put_bytes(&node.values);
+        }
+
+        let mut exceptions: Vec<_> = self.exceptions.iter().collect();
+        exceptions.sort();
+        out.put_int(exceptions.len());
+        for (word, positions) in exceptions {
+            out.put_str(word);
+            out.put_int(positions.len());
+            for p in positions {
+                out.put_int(*p);
+            }
+        }
+
+        out
+    }
+
+    /// Loads patterns produced by [`Self::compile`]
+    pub fn from_compiled(bytes: &[u8]) -> Result<Self, BadPattern> {
+        let mut r = Reader::new(bytes.strip_prefix(MAGIC).ok_or(BadPattern)?, BadPattern);
+
+        let mut trie = Vec::new();
+        for _ in 0..r.int()? {
+            let mut children = Vec::new();
+            for _ in 0..r.int()? {
+                children.push((r.char()?, r.u32()?));
+            }
+            trie.push(TrieNode { children, values: r.bytes()?.to_vec() });
+        }
+
+        let mut exceptions = HashMap::new();
+        for _ in 0..r.int()? {
+            let word = r.str()?.to_string();
+            let positions = (0..r.int()?).map(|_| r.int()).collect::<Result<_, _>>()?;
+            exceptions.insert(word, positions);
+        }
+
+        let len = trie.len();
+        if len == 0 || trie.iter().any(|n| n.children.iter().any(|(_, c)| *c as usize >= len)) {
+            return Err(BadPattern);
+        }
+
+        // the values of a pattern go no further than just after its last letter, so a node can
+        // have no more of them than one past the fewest letters it is reached by
+        let mut depths = vec![usize::MAX; len];
+        depths[0] = 0;
+        let mut queue = std::collections::VecDeque::from([0]);
+        while let Some(node) = queue.pop_front() {
+            for (_, child) in trie[node].children.iter() {
+                if depths[*child as usize] == usize::MAX {
+                    depths[*child as usize] = depths[node] + 1;
+                    queue.push_back(*child as usize);
+                }
+            }
+        }
+        if trie.iter().zip(depths).any(|(n, depth)| depth != usize::MAX && n.values.len() > depth + 1) {
+            return Err(BadPattern);
+        }
+
+        Ok(Self { trie, exceptions })
+    }
+}
@@ -1,3 +1,9 @@
+use crate::font::FontId;
+use crate::lexer::{Span, Token};
+
+/// Marks a rule dimension that is taken from the enclosing box
+pub const RUNNING: f32 = f32::NEG_INFINITY;
+
 #[derive(Debug, Clone)]
 pub enum Node {
     Box {
@@ -16,19 +22,316 @@ pub enum Node {
         ///                   width
         /// ```
         size: [f32; 3],
+        /// How far the box is moved down in a horizontal list, or right in a vertical one, as by
+        /// `\lower` and `\moveright`
+        shift: f32,
+        /// How many boxes deep the box goes: `0` for a character or rule, and one more than the
+        /// deepest box in its list otherwise
+        nesting: usize,
+        /// Where the box came from in the input
+        span: Span,
     },
     Glue {
         nat_size: f32,
         shrinkability: f32,
         stretchability: f32,
+        /// The order of infinity of the stretchability and shrinkability, `0` being finite and
+        /// `1` to `3` being `fil`, `fill` and `filll`
+        stretch_order: u8,
+        shrink_order: u8,
     },
+    Kern(f32),
+    /// A place where a list may be broken, at a cost. `10000` or more forbids breaking and
+    /// `-10000` or less forces it.
+    Penalty(i32),
+    /// A place where a line may be broken, adding `pre_break` at the end of the line if it is
+    Discretionary {
+        pre_break: Vec<Node>,
+    },
+    /// Something to be done when the page is shipped out, rather than shown on it
+    Whatsit(Whatsit),
+}
+
+/// What a whatsit does when its page is shipped out
+#[derive(Debug, Clone)]
+pub enum Whatsit {
+    /// `\openout`, with the stream and the name of the file
+    Open(u8, String),
+    /// `\write`, with the stream, `16` for the terminal and log and `17` for the log only, and the
+    /// text, which is expanded when it is written
+    Write(u8, Vec<Token>),
+    /// `\closeout`, with the stream
+    Close(u8),
+    /// `\special`, with its expanded text, which is for the program reading the output
+    Special(String),
 }
 
 #[derive(Debug, Clone)]
 pub enum BoxContent {
-    Character(char),
+    Character(char, FontId),
     HRule,
     VRule,
     HBox(Vec<Node>),
     VBox(Vec<Node>),
 }
+
+impl Node {
+    pub fn glue(nat_size: f32, stretchability: f32, shrinkability: f32) -> Self {
+        Self::Glue { nat_size, shrinkability, stretchability, stretch_order: 0, shrink_order: 0 }
+    }
+
+    pub fn size(&self) -> [f32; 3] {
+        match self {
+            Self::Box { size, .. } => *size,
+            Self::Glue { nat_size, .. } => [*nat_size, 0.0, 0.0],
+            Self::Kern(k) => [*k, 0.0, 0.0],
+            Self::Penalty(_) | Self::Discretionary { .. } | Self::Whatsit(_) => [0.0; 3],
+        }
+    }
+
+    pub fn is_discardable(&self) -> bool {
+        matches!(self, Self::Glue { .. } | Self::Kern(_) | Self::Penalty(_))
+    }
+
+    /// How many boxes deep this node goes, as for [`Node::Box`]'s `nesting`
+    pub fn nesting(&self) -> usize {
+        match self {
+            Self::Box { nesting, .. } => *nesting,
+            Self::Discretionary { pre_break } => nesting(pre_break) - 1,
+            _ => 0,
+        }
+    }
+
+    /// The number of nodes this one is made of, counting itself
+    pub fn count(&self) -> usize {
+        let inner = match self {
+            Self::Box { content: BoxContent::HBox(list) | BoxContent::VBox(list), .. } => list.as_slice(),
+            Self::Discretionary { pre_break } => pre_break.as_slice(),
+            _ => &[],
+        };
+        1 + inner.iter().map(Node::count).sum::<usize>()
+    }
+}
+
+/// How the glue of a box is stretched or shrunk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlueSet {
+    /// Negative when shrinking
+    pub ratio: f32,
+    pub order: u8,
+}
+
+impl GlueSet {
+    pub fn apply(&self, node: &Node) -> f32 {
+        match *node {
+            Node::Glue { nat_size, stretchability, stretch_order, .. } if self.ratio > 0.0 && stretch_order == self.order => {
+                nat_size + stretchability * self.ratio
+            },
+            Node::Glue { nat_size, shrinkability, shrink_order, .. } if self.ratio < 0.0 && shrink_order == self.order => {
+                nat_size + shrinkability * self.ratio
+            },
+            _ => node.size()[0],
+        }
+    }
+}
+
+/// Computes the glue setting needed to bring `list` from its natural size to `target`. `size`
+/// gives the length of a node along the direction of the list.
+pub fn glue_set(list: &[Node], size: impl Fn(&Node) -> f32, target: f32) -> GlueSet {
+    let mut natural = 0.0;
+    let mut stretch = [0.0; 4];
+    let mut shrink = [0.0; 4];
+
+    for n in list {
+        natural += size(n);
+        if let Node::Glue { stretchability, shrinkability, stretch_order, shrink_order, .. } = n {
+            stretch[*stretch_order as usize] += stretchability;
+            shrink[*shrink_order as usize] += shrinkability;
+        }
+    }
+
+    let excess = target - natural;
+    let totals = if excess > 0.0 { stretch } else { shrink };
+    let Some(order) = (0..4).rev().find(|o| totals[*o] != 0.0) else { return GlueSet { ratio: 0.0, order: 0 } };
+
+    let mut ratio = excess / totals[order];
+    if order == 0 && excess < 0.0 {
+        // finite glue can not shrink more than its shrinkability
+        ratio = ratio.max(-1.0);
+    }
+
+    GlueSet { ratio, order: order as u8 }
+}
+
+/// Calls `f` with every character, rule and whatsit in the box `node` and the position of its
+/// reference point, in points from the top left corner, placing `node` with its reference point at
+/// `(x, y)`. This is how output formats see a page.
+pub fn walk(node: &Node, x: f32, y: f32, f: &mut impl FnMut(&Node, f32, f32)) {
+    let Node::Box { content, size: [width, height, depth], .. } = node else { return };
+
+    match content {
+        BoxContent::Character(..) | BoxContent::HRule | BoxContent::VRule => f(node, x, y),
+        BoxContent::HBox(list) => {
+            let set = glue_set(list, |n| n.size()[0], *width);
+            let mut x = x;
+            for n in list {
+                match n {
+                    Node::Box { shift, .. } => walk(n, x, y + shift, f),
+                    Node::Whatsit(_) => f(n, x, y),
+                    _ => {},
+                }
+                x += set.apply(n);
+            }
+        },
+        BoxContent::VBox(list) => {
+            let size = |n: &Node| match n {
+                Node::Box { size: [_, h, d], .. } => h + d,
+                n => n.size()[0],
+            };
+            let set = glue_set(list, size, height + depth);
+            let mut y = y - height;
+            for n in list {
+                match n {
+                    Node::Box { size: [_, h, d], shift, .. } => {
+                        walk(n, x + shift, y + h, f);
+                        y += h + d;
+                    },
+                    Node::Whatsit(_) => f(n, x, y),
+                    n => y += set.apply(n),
+                }
+            }
+        },
+    }
+}
+
+/// How much `list` exceeds `target` when its finite glue is shrunk as much as it can be, `None` if
+/// it fits
+pub fn overfull(list: &[Node], size: impl Fn(&Node) -> f32, target: f32) -> Option<f32> {
+    let mut natural = 0.0;
+    let mut shrink = 0.0;
+
+    for n in list {
+        natural += size(n);
+        match n {
+            Node::Glue { shrinkability, shrink_order: 0, .. } => shrink += shrinkability,
+            Node::Glue { shrinkability, .. } if *shrinkability != 0.0 => return None,
+            _ => {},
+        }
+    }
+
+    let excess = natural - shrink - target;
+    (excess > 0.0).then_some(excess)
+}
+
+/// How bad it looks to stretch or shrink by `amount` with a total stretchability or
+/// shrinkability of `total`, from `0` to `10000`, as TeX computes it
+pub fn badness(amount: f32, total: f32) -> u32 {
+    if amount <= 0.0 {
+        0
+    } else if total <= 0.0 {
+        10000
+    } else {
+        (100.0 * (amount / total).powi(3)).round().min(10000.0) as u32
+    }
+}
+
+/// The span covering the boxes in `list`, as long as they come from the same file
+pub fn span_of(list: &[Node]) -> Span {
+    let mut spans = list.iter().filter_map(|n| match n {
+        Node::Box { span, .. } if span.file.is_some() => Some(span),
+        _ => None,
+    });
+
+    let Some(first) = spans.next() else { return Span::default() };
+    let mut span = first.clone();
+    for s in spans.filter(|s| s.file == first.file) {
+        span.range.start = span.range.start.min(s.range.start);
+        span.range.end = span.range.end.max(s.range.end);
+    }
+
+    span
+}
+
+/// The `nesting` of a box made of `list`
+pub fn nesting(list: &[Node]) -> usize {
+    1 + list.iter().map(Node::nesting).max().unwrap_or(0)
+}
+
+/// Packs `list` into a horizontal box, of width `width` or its natural width
+pub fn hpack(mut list: Vec<Node>, width: Option<f32>, span: Span) -> Node {
+    let mut natural = 0.0;
+    let mut height: f32 = 0.0;
+    let mut depth: f32 = 0.0;
+
+    for n in list.iter() {
+        let [w, h, d] = n.size();
+        natural += w;
+        if let Node::Box { shift, .. } = n {
+            height = height.max(h - shift);
+            depth = depth.max(d + shift);
+        }
+    }
+
+    for n in list.iter_mut() {
+        if let Node::Box { content: BoxContent::VRule, size, .. } = n {
+            if size[1] == RUNNING {
+                size[1] = height;
+            }
+            if size[2] == RUNNING {
+                size[2] = depth;
+            }
+        }
+    }
+
+    let nesting = nesting(&list);
+    Node::Box { content: BoxContent::HBox(list), size: [width.unwrap_or(natural), height, depth], shift: 0.0, nesting, span }
+}
+
+/// Packs `list` into a vertical box of height `height` or its natural height, with the depth of
+/// its last box
+pub fn vpack(mut list: Vec<Node>, height: Option<f32>, span: Span) -> Node {
+    let mut width: f32 = 0.0;
+    let mut natural = 0.0;
+    let mut depth = 0.0;
+
+    for n in list.iter() {
+        let [w, h, d] = n.size();
+        match n {
+            Node::Box { shift, .. } => {
+                natural += depth + h;
+                depth = d;
+                width = width.max(w + shift);
+            },
+            Node::Whatsit(_) => {},
+            _ => {
+                natural += depth + w;
+                depth = 0.0;
+            },
+        }
+    }
+
+    for n in list.iter_mut() {
+        if let Node::Box { content: BoxContent::HRule, size, .. } = n {
+            if size[0] == RUNNING {
+                size[0] = width;
+            }
+        }
+    }
+
+    let nesting = nesting(&list);
+    Node::Box { content: BoxContent::VBox(list), size: [width, height.unwrap_or(natural), depth], shift: 0.0, nesting, span }
+}
+
+/// Packs `list` into a vertical box like [`vpack`], but with the baseline of its first box as
+/// that of the whole box, as `\vtop` does
+pub fn vtop(list: Vec<Node>, height: Option<f32>, span: Span) -> Node {
+    let mut b = vpack(list, height, span);
+    if let Node::Box { content: BoxContent::VBox(list), size, .. } = &mut b {
+        let top = match list.first() {
+            Some(Node::Box { size: [_, h, _], .. }) => *h,
+            _ => 0.0,
+        };
+        *size = [size[0], top, size[1] + size[2] - top];
+    }
+    b
+}
@@ -1,21 +1,137 @@
+//! TeX's input processor, as described in chapter 8 of The TeXbook.
+//!
+//! A [`Lexer`] turns lines of input into [`Token`]s using category codes. It reduces `^^`
+//! notation, removes trailing spaces from lines, puts an end of line character at the end of
+//! each and skips comments and ignored characters. Like TeX, it is in one of three states: at the
+//! beginning of a line (N), in the middle of a line (M) or skipping blanks (S). Spaces are skipped
+//! in states N and S, and the end of a line gives `\par` in state N and a space in state M.
+
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::rc::Rc;
+
+use crate::error::{Diagnostic, ErrorKind};
 
 pub type Range = core::ops::Range<usize>;
-pub type CowStr<'a> = std::borrow::Cow<'a, str>;
 
 #[derive(Debug, Clone)]
-pub struct Token<'a> {
+pub struct Token {
     pub typ: TokenType,
-    /// The range of the token's origin
+    /// The file the token came from, if any
+    pub file: Option<FileId>,
+    /// The range of the token's origin in `file`
     /// It is `0..0` if it does not come from a file
     pub range: Range,
-    pub source: CowStr<'a>,
+    /// The character of a character token, or the [`CsId`] of a control sequence
+    value: u32,
+}
+
+/// Tokens are equal if they have the same type and value, regardless of where they came from
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.typ == other.typ && self.value == other.value
+    }
+}
+
+impl Token {
+    pub fn new_char(typ: TokenType, c: char, range: Range) -> Self {
+        debug_assert_ne!(typ, TokenType::Escape);
+        Self { typ, file: None, range, value: c as u32 }
+    }
+
+    pub fn new_cs(cs: CsId, range: Range) -> Self {
+        Self { typ: TokenType::Escape, file: None, range, value: cs.0 }
+    }
+
+    /// The character of a character token
+    pub fn char(&self) -> char {
+        match self.typ {
+            TokenType::Escape => '\0',
+            _ => char::from_u32(self.value).unwrap(),
+        }
+    }
+
+    /// The control sequence of a control sequence token
+    pub fn cs(&self) -> Option<CsId> {
+        (self.typ == TokenType::Escape).then_some(CsId(self.value))
+    }
+
+    pub fn is_other(&self, c: char) -> bool {
+        self.typ == TokenType::Other && self.char() == c
+    }
+
+    pub fn span(&self) -> Span {
+        Span { file: self.file, range: self.range.clone() }
+    }
+
+    /// Attributes the token to `span` instead
+    pub fn at(self, span: &Span) -> Self {
+        Self { file: span.file, range: span.range.clone(), ..self }
+    }
+}
+
+/// Where something came from in the input
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    pub file: Option<FileId>,
+    pub range: Range,
+}
+
+/// An index into the files known to the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(pub u32);
+
+/// An interned control sequence name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CsId(u32);
+
+impl CsId {
+    /// The position of the name in its [`CsTable`], as listed by [`CsTable::names`]
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// The table of control sequence names. Clones share the same table, so that tokens from different
+/// lexers can be compared.
+#[derive(Debug, Clone, Default)]
+pub struct CsTable(Rc<RefCell<CsNames>>);
+
+#[derive(Debug, Default)]
+struct CsNames {
+    names: Vec<Rc<str>>,
+    ids: HashMap<Rc<str>, CsId>,
+}
+
+impl CsTable {
+    pub fn intern(&self, name: &str) -> CsId {
+        let mut table = self.0.borrow_mut();
+        if let Some(id) = table.ids.get(name) {
+            return *id;
+        }
+
+        let id = CsId(table.names.len() as u32);
+        let name: Rc<str> = name.into();
+        table.names.push(Rc::clone(&name));
+        table.ids.insert(name, id);
+        id
+    }
+
+    pub fn name(&self, cs: CsId) -> Rc<str> {
+        Rc::clone(&self.0.borrow().names[cs.0 as usize])
+    }
+
+    /// Every name interned, in the order of their ids
+    pub fn names(&self) -> Vec<Rc<str>> {
+        self.0.borrow().names.clone()
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum TokenType {
-    /// This is a command if it happends to exist in the token stream
+    /// A control sequence if it is in a token list
     Escape,
     BeginGroup,
     EndGroup,
@@ -34,48 +150,260 @@ pub enum TokenType {
     Invalid,
 }
 
+impl TokenType {
+    /// All token types, indexed by their category code
+    pub const ALL: [Self; 16] = [
+        Self::Escape,
+        Self::BeginGroup,
+        Self::EndGroup,
+        Self::MathShift,
+        Self::AlignTab,
+        Self::Eol,
+        Self::Parameter,
+        Self::Superscript,
+        Self::Subscript,
+        Self::Ignored,
+        Self::Space,
+        Self::Letter,
+        Self::Other,
+        Self::Active,
+        Self::Comment,
+        Self::Invalid,
+    ];
+
+    pub fn from_catcode(code: u8) -> Self {
+        Self::ALL[code as usize]
+    }
+}
+
+/// The characters the engine works with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// Character codes 0 to 255, where only ASCII letters are letters, as in Knuth's TeX
+    #[default]
+    Classic,
+    /// All of Unicode, with letters and character codes following Unicode properties, as in XeTeX
+    /// and LuaTeX
+    Unicode,
+}
+
+impl Encoding {
+    /// The largest character code
+    pub fn max_char(self) -> u32 {
+        match self {
+            Self::Classic => 255,
+            Self::Unicode => char::MAX as u32,
+        }
+    }
+
+    /// Whether `c` has category code 11 initially
+    pub fn is_letter(self, c: char) -> bool {
+        match self {
+            Self::Classic => c.is_ascii_alphabetic(),
+            Self::Unicode => c.is_alphabetic(),
+        }
+    }
+}
+
+/// Turns lines of input into tokens.
+///
+/// Lines may either include their line break, as with [`str::split_inclusive`], or not, as with
+/// [`str::lines`]. Token ranges are exact byte offsets into the source when line breaks are
+/// included, as with [`Lexer::from_source`] and [`Lexer::from_reader`]. Otherwise every line break
+/// is assumed to be one byte.
 #[derive(Clone)]
-pub struct Lexer<'a, L: 'a + Clone + Iterator<Item = &'a str>> {
+pub struct Lexer<L: Iterator<Item: AsRef<str>>> {
     pub stream: L,
-    cur_line: Option<(usize, &'a str)>,
+    /// The line being read, `None` before the first line and after the last
+    line: Option<L::Item>,
+    /// The offset of the start of `line`
+    line_start: usize,
+    /// The index of `line` among the lines of the stream
+    line_index: usize,
+    /// The character at the end of `line`, which was the `end_line_char` when it was read
+    line_end: Option<char>,
+    /// The character put at the end of each line as it is read, set by `\endlinechar`. `None`
+    /// leaves lines as they are.
+    pub end_line_char: Option<char>,
     pub catcodes: HashMap<char, TokenType>,
+    /// Decides the catcodes of characters not in `catcodes`
+    pub encoding: Encoding,
+    pub cs_table: CsTable,
+    /// The file to attribute tokens to
+    pub file: Option<FileId>,
+    /// Errors found while lexing, to be taken by the user of the lexer
+    pub diagnostics: Vec<Diagnostic>,
+    /// Set by `\endinput` or the end of the stream to stop reading after the current line
+    ended: bool,
     range: Range,
     state: State,
+    /// Buffer for the name of the control word being lexed
+    name: String,
+    par: CsId,
+}
+
+/// What a [`Lexer`] needs to start lexing at the beginning of a line. Every line starts in state
+/// N, so lexing the lines after a checkpoint only depends on the checkpoint and the lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// The offset of the line
+    pub offset: usize,
+    /// The index of the line
+    pub line: usize,
+    catcodes: HashMap<char, TokenType>,
+    encoding: Encoding,
+    end_line_char: Option<char>,
+}
+
+impl Checkpoint {
+    /// Whether lexing from either checkpoint gives the same tokens for the same lines, wherever
+    /// they are. When re-lexing after an edit, this tells when the lexer has caught up with the
+    /// tokens from before the edit.
+    pub fn same_state(&self, other: &Self) -> bool {
+        self.catcodes == other.catcodes
+            && self.encoding == other.encoding
+            && self.end_line_char == other.end_line_char
+    }
 }
 
+/// Where the lexer is in a line, which decides what spaces and line ends do
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 enum State {
-    #[default] N, M, S
+    /// At the beginning of a line
+    #[default]
+    N,
+    /// In the middle of a line
+    M,
+    /// Skipping blanks
+    S,
 }
 
-impl<'a, L: 'a + Clone + Iterator<Item = &'a str>> Lexer<'a, L> {
-    pub fn new(mut stream: L) -> Self {
-        let line = stream.next();
+impl<L: Iterator<Item: AsRef<str>>> Lexer<L> {
+    pub fn new(stream: L) -> Self {
+        Self::with_cs_table(stream, CsTable::default())
+    }
+
+    pub fn with_cs_table(stream: L, cs_table: CsTable) -> Self {
+        let par = cs_table.intern("par");
 
         Self {
             stream,
-            cur_line: line.map(|l| (0, l.trim_end())),
+            line: None,
+            line_start: 0,
+            line_index: 0,
+            line_end: None,
+            end_line_char: Some('\r'),
             catcodes: HashMap::new(),
+            encoding: Encoding::default(),
+            cs_table,
+            file: None,
+            diagnostics: Vec::new(),
+            ended: false,
             range: 0..0,
-            state: State::N
+            state: State::N,
+            name: String::new(),
+            par,
         }
     }
 
-    fn peek_char(&mut self) -> Option<char> {
-        if self.range.end - self.cur_line?.0 > self.cur_line?.1.len() {
-            // println!("reload {:?} {:?}", self.range, self.cur_line);
-            self.cur_line = self.stream.next().map(|l| (self.range.end, l.trim_end()));
-            // println!("{:?}", self.cur_line);
+    /// Sets the offset of the first line in the file, for lexing part of a file
+    pub fn set_offset(&mut self, offset: usize) {
+        self.line_start = offset;
+        self.range = offset..offset;
+    }
+
+    /// The current line without its line break and trailing spaces, as TeX removes them
+    fn line(&self) -> &str {
+        let Some(line) = self.line.as_ref() else { return "" };
+        let line = line.as_ref();
+        let line = line.strip_suffix('\n').map_or(line, |l| l.strip_suffix('\r').unwrap_or(l));
+        line.trim_end_matches(' ')
+    }
+
+    /// The offset and index of the line after the current one
+    fn next_line_start(&self) -> (usize, usize) {
+        match self.line.as_ref().map(|l| l.as_ref()) {
+            // lines without their line break are assumed to have been split at a `\n`
+            Some(line) => (self.line_start + line.len() + usize::from(!line.ends_with('\n')), self.line_index + 1),
+            None => (self.line_start, self.line_index),
         }
+    }
 
-        if self.range.end - self.cur_line?.0 == self.cur_line?.1.len() {
-            // println!("nl {:?}", self.range);
-            return Some('\n');
+    /// Moves on to the next line, returning whether there is one
+    fn next_line(&mut self) -> bool {
+        (self.line_start, self.line_index) = self.next_line_start();
+        self.line = None;
+
+        if !self.ended {
+            self.line = self.stream.next();
         }
+        self.ended |= self.line.is_none();
+        self.range = self.line_start..self.line_start;
+        self.line_end = self.end_line_char;
+        self.state = State::N;
+        self.line.is_some()
+    }
 
-        let c = self.cur_line?.1.split_at_checked(self.range.end - self.cur_line?.0)?.1.chars().nth(0);
-        // println!("{c:?} {:?}", self.range);
-        c
+    /// Skips the rest of the current line
+    fn skip_line(&mut self) {
+        self.range.end = self.line_start + self.line().len() + self.line_end.map_or(0, char::len_utf8);
+    }
+
+    /// The next character of the current line, `None` at its end
+    fn peek_char(&self) -> Option<char> {
+        let line = self.line();
+        let pos = self.range.end.checked_sub(self.line_start)?;
+        match line.get(pos..)?.chars().next() {
+            None => self.line_end.filter(|_| pos == line.len()),
+            c => c,
+        }
+    }
+
+    /// Saves what is needed to lex from the start of the next line, so that lexing can be resumed
+    /// there with [`Self::resume`]
+    pub fn checkpoint(&self) -> Checkpoint {
+        let (offset, line) = self.next_line_start();
+        Checkpoint {
+            offset,
+            line,
+            catcodes: self.catcodes.clone(),
+            encoding: self.encoding,
+            end_line_char: self.end_line_char,
+        }
+    }
+
+    /// Continues lexing from `checkpoint`, with `stream` giving the lines from there on
+    pub fn resume(stream: L, cs_table: CsTable, checkpoint: &Checkpoint) -> Self {
+        let mut lexer = Self::with_cs_table(stream, cs_table);
+        lexer.set_offset(checkpoint.offset);
+        lexer.line_index = checkpoint.line;
+        lexer.catcodes = checkpoint.catcodes.clone();
+        lexer.encoding = checkpoint.encoding;
+        lexer.end_line_char = checkpoint.end_line_char;
+        lexer
+    }
+
+    /// Lexes the next line, skipping whatever is left of the current one. `None` at the end of
+    /// the stream.
+    pub fn lex_line(&mut self) -> Option<Vec<Token>> {
+        if !self.next_line() {
+            return None;
+        }
+
+        let file = self.file;
+        Some(core::iter::from_fn(|| self.next_in_line()).map(|t| Token { file, ..t }).collect())
+    }
+
+    /// The next token of the current line, `None` at its end
+    fn next_in_line(&mut self) -> Option<Token> {
+        self.range.start = self.range.end;
+        let c = self.next_char()?;
+        self._next(c)
+    }
+
+    /// Stops reading after the current line, as `\endinput` does
+    pub fn end_input(&mut self) {
+        self.ended = true;
     }
 
     fn next_char(&mut self) -> Option<char> {
@@ -84,27 +412,33 @@ impl<'a, L: 'a + Clone + Iterator<Item = &'a str>> Lexer<'a, L> {
         Some(c)
     }
 
+    /// The range of the token being lexed. The end of line character takes up the trailing spaces
+    /// and line break of the line, which are not part of the line as TeX sees it.
     fn range(&self) -> Range {
-        self.range.clone()
+        let end = match self.range.end > self.line_start + self.line().len() {
+            true => self.line_start + self.line.as_ref().map_or(0, |l| l.as_ref().len()),
+            false => self.range.end,
+        };
+        self.range.start..end
     }
 
-    fn catcode_of(&self, chr: char) -> TokenType {
+    /// The category code of `chr`, from `catcodes` or the initial ones
+    pub fn catcode_of(&self, chr: char) -> TokenType {
         match chr {
-            // c if let Some(t) = self.catcodes.get(&c) => t.clone(),
-            c if self.catcodes.contains_key(&c) => self.catcodes[&c].clone(),
+            c if self.catcodes.contains_key(&c) => self.catcodes[&c],
 
             '\\' => TokenType::Escape,
             '{' => TokenType::BeginGroup,
             '}' => TokenType::EndGroup,
             '$' => TokenType::MathShift,
             '&' => TokenType::AlignTab,
-            '\n' => TokenType::Eol,
+            '\r' => TokenType::Eol,
             '#' => TokenType::Parameter,
             '^' => TokenType::Superscript,
             '_' => TokenType::Subscript,
-            '\0' | '\r' => TokenType::Ignored,
+            '\0' => TokenType::Ignored,
             ' ' | '\t' => TokenType::Space,
-            c if c.is_ascii_alphabetic() => TokenType::Letter,
+            c if self.encoding.is_letter(c) => TokenType::Letter,
             '~' => TokenType::Active,
             '%' => TokenType::Comment,
             '\x7f' => TokenType::Invalid,
@@ -112,99 +446,99 @@ impl<'a, L: 'a + Clone + Iterator<Item = &'a str>> Lexer<'a, L> {
         }
     }
 
-    // fn peek_next(&mut self) -> (Option<Token<'a>>, usize) {
-    //     let mut ns = self.clone();
-    //     println!("peek");
-    //     let typ = ns.next();
-    //     let diff = ns.range.end - self.range.end;
-    //     println!("{typ:?} {diff}");
-    //     (typ, diff)
-    // }
+    /// Reduces the `^^` notation after the superscript character `c` has been read, returning the
+    /// character meant and how many bytes after `c` the notation takes. `^^` followed by two hex
+    /// digits gives that character code, and followed by any other character below 128 gives the
+    /// character 64 above or below it. With the Unicode encoding, `^^^^xxxx` and `^^^^^^xxxxxx`
+    /// give any character by its code as in XeTeX. As in TeX, only lowercase hex digits count.
+    fn ss_char(&self, c: char) -> Option<(char, usize)> {
+        let rest = self.line().get(self.range.end - self.line_start..)?;
+        // the end of the line counts as a character, as in TeX's buffer
+        let ahead: Vec<char> = rest.chars().chain(self.line_end).take(11).collect();
+        let bytes = |n: usize| ahead[..n].iter().map(|c| c.len_utf8()).sum();
+        let hex = |digits: &[char]| match digits.iter().all(|d| matches!(d, '0'..='9' | 'a'..='f')) {
+            true => u32::from_str_radix(&digits.iter().collect::<String>(), 16).ok().and_then(char::from_u32),
+            false => None,
+        };
 
-    fn is_ss_char(&mut self, c: char) -> bool {
-        self.peek_char() == Some(c)                                 // followed by identical character
-            && self.next_char().is_some()                           // placeholder to skip 1 char
-            && self.peek_char().map_or(false, |c| (c as u32) < 128) // c < 128
-    }
+        if ahead.first() != Some(&c) {
+            return None;
+        }
 
-    fn get_ss_char(&mut self) -> char {
-        let next = self.next_char().unwrap();
-        match next {
-            '0'..='9' | 'a'..='f' if self.peek_char().map_or(false, |c| matches!(c, '0'..='9' | 'a'..='f')) => {
-                let hex = [next as u8, self.next_char().unwrap() as u8];
-                u8::from_str_radix(unsafe { core::str::from_utf8_unchecked(&hex) }, 16).unwrap() as char
+        if self.encoding == Encoding::Unicode {
+            // the first superscript character has been read already
+            for (after, digits) in [(5, 6), (3, 4)] {
+                if ahead.get(..after).is_some_and(|a| a.iter().all(|a| *a == c)) {
+                    if let Some(r) = ahead.get(after..after + digits).and_then(hex) {
+                        return Some((r, bytes(after + digits)));
+                    }
+                }
             }
-            _ => {
-                (next as u8).wrapping_sub(64) as char
-            },
+        }
+
+        if let Some(r) = ahead.get(1..3).and_then(hex) {
+            return Some((r, bytes(3)));
+        }
+
+        match ahead.get(1).map(|a| *a as u32) {
+            Some(a @ 0..64) => Some((char::from_u32(a + 64)?, bytes(2))),
+            Some(a @ 64..128) => Some((char::from_u32(a - 64)?, bytes(2))),
+            _ => None,
         }
     }
 
-    fn _next(&mut self, c: char) -> Option<Token<'a>> {
+    /// Peeks the next character with any `^^` notation reduced, along with the number of bytes it
+    /// takes
+    fn peek_reduced(&mut self) -> Option<(char, usize)> {
+        let c = self.peek_char()?;
+        let start = self.range.end;
+        self.range.end += c.len_utf8();
+
+        let mut reduced = c;
+        while self.catcode_of(reduced) == TokenType::Superscript {
+            let Some((r, len)) = self.ss_char(reduced) else { break };
+            self.range.end += len;
+            reduced = r;
+        }
+
+        let len = self.range.end - start;
+        self.range.end = start;
+        Some((reduced, len))
+    }
+
+    fn _next(&mut self, c: char) -> Option<Token> {
         let typ = self.catcode_of(c);
         match typ {
             TokenType::Escape => {
-                match self.peek_char().map(|c| (c, self.catcode_of(c))) {
-                    Some((_, TokenType::Eol)) | None => {
-                        self.state = State::M;
-                        Some(Token { typ: TokenType::Escape, range: self.range(), source: "".into() })
+                match self.peek_reduced().map(|(c, len)| (c, len, self.catcode_of(c))) {
+                    None => {
+                        self.state = State::S;
+                        Some(Token::new_cs(self.cs_table.intern(""), self.range()))
                     },
-                    Some((_, TokenType::Letter)) => {
-                        let mut source = String::new();
-
-                        loop {
-                            let next = self.peek_char();
-                            match next.map(|c| (c, self.catcode_of(c))) {
-                                Some((_, TokenType::Letter)) => {
-                                    let tok = self.next().unwrap();
-                                    debug_assert_eq!(tok.typ, TokenType::Letter);
-                                    source += &tok.source;
-                                },
-                                Some((c, TokenType::Superscript)) => {
-                                    self.range.end += c.len_utf8();
-                                    let is_ss = self.is_ss_char(c);
-                                    self.range.end -= c.len_utf8();
-
-                                    if is_ss {
-                                        if let Some(Token { typ: TokenType::Letter, source: src, .. }) = self.next() {
-                                            source += &src;
-                                        } else {
-                                            break;
-                                        }
-                                    } else {
-                                        break;
-                                    }
-                                }
-                                _ => break,
-                            }
+                    Some((_, _, TokenType::Letter)) => {
+                        self.name.clear();
+                        while let Some((c, len)) = self.peek_reduced().filter(|(c, _)| self.catcode_of(*c) == TokenType::Letter) {
+                            self.range.end += len;
+                            self.name.push(c);
                         }
 
                         self.state = State::S;
-                        Some(Token {
-                            typ: TokenType::Escape,
-                            range: self.range(),
-                            source: source.into(),
-                        })
+                        Some(Token::new_cs(self.cs_table.intern(&self.name), self.range()))
                     },
-                    Some((c, _)) => {
-                        self.state = State::M;
-                        self.range.end += c.len_utf8();
-                        Some(Token {
-                            typ: TokenType::Escape,
-                            range: self.range(),
-                            source: "".into(), //self.stream[self.range.end - c.len_utf8()..self.range.end].into(),
-                        })
+                    Some((c, len, typ)) => {
+                        self.state = match typ {
+                            TokenType::Space => State::S,
+                            _ => State::M,
+                        };
+                        self.range.end += len;
+                        Some(Token::new_cs(self.cs_table.intern(c.encode_utf8(&mut [0; 4])), self.range()))
                     },
                 }
             },
-            TokenType::Superscript if self.is_ss_char(c) => {
-                // let range = self.range();
-                let c = self.get_ss_char();
-                let mut next = self._next(c)?;
-                // if next.source == &self.stream[range] {
-                //     next.source = c.to_string().into();
-                // }
-                Some(next)
+            TokenType::Superscript if self.ss_char(c).is_some() => {
+                let (c, len) = self.ss_char(c).unwrap();
+                self.range.end += len;
+                self._next(c)
             },
             TokenType::BeginGroup |
             TokenType::EndGroup |
@@ -217,51 +551,84 @@ impl<'a, L: 'a + Clone + Iterator<Item = &'a str>> Lexer<'a, L> {
             TokenType::Other |
             TokenType::Active => {
                 self.state = State::M;
-                // Some(Token { typ, range: self.range(), source: self.stream[self.range()].into() })
-                // TODO: allocless
-                Some(Token { typ, range: self.range(), source: c.to_string().into() })
+                Some(Token::new_char(typ, c, self.range()))
             },
             TokenType::Eol => {
+                self.skip_line();
                 match core::mem::take(&mut self.state) {
-                    State::N => Some(Token { typ: TokenType::Escape, range: self.range(), source: "par".into() }),
-                    State::M => Some(Token { typ: TokenType::Space, range: self.range(), source: " ".into() }),
-                    State::S => self.next(),
+                    State::N => Some(Token::new_cs(self.par, self.range())),
+                    State::M => Some(Token::new_char(TokenType::Space, ' ', self.range())),
+                    State::S => self.next_in_line(),
                 }
             },
-            TokenType::Ignored => self.next(),
+            TokenType::Ignored => self.next_in_line(),
             TokenType::Space => {
                 match self.state {
-                    State::N | State::S => self.next(),
+                    State::N | State::S => self.next_in_line(),
                     State::M => {
                         self.state = State::S;
-                        Some(Token { typ: TokenType::Space, range: self.range(), source: " ".into() })
+                        Some(Token::new_char(TokenType::Space, ' ', self.range()))
                     },
                 }
             },
             TokenType::Comment => {
-                while self.next_char().map_or(false, |c| self.catcode_of(c) != TokenType::Eol) {}
-                self.next()
+                self.skip_line();
+                self.next_in_line()
             },
             TokenType::Invalid => {
-                println!("! encountered invalid character");
-                self.next()
+                let span = Span { file: self.file, range: self.range() };
+                self.diagnostics.push(Diagnostic::new(ErrorKind::InvalidCharacter(c), span));
+                self.next_in_line()
             },
         }
     }
 }
 
-impl<'a, L: 'a + Clone + Iterator<Item = &'a str>> Iterator for Lexer<'a, L> {
-    type Item = Token<'a>;
+impl<'a> Lexer<core::str::SplitInclusive<'a, char>> {
+    /// Lexes a whole source text, with token ranges being byte offsets into it
+    pub fn from_source(source: &'a str) -> Self {
+        Self::new(source.split_inclusive('\n'))
+    }
+}
+
+impl<R: Read> Lexer<ReadLines<R>> {
+    /// Lexes a source read line by line as it is needed, with token ranges being byte offsets into
+    /// it
+    pub fn from_reader(reader: R) -> Self {
+        Self::new(ReadLines(BufReader::new(reader)))
+    }
+}
+
+/// The lines of a [`Read`] source, with their line breaks. Reading stops at the first I/O error,
+/// and invalid UTF-8 is replaced, which throws off the ranges of tokens after it on the same line.
+pub struct ReadLines<R>(BufReader<R>);
+
+impl<R: Read> Iterator for ReadLines<R> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let mut line = Vec::new();
+        match self.0.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(String::from_utf8(line).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())),
+        }
+    }
+}
+
+impl<L: Iterator<Item: AsRef<str>>> Iterator for Lexer<L> {
+    type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.range.start = self.range.end;
+        loop {
+            if let Some(t) = self.next_in_line() {
+                return Some(Token { file: self.file, ..t });
+            }
 
-        if let Some(c) = self.next_char() {
-            self._next(c)
-        } else {
-            None
+            if !self.next_line() {
+                return None;
+            }
         }
     }
 }
 
-impl<'a, L: 'a + Clone + Iterator<Item = &'a str>> core::iter::FusedIterator for Lexer<'a, L> {}
+impl<L: Iterator<Item: AsRef<str>>> core::iter::FusedIterator for Lexer<L> {}
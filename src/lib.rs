@@ -1,11 +1,81 @@
-mod layout;
-mod lexer;
+mod binary;
+mod document;
+mod dvi;
+pub mod engine;
+pub mod error;
+pub mod font;
+mod html;
+pub mod hyphen;
+pub mod layout;
+pub mod lexer;
+mod pdf;
+mod svg;
 
-pub fn render_as_html(tex: &str) -> String {
-    let mut lexer = lexer::Lexer::new(tex.lines());
-    while let Some(t) = lexer.next() {
-        eprintln!("{t:?}");
-    }
+use std::path::Path;
+use std::sync::OnceLock;
+
+use engine::{BadFormat, BuildError, Engine, SourceFile};
+use error::Error;
+pub use document::{Document, MathRenderer, Metadata, OutputFormat};
+pub use html::MATH_STYLE;
+
+/// Typesets a document without a format, breaking it into pages
+pub fn render(tex: &str) -> Document {
+    Engine::builder().render(tex).expect("an engine without a format or fonts to load can be built")
+}
+
+/// Typesets a document after loading a format from disk, either dumped by `\dump` or TeX source
+/// like `plain.tex`, which sets up the macros it uses. Files the format inputs are looked for
+/// next to it, then in the current directory.
+pub fn render_with_format(tex: &str, format: &Path) -> Result<Document, BuildError> {
+    Engine::builder().format_file(format).render(tex)
+}
+
+/// Runs a format like `plain.tex` from disk and returns the state it sets up, as written by
+/// `\dump`, or as it is at the end of the format if it does not use `\dump`
+pub fn dump_format(format: &Path) -> Result<Vec<u8>, BuildError> {
+    let mut engine = Engine::builder().format_file(format).build(texput(""))?;
+    Ok(engine.take_dump().unwrap_or_else(|| engine.dump()))
+}
+
+/// Typesets a document after loading a format dumped by [`dump_format`], which is much faster
+/// than running the format again
+pub fn render_with_dump(tex: &str, format: &[u8]) -> Result<Document, BadFormat> {
+    let mut engine = Engine::builder().build(texput(tex)).expect("an engine without a format or fonts to load can be built");
+    engine.undump(format)?;
+    Ok(engine.typeset())
+}
 
-    String::new()
+fn texput(tex: &str) -> SourceFile {
+    SourceFile { name: "texput".into(), text: tex.into() }
+}
+
+/// Renders a formula by itself, as if it were between `$` signs, or in a display if `display`
+/// is set. The math of the LaTeX kernel built into rstex can be used, like `\frac`, `\sqrt`,
+/// `\sum`, Greek letters, `\mathbb` and matrices. The result is HTML to put in a page along
+/// with [`MATH_STYLE`].
+pub fn render_math(tex: &str, display: bool) -> Result<String, Error> {
+    render_math_with(tex, display, MathRenderer::Html)
+}
+
+/// Renders a formula by itself as [`render_math`] does, as HTML or as MathML
+pub fn render_math_with(tex: &str, display: bool, math: MathRenderer) -> Result<String, Error> {
+    let mut engine = Engine::new(texput(tex));
+    engine.undump(latex_dump()).expect("the kernel was dumped by the same engine");
+    engine.set_math_renderer(math);
+    engine.formula(display)
+}
+
+/// The state the built-in LaTeX kernel sets up, which is only run the first time it is needed
+fn latex_dump() -> &'static [u8] {
+    static DUMP: OnceLock<Vec<u8>> = OnceLock::new();
+    DUMP.get_or_init(|| {
+        let mut engine = Engine::new(texput(""));
+        engine.load_latex();
+        engine.dump()
+    })
+}
+
+pub fn render_as_html(tex: &str) -> String {
+    render(tex).to_html()
 }
@@ -0,0 +1,308 @@
+//! The `rstex` command, which typesets a TeX file like `tex` does and writes the page as HTML,
+//! SVG, PDF or DVI.
+
+mod watch;
+
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rstex::engine::{DirResolver, Engine, InteractionMode, Limits, Response, SourceFile, Terminal};
+use rstex::error::Diagnostic;
+use rstex::OutputFormat;
+
+const USAGE: &str = "\
+usage: rstex [options] <file.tex>
+       rstex watch [options] [--port <port>] <file.tex>
+
+The second form typesets the file as HTML again whenever it or a file it inputs changes, and
+serves it on http://127.0.0.1:<port>/, 8000 by default, reloading the page in the browser.
+
+options:
+    --format <html|svg|pdf|dvi>    the output format, html by default
+    -o, --output <path>            where to write the output, `-` for standard output. By default
+                                   it is the name of the input with the extension of the format.
+    --format-file <path>           a format to load first, either a file dumped by `\\dump` or
+                                   TeX source like `plain.tex`
+    --latex                        load the subset of the LaTeX kernel built into rstex, after the
+                                   format file if one is given
+    -I, --search-path <dir>        a directory to look for `\\input` files in, after the current
+                                   directory and that of the input. Can be given more than once.
+    --font-dir <dir>               a directory to look for TFM files in. Can be given more than
+                                   once. Computer Modern fonts not found are built in.
+    --interaction <mode>           batchmode, nonstopmode, scrollmode or errorstopmode, as set by
+                                   the primitives of the same names. nonstopmode by default.
+    --no-log                       do not write a log file
+    -h, --help                     show this help
+    -V, --version                  show the version
+
+A log file like TeX's is written next to the output, or to the current directory when writing to
+standard output. The exit status is 1 if there were errors.";
+
+struct Options {
+    input: PathBuf,
+    format: OutputFormat,
+    output: Option<PathBuf>,
+    format_file: Option<PathBuf>,
+    latex: bool,
+    search_paths: Vec<PathBuf>,
+    font_dirs: Vec<PathBuf>,
+    interaction: InteractionMode,
+    log: bool,
+    /// Set for `rstex watch`, with the port to serve the preview on
+    watch: Option<u16>,
+}
+
+/// Parses the command line, `None` if it only asks for the help or version
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut args = args.peekable();
+    let mut input = None;
+    let mut options = Options {
+        input: PathBuf::new(),
+        format: OutputFormat::default(),
+        output: None,
+        format_file: None,
+        latex: false,
+        search_paths: Vec::new(),
+        font_dirs: Vec::new(),
+        interaction: InteractionMode::NonStop,
+        log: true,
+        watch: None,
+    };
+    if args.next_if_eq("watch").is_some() {
+        options.watch = Some(8000);
+    }
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {arg}"));
+        match arg.as_str() {
+            "--format" => {
+                let name = value()?;
+                options.format = OutputFormat::from_name(&name).ok_or_else(|| format!("unknown output format `{name}'"))?;
+            },
+            "-o" | "--output" => options.output = Some(value()?.into()),
+            "--format-file" => options.format_file = Some(value()?.into()),
+            "--latex" => options.latex = true,
+            "-I" | "--search-path" => options.search_paths.push(value()?.into()),
+            "--font-dir" => options.font_dirs.push(value()?.into()),
+            "--interaction" => {
+                let name = value()?;
+                options.interaction = match name.as_str() {
+                    "batchmode" => InteractionMode::Batch,
+                    "nonstopmode" => InteractionMode::NonStop,
+                    "scrollmode" => InteractionMode::Scroll,
+                    "errorstopmode" => InteractionMode::ErrorStop,
+                    _ => return Err(format!("unknown interaction mode `{name}'")),
+                };
+            },
+            "--no-log" => options.log = false,
+            "--port" if options.watch.is_some() => {
+                let port = value()?;
+                options.watch = Some(port.parse().map_err(|_| format!("invalid port `{port}'"))?);
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(None);
+            },
+            "-V" | "--version" => {
+                println!("rstex {}", env!("CARGO_PKG_VERSION"));
+                return Ok(None);
+            },
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option {arg}")),
+            _ if input.is_some() => return Err("more than one input file".into()),
+            _ => input = Some(PathBuf::from(arg)),
+        }
+    }
+
+    options.input = input.ok_or("no input file")?;
+    Ok(Some(options))
+}
+
+/// The terminal of the command, which shows messages on standard error, so that the output can
+/// go to standard output, and asks about errors on standard input in error stop mode
+struct Console;
+
+impl Terminal for Console {
+    fn error(&mut self, diagnostic: &Diagnostic) -> Response {
+        eprintln!("{}", diagnostic.render(&[]));
+        loop {
+            let Some(line) = self.read_line("? ") else { return Response::Quit };
+            let line = line.trim();
+            let mut chars = line.chars();
+            return match chars.next() {
+                None => Response::Continue,
+                Some('x' | 'X') => Response::Quit,
+                Some('q' | 'Q') => Response::SetMode(InteractionMode::Batch),
+                Some('r' | 'R') => Response::SetMode(InteractionMode::NonStop),
+                Some('s' | 'S') => Response::SetMode(InteractionMode::Scroll),
+                Some('i' | 'I') => Response::Insert(chars.as_str().to_string()),
+                Some(_) => match line.parse() {
+                    Ok(n) => Response::Delete(n),
+                    Err(_) => {
+                        eprintln!("Type <return> to proceed, S to scroll future error messages,");
+                        eprintln!("R to run without stopping, Q to run quietly,");
+                        eprintln!("I to insert something, 1-9 to delete tokens, X to quit.");
+                        continue;
+                    },
+                },
+            };
+        }
+    }
+
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        eprint!("{prompt}");
+        let _ = io::stderr().flush();
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+        }
+    }
+
+    fn print(&mut self, text: &str) {
+        eprint!("{text}");
+        if let Some(c) = text.chars().last() {
+            MID_LINE.store(c != '\n', Ordering::Relaxed);
+        }
+    }
+}
+
+/// Set when the terminal is not at the start of a line
+static MID_LINE: AtomicBool = AtomicBool::new(false);
+
+/// Starts a new line on the terminal unless it is at the start of one
+fn end_line() {
+    if MID_LINE.swap(false, Ordering::Relaxed) {
+        eprintln!();
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => return ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("rstex: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        },
+    };
+
+    let result = match options.watch {
+        Some(port) => watch::watch(&options, port),
+        None => run(&options),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("rstex: {e}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+/// The banner printed on the terminal and at the top of the log
+fn banner(options: &Options) -> String {
+    let mut banner = format!("This is rstex, Version {}", env!("CARGO_PKG_VERSION"));
+    if options.latex {
+        banner += "  (format=latex)";
+    } else if let Some(name) = options.format_file.as_deref().and_then(Path::file_stem) {
+        banner += &format!("  (format={})", name.to_string_lossy());
+    }
+    banner
+}
+
+/// Creates an engine for the input with the format loaded, ready to run
+fn new_engine(options: &Options) -> Result<Engine, String> {
+    let input = &options.input;
+    let text = std::fs::read_to_string(input).map_err(|e| format!("{}: {e}", input.display()))?;
+
+    let mut dirs = vec![PathBuf::from(".")];
+    dirs.extend(input.parent().filter(|d| !d.as_os_str().is_empty()).map(Path::to_path_buf));
+    dirs.extend(options.search_paths.iter().cloned());
+    if let Some(dir) = options.format_file.as_deref().and_then(Path::parent).filter(|d| !d.as_os_str().is_empty()) {
+        dirs.push(dir.to_path_buf());
+    }
+
+    // a format may change the interaction mode, but the command line has the last word
+    let mut builder = Engine::builder()
+        .limits(Limits::trusted())
+        .resolver(DirResolver::new(dirs))
+        .terminal(Console)
+        .interaction(options.interaction);
+    for dir in options.font_dirs.iter() {
+        builder = builder.font_dir(dir);
+    }
+    if let Some(path) = &options.format_file {
+        builder = builder.format_file(path);
+    }
+    if options.latex {
+        builder = builder.latex();
+    }
+    builder.build(SourceFile { name: input.display().to_string(), text: text.into() }).map_err(|e| e.to_string())
+}
+
+/// Typesets the input and writes the output and log, returning whether there were no errors
+fn run(options: &Options) -> Result<bool, String> {
+    let input = &options.input;
+    let job = input.file_stem().map_or_else(|| "texput".into(), |s| s.to_string_lossy().into_owned());
+    let banner = banner(options);
+    eprintln!("{banner}");
+
+    let mut engine = new_engine(options)?;
+    let doc = engine.typeset();
+    let bytes = doc.write(options.format);
+    let pages = match doc.pages.len() {
+        1 => "1 page".to_string(),
+        n => format!("{n} pages"),
+    };
+
+    let output = match &options.output {
+        Some(path) if path.as_os_str() == "-" => None,
+        Some(path) => Some(path.clone()),
+        None => Some(PathBuf::from(format!("{job}.{}", options.format.extension()))),
+    };
+    let written = match &output {
+        Some(path) => {
+            std::fs::write(path, &bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+            format!("Output written on {} ({pages}, {} bytes).", path.display(), bytes.len())
+        },
+        None => {
+            io::stdout().write_all(&bytes).map_err(|e| format!("standard output: {e}"))?;
+            format!("Output written on standard output ({pages}, {} bytes).", bytes.len())
+        },
+    };
+
+    let report = doc.report();
+    // in error stop mode the errors have been shown as they happened
+    if matches!(options.interaction, InteractionMode::NonStop | InteractionMode::Scroll) {
+        end_line();
+        eprint!("{report}");
+    }
+    if options.interaction != InteractionMode::Batch {
+        end_line();
+        eprintln!("{written}");
+    }
+
+    if options.log {
+        let path = match &output {
+            Some(path) => path.with_extension("log"),
+            None => PathBuf::from(format!("{job}.log")),
+        };
+        let mut log = format!("{banner}\n**{}\n", input.display());
+        log += &doc.metadata.log;
+        if !log.ends_with('\n') {
+            log.push('\n');
+        }
+        log += &report;
+        log += &written;
+        log.push('\n');
+        std::fs::write(&path, log).map_err(|e| format!("{}: {e}", path.display()))?;
+        if options.interaction != InteractionMode::Batch {
+            eprintln!("Transcript written on {}.", path.display());
+        }
+    }
+
+    Ok(!doc.has_errors())
+}
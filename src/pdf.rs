@@ -0,0 +1,159 @@
+use std::fmt::Write;
+
+use crate::document::Document;
+use crate::font::{Family, Font, Shape};
+use crate::layout::{self, BoxContent, Node};
+
+/// PDF units, big points, per point
+const BP_PER_PT: f32 = 72.0 / 72.27;
+
+/// The standard fonts every PDF reader has, indexed by [`base_font`]
+const BASE_FONTS: [&str; 12] = [
+    "Times-Roman",
+    "Times-Bold",
+    "Times-Italic",
+    "Times-BoldItalic",
+    "Helvetica",
+    "Helvetica-Bold",
+    "Helvetica-Oblique",
+    "Helvetica-BoldOblique",
+    "Courier",
+    "Courier-Bold",
+    "Courier-Oblique",
+    "Courier-BoldOblique",
+];
+
+/// Renders a document as PDF. Text is set in the standard fonts, so that nothing needs to be
+/// embedded, and characters they do not have are shown as `?`.
+pub fn render(doc: &Document) -> Vec<u8> {
+    let mut used = [false; BASE_FONTS.len()];
+    let contents: Vec<String> = doc.pages.iter().map(|page| content(page, doc, &mut used)).collect();
+
+    let mut objects = vec!["<< /Type /Catalog /Pages 2 0 R >>".to_string(), String::new()];
+    let mut fonts = String::new();
+    for (i, name) in BASE_FONTS.iter().enumerate().filter(|(i, _)| used[*i]) {
+        objects.push(format!("<< /Type /Font /Subtype /Type1 /BaseFont /{name} /Encoding /WinAnsiEncoding >>"));
+        let _ = write!(fonts, " /F{i} {} 0 R", objects.len());
+    }
+
+    let mut kids = Vec::new();
+    for (page, content) in doc.pages.iter().zip(contents) {
+        let [width, height, depth] = page.size();
+        kids.push(format!("{} 0 R", objects.len() + 1));
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font <<{fonts} >> >> /Contents {} 0 R >>",
+            num(width * BP_PER_PT),
+            num((height + depth) * BP_PER_PT),
+            objects.len() + 2,
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{content}endstream", content.len()));
+    }
+    objects[1] = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), kids.len());
+
+    let mut out = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, obj) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend(format!("{} 0 obj\n{obj}\nendobj\n", i + 1).bytes());
+    }
+
+    let xref = out.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(trailer, "{offset:010} 00000 n ");
+    }
+    let _ = write!(trailer, "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n", objects.len() + 1);
+    out.extend(trailer.bytes());
+    out
+}
+
+/// The content stream that draws `page`, noting the standard fonts it uses in `used`
+fn content(page: &Node, doc: &Document, used: &mut [bool; BASE_FONTS.len()]) -> String {
+    let [_, height, depth] = page.size();
+    let total = height + depth;
+
+    let mut rules = String::new();
+    let mut text = String::new();
+    let mut current = None;
+    layout::walk(page, 0.0, height, &mut |n, x, y| {
+        let Node::Box { content, size: [w, h, d], .. } = n else { return };
+        match content {
+            BoxContent::Character(c, f) => {
+                let font = doc.font(*f);
+                let i = base_font(font);
+                used[i] = true;
+                if current != Some((i, font.size)) {
+                    let _ = writeln!(text, "/F{i} {} Tf", num(font.size));
+                    current = Some((i, font.size));
+                }
+                let _ = writeln!(text, "1 0 0 1 {} {} Tm ({}) Tj", num(x), num(total - y - font.baseline_shift(*c)), encode(font.glyph(*c)));
+            },
+            _ => {
+                let _ = writeln!(rules, "{} {} {} {} re f", num(x), num(total - y - d), num(*w), num(h + d));
+            },
+        }
+    });
+
+    // everything is drawn in points
+    format!("{BP_PER_PT} 0 0 {BP_PER_PT} 0 0 cm\n{rules}BT\n{text}ET\n")
+}
+
+/// The index of the standard font that looks most like `font` in [`BASE_FONTS`]
+fn base_font(font: &Font) -> usize {
+    let family = match font.style.family {
+        Family::Serif => 0,
+        Family::Sans => 4,
+        Family::Monospace => 8,
+    };
+    family + font.style.bold as usize + if font.style.shape == Shape::Upright { 0 } else { 2 }
+}
+
+/// Encodes `c` as a PDF string in WinAnsiEncoding, splitting ligatures
+fn encode(c: char) -> String {
+    let s = match c {
+        'ﬀ' => "ff",
+        'ﬁ' => "fi",
+        'ﬂ' => "fl",
+        'ﬃ' => "ffi",
+        'ﬄ' => "ffl",
+        _ => return encode_byte(win_ansi(c).unwrap_or(b'?')),
+    };
+    s.bytes().map(encode_byte).collect()
+}
+
+fn encode_byte(b: u8) -> String {
+    match b {
+        b'(' | b')' | b'\\' => format!("\\{}", b as char),
+        0x20..=0x7e => (b as char).to_string(),
+        _ => format!("\\{b:03o}"),
+    }
+}
+
+/// The code of `c` in WinAnsiEncoding, which is Latin-1 with some punctuation added
+fn win_ansi(c: char) -> Option<u8> {
+    Some(match c {
+        ' '..='~' | '\u{a0}'..='ÿ' => c as u8,
+        '…' => 0x85,
+        'ˆ' => 0x88,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '˜' => 0x98,
+        'ı' => b'i',
+        _ => return None,
+    })
+}
+
+/// Formats a length compactly, to a thousandth of a point
+fn num(x: f32) -> String {
+    let s = format!("{x:.3}");
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt::Write;
+
+use crate::document::Document;
+use crate::font::{Family, Font, Shape};
+use crate::html::escape;
+use crate::layout::{self, BoxContent, Node};
+
+/// The space between pages, in points
+const GAP: f32 = 10.0;
+
+/// Renders a document as a standalone SVG image, with one unit to a point, text in system fonts
+/// and the pages one below the other
+pub fn render(doc: &Document) -> String {
+    let width = doc.pages.iter().map(|p| p.size()[0]).fold(0.0, f32::max);
+    let total = doc.pages.iter().map(|p| p.size()[1] + p.size()[2]).sum::<f32>() + GAP * doc.pages.len().saturating_sub(1) as f32;
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}pt\" height=\"{total}pt\" viewBox=\"0 0 {width} {total}\" \
+        font-family=\"'Latin Modern Roman','Computer Modern',serif\" font-size=\"10\">",
+    );
+    let mut top = 0.0;
+    for page in doc.pages.iter() {
+        let [_, height, depth] = page.size();
+        layout::walk(page, 0.0, top + height, &mut |n, x, y| render_box(&mut out, n, x, y, doc));
+        top += height + depth + GAP;
+    }
+    out += "</svg>";
+    out
+}
+
+/// Renders a character or rule with its reference point at `(x, y)`
+fn render_box(out: &mut String, node: &Node, x: f32, y: f32, doc: &Document) {
+    let Node::Box { content, size: [width, height, depth], .. } = node else { return };
+
+    match content {
+        BoxContent::Character(c, f) => {
+            let font = doc.font(*f);
+            let _ = write!(
+                out,
+                "<text x=\"{x}\" y=\"{}\"{} xml:space=\"preserve\">{}</text>",
+                y + font.baseline_shift(*c),
+                font_attributes(font),
+                escape(&font.glyph(*c).to_string()),
+            );
+        },
+        _ => {
+            let _ = write!(out, "<rect x=\"{x}\" y=\"{}\" width=\"{width}\" height=\"{}\"/>", y - height, height + depth);
+        },
+    }
+}
+
+/// The attributes that set a character in `font`, on top of the 10pt roman of the image
+fn font_attributes(font: &Font) -> String {
+    let mut attrs = String::new();
+    if font.size != 10.0 {
+        let _ = write!(attrs, " font-size=\"{}\"", font.size);
+    }
+    if font.style.bold {
+        attrs += " font-weight=\"bold\"";
+    }
+    match font.style.shape {
+        Shape::Upright => {},
+        Shape::Slanted => attrs += " font-style=\"oblique\"",
+        Shape::Italic => attrs += " font-style=\"italic\"",
+    }
+    match font.style.family {
+        Family::Serif => {},
+        Family::Sans => attrs += " font-family=\"'Latin Modern Sans',sans-serif\"",
+        Family::Monospace => attrs += " font-family=\"'Latin Modern Mono',monospace\"",
+    }
+    attrs
+}
@@ -0,0 +1,158 @@
+//! `rstex watch`, which serves a live preview of a document on localhost, typesetting it again
+//! whenever one of its files changes. Browsers showing the preview wait on `/wait` for the next
+//! version, and reload when it is ready.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, SystemTime};
+
+use rstex::engine::InteractionMode;
+
+use crate::{banner, end_line, new_engine, Options};
+
+/// How often the files are checked for changes
+const POLL: Duration = Duration::from_millis(250);
+
+/// How long a browser waits for a new version before asking again
+const WAIT: Duration = Duration::from_secs(30);
+
+/// Waits for the version after the one the page was served with, reloading when it arrives. If
+/// the server goes away, it keeps trying until it is back.
+const RELOAD: &str = "<script>
+(async () => {
+    for (;;) {
+        try {
+            const r = await fetch('/wait?version=' + VERSION);
+            if (await r.text() !== String(VERSION)) return location.reload();
+        } catch (e) {
+            await new Promise(r => setTimeout(r, 1000));
+        }
+    }
+})();
+</script>";
+
+/// The latest rendering of the document
+#[derive(Default)]
+struct Preview {
+    version: u64,
+    page: String,
+}
+
+type Shared = Arc<(Mutex<Preview>, Condvar)>;
+
+/// Serves the preview on `port` and keeps it up to date, until the process is killed
+pub fn watch(options: &Options, port: u16) -> Result<bool, String> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).map_err(|e| format!("port {port}: {e}"))?;
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    let shared = Shared::default();
+
+    eprintln!("{}", banner(options));
+    let mut files = render(options, &shared);
+    eprintln!("Serving a preview of {} on http://{addr}/", options.input.display());
+
+    let server = Arc::clone(&shared);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let shared = Arc::clone(&server);
+            std::thread::spawn(move || {
+                let _ = respond(stream, &shared);
+            });
+        }
+    });
+
+    loop {
+        std::thread::sleep(POLL);
+        if files.iter().any(|(path, time)| modified(path) != *time) {
+            files = render(options, &shared);
+        }
+    }
+}
+
+/// Typesets the document and publishes it as the next version, returning the files it read with
+/// the times they were modified
+fn render(options: &Options, shared: &Shared) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut paths = vec![options.input.clone()];
+    paths.extend(options.format_file.clone());
+
+    // the times are taken first, so that a change made while typesetting is not missed
+    let mut files: Vec<_> = paths.iter().map(|p| (p.clone(), modified(p))).collect();
+    let (body, report) = match new_engine(options) {
+        Ok(mut engine) => {
+            // nobody is at the terminal to answer questions about errors
+            if engine.interaction() == InteractionMode::ErrorStop {
+                engine.set_interaction(InteractionMode::NonStop);
+            }
+            let doc = engine.typeset();
+            end_line();
+            for file in doc.files.iter() {
+                let path = PathBuf::from(&file.name);
+                if !paths.contains(&path) {
+                    files.push((path.clone(), modified(&path)));
+                    paths.push(path);
+                }
+            }
+            (doc.to_html(), doc.report())
+        },
+        Err(e) => (String::new(), e + "\n"),
+    };
+
+    eprint!("{report}");
+    let mut preview = shared.0.lock().unwrap();
+    preview.version += 1;
+    eprintln!("Typeset {} (version {}).", options.input.display(), preview.version);
+
+    let title = escape(&options.input.display().to_string());
+    let errors = if report.is_empty() {
+        String::new()
+    } else {
+        format!("<pre style=\"color:#b00;white-space:pre-wrap\">{}</pre>", escape(&report))
+    };
+    preview.page = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title></head><body>{errors}{body}{}</body></html>",
+        RELOAD.replace("VERSION", &preview.version.to_string()),
+    );
+    shared.1.notify_all();
+    files
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Answers a request for the preview or for the next version of it
+fn respond(stream: TcpStream, shared: &Shared) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // the headers are of no interest
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let target = request.split(' ').nth(1).unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (status, content_type, body) = match path {
+        "/" => ("200 OK", "text/html; charset=utf-8", shared.0.lock().unwrap().page.clone()),
+        "/wait" => {
+            let seen: u64 = query.strip_prefix("version=").and_then(|v| v.parse().ok()).unwrap_or(0);
+            let preview = shared.0.lock().unwrap();
+            let (preview, _) = shared.1.wait_timeout_while(preview, WAIT, |p| p.version == seen).unwrap();
+            ("200 OK", "text/plain", preview.version.to_string())
+        },
+        _ => ("404 Not Found", "text/plain", "Not found".to_string()),
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len(),
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use rstex::engine::Engine;
+use rstex::layout::{BoxContent, Node};
+
+/// The glyphs of the HTML for `tex`, typeset with the LaTeX kernel, with their `data-src`
+fn glyphs(tex: &str) -> Vec<(String, String)> {
+    let doc = Engine::builder().latex().render(tex).unwrap();
+    assert!(!doc.has_errors(), "{}", doc.report());
+    common::html_glyphs(&doc.to_html())
+}
+
+fn glyph(glyph: &str, src: &str) -> (String, String) {
+    (glyph.to_string(), src.to_string())
+}
+
+#[test]
+fn precomposed_accents() {
+    assert_eq!(glyphs(r"\'e"), [glyph("é", "0:0-3")]);
+    assert_eq!(glyphs(r#"\"o"#), [glyph("ö", "0:0-3")]);
+    assert_eq!(glyphs(r"\c c"), [glyph("ç", "0:0-4")]);
+    assert_eq!(glyphs(r"a\^o b"), [glyph("a", "0:0-1"), glyph("ô", "0:1-4"), glyph("b", "0:5-6")]);
+}
+
+#[test]
+fn accents_without_precomposed_letters() {
+    assert_eq!(glyphs(r"\'x"), [glyph("´", "0:0-2"), glyph("x", "0:2-3")]);
+}
+
+#[test]
+fn accent_primitive() {
+    let doc = common::render(r"\accent18 o\char`x \chardef\y=`y \y");
+    assert!(doc.diagnostics.is_empty(), "{}", doc.report());
+    assert_eq!(common::text(&doc.pages), "\u{12}oxy");
+
+    // the accent is centred over the letter, between kerns that take back its width
+    let Node::Box { content: BoxContent::VBox(lines), .. } = &doc.pages[0] else { panic!() };
+    let Node::Box { content: BoxContent::HBox(line), .. } = &lines[0] else { panic!() };
+    let [_, Node::Kern(before), accent, Node::Kern(after), o, ..] = &line[..] else { panic!("{line:?}") };
+    let (a, w) = (accent.size()[0], o.size()[0]);
+    assert_eq!((*before, before + a + after), ((w - a) / 2.0, 0.0));
+}
+
+#[test]
+fn leavevmode_starts_a_paragraph() {
+    let indent = |tex| {
+        let doc = Engine::builder().latex().render(tex).unwrap();
+        match common::leaves(&doc.pages)[0] {
+            Node::Box { content: BoxContent::HBox(list), size, .. } if list.is_empty() => size[0],
+            _ => 0.0,
+        }
+    };
+    // the box is indented by \parindent in a paragraph, rather than stacked on the page
+    assert_eq!(indent(r"\leavevmode\hbox{x}"), 15.0);
+    assert_eq!(indent(r"\hbox{x}"), 0.0);
+}
+
+#[test]
+fn plain_accents() {
+    let doc = common::render(r#"\"O\v s \ae\ss"#);
+    assert!(!doc.has_errors(), "{}", doc.report());
+    assert_eq!(common::html_glyphs(&doc.to_html()), [glyph("Ö", "0:0-3"), glyph("š", "0:3-7"), glyph("æ", "0:8-11"), glyph("ß", "0:11-14")]);
+}
+
+#[test]
+fn marks_under_letters() {
+    assert_eq!(glyphs(r"\d a"), [glyph("ạ", "0:0-4")]);
+    assert_eq!(glyphs(r"x\b t"), [glyph("x", "0:0-1"), glyph("ṯ", "0:1-5")]);
+    // \c sets the cedilla with \oalign under letters taller than an x
+    assert_eq!(glyphs(r"\c C"), [glyph("Ç", "0:0-4")]);
+    assert_eq!(glyphs(r"\d x"), [glyph("x", "0:3-4"), glyph(".", "0:0-2")]);
+
+    let doc = common::render(r"\d h\b h");
+    assert_eq!(common::html_glyphs(&doc.to_html()), [glyph("ḥ", "0:0-4"), glyph("ẖ", "0:4-8")]);
+}
@@ -0,0 +1,48 @@
+mod common;
+
+/// The messages of the errors and warnings from typesetting `tex` without a format
+fn messages(tex: &str) -> Vec<String> {
+    common::messages(&common::render(tex))
+}
+
+fn assert_reports(tex: &str, expected: &[&str]) {
+    let messages = messages(tex);
+    for e in expected {
+        assert!(messages.iter().any(|m| m == e), "{tex}: expected {e:?} in {messages:?}");
+    }
+}
+
+const MISSING_BRACE: &str = "Missing { inserted.";
+const MISSING_SHARP: &str = "Missing # inserted in alignment preamble.";
+
+#[test]
+fn halign_at_end_of_input() {
+    assert_reports(r"\halign", &[MISSING_BRACE, MISSING_SHARP]);
+}
+
+#[test]
+fn preamble_ended_by_the_input() {
+    assert_reports(r"\halign{", &[MISSING_SHARP]);
+    assert_reports(r"\halign{a&b", &[MISSING_SHARP]);
+}
+
+#[test]
+fn halign_in_a_paragraph() {
+    assert_reports(r"x\halign", &[MISSING_BRACE, MISSING_SHARP]);
+}
+
+#[test]
+fn halign_without_brace() {
+    assert_reports(r"\halign\relax", &[MISSING_BRACE, MISSING_SHARP]);
+    assert_reports(r"\halign\par", &[MISSING_BRACE, MISSING_SHARP]);
+}
+
+#[test]
+fn rows_after_a_preamble_without_sharp() {
+    assert_reports(r"\halign{a\cr b\cr}", &[MISSING_SHARP]);
+}
+
+#[test]
+fn complete_alignment() {
+    assert_eq!(messages(r"\halign{#\hfil&\hfil#\cr a&b\cr ccc&d\cr}"), Vec::<String>::new());
+}
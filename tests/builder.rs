@@ -0,0 +1,82 @@
+mod common;
+
+use rstex::engine::{BuildError, Engine, Limits, SourceFile};
+use rstex::layout::{BoxContent, Node};
+use rstex::{Document, MathRenderer};
+
+/// Six one-line paragraphs, 12pt apart
+const LINES: &str = r"\parindent=0pt \baselineskip=12pt a\par b\par c\par d\par e\par f";
+
+/// The number of glyphs on each page of `doc`
+fn glyphs_per_page(doc: &Document) -> Vec<usize> {
+    doc.pages.chunks(1).map(|page| common::glyphs(page).len()).collect()
+}
+
+#[test]
+fn page_size() {
+    let doc = Engine::builder().page_size(200.0, 100.0).render(r"\the\hsize,\the\vsize").unwrap();
+    assert_eq!(common::text(&doc.pages), "200.0pt,100.0pt");
+    assert_eq!(doc.pages[0].size()[0], 200.0);
+}
+
+#[test]
+fn pages() {
+    let doc = Engine::builder().page_size(200.0, 50.0).render(LINES).unwrap();
+    assert_eq!(glyphs_per_page(&doc), [4, 2]);
+
+    let doc = Engine::builder().page_size(200.0, 100.0).render(LINES).unwrap();
+    assert_eq!(glyphs_per_page(&doc), [6]);
+}
+
+#[test]
+fn free_functions_break_pages() {
+    let tex = format!(r"\vsize=50pt {LINES}");
+    assert_eq!(glyphs_per_page(&rstex::render(&tex)), [4, 2]);
+
+    let format = Engine::builder().build(SourceFile { name: "texput".into(), text: "".into() }).unwrap().dump();
+    assert_eq!(glyphs_per_page(&rstex::render_with_dump(&tex, &format).unwrap()), [4, 2]);
+}
+
+#[test]
+fn base_font() {
+    let font_of_x = |doc: &Document| match common::leaves(&doc.pages)[..] {
+        [_, x @ Node::Box { content: BoxContent::Character('x', font), .. }] => (doc.font(*font).clone(), common::src(x)),
+        ref leaves => panic!("{leaves:?}"),
+    };
+
+    let doc = Engine::builder().base_font("cmr10", Some(12.0)).render("x").unwrap();
+    let (font, src) = font_of_x(&doc);
+    assert_eq!((font.size, src.as_str()), (12.0, "0:0-1"));
+
+    let doc = Engine::builder().base_font("cmbx10", None).render("x").unwrap();
+    assert!(font_of_x(&doc).0.style.bold);
+    assert_eq!(doc.fonts.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), ["nullfont", "cmbx10"]);
+
+    let missing = Engine::builder().base_font("nofont", None).render("x");
+    assert!(matches!(missing, Err(BuildError::FontNotFound(name)) if name == "nofont"));
+}
+
+#[test]
+fn limits() {
+    let limits = Limits { max_pages: 1, ..Limits::default() };
+    let doc = Engine::builder().page_size(200.0, 50.0).limits(limits).render(LINES).unwrap();
+    assert_eq!(doc.pages.len(), 1);
+    assert_eq!(doc.limit_exceeded(), Some(("pages", 1)));
+    assert!(doc.has_errors());
+}
+
+#[test]
+fn metadata() {
+    let doc = Engine::builder().latex().render(r"\message{hi}x").unwrap();
+    assert_eq!(doc.metadata.format.as_deref(), Some("latex"));
+    assert_eq!(doc.metadata.job_name, "texput");
+    assert_eq!(doc.metadata.log, "hi");
+    assert_eq!(doc.metadata.math, MathRenderer::Html);
+
+    let main = SourceFile { name: "dir/doc.tex".into(), text: r"\message{\jobname}x".into() };
+    let mut engine = Engine::builder().math_renderer(MathRenderer::MathMl).build(main).unwrap();
+    let doc = engine.typeset();
+    assert_eq!(doc.metadata.format, None);
+    assert_eq!((doc.metadata.job_name.as_str(), doc.metadata.log.as_str()), ("doc", "doc"));
+    assert_eq!(doc.metadata.math, MathRenderer::MathMl);
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::TempDir;
+
+/// A directory named after `test` holding `doc.tex`, which inputs `inc/sub.tex`
+fn workspace(test: &str) -> TempDir {
+    let dir = TempDir::new(&format!("cli-{test}"));
+    fs::create_dir_all(dir.path().join("inc")).unwrap();
+    dir.write("doc.tex", "a\\input sub \\message{hi}b\n");
+    dir.write("inc/sub.tex", "S");
+    dir
+}
+
+fn rstex(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rstex")).current_dir(dir).args(args).output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn html_and_log_next_to_the_input() {
+    let workspace = workspace("html");
+    let dir = workspace.path();
+    let output = rstex(dir, &["-I", "inc", "doc.tex"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("hi\nOutput written on doc.html (1 page, "), "{}", stderr(&output));
+
+    let html = fs::read_to_string(dir.join("doc.html")).unwrap();
+    assert!(html.contains(">S</span>"), "{html}");
+    let log = fs::read_to_string(dir.join("doc.log")).unwrap();
+    assert!(log.starts_with("This is rstex, Version 0.1.0\n**doc.tex\nhi\nOutput written on doc.html"), "{log}");
+}
+
+#[test]
+fn output_formats() {
+    let workspace = workspace("formats");
+    let dir = workspace.path();
+    for (format, magic) in [("svg", &b"<svg"[..]), ("pdf", b"%PDF-1.4"), ("dvi", &[247, 2])] {
+        let path = format!("out/doc.{format}");
+        fs::create_dir_all(dir.join("out")).unwrap();
+        let output = rstex(dir, &["--format", format, "-o", &path, "-I", "inc", "doc.tex"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(fs::read(dir.join(&path)).unwrap().starts_with(magic), "{format}");
+        assert!(dir.join(&path).with_extension("log").exists(), "{format}");
+    }
+}
+
+#[test]
+fn standard_output() {
+    let workspace = workspace("stdout");
+    let dir = workspace.path();
+    let output = rstex(dir, &["--format", "dvi", "-o", "-", "--no-log", "-I", "inc", "doc.tex"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(output.stdout.starts_with(&[247, 2]));
+    assert!(stderr(&output).contains("Output written on standard output (1 page, "), "{}", stderr(&output));
+    assert!(!dir.join("doc.log").exists());
+}
+
+#[test]
+fn errors() {
+    let workspace = workspace("errors");
+    let dir = workspace.path();
+    // without the search path the input is not found
+    let output = rstex(dir, &["--interaction", "batchmode", "doc.tex"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "This is rstex, Version 0.1.0\n");
+    let log = fs::read_to_string(dir.join("doc.log")).unwrap();
+    assert!(log.contains("! I can't find file `sub'.\nl.1 a\\input sub \n"), "{log}");
+
+    let output = rstex(dir, &["--bogus", "doc.tex"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("rstex: unknown option --bogus\n\nusage:"), "{}", stderr(&output));
+
+    assert_eq!(rstex(dir, &["--format", "png", "doc.tex"]).status.code(), Some(2));
+    assert_eq!(rstex(dir, &["missing.tex"]).status.code(), Some(1));
+}
@@ -0,0 +1,95 @@
+//! Helpers shared by the integration tests, which each use some of them
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rstex::engine::Engine;
+use rstex::layout::{BoxContent, Node};
+use rstex::Document;
+
+/// Typesets `tex` without a format
+pub fn render(tex: &str) -> Document {
+    Engine::builder().render(tex).unwrap()
+}
+
+/// The characters on `pages`, in reading order
+pub fn text(pages: &[Node]) -> String {
+    glyphs(pages).into_iter().map(|(c, _)| c).collect()
+}
+
+/// The characters of each line on `pages` that has some
+pub fn lines(pages: &[Node]) -> Vec<String> {
+    pages.iter().flat_map(|page| match page {
+        Node::Box { content: BoxContent::VBox(list), .. } => list.iter().map(|line| text(core::slice::from_ref(line))).collect(),
+        _ => Vec::new(),
+    }).filter(|line| !line.is_empty()).collect()
+}
+
+/// The characters, rules and empty boxes on `pages`, in reading order
+pub fn leaves(pages: &[Node]) -> Vec<&Node> {
+    pages.iter().flat_map(|node| match node {
+        Node::Box { content: BoxContent::HBox(list) | BoxContent::VBox(list), .. } if !list.is_empty() => leaves(list),
+        Node::Box { .. } => vec![node],
+        _ => Vec::new(),
+    }).collect()
+}
+
+/// The characters on `pages` with the span they came from, as `file:start-end`
+pub fn glyphs(pages: &[Node]) -> Vec<(char, String)> {
+    leaves(pages).into_iter().filter_map(|node| match node {
+        Node::Box { content: BoxContent::Character(c, _), .. } => Some((*c, src(node))),
+        _ => None,
+    }).collect()
+}
+
+/// Where a box came from, as `file:start-end`, or an empty string for boxes made up by the engine
+pub fn src(node: &Node) -> String {
+    match node {
+        Node::Box { span, .. } => span.file.map(|f| format!("{}:{}-{}", f.0, span.range.start, span.range.end)).unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// The messages of the diagnostics of `doc`
+pub fn messages(doc: &Document) -> Vec<String> {
+    doc.diagnostics.iter().map(|d| d.kind.to_string()).collect()
+}
+
+/// A directory of its own for a test, removed with everything in it when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("rstex-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Writes a file in the directory, returning its path
+    pub fn write(&self, name: &str, text: &str) -> PathBuf {
+        let path = self.0.join(name);
+        fs::write(&path, text).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The glyphs of `html` with their `data-src`, for what only the HTML shows, like the
+/// precomposed letters it gives accents
+pub fn html_glyphs(html: &str) -> Vec<(String, String)> {
+    html.split("<span ").skip(1).map(|span| {
+        let src = span.split("data-src=\"").nth(1).and_then(|s| s.split('"').next()).unwrap_or_default();
+        let glyph = span.split('>').nth(1).and_then(|s| s.split('<').next()).unwrap_or_default();
+        (glyph.to_string(), src.to_string())
+    }).collect()
+}
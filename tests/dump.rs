@@ -0,0 +1,164 @@
+mod common;
+
+use std::fs;
+
+use rstex::engine::{BadFormat, BuildError, Engine, InteractionMode, SourceFile, FORMAT_VERSION};
+use rstex::layout::{BoxContent, Node};
+use rstex::Document;
+
+use common::TempDir;
+
+/// A format setting a bit of every kind of state, then dumping it before the end
+const FORMAT: &str = r"\catcode`\{=1 \catcode`\}=2 \catcode`\#=6 \catcode`\~=13
+\def~#1{<#1>}
+\count1=42 \dimen2=3pt \toks3={tok}\skip4=1pt plus 1fil
+\chardef\c=65 \countdef\n=5 \n=7
+\font\big=cmr10 at 20pt
+\lccode`a=`a \lccode`b=`b \patterns{a1b}
+\batchmode
+\dump
+\def\after{A}
+";
+
+/// Dumps [`FORMAT`] from a directory named after `test`
+fn dumped(test: &str) -> Vec<u8> {
+    let dir = TempDir::new(&format!("dump-{test}"));
+    rstex::dump_format(&dir.write("rich.tex", FORMAT)).unwrap()
+}
+
+/// An engine for `tex` with `format` loaded
+fn engine(tex: &str, format: &[u8]) -> Engine {
+    let mut engine = Engine::builder().build(SourceFile { name: "texput".into(), text: tex.into() }).unwrap();
+    engine.undump(format).unwrap();
+    engine
+}
+
+fn render(tex: &str, format: &[u8]) -> Document {
+    engine(tex, format).typeset()
+}
+
+/// The characters typeset for `tex` after loading `format`, asserting that it has no errors
+fn text(tex: &str, format: &[u8]) -> String {
+    let doc = render(tex, format);
+    assert!(doc.diagnostics.is_empty(), "{tex}: {}", doc.report());
+    common::text(&doc.pages)
+}
+
+#[test]
+fn state_survives_the_round_trip() {
+    let format = dumped("state");
+    let tex = r"~x\the\count1,\the\dimen2,\the\toks3,\the\skip4,\c\the\n";
+    assert_eq!(text(tex, &format), "<x>42,3.0pt,tok,1.0ptplus1.0fil,A7");
+
+    let doc = render(r"{\big B}", &format);
+    let b = common::leaves(&doc.pages).into_iter().find(|n| matches!(n, Node::Box { content: BoxContent::Character(..), .. })).unwrap();
+    let Node::Box { content: BoxContent::Character('B', font), .. } = b else { panic!("{b:?}") };
+    assert_eq!((doc.font(*font).size, common::src(b)), (20.0, "0:6-7".to_string()));
+
+    let tex = r"\defaulthyphenchar=`- \hsize=20pt \parindent=0pt \pretolerance=-1 x ababab";
+    assert_eq!(text(tex, &format), "xaba-bab");
+}
+
+#[test]
+fn the_format_stops_at_dump() {
+    assert_eq!(text(r"\ifx\after\undefined U\fi", &dumped("stop")), "U");
+}
+
+#[test]
+fn engine_dump_and_undump() {
+    let engine = engine("", &dumped("engine"));
+    assert_eq!(engine.interaction(), InteractionMode::Batch);
+
+    // a format dumped again from a loaded one works the same
+    assert_eq!(text(r"\the\count1~y", &engine.dump()), "42<y>");
+}
+
+#[test]
+fn dumped_format_through_the_builder() {
+    let dir = TempDir::new("dump-builder");
+    let path = dir.path().join("rich.fmt");
+    fs::write(&path, rstex::dump_format(&dir.write("rich.tex", FORMAT)).unwrap()).unwrap();
+    let doc = Engine::builder().format_file(&path).render(r"\the\count1").unwrap();
+    assert_eq!(common::text(&doc.pages), "42");
+}
+
+#[test]
+fn rendering_with_a_dump() {
+    let doc = rstex::render_with_dump(r"\the\count1", &dumped("render")).unwrap();
+    assert!(doc.diagnostics.is_empty(), "{}", doc.report());
+    assert_eq!(doc.files.len(), 1);
+    assert_eq!(common::text(&doc.pages), "42");
+}
+
+#[test]
+fn bad_formats_are_rejected() {
+    let format = dumped("bad");
+    assert_eq!(rstex::render_with_dump("x", b"junk").err(), Some(BadFormat::NotAFormat));
+    assert_eq!(rstex::render_with_dump("x", &format[..format.len() / 2]).err(), Some(BadFormat::Corrupt));
+
+    // the version follows the magic number
+    let mut other = format.clone();
+    other[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    assert_eq!(rstex::render_with_dump("x", &other).err(), Some(BadFormat::Version(FORMAT_VERSION + 1)));
+
+    let dir = TempDir::new("dump-bad-builder");
+    let path = dir.path().join("rich.fmt");
+    fs::write(&path, &format[..format.len() / 2]).unwrap();
+    let built = Engine::builder().format_file(&path).render("x");
+    assert!(matches!(built, Err(BuildError::Format(p, BadFormat::Corrupt)) if p == path));
+}
+
+/// Flips the bits of `mask` in the byte of `format` at `offset` past the first occurrence of
+/// `bytes`
+fn flipped(format: &[u8], bytes: &[u8], offset: usize, mask: u8) -> Vec<u8> {
+    let at = format.windows(bytes.len()).position(|w| w == bytes).expect("the bytes are in the format");
+    let mut format = format.to_vec();
+    format[at + offset] ^= mask;
+    format
+}
+
+#[test]
+fn damaged_formats_are_rejected() {
+    let format = dumped("damaged");
+    let load = |damaged: &[u8]| engine("", &format).undump(damaged);
+    assert_eq!(load(&format), Ok(()));
+
+    // \catcode`\~=13, made 29
+    let catcode = [8, b'~', 0, 0, 0, 1, 13, 0, 0, 0];
+    assert_eq!(load(&flipped(&format, &catcode, 6, 0x10)), Err(BadFormat::Corrupt));
+    // the fil of \skip4 made an order of infinity past filll
+    let skip = [4, 4, 2, 0, 0, 1, 0, 0, 0, 1, 0, 1];
+    assert_eq!(load(&flipped(&format, &skip, 11, 0x10)), Err(BadFormat::Corrupt));
+    // the parameter of ~ made #3, which it does not have
+    let param = [1, b'~', 0, 0, 0, 0, 1, 1, 0, 0, 0, 1, 1, 3, 0, 0, 0];
+    assert_eq!(load(&flipped(&format, &param, 12, 0x02)), Err(BadFormat::Corrupt));
+
+    // flipping any bit of the patterns at the end either breaks them or leaves patterns that
+    // can be used
+    let mut patterns = rstex::hyphen::Patterns::new();
+    patterns.add_pattern("a1b").unwrap();
+    let compiled = patterns.compile();
+    for at in format.len() - compiled.len()..format.len() {
+        for bit in 0..8 {
+            let mut damaged = format.clone();
+            damaged[at] ^= 1 << bit;
+            let mut engine = engine(r"\hsize=20pt \parindent=0pt \pretolerance=-1 x ababab", &format);
+            if engine.undump(&damaged).is_ok() {
+                engine.typeset();
+            }
+        }
+    }
+}
+
+#[test]
+fn trie_values_past_the_word_are_rejected() {
+    // a trie of the pattern `a` with four values, where `a` can have two
+    let mut bytes = b"RSHY".to_vec();
+    for n in [2, 1, 'a' as u32, 1, 0, 0] {
+        bytes.extend_from_slice(&n.to_le_bytes());
+    }
+    bytes.extend_from_slice(&4u32.to_le_bytes());
+    bytes.extend_from_slice(&[1, 1, 1, 1]);
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    assert!(rstex::hyphen::Patterns::from_compiled(&bytes).is_err());
+}
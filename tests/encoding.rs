@@ -0,0 +1,63 @@
+mod common;
+
+use rstex::engine::Engine;
+use rstex::lexer::Encoding;
+
+/// The characters typeset for `tex` with `encoding`, and the diagnostics
+fn render(tex: &str, encoding: Encoding) -> (String, Vec<String>) {
+    let doc = Engine::builder().encoding(encoding).render(tex).unwrap();
+    (common::text(&doc.pages), common::messages(&doc))
+}
+
+fn text(tex: &str, encoding: Encoding) -> String {
+    let (text, diagnostics) = render(tex, encoding);
+    assert!(diagnostics.is_empty(), "{tex}: {diagnostics:?}");
+    text
+}
+
+#[test]
+fn letters() {
+    assert_eq!(text(r"\the\catcode`\ö,\the\catcode`\a", Encoding::Classic), "12,11");
+    assert_eq!(text(r"\the\catcode`\ö,\the\catcode`\α,\the\catcode`\字", Encoding::Unicode), "11,11,11");
+    assert_eq!(text(r"\the\catcode`\1,\the\catcode`\.", Encoding::Unicode), "12,12");
+
+    // in classic mode the name stops at the ö, which starts the delimiter of \gr
+    let tex = r"\def\größe{x}\def\gr{y}\größe";
+    assert_eq!(text(tex, Encoding::Classic), "yöße");
+    assert_eq!(text(tex, Encoding::Unicode), "x");
+}
+
+#[test]
+fn case_codes() {
+    assert_eq!(text(r"\the\lccode`\Ö,\the\uccode`\ö,\the\lccode`\A", Encoding::Classic), "0,0,97");
+    assert_eq!(text(r"\the\lccode`\Ö,\the\uccode`\ö,\the\lccode`\A", Encoding::Unicode), "246,214,97");
+    // a letter whose uppercase is two letters maps to itself
+    assert_eq!(text(r"\the\uccode`\ß", Encoding::Unicode), "223");
+
+    assert_eq!(text(r"\uppercase{öa}\lowercase{ÖΩ}", Encoding::Classic), "öAÖΩ");
+    assert_eq!(text(r"\uppercase{öa}\lowercase{ÖΩ}", Encoding::Unicode), "ÖAöω");
+}
+
+#[test]
+fn space_factor_codes() {
+    assert_eq!(text(r"\the\sfcode`\Ö,\the\sfcode`\A,\the\sfcode`\.", Encoding::Classic), "1000,999,1000");
+    assert_eq!(text(r"\the\sfcode`\Ö,\the\sfcode`\A,\the\sfcode`\.", Encoding::Unicode), "999,999,1000");
+}
+
+#[test]
+fn math_codes() {
+    assert_eq!(text(r"\the\mathcode`\a,\the\mathcode`\1,\the\mathcode`\+", Encoding::Classic), "29025,28721,43");
+    let unicode = [(7 << 28) + (1 << 21) + 'α' as i32, (7 << 28) + '1' as i32, '+' as i32];
+    let expected = unicode.map(|code| code.to_string()).join(",");
+    assert_eq!(text(r"\the\mathcode`\α,\the\mathcode`\1,\the\mathcode`\+", Encoding::Unicode), expected);
+}
+
+#[test]
+fn character_codes_past_255() {
+    let (shown, diagnostics) = render(r"\catcode`\α=11 \the\catcode`\α", Encoding::Classic);
+    assert_eq!(diagnostics, ["Bad character code (945).", "Bad character code (945)."]);
+    // the code read instead is 0, so that is the character assigned to
+    assert_eq!(shown, "11");
+
+    assert_eq!(text(r"\catcode`\α=12 \the\catcode`\α", Encoding::Unicode), "12");
+}
@@ -0,0 +1,66 @@
+mod common;
+
+/// The characters typeset for `tex` without a format, asserting that it has no errors
+fn text(tex: &str) -> String {
+    let doc = common::render(tex);
+    assert!(doc.diagnostics.is_empty(), "{tex}: {}", doc.report());
+    common::text(&doc.pages)
+}
+
+#[test]
+fn edef_expands_its_body() {
+    assert_eq!(text(r"\def\a{x}\edef\b{\a\a}\def\a{y}\b"), "xx");
+    assert_eq!(text(r"\def\a{b}\edef\c{\noexpand\a}\def\a{z}\c"), "z");
+    assert_eq!(text(r"\count1=42 \edef\c{\the\count1}\count1=0 \c"), "42");
+}
+
+#[test]
+fn xdef_is_global() {
+    assert_eq!(text(r"\def\q{x}{\edef\q{y}}\q"), "x");
+    assert_eq!(text(r"\def\q{x}{\xdef\q{y}}\q"), "y");
+}
+
+#[test]
+fn expandafter() {
+    assert_eq!(text(r"\def\a{1}\expandafter\def\expandafter\b\expandafter{\a2}\def\a{3}\b"), "12");
+}
+
+#[test]
+fn csname() {
+    assert_eq!(text(r"\expandafter\def\csname a b\endcsname{ok}\csname a b\endcsname"), "ok");
+    // an undefined name means \relax
+    assert_eq!(text(r"\csname undefined\endcsname x"), "x");
+}
+
+#[test]
+fn numbers() {
+    assert_eq!(text(r"\romannumeral1984 \number007"), "mcmlxxxiv7");
+    assert_eq!(text(r"\romannumeral0 \number-5"), "-5");
+}
+
+#[test]
+fn the_registers_and_parameters() {
+    assert_eq!(text(r"\count1=42 \the\count1"), "42");
+    assert_eq!(text(r"\dimen0=1.5pt \the\dimen0"), "1.5pt");
+    assert_eq!(text(r"\skip0=1pt plus 2fil\relax \the\skip0"), "1.0ptplus2.0fil");
+    assert_eq!(text(r"\toks0={ab}\the\toks0"), "ab");
+    assert_eq!(text(r"\hsize=100pt \the\hsize"), "100.0pt");
+    assert_eq!(text(r"\mag=2000 \the\mag"), "2000");
+    assert_eq!(text(r"\countdef\n=3 \n=7 \the\count3"), "7");
+}
+
+#[test]
+fn parameter_text_ending_in_a_brace() {
+    // the brace delimits the last argument and is put back after the body
+    assert_eq!(text(r"\def\a#1#{[#1]}\a xy{z}"), "[xy]z");
+    assert_eq!(text(r"\def\b#{!}\b{y}"), "!y");
+    assert_eq!(text(r"\def\wide#1#{\setbox0\hbox#1}\wide to 20pt{x}\the\wd0"), "20.0pt");
+    assert_eq!(text(r"\def\a#1#{[#1]}\meaning\a"), "macro:#1{->[#1]{");
+}
+
+#[test]
+fn string_and_meaning() {
+    assert_eq!(text(r"\string\foo"), "\\foo");
+    assert_eq!(text(r"\def\x#1{[#1]}\meaning\x"), "macro:#1->[#1]");
+    assert_eq!(text(r"\meaning a"), "thelettera");
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::path::PathBuf;
+
+use rstex::engine::{BuildError, Engine};
+
+use common::TempDir;
+
+/// A small format that sets catcodes, defines macros and inputs a file of its own
+const FORMAT: &str = r"\catcode`\{=1 \catcode`\}=2 \catcode`\#=6 \catcode`\~=13
+\def~{X}
+\def\twice#1{#1#1}
+\input extra
+\def\loaded{yes}
+";
+
+/// Writes the format and the file it inputs to a directory of their own, named after `test`
+fn format_dir(test: &str) -> TempDir {
+    let dir = TempDir::new(&format!("format-{test}"));
+    dir.write("mini.tex", FORMAT);
+    dir.write("extra.tex", "\\def\\extra{E}\n");
+    dir
+}
+
+#[test]
+fn macros_and_catcodes_of_the_format() {
+    let dir = format_dir("macros");
+    let format = dir.path().join("mini.tex");
+    let doc = Engine::builder().format_file(&format).render(r"\twice{ab}~\extra\loaded").unwrap();
+    assert!(doc.diagnostics.is_empty(), "{}", doc.report());
+    assert_eq!(common::text(&doc.pages), "ababXEyes");
+}
+
+#[test]
+fn format_from_disk() {
+    let dir = format_dir("disk");
+    let format = dir.path().join("mini.tex");
+    let doc = rstex::render_with_format(r"\twice{ab}~\extra\loaded", &format).unwrap();
+    assert!(doc.diagnostics.is_empty(), "{}", doc.report());
+    assert_eq!(common::text(&doc.pages), "ababXEyes");
+
+    // the format and what it inputs are source files too
+    let names: Vec<PathBuf> = doc.files.iter().map(|f| PathBuf::from(&f.name)).collect();
+    assert_eq!(names, [PathBuf::from("texput"), format.clone(), format.with_file_name("extra.tex")]);
+}
+
+#[test]
+fn missing_format() {
+    let dir = format_dir("missing");
+    let format = dir.path().join("none.tex");
+    assert!(matches!(rstex::render_with_format("x", &format), Err(BuildError::Io(path, _)) if path == format));
+    assert!(matches!(Engine::builder().format_file(&format).render("x"), Err(BuildError::Io(path, _)) if path == format));
+}
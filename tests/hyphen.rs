@@ -0,0 +1,89 @@
+mod common;
+
+use rstex::engine::Engine;
+use rstex::hyphen::{BadPattern, Patterns};
+
+/// Patterns for a single pattern and exception, with a column narrow enough to need them
+const SETUP: &str = r"\patterns{a1b} \hyphenation{ta-ble}
+\hsize=20pt \parindent=0pt \pretolerance=-1 ";
+
+/// The lines of the paragraph `tex`, typeset after [`SETUP`]
+fn lines(tex: &str) -> Vec<String> {
+    common::lines(&common::render(&(SETUP.to_string() + tex)).pages)
+}
+
+fn patterns() -> Patterns {
+    let mut patterns = Patterns::new();
+    for pattern in ["a1b", ".ta4", "2bb"] {
+        patterns.add_pattern(pattern).unwrap();
+    }
+    patterns.add_exception("ta-ble").unwrap();
+    patterns
+}
+
+#[test]
+fn paragraphs_are_hyphenated() {
+    assert_eq!(lines("x ababab"), ["x", "aba-", "bab"]);
+    assert_eq!(lines("x table"), ["xta-", "ble"]);
+    // words are hyphenated only after glue
+    assert_eq!(lines("ababab"), ["ababab"]);
+}
+
+#[test]
+fn hyphenation_parameters() {
+    assert_eq!(lines(r"\lefthyphenmin=4 \righthyphenmin=3 x ababab"), ["x", "ababab"]);
+    assert_eq!(lines(r"\uchyph=0 x Ababab"), ["x", "Ababab"]);
+    assert_eq!(lines("x Ababab"), ["x", "Aba-", "bab"]);
+    // the patterns were given for language 0
+    assert_eq!(lines(r"\language=1 x ababab"), ["x", "ababab"]);
+}
+
+#[test]
+fn liang() {
+    let patterns = patterns();
+    assert_eq!(patterns.hyphenate("abab", 1, 1), [1, 3]);
+    assert_eq!(patterns.hyphenate("abab", 2, 2), Vec::<usize>::new());
+    assert_eq!(patterns.hyphenate("ABAB", 1, 1), [1, 3]);
+    // a higher even value wins
+    assert_eq!(patterns.hyphenate("abba", 1, 1), Vec::<usize>::new());
+    assert_eq!(patterns.hyphenate("tab", 1, 1), Vec::<usize>::new());
+    assert_eq!(patterns.hyphenate("xab", 1, 1), [2]);
+}
+
+#[test]
+fn exceptions() {
+    let patterns = patterns();
+    assert_eq!(patterns.hyphenate("table", 1, 1), [2]);
+    assert_eq!(patterns.hyphenate("Table", 3, 1), Vec::<usize>::new());
+}
+
+#[test]
+fn bad_patterns() {
+    let mut patterns = Patterns::new();
+    assert_eq!(patterns.add_pattern("12a"), Err(BadPattern));
+    assert_eq!(patterns.add_pattern("1"), Err(BadPattern));
+    assert_eq!(patterns.add_exception("-ab"), Err(BadPattern));
+}
+
+#[test]
+fn compiled() {
+    let patterns = patterns();
+    let compiled = Patterns::from_compiled(&patterns.compile()).unwrap();
+    for word in ["abab", "table", "abba", "xab"] {
+        assert_eq!(compiled.hyphenate(word, 1, 1), patterns.hyphenate(word, 1, 1), "{word}");
+    }
+    assert!(Patterns::from_compiled(b"RSHY").is_err());
+    assert!(Patterns::from_compiled(b"junk").is_err());
+}
+
+#[test]
+fn compiled_patterns_through_the_builder() {
+    let compiled = Patterns::from_compiled(&patterns().compile()).unwrap();
+    let lines = |tex: &str| {
+        let tex = r"\hsize=20pt \parindent=0pt \pretolerance=-1 ".to_string() + tex;
+        common::lines(&Engine::builder().patterns(1, compiled.clone()).render(&tex).unwrap().pages)
+    };
+    assert_eq!(lines(r"\language=1 x ababab"), ["x", "aba-", "bab"]);
+    assert_eq!(lines(r"\language=1 x table"), ["xta-", "ble"]);
+    assert_eq!(lines("x ababab"), ["x", "ababab"]);
+}
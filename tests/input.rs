@@ -0,0 +1,81 @@
+mod common;
+
+use std::collections::HashMap;
+
+use rstex::engine::{DirResolver, Engine, FileAccess, Limits, MapResolver};
+use rstex::Document;
+
+/// Typesets `tex` with the given files available through a resolver
+fn render(tex: &str, files: &[(&str, &str)], file_access: FileAccess) -> Document {
+    let files: HashMap<String, String> = files.iter().map(|(name, text)| (name.to_string(), text.to_string())).collect();
+    let limits = Limits { file_access, ..Limits::default() };
+    Engine::builder().limits(limits).resolver(MapResolver::new(files)).render(tex).unwrap()
+}
+
+/// The characters typeset for `tex` with the given files readable
+fn text(tex: &str, files: &[(&str, &str)]) -> String {
+    let doc = render(tex, files, FileAccess::Read);
+    assert!(doc.diagnostics.is_empty(), "{}", doc.report());
+    common::text(&doc.pages)
+}
+
+#[test]
+fn input_adds_the_extension() {
+    assert_eq!(text(r"x\input sub y", &[("sub.tex", "in")]), "xiny");
+}
+
+#[test]
+fn nested_input_and_endinput() {
+    let files = [("a.tex", r"a\input b a"), ("b.tex", "b\\endinput c\nd")];
+    // the rest of the line after \endinput is still read
+    assert_eq!(text(r"\input a", &files), "abca");
+}
+
+#[test]
+fn glyphs_point_into_their_file() {
+    let doc = render(r"x\input sub y", &[("sub.tex", "ab")], FileAccess::Read);
+    let names: Vec<&str> = doc.files.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["texput", "sub.tex"]);
+    let expected = [('x', "0:0-1"), ('a', "1:0-1"), ('b', "1:1-2"), ('y', "0:12-13")];
+    assert_eq!(common::glyphs(&doc.pages), expected.map(|(c, src)| (c, src.to_string())));
+}
+
+#[test]
+fn read_lines_from_a_file() {
+    let files = [("data", "one\n{two\nthree}\n")];
+    let tex = r"\openin1=data \read1 to\x \read1 to\y \ifeof1 E\fi \x|\y \read1 to\z \ifeof1 E\fi \closein1";
+    // a group spans lines, and the stream ends after the last one
+    assert_eq!(text(tex, &files), "one|twothreeE");
+}
+
+#[test]
+fn files_are_closed_by_default() {
+    let doc = render(r"x\input sub y", &[("sub.tex", "in")], FileAccess::None);
+    assert_eq!(common::messages(&doc), ["I'm not allowed to open file `sub'."]);
+    assert_eq!(common::text(&doc.pages), "xy");
+
+    // an unopened stream is at its end
+    let doc = render(r"\openin1=data \ifeof1 E\fi", &[("data", "x")], FileAccess::None);
+    assert_eq!(common::text(&doc.pages), "E");
+}
+
+#[test]
+fn directories_are_not_left() {
+    let dir = common::TempDir::new("input-dirs");
+    let secret = dir.write("secret.tex", "secret");
+    std::fs::create_dir_all(dir.path().join("doc/sub")).unwrap();
+    dir.write("doc/sub/part.tex", "part");
+
+    let render = |tex: &str| {
+        let limits = Limits { file_access: FileAccess::Read, ..Limits::default() };
+        let resolver = DirResolver::new(vec![dir.path().join("doc")]);
+        Engine::builder().limits(limits).resolver(resolver).render(tex).unwrap()
+    };
+    assert_eq!(common::text(&render(r"\input sub/part \input ./sub/part").pages), "partpart");
+
+    for name in ["../secret", "sub/../../secret", secret.to_str().unwrap()] {
+        let doc = render(&format!(r"\input {name} x"));
+        assert_eq!(common::text(&doc.pages), "x", "{name}");
+        assert_eq!(doc.files.len(), 1, "{name}");
+    }
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rstex::engine::{Engine, InteractionMode, Response, Terminal};
+use rstex::error::Diagnostic;
+
+use common::messages;
+
+/// A terminal giving the answers it was made with, then `Continue`, and recording what it is asked
+#[derive(Default)]
+struct Script {
+    answers: Vec<Response>,
+    transcript: Rc<RefCell<Vec<String>>>,
+}
+
+impl Terminal for Script {
+    fn error(&mut self, diagnostic: &Diagnostic) -> Response {
+        self.transcript.borrow_mut().push(format!("error: {}", diagnostic.kind));
+        if self.answers.is_empty() { Response::Continue } else { self.answers.remove(0) }
+    }
+
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        self.transcript.borrow_mut().push(format!("read: {prompt}"));
+        Some("typed".to_string())
+    }
+
+    fn print(&mut self, text: &str) {
+        self.transcript.borrow_mut().push(format!("print: {text}"));
+    }
+}
+
+/// Typesets `tex` with a terminal giving `answers`, returning the text, the messages and the
+/// transcript
+fn render(tex: &str, answers: Vec<Response>) -> (String, Vec<String>, Vec<String>) {
+    let transcript = Rc::new(RefCell::new(Vec::new()));
+    let terminal = Script { answers, transcript: transcript.clone() };
+    let doc = Engine::builder().terminal(terminal).render(tex).unwrap();
+    let transcript = transcript.borrow().clone();
+    (common::text(&doc.pages), messages(&doc), transcript)
+}
+
+#[test]
+fn recovery() {
+    let (text, messages, _) = render("a}b", vec![]);
+    assert_eq!((text, messages), ("ab".to_string(), vec!["Too many }'s.".to_string()]));
+
+    let (text, messages, _) = render("{a", vec![]);
+    assert_eq!((text, messages), ("a".to_string(), vec!["Missing } inserted.".to_string()]));
+
+    let (_, messages, _) = render("a^b", vec![]);
+    assert_eq!(messages[..2], ["Missing $ inserted.", "Missing $ inserted."]);
+    assert_eq!(render(r"x\hbox{a$b}c", vec![]).0, "xa");
+}
+
+#[test]
+fn typing_on_with_errors() {
+    let tex = r"a\undefined bc\undefined d";
+    let (text, messages, transcript) = render(tex, vec![]);
+    assert_eq!(text, "abcd");
+    assert_eq!(messages, ["Undefined control sequence.", "Undefined control sequence."]);
+    assert_eq!(transcript, ["error: Undefined control sequence.", "error: Undefined control sequence."]);
+}
+
+#[test]
+fn answers() {
+    let tex = r"a\undefined bc\undefined d";
+    assert_eq!(render(tex, vec![Response::Insert("Q".to_string())]).0, "aQbcd");
+
+    // deleting asks again
+    let (text, _, transcript) = render(tex, vec![Response::Delete(1)]);
+    assert_eq!((text, transcript.len()), ("acd".to_string(), 3));
+
+    let (text, _, transcript) = render(tex, vec![Response::Quit]);
+    assert_eq!((text, transcript.len()), ("a".to_string(), 1));
+
+    // the second error is not asked about in batch mode
+    let (text, messages, transcript) = render(tex, vec![Response::SetMode(InteractionMode::Batch)]);
+    assert_eq!((text, transcript.len(), messages.len()), ("abcd".to_string(), 1, 2));
+}
+
+#[test]
+fn modes() {
+    let (_, messages, transcript) = render(r"\message{hi}\batchmode\message{ho}\undefined x", vec![]);
+    assert_eq!(transcript, ["print: hi"]);
+    assert_eq!(messages, ["Undefined control sequence."]);
+
+    let (text, _, transcript) = render(r"\scrollmode\undefined x", vec![]);
+    assert_eq!((text, transcript.len()), ("x".to_string(), 0));
+
+    let (_, messages, transcript) = render(r"\nonstopmode\read16 to\x", vec![]);
+    assert_eq!(messages, [r"*** (cannot \read from terminal in nonstop modes)"]);
+    assert!(transcript.is_empty());
+}
+
+#[test]
+fn reading_from_the_terminal() {
+    let (text, _, transcript) = render(r"\read16 to\x \x", vec![]);
+    assert_eq!(text, "typed");
+    assert_eq!(transcript, [r"read: \x="]);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use rstex::engine::Engine;
+use rstex::layout::{BoxContent, Node};
+use rstex::Document;
+
+/// An article using most of what the kernel provides
+const SAMPLE: &str = r"\documentclass[11pt]{article}
+\usepackage{amsmath}
+\title{Quarterly Report}
+\author{A. Writer}
+\date{1 April 2024}
+\begin{document}
+\maketitle
+\section{Costs}\label{costs}
+Prices rose by 5\% \& rents by \$3, see item \#2 of the\_list in \{braces\}.
+\hspace{1em}Costs\footnote{Before tax.} are in Table~\ref{tab}.
+\vspace{6pt}
+Type \verb|\cost{x}_%| to show them.
+\begin{verbatim}
+  if (a < b) { x_1 = $y; }
+\end{verbatim}
+\begin{table}[h]
+\begin{tabular}{|l|r|}
+\hline
+rent & 3 \\
+food & 12 \\
+\hline
+\end{tabular}
+\caption{Costs}\label{tab}
+\end{table}
+\section{Outlook}
+As Section~\ref{costs} shows, $\{x : x > 0\}$ grows.\footnote{So far.}
+\end{document}
+";
+
+/// The characters of each line of `doc` as they are shown, with rules as `_` and spaces between
+/// words as one space
+fn shown_lines(doc: &Document) -> Vec<String> {
+    fn shown(doc: &Document, node: &Node) -> String {
+        match node {
+            Node::Box { content: BoxContent::HBox(list) | BoxContent::VBox(list), .. } => list.iter().map(|n| shown(doc, n)).collect(),
+            Node::Box { content: BoxContent::Character(c, font), .. } => doc.font(*font).glyph(*c).to_string(),
+            Node::Box { content: BoxContent::HRule, .. } => "_".into(),
+            Node::Glue { nat_size, .. } if *nat_size > 0.0 => " ".into(),
+            _ => String::new(),
+        }
+    }
+
+    doc.pages.iter().flat_map(|page| match page {
+        Node::Box { content: BoxContent::VBox(list), .. } => list.iter().map(|line| shown(doc, line).split_whitespace().collect::<Vec<_>>().join(" ")).collect(),
+        _ => Vec::new(),
+    }).filter(|line| !line.is_empty()).collect()
+}
+
+#[test]
+fn sample_document() {
+    let passes = Engine::builder().latex().render_passes(SAMPLE, 3).unwrap();
+    let doc = &passes.document;
+    assert!(!doc.has_errors(), "{}", doc.report());
+    assert!(passes.settled);
+
+    let lines = shown_lines(doc);
+    let line = |start: &str| lines.iter().find(|l| l.starts_with(start)).unwrap_or_else(|| panic!("{start:?} in {lines:#?}"));
+    assert_eq!(lines[..3], ["Quarterly Report", "A. Writer", "1 April 2024"]);
+    assert!(line("1 Costs").ends_with("Costs"));
+    assert!(line("Prices").contains("5% & rents by $3, see item #2 of the_list in {braces}."), "{lines:#?}");
+    assert!(line("Type").contains(r"\cost{x}_% to show them."), "{lines:#?}");
+    assert_eq!(line("if"), "if (a < b) { x_1 = $y; }");
+    assert_eq!(line("_ rent"), "_ rent 3 food 12 _");
+    assert_eq!(line("Table"), "Table 1: Costs");
+    assert!(line("As").starts_with("As Section 1 shows, {x : x > 0} grows.2"), "{lines:#?}");
+    // the notes come last, under a rule
+    assert_eq!(lines[lines.len() - 3..], ["_", "1Before tax.", "2So far."]);
+}
+
+#[test]
+fn special_characters() {
+    let doc = Engine::builder().latex().render(r"\% \& \$ \# \{ \}").unwrap();
+    assert_eq!(shown_lines(&doc), ["% & $ # { }"]);
+}
+
+#[test]
+fn spacing_commands() {
+    let width = |tex: &str| {
+        Engine::builder().latex().render(&format!(r"\setbox0\hbox{{{tex}}}\message{{\the\wd0}}")).unwrap().metadata.log
+    };
+    assert_eq!(width(r"\hspace{12pt}"), "12.0pt");
+    assert_eq!(width(r"\hspace*{3pt}\hspace{2pt}"), "5.0pt");
+
+    let doc = Engine::builder().latex().render(r"a\vspace{30pt}b").unwrap();
+    let heights: Vec<f32> = match &doc.pages[0] {
+        Node::Box { content: BoxContent::VBox(list), .. } => list.iter().filter_map(|n| match n {
+            Node::Glue { nat_size, .. } => Some(*nat_size),
+            _ => None,
+        }).collect(),
+        _ => Vec::new(),
+    };
+    assert!(heights.contains(&30.0), "{heights:?}");
+}
+
+#[test]
+fn footnotes_are_numbered() {
+    let doc = Engine::builder().latex().render(r"One\footnote{First.} two\footnote{Second.}\end{document}").unwrap();
+    assert_eq!(shown_lines(&doc), ["One1 two2", "_", "1First.", "2Second."]);
+}
@@ -0,0 +1,269 @@
+use rstex::lexer::{Encoding, Lexer, Token, TokenType};
+
+/// Lexes `src`, describing each token as its type and character or control sequence name
+fn lex_with(src: &str, encoding: Encoding) -> Vec<(TokenType, String)> {
+    let mut lexer = Lexer::new(src.lines());
+    lexer.encoding = encoding;
+    let cs_table = lexer.cs_table.clone();
+    lexer.map(|t: Token| match t.cs() {
+        Some(cs) => (t.typ, cs_table.name(cs).to_string()),
+        None => (t.typ, t.char().to_string()),
+    }).collect()
+}
+
+fn lex(src: &str) -> Vec<(TokenType, String)> {
+    lex_with(src, Encoding::Classic)
+}
+
+fn cs(name: &str) -> (TokenType, String) {
+    (TokenType::Escape, name.to_string())
+}
+
+/// The space at the end of the last line
+fn eol() -> (TokenType, String) {
+    (TokenType::Space, " ".to_string())
+}
+
+fn chr(typ: TokenType, c: char) -> (TokenType, String) {
+    (typ, c.to_string())
+}
+
+#[test]
+fn hex_in_control_word() {
+    assert_eq!(lex(r"\b^^61ch"), vec![cs("bach")]);
+    assert_eq!(lex(r"\^^61b"), vec![cs("ab")]);
+    assert_eq!(lex(r"\b^^5cc"), vec![cs("b"), cs("c")]);
+}
+
+#[test]
+fn plus_minus_64() {
+    // ^^` is a space, skipped after a control word
+    assert_eq!(lex(r"\TeX ^^`^^` $"), vec![cs("TeX"), chr(TokenType::MathShift, '$'), eol()]);
+    assert_eq!(lex("a^^?"), vec![chr(TokenType::Letter, 'a'), eol()]);
+    assert_eq!(lex("^^@x"), vec![chr(TokenType::Letter, 'x'), eol()]);
+    assert_eq!(lex("^^Zx"), vec![chr(TokenType::Other, '\x1a'), chr(TokenType::Letter, 'x'), eol()]);
+}
+
+#[test]
+fn hex_digits() {
+    assert_eq!(lex("^^41^^4a"), vec![chr(TokenType::Letter, 'A'), chr(TokenType::Letter, 'J'), eol()]);
+    // uppercase letters are not hex digits, so ^^4A is ^^4 then A
+    assert_eq!(lex("^^4A"), vec![chr(TokenType::Letter, 't'), chr(TokenType::Letter, 'A'), eol()]);
+    // only one hex digit, so ^^4 is t
+    assert_eq!(lex("^^4g"), vec![chr(TokenType::Letter, 't'), chr(TokenType::Letter, 'g'), eol()]);
+    assert_eq!(lex("^^e9"), vec![chr(TokenType::Other, 'é'), eol()]);
+}
+
+#[test]
+fn repeated_reduction() {
+    // ^^5e is ^, which makes another ^^ with the following ^
+    assert_eq!(lex("^^5e^41"), vec![chr(TokenType::Letter, 'A'), eol()]);
+    assert_eq!(lex("^^5e^^"), vec![chr(TokenType::Other, '\x1e'), eol()]);
+    assert_eq!(lex("^x"), vec![chr(TokenType::Superscript, '^'), chr(TokenType::Letter, 'x'), eol()]);
+}
+
+#[test]
+fn unicode_forms() {
+    assert_eq!(lex_with("^^^^00e9^^^^^^01f600", Encoding::Unicode), vec![
+        chr(TokenType::Letter, 'é'),
+        chr(TokenType::Other, '😀'),
+        eol(),
+    ]);
+    assert_eq!(lex_with(r"\caf^^^^00e9", Encoding::Unicode), vec![cs("café")]);
+    // classic TeX reads ^^^ as character 30
+    assert_eq!(lex("^^^^00e9")[0], chr(TokenType::Other, '\x1e'));
+}
+
+#[test]
+fn end_line_char() {
+    let lex_lines = |src: &str, end_line_char| {
+        let mut lexer = Lexer::new(src.lines());
+        lexer.end_line_char = end_line_char;
+        lexer.map(|t| (t.typ, t.char())).collect::<Vec<_>>()
+    };
+
+    assert_eq!(lex_lines("a\nb", None), vec![(TokenType::Letter, 'a'), (TokenType::Letter, 'b')]);
+    assert_eq!(lex_lines("a\n\nb", None), vec![(TokenType::Letter, 'a'), (TokenType::Letter, 'b')]);
+    // the end of line character is an ordinary character if its catcode is not 5
+    assert_eq!(lex_lines("a", Some('x')), vec![(TokenType::Letter, 'a'), (TokenType::Letter, 'x')]);
+    // a comment takes the end of the line with it
+    assert_eq!(lex_lines("a%\nb", Some('x')), vec![(TokenType::Letter, 'a'), (TokenType::Letter, 'b'), (TokenType::Letter, 'x')]);
+}
+
+/// The source text of each token
+fn sources(lexer: impl Iterator<Item = Token>, src: &str) -> Vec<String> {
+    lexer.map(|t| src[t.range].to_string()).collect()
+}
+
+#[test]
+fn crlf_ranges() {
+    let src = "ab\r\n\\foo c\r\n\r\nd";
+    assert_eq!(sources(Lexer::from_source(src), src), ["a", "b", "\r\n", "\\foo", "c", "\r\n", "\r\n", "d", ""]);
+    assert_eq!(sources(Lexer::from_reader(src.as_bytes()), src), sources(Lexer::from_source(src), src));
+}
+
+#[test]
+fn trailing_space_ranges() {
+    let src = "a   \n\\b  \n  c\t \nd";
+    assert_eq!(sources(Lexer::from_source(src), src), ["a", "   \n", "\\b", "c", "\t", "d", ""]);
+
+    let tokens: Vec<Token> = Lexer::from_source(src).collect();
+    assert_eq!(tokens[1].range, 1..5);
+    assert_eq!(tokens[3].range, 12..13);
+    assert_eq!(tokens[5].range, 16..17);
+}
+
+#[test]
+fn spaces_collapse_in_state_m() {
+    assert_eq!(lex("a  \t b"), vec![
+        chr(TokenType::Letter, 'a'),
+        chr(TokenType::Space, ' '),
+        chr(TokenType::Letter, 'b'),
+        eol(),
+    ]);
+}
+
+#[test]
+fn spaces_skipped_in_state_n() {
+    assert_eq!(lex("   a"), vec![chr(TokenType::Letter, 'a'), eol()]);
+}
+
+#[test]
+fn spaces_skipped_after_control_words() {
+    assert_eq!(lex(r"\a  b"), vec![cs("a"), chr(TokenType::Letter, 'b'), eol()]);
+    // and at the end of the line
+    assert_eq!(lex("\\a\nb"), vec![cs("a"), chr(TokenType::Letter, 'b'), eol()]);
+}
+
+#[test]
+fn control_symbols() {
+    // a control space skips the following blanks, other control symbols do not
+    assert_eq!(lex(r"\  a"), vec![cs(" "), chr(TokenType::Letter, 'a'), eol()]);
+    assert_eq!(lex(r"\% a"), vec![cs("%"), chr(TokenType::Space, ' '), chr(TokenType::Letter, 'a'), eol()]);
+    assert_eq!(lex(r"\1a"), vec![cs("1"), chr(TokenType::Letter, 'a'), eol()]);
+}
+
+#[test]
+fn escape_at_end_of_line() {
+    // the end of line character follows the escape character
+    assert_eq!(lex("a\\"), vec![chr(TokenType::Letter, 'a'), cs("\r")]);
+
+    let mut lexer = Lexer::new("a\\".lines());
+    lexer.end_line_char = None;
+    let cs_table = lexer.cs_table.clone();
+    let toks: Vec<Token> = lexer.collect();
+    assert_eq!(cs_table.name(toks[1].cs().unwrap()).as_ref(), "");
+}
+
+#[test]
+fn end_of_line_by_state() {
+    // a space in state M, nothing in state S and \par in state N
+    assert_eq!(lex("a\nb"), vec![chr(TokenType::Letter, 'a'), eol(), chr(TokenType::Letter, 'b'), eol()]);
+    assert_eq!(lex("a \nb"), vec![chr(TokenType::Letter, 'a'), eol(), chr(TokenType::Letter, 'b'), eol()]);
+    assert_eq!(lex("a\n\n  \nb"), vec![
+        chr(TokenType::Letter, 'a'),
+        eol(),
+        cs("par"),
+        cs("par"),
+        chr(TokenType::Letter, 'b'),
+        eol(),
+    ]);
+}
+
+#[test]
+fn end_of_line_discards_rest() {
+    let mut lexer = Lexer::new("a!b\nc".lines());
+    lexer.catcodes.insert('!', TokenType::Eol);
+    let toks: Vec<_> = lexer.map(|t| (t.typ, t.char())).collect();
+    assert_eq!(toks, vec![
+        (TokenType::Letter, 'a'),
+        (TokenType::Space, ' '),
+        (TokenType::Letter, 'c'),
+        (TokenType::Space, ' '),
+    ]);
+}
+
+#[test]
+fn comments() {
+    assert_eq!(lex("a% b\nc"), vec![chr(TokenType::Letter, 'a'), chr(TokenType::Letter, 'c'), eol()]);
+    // a line with just a comment does not end a paragraph
+    assert_eq!(lex("a\n%\nb"), vec![chr(TokenType::Letter, 'a'), eol(), chr(TokenType::Letter, 'b'), eol()]);
+}
+
+#[test]
+fn ignored_and_invalid() {
+    let mut lexer = Lexer::new("a\0b\x7fc".lines());
+    let toks: Vec<_> = lexer.by_ref().map(|t| (t.typ, t.char())).collect();
+    assert_eq!(toks, vec![
+        (TokenType::Letter, 'a'),
+        (TokenType::Letter, 'b'),
+        (TokenType::Letter, 'c'),
+        (TokenType::Space, ' '),
+    ]);
+    assert_eq!(lexer.diagnostics.len(), 1);
+    assert_eq!(lexer.diagnostics[0].span.range, 3..4);
+}
+
+#[test]
+fn catcodes() {
+    assert_eq!(lex("{~#^_$&}"), vec![
+        chr(TokenType::BeginGroup, '{'),
+        chr(TokenType::Active, '~'),
+        chr(TokenType::Parameter, '#'),
+        chr(TokenType::Superscript, '^'),
+        chr(TokenType::Subscript, '_'),
+        chr(TokenType::MathShift, '$'),
+        chr(TokenType::AlignTab, '&'),
+        chr(TokenType::EndGroup, '}'),
+        eol(),
+    ]);
+
+    let mut lexer = Lexer::new(r"\a@b @".lines());
+    lexer.catcodes.insert('@', TokenType::Letter);
+    assert_eq!(lexer.catcode_of('@'), TokenType::Letter);
+    let cs_table = lexer.cs_table.clone();
+    let toks: Vec<Token> = lexer.collect();
+    assert_eq!(cs_table.name(toks[0].cs().unwrap()).as_ref(), "a@b");
+    assert_eq!((toks[1].typ, toks[1].char()), (TokenType::Letter, '@'));
+}
+
+#[test]
+fn unicode_letters() {
+    assert_eq!(lex_with(r"\für", Encoding::Unicode), vec![cs("für")]);
+    assert_eq!(lex(r"\für"), vec![cs("f"), chr(TokenType::Other, 'ü'), chr(TokenType::Letter, 'r'), eol()]);
+}
+
+#[test]
+fn incremental() {
+    let lex_lines = |mut lexer: Lexer<core::str::SplitInclusive<'_, char>>| {
+        let mut lines = Vec::new();
+        loop {
+            let checkpoint = lexer.checkpoint();
+            let Some(toks) = lexer.lex_line() else { break };
+            lines.push((checkpoint, toks));
+        }
+        lines
+    };
+
+    let before = "\\def\\a{x}\r\n\nsome text % comment\n\\a\\b  c\n";
+    let after = "\\def\\a{x}\r\n\nsome more text % comment\n\\a\\b  c\n";
+    let old = lex_lines(Lexer::from_source(before));
+    let new = lex_lines(Lexer::from_source(after));
+    assert_eq!(old.len(), 4);
+    assert_eq!(old[2].0.offset, 12);
+    assert_eq!(old[2].0.line, 2);
+
+    // re-lex from the edited line until the state converges
+    let edited = &old[2].0;
+    let mut lexer = Lexer::resume(after[edited.offset..].split_inclusive('\n'), Default::default(), edited);
+    let toks = lexer.lex_line().unwrap();
+    let next = lexer.checkpoint();
+    assert!(next.same_state(&old[3].0));
+    assert_eq!(next.offset, new[3].0.offset);
+
+    assert_eq!(toks, new[2].1);
+    let ranges = |toks: &[Token]| toks.iter().map(|t| t.range.clone()).collect::<Vec<_>>();
+    assert_eq!(ranges(&toks), ranges(&new[2].1));
+    let whole: Vec<Token> = Lexer::from_source(after).collect();
+    assert_eq!(new.iter().flat_map(|(_, t)| t.clone()).collect::<Vec<_>>(), whole);
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use std::collections::HashMap;
+
+use rstex::engine::{DirResolver, Engine, FileAccess, Limits, MapResolver};
+
+/// The capacity `tex` exceeds when it is typeset with `limits`, if any
+fn exceeded(tex: &str, limits: Limits) -> Option<String> {
+    let doc = Engine::builder().limits(limits).render(tex).unwrap();
+    common::messages(&doc).into_iter().find(|m| m.starts_with("TeX capacity exceeded"))
+}
+
+fn assert_exceeds(tex: &str, limits: Limits, resource: &str) {
+    let message = exceeded(tex, limits);
+    let expected = format!("TeX capacity exceeded, sorry [{resource}].");
+    assert_eq!(message.as_deref(), Some(expected.as_str()), "{tex}");
+}
+
+#[test]
+fn runaway_macros() {
+    assert_exceeds(r"\def\a{\a}\a", Limits::default(), "expansions=5000000");
+    assert_exceeds(r"\def\a{x\a}\a", Limits::default(), "main memory size=1000000");
+    assert_exceeds(r"\def\a{\relax\a}\a", Limits::default(), "steps=5000000");
+}
+
+#[test]
+fn deep_nesting() {
+    assert_exceeds(r"\def\a{\ifnum\a}\a", Limits::default(), "expansion depth=200");
+    let limits = Limits { max_input_stack: 1000, ..Limits::default() };
+    assert_exceeds(r"\def\a{\a x}\a", limits, "input stack size=1000");
+
+    let files = HashMap::from([("a.tex".to_string(), r"\input a".to_string())]);
+    let limits = Limits { file_access: FileAccess::Read, ..Limits::default() };
+    let doc = Engine::builder().limits(limits).resolver(MapResolver::new(files)).render(r"\input a").unwrap();
+    assert_eq!(doc.limit_exceeded(), Some(("text input levels", 15)));
+}
+
+#[test]
+fn reading_stays_in_the_directory() {
+    let dir = common::TempDir::new("limits-read");
+    let secret = dir.write("secret.tex", "secret");
+    std::fs::create_dir_all(dir.path().join("doc")).unwrap();
+    dir.write("doc/part.tex", "part");
+
+    let render = |tex: &str| {
+        let limits = Limits { file_access: FileAccess::Read, ..Limits::default() };
+        let resolver = DirResolver::new(vec![dir.path().join("doc")]);
+        let doc = Engine::builder().limits(limits).resolver(resolver).render(tex).unwrap();
+        common::text(&doc.pages)
+    };
+    assert_eq!(render(r"\input part"), "part");
+    assert_eq!(render(r"\input ../secret x"), "x");
+    assert_eq!(render(&format!(r"\input {} x", secret.display())), "x");
+    assert_eq!(render(r"\openin1=../secret \ifeof1 E\fi"), "E");
+    assert_eq!(render(&format!(r"\openin1={} \ifeof1 E\fi", secret.display())), "E");
+}
+
+#[test]
+fn smaller_limits() {
+    let limits = Limits { max_expansions: 10, ..Limits::default() };
+    let tex = r"\def\a{x}\a\a\a\a\a\a\a\a\a\a\a";
+    assert_exceeds(tex, limits.clone(), "expansions=10");
+    let doc = Engine::builder().limits(limits).render(tex).unwrap();
+    assert_eq!(doc.limit_exceeded(), Some(("expansions", 10)));
+    assert!(doc.has_errors());
+
+    let limits = Limits { max_steps: usize::MAX, ..Limits::trusted() };
+    assert_eq!(exceeded(r"\def\a{x}\a\a", limits), None);
+}
+
+#[test]
+fn recursive_boxes() {
+    assert_exceeds(r"\def\a{\hbox{\a}}\a", Limits::default(), "grouping levels=255");
+    assert_exceeds(r"\def\a{\vbox{\a}}\a", Limits::default(), "grouping levels=255");
+    assert_exceeds(&r"\hbox{".repeat(200000), Limits::default(), "grouping levels=255");
+}
+
+#[test]
+fn nested_lists() {
+    let limits = Limits { max_groups: 10000, ..Limits::default() };
+    assert_exceeds(&r"\hbox{".repeat(1000), limits, "semantic nest size=500");
+}
+
+#[test]
+fn boxes_nested_through_a_register() {
+    let tex = r"\setbox0\hbox{}\def\a{\setbox0\hbox{\box0}\a}\a";
+    assert_exceeds(tex, Limits::default(), "box nesting depth=500");
+}
+
+#[test]
+fn saved_values() {
+    let tex = r"{\def\a{\advance\count1 1 \expandafter\def\csname x\the\count1\endcsname{}\a}\a}";
+    assert_exceeds(tex, Limits::default(), "save size=100000");
+}
+
+#[test]
+fn growing_token_list() {
+    let tex = r"\toks0={x}\def\b{\toks0=\expandafter{\the\toks0 \the\toks0}\b}\b";
+    assert_exceeds(tex, Limits::default(), "token memory size=10000000");
+}
+
+#[test]
+fn endless_paragraphs() {
+    assert_exceeds(r"\everypar{\par}x", Limits::default(), "pages=1000");
+}
+
+#[test]
+fn nesting_within_limits() {
+    let tex = format!("{}x{}", r"\hbox{".repeat(200), "}".repeat(200));
+    assert_eq!(exceeded(&tex, Limits::default()), None);
+}
@@ -0,0 +1,74 @@
+use rstex::engine::Engine;
+use rstex::error::Error;
+use rstex::MathRenderer;
+
+#[test]
+fn formula_as_html() {
+    let html = rstex::render_math(r"x^2 + \frac{a}{b}", false).unwrap();
+    assert!(html.starts_with("<span class=\"rstex-math\""), "{html}");
+    assert!(html.contains(">x</span>"), "{html}");
+    // the style is put in the page once, not with every formula
+    assert!(!html.contains("<style>"), "{html}");
+    assert!(rstex::MATH_STYLE.contains(".rstex-math"));
+
+    let display = rstex::render_math(r"\sum_{i=1}^n i", true).unwrap();
+    assert!(display.starts_with("<span class=\"rstex-math rstex-display\""), "{display}");
+}
+
+#[test]
+fn formulas_are_independent() {
+    let first = rstex::render_math(r"\def\x{y}\x", false).unwrap();
+    let again = rstex::render_math(r"\x", false);
+    assert!(first.contains(">y</span>"), "{first}");
+    assert!(matches!(again, Err(Error::Tex(..))));
+}
+
+#[test]
+fn same_as_a_built_engine() {
+    let tex = r"\sqrt{\alpha + \beta}";
+    let built = Engine::builder().latex().render_math(tex, false).unwrap();
+    assert_eq!(rstex::render_math(tex, false).unwrap(), built);
+}
+
+#[test]
+fn formula_as_mathml() {
+    let mathml = Engine::builder().latex().math_renderer(MathRenderer::MathMl).render_math(r"\frac{1}{x}", true).unwrap();
+    assert_eq!(
+        mathml,
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\"><mrow><mrow><mfrac><mrow><mn>1</mn></mrow>\
+         <mrow><mi>x</mi></mrow></mfrac></mrow></mrow></math>",
+    );
+}
+
+#[test]
+fn mathml_without_a_builder() {
+    let tex = r"\frac{1}{x}";
+    let built = Engine::builder().latex().math_renderer(MathRenderer::MathMl).render_math(tex, true).unwrap();
+    assert_eq!(rstex::render_math_with(tex, true, MathRenderer::MathMl).unwrap(), built);
+    assert_eq!(rstex::render_math_with(tex, true, MathRenderer::Html).unwrap(), rstex::render_math(tex, true).unwrap());
+}
+
+#[test]
+fn roots() {
+    let html = rstex::render_math(r"\sqrt[3]{x}", false).unwrap();
+    assert!(!html.contains('['), "{html}");
+    // the index is set small, before the sign
+    let index = html.find(">3</span>").unwrap();
+    assert!(html[..index].ends_with("font-size:5pt\" data-src=\"0:6-7\""), "{html}");
+    assert!(index < html.find('√').unwrap(), "{html}");
+
+    let mathml = rstex::render_math_with(r"\sqrt[n]{a+b}", false, MathRenderer::MathMl).unwrap();
+    assert!(mathml.contains("<mi>n</mi></mrow><mspace width=\"-0.5555556em\"/><msqrt><mrow><mi>a</mi><mo>+</mo><mi>b</mi></mrow></msqrt>"), "{mathml}");
+    assert_eq!(
+        rstex::render_math_with(r"\sqrt{x}", false, MathRenderer::MathMl).unwrap(),
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow><msqrt><mi>x</mi></msqrt></mrow></math>",
+    );
+}
+
+#[test]
+fn errors_are_returned() {
+    let Err(Error::Tex(diagnostics, _)) = rstex::render_math(r"\undefined", false) else {
+        panic!("an undefined control sequence is an error");
+    };
+    assert_eq!(diagnostics[0].kind.to_string(), "Undefined control sequence.");
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use rstex::engine::Engine;
+
+const LABEL: &str = r"\documentclass{article}\begin{document}\section{One}\label{one}See~\ref{one}.\end{document}";
+
+#[test]
+fn references_resolve_in_the_second_run() {
+    let passes = Engine::builder().latex().render_passes(LABEL, 5).unwrap();
+    assert_eq!(passes.runs, 2);
+    assert!(passes.settled);
+    assert_eq!(passes.files.get("texput.aux").map(String::as_str), Some("\\newlabel{one}{1}\n"));
+    assert!(!common::text(&passes.document.pages).contains('?'));
+}
+
+#[test]
+fn runs_stop_at_the_maximum() {
+    let passes = Engine::builder().latex().render_passes(LABEL, 1).unwrap();
+    assert_eq!(passes.runs, 1);
+    assert!(!passes.settled);
+}
+
+/// Counts its runs up to 3 in a file of its own, without a format
+const COUNTER: &str = r"\openin1=count \ifeof1 \count1=0 \else \read1 to\n \count1=\n \closein1 \fi
+\ifnum\count1<3 \advance\count1 by1 \fi
+\immediate\openout1=count \immediate\write1{\the\count1}\immediate\closeout1 \the\count1";
+
+#[test]
+fn documents_without_files_settle_at_once() {
+    let passes = Engine::builder().render_passes("x", 5).unwrap();
+    assert_eq!((passes.runs, passes.settled), (1, true));
+    assert!(passes.files.is_empty());
+}
+
+#[test]
+fn runs_until_the_files_stop_changing() {
+    // files are kept in memory, so the default limits do not stop them from being read back
+    let passes = Engine::builder().render_passes(COUNTER, 10).unwrap();
+    assert_eq!((passes.runs, passes.settled), (4, true));
+    assert_eq!(passes.files.get("count.tex").map(String::as_str), Some("3\n"));
+    assert_eq!(common::text(&passes.document.pages), "3");
+
+    let passes = Engine::builder().render_passes(COUNTER, 2).unwrap();
+    assert_eq!((passes.runs, passes.settled), (2, false));
+    assert_eq!(passes.files.get("count.tex").map(String::as_str), Some("2\n"));
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use rstex::layout::{BoxContent, Node};
+
+/// The glyphs typeset for `tex` without a format, with their spans
+fn glyphs(tex: &str) -> Vec<(char, String)> {
+    let doc = common::render(tex);
+    assert!(doc.diagnostics.is_empty(), "{tex}: {}", doc.report());
+    common::glyphs(&doc.pages)
+}
+
+fn spans(expected: &[(char, &str)]) -> Vec<(char, String)> {
+    expected.iter().map(|(glyph, src)| (*glyph, src.to_string())).collect()
+}
+
+#[test]
+fn characters_map_to_their_bytes() {
+    assert_eq!(glyphs("ab"), spans(&[('a', "0:0-1"), ('b', "0:1-2")]));
+    assert_eq!(glyphs("a  b"), spans(&[('a', "0:0-1"), ('b', "0:3-4")]));
+    // byte offsets, not character offsets
+    assert_eq!(glyphs("é b"), spans(&[('é', "0:0-2"), ('b', "0:3-4")]));
+}
+
+#[test]
+fn macro_bodies_map_to_the_call() {
+    assert_eq!(glyphs(r"\def\m{xy}a\m b"), spans(&[('a', "0:10-11"), ('x', "0:11-13"), ('y', "0:11-13"), ('b', "0:14-15")]));
+    // arguments keep their own bytes
+    assert_eq!(glyphs(r"\def\m#1{[#1]}\m{cd}"), spans(&[('[', "0:14-16"), ('c', "0:17-18"), ('d', "0:18-19"), (']', "0:14-16")]));
+}
+
+#[test]
+fn the_maps_to_the_command() {
+    assert_eq!(glyphs(r"\count1=5 \the\count1"), spans(&[('5', "0:10-14")]));
+}
+
+#[test]
+fn rules_have_a_source() {
+    let doc = common::render(r"a\vrule width 2pt b");
+    let rule = common::leaves(&doc.pages).into_iter().find(|n| matches!(n, Node::Box { content: BoxContent::VRule, .. })).unwrap();
+    assert_eq!(common::src(rule), "0:1-7");
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use common::TempDir;
+
+/// The watch server, killed when dropped so that a failed test does not leave it running
+struct Server {
+    child: Child,
+    addr: String,
+}
+
+impl Server {
+    /// Starts `rstex watch` on a free port in `dir`, and waits for it to serve the first version
+    fn start(dir: &Path, input: &str) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rstex"))
+            .current_dir(dir)
+            .args(["watch", "--port", "0", input])
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut stderr = BufReader::new(child.stderr.take().unwrap());
+        let mut line = String::new();
+        let addr = loop {
+            line.clear();
+            assert!(stderr.read_line(&mut line).unwrap() > 0, "the server stopped");
+            if let Some(url) = line.split("http://").nth(1) {
+                break url.trim_end().trim_end_matches('/').to_string();
+            }
+        };
+        // keep reading, so that the server never blocks on a full pipe
+        std::thread::spawn(move || std::io::copy(&mut stderr, &mut std::io::sink()));
+
+        Self { child, addr }
+    }
+
+    /// The status line and body of the answer to a GET of `target`
+    fn get(&self, target: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(&self.addr).unwrap();
+        write!(stream, "GET {target} HTTP/1.1\r\nHost: {}\r\n\r\n", self.addr).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Writes `text` to `path` with a modification time clearly after the last one
+fn touch(path: &Path, text: &str) {
+    fs::write(path, text).unwrap();
+    let later = SystemTime::now() + Duration::from_secs(5);
+    File::options().write(true).open(path).unwrap().set_modified(later).unwrap();
+}
+
+#[test]
+fn serves_the_preview() {
+    let dir = TempDir::new("watch-preview");
+    dir.write("doc.tex", "a<b");
+    let server = Server::start(dir.path(), "doc.tex");
+
+    let (status, page) = server.get("/");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(page.starts_with("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>doc.tex</title>"), "{page}");
+    assert!(page.contains(">&lt;</span>"), "{page}");
+    assert!(page.contains("fetch('/wait?version=' + 1)"), "{page}");
+
+    assert_eq!(server.get("/other").0, "HTTP/1.1 404 Not Found");
+}
+
+#[test]
+fn reloads_when_a_file_changes() {
+    let dir = TempDir::new("watch-reload");
+    let doc = dir.write("doc.tex", r"\undefined x");
+    let server = Server::start(dir.path(), "doc.tex");
+
+    // errors are shown above the page
+    let (_, page) = server.get("/");
+    assert!(page.contains("<pre style=\"color:#b00;white-space:pre-wrap\">! Undefined control sequence."), "{page}");
+
+    touch(&doc, "y");
+    assert_eq!(server.get("/wait?version=1").1, "2");
+    let (_, page) = server.get("/");
+    assert!(page.contains(">y</span>") && !page.contains("<pre"), "{page}");
+    assert!(page.contains("fetch('/wait?version=' + 2)"), "{page}");
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+
+use rstex::engine::{DirSink, Engine, FileAccess, Limits, MemorySink};
+use rstex::{Document, OutputFormat};
+
+use common::{messages, TempDir};
+
+/// Typesets `tex` with files written to a [`MemorySink`], returning the document and the files
+fn render(tex: &str, file_access: FileAccess) -> (Document, HashMap<String, String>) {
+    let sink = MemorySink::new();
+    let limits = Limits { file_access, ..Limits::default() };
+    let doc = Engine::builder().limits(limits).sink(sink.clone()).render(tex).unwrap();
+    (doc, sink.files())
+}
+
+fn files(entries: &[(&str, &str)]) -> HashMap<String, String> {
+    entries.iter().map(|(name, text)| (name.to_string(), text.to_string())).collect()
+}
+
+#[test]
+fn writes_are_expanded_at_shipout() {
+    let tex = r"\openout1=out.txt \def\a{A}\write1{\a}\def\a{B}x\immediate\write1{now \a}\closeout1";
+    let (doc, written) = render(tex, FileAccess::ReadWrite);
+    assert!(doc.diagnostics.is_empty(), "{}", doc.report());
+    assert_eq!(written, files(&[("out.txt", "B\n")]));
+    // the stream is only opened at shipout, so the immediate write goes to the log
+    assert_eq!(doc.metadata.log, "now B\n");
+}
+
+#[test]
+fn immediate() {
+    let tex = r"\immediate\openout2=o \immediate\write2{one}\immediate\write2{two}\immediate\closeout2 ";
+    assert_eq!(render(tex, FileAccess::ReadWrite).1, files(&[("o.tex", "one\ntwo\n")]));
+
+    // opening again at shipout empties the file
+    let tex = tex.to_string() + r"\openout2=o";
+    assert_eq!(render(&tex, FileAccess::ReadWrite).1, files(&[("o.tex", "")]));
+}
+
+#[test]
+fn terminal_and_log() {
+    let tex = r"\write16{term}\immediate\write17{logonly}\immediate\write5{unopened}\message{m}x";
+    let (doc, written) = render(tex, FileAccess::ReadWrite);
+    assert!(written.is_empty());
+    assert_eq!(doc.metadata.log, "logonly\nunopened\nm\nterm\n");
+
+    let (doc, _) = render(r"\newlinechar=`| \immediate\write16{a|b}", FileAccess::ReadWrite);
+    assert_eq!(doc.metadata.log, "a\nb\n");
+}
+
+#[test]
+fn writing_needs_access() {
+    let tex = r"\immediate\openout1=out \immediate\write1{a}";
+    let (doc, written) = render(tex, FileAccess::Read);
+    assert!(written.is_empty());
+    assert_eq!(messages(&doc), ["I'm not allowed to open file `out'."]);
+    assert_eq!(doc.metadata.log, "a\n");
+}
+
+#[test]
+fn dir_sink_stays_in_its_directory() {
+    let temp = TempDir::new("write");
+    let dir = temp.path().join("out");
+    fs::create_dir_all(&dir).unwrap();
+    let limits = Limits { file_access: FileAccess::ReadWrite, ..Limits::default() };
+    let tex = r"\immediate\openout1=in \immediate\write1{a}\immediate\openout2=../out \immediate\write2{b}";
+    let doc = Engine::builder().limits(limits).sink(DirSink::new(&dir)).render(tex).unwrap();
+
+    assert_eq!(fs::read_to_string(dir.join("in.tex")).unwrap(), "a\n");
+    assert!(!dir.with_file_name("out.tex").exists());
+    assert_eq!(messages(&doc), ["I can't write on file `../out.tex'."]);
+}
+
+#[test]
+fn errmessage_and_special() {
+    let (doc, _) = render(r"\errmessage{oops}\special{hi!}x", FileAccess::None);
+    assert_eq!(messages(&doc), ["oops."]);
+
+    // xxx1 with the length of the text
+    let dvi = doc.write(OutputFormat::Dvi);
+    assert!(dvi.windows(5).any(|w| w == [239, 3, b'h', b'i', b'!']));
+}